@@ -0,0 +1,749 @@
+use crate::client::CloudManagerClient;
+use crate::domains::{create_domains, get_domains};
+use crate::exit_code::ExitCode;
+use crate::ip_allowlists::{
+    bind_ip_allowlists_from_file, find_binding, find_ip_allowlist, get_ip_allowlists,
+};
+use crate::models::config::{BindingConfig, YamlConfig};
+use crate::models::domain::Domain;
+use crate::models::environment::EnvironmentId;
+use crate::models::ip_allowlist::IpAllowlistsList;
+use crate::models::variables::{EnvironmentVariable, PipelineVariable, VariableType};
+use crate::programs::read_config;
+use crate::variables::{
+    get_env_vars, get_pipeline_vars, merge_with_defaults, set_env_vars_from_file,
+    set_pipeline_vars_from_file, SyncMode,
+};
+use colored::*;
+use reqwest::StatusCode;
+use std::fmt;
+
+/// A part of the configuration YAML that `apply` processes, in the order they are applied
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum ApplyStep {
+    /// Domains have to exist before anything refers to them
+    Domains,
+    EnvironmentVariables,
+    PipelineVariables,
+    IpAllowlistBindings,
+}
+
+impl fmt::Display for ApplyStep {
+    fn fmt(&self, formatter: &mut fmt::Formatter) -> fmt::Result {
+        let name = match self {
+            ApplyStep::Domains => "domains",
+            ApplyStep::EnvironmentVariables => "environment variables",
+            ApplyStep::PipelineVariables => "pipeline variables",
+            ApplyStep::IpAllowlistBindings => "IP allowlist bindings",
+        };
+        write!(formatter, "{}", name)
+    }
+}
+
+/// What happened to a step of `apply`
+#[derive(Debug, PartialEq)]
+pub enum ApplyOutcome {
+    Applied,
+    /// The file has no such section
+    NotInFile,
+    /// The step was skipped for the contained reason
+    Skipped(String),
+    Failed(String),
+}
+
+/// Returns every step in the order they are applied, or whether the file has nothing for it.
+///
+/// # Arguments
+///
+/// * `config` - The parsed configuration YAML
+pub fn planned_steps(config: &YamlConfig) -> Vec<(ApplyStep, bool)> {
+    let environments = || {
+        config
+            .programs
+            .iter()
+            .flat_map(|p| p.environments.iter().flatten())
+    };
+    let has_domains = environments().any(|e| e.domains.as_ref().is_some_and(|d| !d.is_empty()));
+    let has_env_vars = environments().any(|e| !e.variables.is_empty())
+        || config
+            .programs
+            .iter()
+            .any(|p| p.defaults.as_ref().is_some_and(|d| !d.variables.is_empty()));
+    let has_pipeline_vars = config
+        .programs
+        .iter()
+        .flat_map(|p| p.pipelines.iter().flatten())
+        .any(|l| !l.variables.is_empty());
+    let has_bindings = environments().any(|e| e.bindings.as_ref().is_some_and(|b| !b.is_empty()));
+    vec![
+        (ApplyStep::Domains, has_domains),
+        (ApplyStep::EnvironmentVariables, has_env_vars),
+        (ApplyStep::PipelineVariables, has_pipeline_vars),
+        (ApplyStep::IpAllowlistBindings, has_bindings),
+    ]
+}
+
+/// Applies everything a configuration YAML contains, step by step, and prints a summary.
+///
+/// Returns whether all steps succeeded. Like the single commands, errors in the file that can't be
+/// recovered from, e.g. an unreadable config, still abort pippo right away.
+///
+/// # Arguments
+///
+/// * `file_path` - String slice that holds the path to the YAML config file
+/// * `client` - A mutable reference to a CloudManagerClient instance
+/// * `ci_mode` - Skip resources that can't be updated at the moment
+/// * `dry_run` - Only print what would be changed
+pub async fn apply_config(
+    file_path: &str,
+    client: &mut CloudManagerClient,
+    ci_mode: bool,
+    dry_run: bool,
+) -> bool {
+    let config = read_config(client, file_path).await;
+    let mut results = vec![];
+    for (step, in_file) in planned_steps(&config) {
+        if !in_file {
+            results.push((step, ApplyOutcome::NotInFile));
+            continue;
+        }
+        println!("\n🚀 Applying {} from {}\n", step, file_path);
+        let outcome = match step {
+            ApplyStep::Domains if dry_run => {
+                ApplyOutcome::Skipped(String::from("domains don't support --dry-run"))
+            }
+            ApplyStep::Domains => match create_domains(file_path.to_string(), client).await {
+                Ok(StatusCode::OK) => ApplyOutcome::Applied,
+                Ok(_) => ApplyOutcome::Failed(String::from("not all domains were created")),
+                Err(err) => ApplyOutcome::Failed(err.to_string()),
+            },
+            ApplyStep::EnvironmentVariables => step_outcome(
+                set_env_vars_from_file(
+                    file_path,
+                    client,
+                    ci_mode,
+                    dry_run,
+                    false,
+                    None,
+                    SyncMode::Full,
+                )
+                .await,
+            ),
+            ApplyStep::PipelineVariables => {
+                step_outcome(set_pipeline_vars_from_file(file_path, client, ci_mode, dry_run).await)
+            }
+            ApplyStep::IpAllowlistBindings => {
+                step_outcome(bind_ip_allowlists_from_file(file_path, client, dry_run).await)
+            }
+        };
+        results.push((step, outcome));
+    }
+
+    println!("\n📋 Summary of {}", file_path);
+    for line in render_summary(&results) {
+        println!("{}", line);
+    }
+    results
+        .iter()
+        .all(|(_, outcome)| !matches!(outcome, ApplyOutcome::Failed(_)))
+}
+
+/// Turns the result of a step that reports failures with an exit code into its outcome.
+fn step_outcome(result: Result<(), ExitCode>) -> ApplyOutcome {
+    match result {
+        Ok(()) => ApplyOutcome::Applied,
+        Err(code) => ApplyOutcome::Failed(code.to_string()),
+    }
+}
+
+/// Renders one line per step for the summary of `apply`.
+///
+/// # Arguments
+///
+/// * `results` - The steps and what happened to them
+pub fn render_summary(results: &[(ApplyStep, ApplyOutcome)]) -> Vec<String> {
+    results
+        .iter()
+        .map(|(step, outcome)| match outcome {
+            ApplyOutcome::Applied => format!("{:>4} {}", "✔".green(), step),
+            ApplyOutcome::NotInFile => format!("{:>4} {} (not in file)", "-", step),
+            ApplyOutcome::Skipped(reason) => {
+                format!("{:>4} {} skipped: {}", "⚠".yellow(), step, reason)
+            }
+            ApplyOutcome::Failed(reason) => {
+                format!("{:>4} {} failed: {}", "❌".red(), step, reason)
+            }
+        })
+        .collect()
+}
+
+/// Number of domains that are fetched per request while planning
+const PLAN_DOMAIN_PAGE_SIZE: u32 = 1000;
+
+/// What `apply` would do to a single resource
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum PlanAction {
+    Create,
+    Update,
+    Delete,
+    /// A secret that exists on both sides; Cloud Manager doesn't return its value to compare it
+    Unknown,
+}
+
+/// A resource that differs between the configuration YAML and Cloud Manager
+#[derive(Debug, PartialEq)]
+pub struct PlanEntry {
+    pub step: ApplyStep,
+    /// Program, environment or pipeline the resource belongs to
+    pub target: String,
+    pub action: PlanAction,
+    pub name: String,
+}
+
+/// A variable as far as the plan compares it, for environment and pipeline variables alike
+#[derive(Debug, PartialEq)]
+pub struct PlannedVariable {
+    pub name: String,
+    pub service: String,
+    pub value: Option<String>,
+    pub secret: bool,
+}
+
+impl From<&EnvironmentVariable> for PlannedVariable {
+    fn from(variable: &EnvironmentVariable) -> Self {
+        PlannedVariable {
+            name: variable.name.clone(),
+            service: variable.service.to_string(),
+            value: variable.value.clone(),
+            secret: variable.variable_type == VariableType::SecretString,
+        }
+    }
+}
+
+impl From<&PipelineVariable> for PlannedVariable {
+    fn from(variable: &PipelineVariable) -> Self {
+        PlannedVariable {
+            name: variable.name.clone(),
+            service: variable.service.to_string(),
+            value: variable.value.clone(),
+            secret: variable.variable_type == VariableType::SecretString,
+        }
+    }
+}
+
+/// Compares the variables of the configuration YAML with those in Cloud Manager.
+///
+/// Like `vars set`, variables that only exist in Cloud Manager are deleted. Unchanged variables
+/// are left out.
+///
+/// # Arguments
+///
+/// * `yaml` - The variables of the configuration YAML
+/// * `cloud` - The variables that are currently set in Cloud Manager
+pub fn diff_variables(
+    yaml: &[PlannedVariable],
+    cloud: &[PlannedVariable],
+) -> Vec<(PlanAction, String)> {
+    let same =
+        |a: &PlannedVariable, b: &PlannedVariable| a.name == b.name && a.service == b.service;
+    let label = |v: &PlannedVariable| format!("{} ({})", v.name, v.service);
+    let mut changes = vec![];
+    for vy in yaml {
+        let action = match cloud.iter().find(|vc| same(vy, vc)) {
+            None => Some(PlanAction::Create),
+            Some(vc) if vy.secret != vc.secret => Some(PlanAction::Update),
+            Some(_) if vy.secret => Some(PlanAction::Unknown),
+            Some(vc) if vy.value != vc.value => Some(PlanAction::Update),
+            Some(_) => None,
+        };
+        if let Some(action) = action {
+            changes.push((action, label(vy)));
+        }
+    }
+    for vc in cloud {
+        if !yaml.iter().any(|vy| same(vy, vc)) {
+            changes.push((PlanAction::Delete, label(vc)));
+        }
+    }
+    changes
+}
+
+/// Returns the domains of the configuration YAML that don't exist in Cloud Manager yet.
+///
+/// `domain create` never changes or deletes domains, so neither does the plan.
+///
+/// # Arguments
+///
+/// * `wanted` - Environment and name of every domain in the configuration YAML
+/// * `existing` - The domains of the program in Cloud Manager
+pub fn diff_domains(
+    wanted: &[(EnvironmentId, String)],
+    existing: &[Domain],
+) -> Vec<(PlanAction, String)> {
+    wanted
+        .iter()
+        .filter(|(_, name)| !existing.iter().any(|domain| &domain.name == name))
+        .map(|(env_id, name)| {
+            (
+                PlanAction::Create,
+                format!("{} (environment {})", name, env_id),
+            )
+        })
+        .collect()
+}
+
+/// Compares everything a configuration YAML contains with Cloud Manager, without changing
+/// anything.
+///
+/// # Arguments
+///
+/// * `config` - The parsed configuration YAML
+/// * `client` - A mutable reference to a CloudManagerClient instance
+pub async fn plan_config(
+    config: &YamlConfig,
+    client: &mut CloudManagerClient,
+) -> Result<Vec<PlanEntry>, reqwest::Error> {
+    let mut plan = vec![];
+    let mut push = |step: ApplyStep, target: &str, changes: Vec<(PlanAction, String)>| {
+        plan.extend(changes.into_iter().map(|(action, name)| PlanEntry {
+            step,
+            target: target.to_string(),
+            action,
+            name,
+        }))
+    };
+    for (step, in_file) in planned_steps(config) {
+        if !in_file {
+            continue;
+        }
+        for p in &config.programs {
+            match step {
+                ApplyStep::Domains => {
+                    let wanted: Vec<(EnvironmentId, String)> = p
+                        .environments
+                        .iter()
+                        .flatten()
+                        .flat_map(|e| {
+                            e.domains
+                                .iter()
+                                .flatten()
+                                .map(move |d| (e.id, d.domainname.clone()))
+                        })
+                        .collect();
+                    if wanted.is_empty() {
+                        continue;
+                    }
+                    let mut existing = vec![];
+                    let mut start = 0;
+                    loop {
+                        let page =
+                            get_domains(client, p.program_id(), &start, &PLAN_DOMAIN_PAGE_SIZE)
+                                .await?
+                                .list;
+                        let complete = page.len() < PLAN_DOMAIN_PAGE_SIZE as usize;
+                        existing.extend(page);
+                        if complete {
+                            break;
+                        }
+                        start += PLAN_DOMAIN_PAGE_SIZE;
+                    }
+                    push(
+                        step,
+                        &format!("program {}", p.program_id()),
+                        diff_domains(&wanted, &existing),
+                    );
+                }
+                ApplyStep::EnvironmentVariables => {
+                    let defaults = p.defaults.as_ref().map_or(&[][..], |d| &d.variables[..]);
+                    for e in p.environments.iter().flatten() {
+                        let yaml: Vec<PlannedVariable> =
+                            merge_with_defaults(defaults, &e.variables)
+                                .iter()
+                                .map(PlannedVariable::from)
+                                .collect();
+                        let cloud: Vec<PlannedVariable> =
+                            get_env_vars(client, p.program_id(), e.id)
+                                .await?
+                                .variables
+                                .iter()
+                                .map(PlannedVariable::from)
+                                .collect();
+                        push(
+                            step,
+                            &format!("environment {}", e.id),
+                            diff_variables(&yaml, &cloud),
+                        );
+                    }
+                }
+                ApplyStep::PipelineVariables => {
+                    for l in p.pipelines.iter().flatten() {
+                        let yaml: Vec<PlannedVariable> =
+                            l.variables.iter().map(PlannedVariable::from).collect();
+                        let cloud: Vec<PlannedVariable> =
+                            get_pipeline_vars(client, p.program_id(), &l.id)
+                                .await?
+                                .variables
+                                .iter()
+                                .map(PlannedVariable::from)
+                                .collect();
+                        push(
+                            step,
+                            &format!("pipeline {}", l.id),
+                            diff_variables(&yaml, &cloud),
+                        );
+                    }
+                }
+                ApplyStep::IpAllowlistBindings => {
+                    let wanted: Vec<(EnvironmentId, &BindingConfig)> = p
+                        .environments
+                        .iter()
+                        .flatten()
+                        .flat_map(|e| e.bindings.iter().flatten().map(move |b| (e.id, b)))
+                        .collect();
+                    if wanted.is_empty() {
+                        continue;
+                    }
+                    let existing = get_ip_allowlists(client, p.program_id()).await?;
+                    push(
+                        step,
+                        &format!("program {}", p.program_id()),
+                        diff_bindings(&wanted, &existing),
+                    );
+                }
+            }
+        }
+    }
+    Ok(plan)
+}
+
+/// Returns the bindings of the file that don't exist in Cloud Manager yet. Bindings are never
+/// removed, so the plan only contains creations.
+///
+/// # Arguments
+///
+/// * `wanted` - The environments and bindings of the file
+/// * `existing` - The IP allowlists of the program, including their bindings
+pub fn diff_bindings(
+    wanted: &[(EnvironmentId, &BindingConfig)],
+    existing: &IpAllowlistsList,
+) -> Vec<(PlanAction, String)> {
+    wanted
+        .iter()
+        .filter(|(env_id, binding)| {
+            find_ip_allowlist(existing, &binding.allowlist)
+                .and_then(|allowlist| find_binding(allowlist, *env_id, &binding.service))
+                .is_none()
+        })
+        .map(|(env_id, binding)| {
+            (
+                PlanAction::Create,
+                format!(
+                    "{} ({} of environment {})",
+                    binding.allowlist,
+                    Into::<&str>::into(&binding.service),
+                    env_id
+                ),
+            )
+        })
+        .collect()
+}
+
+/// Returns whether applying the plan would change anything in Cloud Manager that can be detected.
+///
+/// # Arguments
+///
+/// * `plan` - The entries of the plan
+pub fn has_drift(plan: &[PlanEntry]) -> bool {
+    plan.iter().any(|entry| entry.action != PlanAction::Unknown)
+}
+
+/// Renders the plan grouped by step and target, followed by a line with the totals.
+///
+/// # Arguments
+///
+/// * `plan` - The entries of the plan
+pub fn render_plan(plan: &[PlanEntry]) -> Vec<String> {
+    let mut lines = vec![];
+    let mut group: Option<(ApplyStep, &str)> = None;
+    for entry in plan {
+        if group != Some((entry.step, entry.target.as_str())) {
+            lines.push(format!("{:>4} {} of {}", "⬛", entry.step, entry.target));
+            group = Some((entry.step, entry.target.as_str()));
+        }
+        lines.push(match entry.action {
+            PlanAction::Create => format!("{:>8} {}", "+".green(), entry.name),
+            PlanAction::Update => format!("{:>8} {}", "~".yellow(), entry.name),
+            PlanAction::Delete => format!("{:>8} {}", "-".red(), entry.name),
+            PlanAction::Unknown => format!(
+                "{:>8} {} (secret, value can't be compared)",
+                "?", entry.name
+            ),
+        });
+    }
+    let count = |action: PlanAction| plan.iter().filter(|e| e.action == action).count();
+    if !has_drift(plan) {
+        lines.push(String::from("No changes, Cloud Manager matches the file."));
+    } else {
+        lines.push(format!(
+            "Plan: {} to create, {} to change, {} to delete.",
+            count(PlanAction::Create),
+            count(PlanAction::Update),
+            count(PlanAction::Delete)
+        ));
+    }
+    lines
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::client::mock::{MockResponse, MockServer};
+    use crate::models::ip_allowlist::{BindingTier, IpAllowlistsResponse};
+    use crate::models::tests::{read_json_from_file, read_yaml_from_file};
+
+    fn steps_of(file: &str) -> Vec<(ApplyStep, bool)> {
+        let config: YamlConfig = read_yaml_from_file(file).unwrap();
+        planned_steps(&config)
+    }
+
+    #[test]
+    fn steps_are_planned_in_dependency_order() {
+        assert_eq!(
+            steps_of("test/test_yaml_config_apply.yml"),
+            vec![
+                (ApplyStep::Domains, true),
+                (ApplyStep::EnvironmentVariables, true),
+                (ApplyStep::PipelineVariables, true),
+                (ApplyStep::IpAllowlistBindings, true),
+            ]
+        );
+    }
+
+    #[test]
+    fn sections_that_are_missing_are_not_applied() {
+        assert_eq!(
+            steps_of("test/test_yaml_config_domains.yml"),
+            vec![
+                (ApplyStep::Domains, true),
+                (ApplyStep::EnvironmentVariables, false),
+                (ApplyStep::PipelineVariables, false),
+                (ApplyStep::IpAllowlistBindings, false),
+            ]
+        );
+        assert_eq!(
+            steps_of("test/test_yaml_config.yml"),
+            vec![
+                (ApplyStep::Domains, false),
+                (ApplyStep::EnvironmentVariables, false),
+                (ApplyStep::PipelineVariables, true),
+                (ApplyStep::IpAllowlistBindings, false),
+            ]
+        );
+    }
+
+    fn variable(name: &str, value: Option<&str>, secret: bool) -> PlannedVariable {
+        PlannedVariable {
+            name: name.to_string(),
+            service: String::from("all"),
+            value: value.map(String::from),
+            secret,
+        }
+    }
+
+    #[test]
+    fn diff_variables_finds_every_kind_of_change() {
+        let yaml = vec![
+            variable("SAME", Some("1"), false),
+            variable("CHANGED", Some("new"), false),
+            variable("NEW", Some("x"), false),
+            variable("SECRET", Some("$enc abc"), true),
+            variable("NOW_SECRET", Some("$enc abc"), true),
+        ];
+        let cloud = vec![
+            variable("SAME", Some("1"), false),
+            variable("CHANGED", Some("old"), false),
+            variable("SECRET", None, true),
+            variable("NOW_SECRET", Some("plain"), false),
+            variable("GONE", Some("y"), false),
+        ];
+        assert_eq!(
+            diff_variables(&yaml, &cloud),
+            vec![
+                (PlanAction::Update, String::from("CHANGED (all)")),
+                (PlanAction::Create, String::from("NEW (all)")),
+                (PlanAction::Unknown, String::from("SECRET (all)")),
+                (PlanAction::Update, String::from("NOW_SECRET (all)")),
+                (PlanAction::Delete, String::from("GONE (all)")),
+            ]
+        );
+    }
+
+    #[test]
+    fn diff_domains_only_creates_missing_domains() {
+        let existing: Vec<Domain> = serde_json::from_value(serde_json::json!([{
+            "name": "www.example.com",
+            "dnsTxtRecord": "adobe-aem-verification=www.example.com/1/2/3",
+            "environmentId": 333333,
+            "certificateId": 1111
+        }]))
+        .unwrap();
+        let wanted = vec![
+            (EnvironmentId(333333), String::from("www.example.com")),
+            (EnvironmentId(333333), String::from("shop.example.com")),
+        ];
+        assert_eq!(
+            diff_domains(&wanted, &existing),
+            vec![(
+                PlanAction::Create,
+                String::from("shop.example.com (environment 333333)")
+            )]
+        );
+    }
+
+    #[test]
+    fn diff_bindings_only_creates_missing_bindings() {
+        let existing: IpAllowlistsResponse =
+            read_json_from_file("test/test_ip_allowlists_response.json").unwrap();
+        let author = BindingConfig {
+            allowlist: String::from("office"),
+            service: BindingTier::Author,
+        };
+        let publish = BindingConfig {
+            allowlist: String::from("office"),
+            service: BindingTier::Publish,
+        };
+        let wanted = vec![
+            (EnvironmentId(33333), &author),
+            (EnvironmentId(33333), &publish),
+        ];
+        assert_eq!(
+            diff_bindings(&wanted, &existing.ip_allowlists_list),
+            vec![(
+                PlanAction::Create,
+                String::from("office (publish of environment 33333)")
+            )]
+        );
+    }
+
+    fn bindings_config(allowlist: &str) -> String {
+        let path = std::env::temp_dir().join(format!("pippo-apply-{}.yml", uuid::Uuid::new_v4()));
+        let yaml = format!(
+            "programs:\n  - id: 22222\n    environments:\n      - id: 33333\n        bindings:\n          - allowlist: {}\n            service: publish\n",
+            allowlist
+        );
+        std::fs::write(&path, yaml).unwrap();
+        path.to_str().unwrap().to_string()
+    }
+
+    #[tokio::test]
+    async fn bindings_of_the_file_are_applied() {
+        let server = MockServer::start();
+        server
+            .route(
+                "GET",
+                "/api/program/22222/ipAllowlists",
+                vec![MockResponse::json(
+                    read_json_from_file("test/test_ip_allowlists_response.json").unwrap(),
+                )],
+            )
+            .route(
+                "POST",
+                "/api/program/22222/ipAllowlist/1111/bindings",
+                vec![MockResponse::new(201, "{}")],
+            );
+        let path = bindings_config("office");
+
+        assert!(apply_config(&path, &mut server.client(), false, false).await);
+        let posts: Vec<_> = server
+            .requests()
+            .into_iter()
+            .filter(|r| r.method == "POST")
+            .collect();
+        assert_eq!(posts.len(), 1);
+        assert!(
+            posts[0].body.contains(r#""tier":"publish""#),
+            "{}",
+            posts[0].body
+        );
+        std::fs::remove_file(path).unwrap();
+    }
+
+    #[tokio::test]
+    async fn failed_steps_are_reported_instead_of_exiting() {
+        let server = MockServer::start();
+        server.route(
+            "GET",
+            "/api/program/22222/ipAllowlists",
+            vec![MockResponse::json(
+                read_json_from_file("test/test_ip_allowlists_response.json").unwrap(),
+            )],
+        );
+        let path = bindings_config("unknown");
+
+        assert!(!apply_config(&path, &mut server.client(), false, false).await);
+        std::fs::remove_file(path).unwrap();
+    }
+
+    #[test]
+    fn plan_is_rendered_per_target() {
+        colored::control::set_override(false);
+        let entry = |step, target: &str, action, name: &str| PlanEntry {
+            step,
+            target: target.to_string(),
+            action,
+            name: name.to_string(),
+        };
+        let plan = vec![
+            entry(
+                ApplyStep::Domains,
+                "program 1",
+                PlanAction::Create,
+                "www.example.com (environment 2)",
+            ),
+            entry(
+                ApplyStep::EnvironmentVariables,
+                "environment 2",
+                PlanAction::Update,
+                "FOO (all)",
+            ),
+            entry(
+                ApplyStep::EnvironmentVariables,
+                "environment 2",
+                PlanAction::Unknown,
+                "SECRET (all)",
+            ),
+        ];
+        let lines = render_plan(&plan);
+        assert_eq!(lines.len(), 6);
+        assert!(lines[0].ends_with("domains of program 1"));
+        assert!(lines[2].ends_with("environment variables of environment 2"));
+        assert!(lines[3].ends_with("~ FOO (all)"));
+        assert_eq!(lines[5], "Plan: 1 to create, 1 to change, 0 to delete.");
+        assert!(has_drift(&plan));
+        assert!(!has_drift(&plan[2..]));
+        assert_eq!(
+            render_plan(&plan[2..]).last().unwrap(),
+            "No changes, Cloud Manager matches the file."
+        );
+    }
+
+    #[test]
+    fn summary_has_a_line_per_step() {
+        colored::control::set_override(false);
+        let summary = render_summary(&[
+            (
+                ApplyStep::Domains,
+                ApplyOutcome::Skipped(String::from("dry-run")),
+            ),
+            (ApplyStep::EnvironmentVariables, ApplyOutcome::Applied),
+            (
+                ApplyStep::PipelineVariables,
+                ApplyOutcome::Failed(String::from("boom")),
+            ),
+        ]);
+        assert_eq!(summary.len(), 3);
+        assert!(summary[0].ends_with("domains skipped: dry-run"));
+        assert!(summary[1].ends_with("environment variables"));
+        assert!(summary[2].ends_with("pipeline variables failed: boom"));
+    }
+}
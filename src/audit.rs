@@ -0,0 +1,292 @@
+use crate::clap_models::{
+    Commands, DomainCommands, EnvCommands, EnvVarsCommands, IpAllowlistCommands, PipelineCommands,
+    PipelineVarsCommands,
+};
+use crate::models::environment::EnvironmentId;
+use chrono::{SecondsFormat, Utc};
+use serde::Serialize;
+use std::fs::OpenOptions;
+use std::io::Write;
+use std::process;
+use std::sync::Mutex;
+
+/// Stage of a command that an audit line records
+#[derive(Clone, Debug, PartialEq, Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum AuditOutcome {
+    Started,
+    Succeeded,
+    Failed,
+}
+
+/// A line of the audit log.
+///
+/// Only holds IDs, paths and the reason, never variable values, headers or credentials.
+#[derive(Clone, Debug, Serialize)]
+pub struct AuditEntry {
+    pub timestamp: String,
+    pub user: Option<String>,
+    pub command: String,
+    pub program: Option<u32>,
+    pub environment: Option<EnvironmentId>,
+    pub pipeline: Option<u32>,
+    pub input: Option<String>,
+    pub reason: Option<String>,
+    pub dry_run: bool,
+    pub outcome: AuditOutcome,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub exit_code: Option<i32>,
+}
+
+/// Audit log path and entry of the command that is currently running
+static RUNNING: Mutex<Option<(String, AuditEntry)>> = Mutex::new(None);
+
+/// Returns the name and input file of a command if it changes something in Cloud Manager.
+///
+/// # Arguments
+///
+/// * `command` - The command that pippo runs
+pub fn audited_command(command: &Commands) -> Option<(&'static str, Option<String>)> {
+    match command {
+        Commands::Env { env_command } => match env_command {
+            EnvCommands::Vars {
+                env_vars_command: EnvVarsCommands::Set { input, .. },
+            } => Some(("env vars set", Some(input.clone()))),
+            EnvCommands::Vars {
+                env_vars_command: EnvVarsCommands::Copy { .. },
+            } => Some(("env vars copy", None)),
+            EnvCommands::Update { .. } => Some(("env update", None)),
+            EnvCommands::Hibernate { .. } => Some(("env hibernate", None)),
+            EnvCommands::Dehibernate { .. } => Some(("env dehibernate", None)),
+            EnvCommands::FlushCache { .. } => Some(("env flush-cache", None)),
+            _ => None,
+        },
+        Commands::Pipeline { pipeline_command } => match pipeline_command {
+            PipelineCommands::Run => Some(("pipeline run", None)),
+            PipelineCommands::Advance { .. } => Some(("pipeline advance", None)),
+            PipelineCommands::Reject { .. } => Some(("pipeline reject", None)),
+            PipelineCommands::InvalidateCache => Some(("pipeline invalidate-cache", None)),
+            PipelineCommands::Vars {
+                pipeline_vars_command: PipelineVarsCommands::Set { input },
+            } => Some(("pipeline vars set", Some(input.clone()))),
+            _ => None,
+        },
+        Commands::Apply { input, diff: false } => Some(("apply", Some(input.clone()))),
+        Commands::Domain {
+            domain_command: DomainCommands::Create { input },
+        } => Some(("domain create", Some(input.clone()))),
+        Commands::IpAllowlist {
+            ip_allowlist_command,
+        } => match ip_allowlist_command {
+            IpAllowlistCommands::Bind { .. } => Some(("ip-allowlist bind", None)),
+            IpAllowlistCommands::Unbind { .. } => Some(("ip-allowlist unbind", None)),
+            IpAllowlistCommands::BindFromFile { input } => {
+                Some(("ip-allowlist bind-from-file", Some(input.clone())))
+            }
+            IpAllowlistCommands::List => None,
+        },
+        _ => None,
+    }
+}
+
+/// Appends an entry as a JSON line to the audit log.
+///
+/// # Arguments
+///
+/// * `path` - String slice that holds the path to the audit log
+/// * `entry` - The entry to append
+pub fn append(path: &str, entry: &AuditEntry) -> std::io::Result<()> {
+    let mut file = OpenOptions::new().create(true).append(true).open(path)?;
+    writeln!(file, "{}", serde_json::to_string(entry)?)
+}
+
+/// Records that a command started and remembers it, so [`finish`] can record its outcome.
+///
+/// # Arguments
+///
+/// * `path` - String slice that holds the path to the audit log
+/// * `entry` - The entry of the starting command
+pub fn start(path: &str, entry: AuditEntry) -> std::io::Result<()> {
+    append(path, &entry)?;
+    *RUNNING.lock().unwrap() = Some((path.to_string(), entry));
+    Ok(())
+}
+
+/// Records the outcome of the running command, if there is one.
+///
+/// Commands that crash or are killed only leave their `started` line, which marks them as aborted.
+///
+/// # Arguments
+///
+/// * `exit_code` - Exit code that pippo is about to exit with
+pub fn finish(exit_code: i32) {
+    if let Some((path, mut entry)) = RUNNING.lock().unwrap().take() {
+        entry.timestamp = now();
+        entry.outcome = if exit_code == 0 {
+            AuditOutcome::Succeeded
+        } else {
+            AuditOutcome::Failed
+        };
+        entry.exit_code = Some(exit_code);
+        if let Err(err) = append(&path, &entry) {
+            eprintln!("⚠ Unable to write audit log {}: {}", path, err);
+        }
+    }
+}
+
+/// Records the outcome of the running command, if there is one, and ends the process.
+///
+/// Every exit of pippo goes through here, so an audited command never ends with only its
+/// `started` line.
+///
+/// # Arguments
+///
+/// * `exit_code` - Exit code that pippo exits with
+pub fn exit(exit_code: i32) -> ! {
+    finish(exit_code);
+    process::exit(exit_code)
+}
+
+/// Current time as used in the audit log
+pub fn now() -> String {
+    Utc::now().to_rfc3339_opts(SecondsFormat::Secs, true)
+}
+
+/// Name of the user that runs pippo
+pub fn current_user() -> Option<String> {
+    std::env::var("USER")
+        .or_else(|_| std::env::var("USERNAME"))
+        .ok()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::clap_models::Cli;
+    use clap::Parser;
+
+    fn command(args: &[&str]) -> Option<(&'static str, Option<String>)> {
+        let cli = Cli::try_parse_from(args).unwrap();
+        audited_command(cli.command.as_ref().unwrap())
+    }
+
+    fn entry() -> AuditEntry {
+        AuditEntry {
+            timestamp: String::from("2025-03-01T10:00:00Z"),
+            user: Some(String::from("jdoe")),
+            command: String::from("env vars set"),
+            program: Some(11111),
+            environment: Some(EnvironmentId(22222)),
+            pipeline: None,
+            input: Some(String::from("vars.yml")),
+            reason: Some(String::from("CHG-42 rotate log level")),
+            dry_run: false,
+            outcome: AuditOutcome::Started,
+            exit_code: None,
+        }
+    }
+
+    #[test]
+    fn mutating_commands_are_audited() {
+        assert_eq!(
+            command(&["pippo", "env", "vars", "set", "vars.yml"]),
+            Some(("env vars set", Some(String::from("vars.yml"))))
+        );
+        assert_eq!(
+            command(&[
+                "pippo",
+                "env",
+                "vars",
+                "copy",
+                "--from-env",
+                "111",
+                "--to-env",
+                "222"
+            ]),
+            Some(("env vars copy", None))
+        );
+        assert_eq!(
+            command(&["pippo", "env", "flush-cache", "--all"]),
+            Some(("env flush-cache", None))
+        );
+        assert_eq!(
+            command(&["pippo", "pipeline", "run"]),
+            Some(("pipeline run", None))
+        );
+        assert_eq!(
+            command(&["pippo", "apply", "config.yml"]),
+            Some(("apply", Some(String::from("config.yml"))))
+        );
+        assert_eq!(
+            command(&["pippo", "domain", "create", "domains.yml"]),
+            Some(("domain create", Some(String::from("domains.yml"))))
+        );
+        assert_eq!(
+            command(&[
+                "pippo",
+                "ip-allowlist",
+                "bind",
+                "-a",
+                "office",
+                "-s",
+                "author"
+            ]),
+            Some(("ip-allowlist bind", None))
+        );
+    }
+
+    #[test]
+    fn read_only_commands_are_not_audited() {
+        assert_eq!(command(&["pippo", "env", "list"]), None);
+        assert_eq!(command(&["pippo", "pipeline", "vars", "list"]), None);
+        assert_eq!(command(&["pippo", "domain", "list"]), None);
+        assert_eq!(command(&["pippo", "apply", "--diff", "config.yml"]), None);
+    }
+
+    #[test]
+    fn audit_entry_only_holds_known_fields() {
+        let line = serde_json::to_value(entry()).unwrap();
+        let keys: Vec<&String> = line.as_object().unwrap().keys().collect();
+        assert_eq!(
+            keys,
+            vec![
+                "command",
+                "dry_run",
+                "environment",
+                "input",
+                "outcome",
+                "pipeline",
+                "program",
+                "reason",
+                "timestamp",
+                "user"
+            ]
+        );
+        assert_eq!(line["outcome"], "started");
+        assert_eq!(line["environment"], 22222);
+    }
+
+    #[test]
+    fn append_writes_one_line_per_entry() {
+        let path = std::env::temp_dir().join(format!("pippo-audit-{}.log", uuid::Uuid::new_v4()));
+        let path = path.to_str().unwrap();
+
+        append(path, &entry()).unwrap();
+        let mut finished = entry();
+        finished.outcome = AuditOutcome::Succeeded;
+        finished.exit_code = Some(0);
+        append(path, &finished).unwrap();
+
+        let content = std::fs::read_to_string(path).unwrap();
+        std::fs::remove_file(path).unwrap();
+        let lines: Vec<serde_json::Value> = content
+            .lines()
+            .map(|l| serde_json::from_str(l).unwrap())
+            .collect();
+        assert_eq!(lines.len(), 2);
+        assert_eq!(lines[0]["outcome"], "started");
+        assert_eq!(lines[1]["outcome"], "succeeded");
+        assert_eq!(lines[1]["exit_code"], 0);
+        assert_eq!(lines[1]["reason"], "CHG-42 rotate log level");
+    }
+}
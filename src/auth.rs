@@ -1,20 +1,35 @@
-use crate::client::CloudManagerClient;
+use crate::client::{send_with_retries, CloudManagerClient};
 use crate::config::{AuthStrategy, Scope};
+use crate::errors::truncate_error_body;
 use crate::models::auth::{BearerResponse, JwtClaims};
+use crate::models::program::Program;
 use crate::IMS_ENDPOINT;
-use chrono::{Duration, Utc};
+use chrono::{DateTime, Duration, TimeZone, Utc};
 use jsonwebtoken::{encode, Algorithm, EncodingKey, Header};
 use log::debug;
+use reqwest::StatusCode;
+use serde::Serialize;
+use std::fmt;
 
-/// Generates a JWT to authenticate with the Adobe API.
+/// Keys of IMS form parameters and responses whose values must never show up in full
+const SENSITIVE_KEYS: [&str; 6] = [
+    "client_secret",
+    "jwt_token",
+    "access_token",
+    "refresh_token",
+    "id_token",
+    "private_key",
+];
+
+/// Builds the claims of the JWT that is used to authenticate with the Adobe API.
 ///
 /// # Arguments
 ///
 /// * `client` - A reference to a CloudManagerClient instance
-fn generate_jwt(client: &CloudManagerClient) -> String {
+pub fn jwt_claims(client: &CloudManagerClient) -> JwtClaims {
     let date = Utc::now() + Duration::minutes(1);
     debug!("scope from config: {:?}", client.config.scope);
-    let claims = JwtClaims {
+    JwtClaims {
         exp: date.timestamp() as usize,
         iss: client.config.organization_id.clone(),
         sub: client.config.technical_account_id.clone(),
@@ -25,8 +40,16 @@ fn generate_jwt(client: &CloudManagerClient) -> String {
         ),
         scope_ent_cloudmgr_sdk: client.config.scope == Scope::EntCloudmgrSdk,
         scope_ent_aem_cloud_api: client.config.scope == Scope::EntAemCloudApi,
-    };
+    }
+}
 
+/// Generates a JWT to authenticate with the Adobe API.
+///
+/// # Arguments
+///
+/// * `client` - A reference to a CloudManagerClient instance
+fn generate_jwt(client: &CloudManagerClient) -> String {
+    let claims = jwt_claims(client);
     let private_key = client.config.private_key.as_bytes();
     encode(
         &Header::new(Algorithm::RS256),
@@ -36,6 +59,122 @@ fn generate_jwt(client: &CloudManagerClient) -> String {
     .unwrap()
 }
 
+/// Returns the JWT claims in human-readable form. The private key is never part of the output.
+///
+/// # Arguments
+///
+/// * `claims` - A reference to the JwtClaims to describe
+/// * `private_key_valid` - Whether the configured private key can be used to sign the JWT
+pub fn describe_jwt_claims(claims: &JwtClaims, private_key_valid: bool) -> String {
+    let expiry = Utc
+        .timestamp_opt(claims.exp as i64, 0)
+        .single()
+        .map(|date| date.to_rfc3339())
+        .unwrap_or_else(|| String::from("invalid"));
+    let mut scopes = vec![];
+    if claims.scope_ent_cloudmgr_sdk {
+        scopes.push("ent_cloudmgr_sdk");
+    }
+    if claims.scope_ent_aem_cloud_api {
+        scopes.push("ent_aem_cloud_api");
+    }
+    format!(
+        "{:>10} {}\n{:>10} {}\n{:>10} {}\n{:>10} {} ({})\n{:>10} {}\n{:>10} {}",
+        "issuer",
+        claims.iss,
+        "subject",
+        claims.sub,
+        "audience",
+        claims.aud,
+        "expiry",
+        claims.exp,
+        expiry,
+        "scopes",
+        scopes.join(", "),
+        "key",
+        if private_key_valid {
+            "valid RSA private key"
+        } else {
+            "invalid RSA private key"
+        }
+    )
+}
+
+/// Checks whether the configured private key can be used to sign a JWT.
+///
+/// # Arguments
+///
+/// * `client` - A reference to a CloudManagerClient instance
+pub fn private_key_is_valid(client: &CloudManagerClient) -> bool {
+    EncodingKey::from_rsa_pem(client.config.private_key.as_bytes()).is_ok()
+}
+
+/// Masks a credential so only its first and last few characters are shown.
+///
+/// Values that are too short to keep anything readable are masked completely.
+///
+/// # Arguments
+///
+/// * `value` - String slice that holds the credential
+pub fn redact(value: &str) -> String {
+    const VISIBLE: usize = 4;
+    let chars: Vec<char> = value.chars().collect();
+    if chars.len() <= VISIBLE * 3 {
+        return String::from("****");
+    }
+    let head: String = chars[..VISIBLE].iter().collect();
+    let tail: String = chars[chars.len() - VISIBLE..].iter().collect();
+    format!("{}****{}", head, tail)
+}
+
+/// Formats form parameters for logging with the values of all sensitive keys masked.
+///
+/// # Arguments
+///
+/// * `form_params` - The form parameters that are sent to IMS
+fn redact_form_params(form_params: &[(&str, String)]) -> String {
+    form_params
+        .iter()
+        .map(|(key, value)| {
+            if SENSITIVE_KEYS.contains(key) {
+                format!("{}={}", key, redact(value))
+            } else {
+                format!("{}={}", key, value)
+            }
+        })
+        .collect::<Vec<_>>()
+        .join(", ")
+}
+
+/// Masks credentials in a response from IMS before it is printed.
+///
+/// JSON responses get the values of all sensitive keys masked, and any occurrence of the given
+/// secrets is masked in every response, JSON or not.
+///
+/// # Arguments
+///
+/// * `response` - String slice that holds the response body
+/// * `secrets` - Credentials that were sent with the request
+fn redact_response(response: &str, secrets: &[&str]) -> String {
+    let mut redacted = match serde_json::from_str::<serde_json::Value>(response) {
+        Ok(serde_json::Value::Object(mut map)) => {
+            for (key, value) in map.iter_mut() {
+                if let serde_json::Value::String(text) = value {
+                    if SENSITIVE_KEYS.contains(&key.as_str()) {
+                        *value = serde_json::Value::String(redact(text));
+                    }
+                }
+            }
+            serde_json::Value::Object(map).to_string()
+        }
+        _ => response.to_string(),
+    };
+    for secret in secrets.iter().filter(|secret| !secret.is_empty()) {
+        redacted = redacted.replace(secret, &redact(secret));
+    }
+    redacted
+}
+
 /// Uses a JWT to obtain an access token from Adobe.
 ///
 /// # Arguments
@@ -47,8 +186,8 @@ fn generate_jwt(client: &CloudManagerClient) -> String {
 /// ```
 /// POST https://ims-na1.adobelogin.com/ims/exchange/jwt/
 /// ```
-pub async fn obtain_access_token(client: &mut CloudManagerClient) -> Result<(), reqwest::Error> {
-    if client.config.auth_strategy == AuthStrategy::JWT {
+pub async fn obtain_access_token(client: &mut CloudManagerClient) -> Result<(), AuthError> {
+    if client.config.auth_strategy == AuthStrategy::Jwt {
         obtain_jwt_token(client).await?;
     } else {
         obtain_oauth_token(client).await?;
@@ -56,7 +195,7 @@ pub async fn obtain_access_token(client: &mut CloudManagerClient) -> Result<(),
     Ok(())
 }
 
-async fn obtain_oauth_token(client: &mut CloudManagerClient) -> Result<(), reqwest::Error> {
+async fn obtain_oauth_token(client: &mut CloudManagerClient) -> Result<(), AuthError> {
     //client.config.jwt = generate_jwt(client);
     let form_params = [
         ("client_id", client.config.client_id.clone()),
@@ -64,41 +203,317 @@ async fn obtain_oauth_token(client: &mut CloudManagerClient) -> Result<(), reqwe
         ("scope", "read_pc.dma_aem_ams,openid,AdobeID,read_organizations,additional_info.projectedProductContext".to_owned()),
         ("grant_type", "client_credentials".to_owned()),
     ];
+    debug!("IMS token request: {}", redact_form_params(&form_params));
 
-    let token = &client
-        .client
-        .post(format!("https://{}/ims/token/v3/", IMS_ENDPOINT))
-        .form(&form_params)
-        .send()
-        .await?
-        .text()
-        .await?;
-
-    let bearer_response: BearerResponse = serde_json::from_str(token)
-        .unwrap_or_else(|_| panic!("Unable to authenticate: {}", token.as_str()));
+    let bearer_response = request_token(
+        client,
+        format!("https://{}/ims/token/v3/", IMS_ENDPOINT),
+        &form_params,
+    )
+    .await?;
     client.config.access_token = bearer_response.access_token;
+    client.config.access_token_expires_at = bearer_response
+        .expires_in
+        .map(|seconds| Utc::now() + Duration::seconds(seconds));
     Ok(())
 }
 
-async fn obtain_jwt_token(client: &mut CloudManagerClient) -> Result<(), reqwest::Error> {
+async fn obtain_jwt_token(client: &mut CloudManagerClient) -> Result<(), AuthError> {
     client.config.jwt = generate_jwt(client);
     let form_params = [
         ("client_id", client.config.client_id.clone()),
         ("client_secret", client.config.client_secret.clone()),
         ("jwt_token", client.config.jwt.clone()),
     ];
+    debug!(
+        "IMS JWT exchange request: {}",
+        redact_form_params(&form_params)
+    );
 
-    let token = &client
-        .client
-        .post(format!("https://{}/ims/exchange/jwt/", IMS_ENDPOINT))
-        .form(&form_params)
-        .send()
-        .await?
-        .text()
-        .await?;
-
-    let bearer_response: BearerResponse = serde_json::from_str(token)
-        .unwrap_or_else(|_| panic!("Unable to authenticate: {}", token.as_str()));
+    let bearer_response = request_token(
+        client,
+        format!("https://{}/ims/exchange/jwt/", IMS_ENDPOINT),
+        &form_params,
+    )
+    .await?;
     client.config.access_token = bearer_response.access_token;
+    // Unlike the OAuth endpoint, the JWT exchange states the lifetime in milliseconds
+    client.config.access_token_expires_at = bearer_response
+        .expires_in
+        .map(|millis| Utc::now() + Duration::milliseconds(millis));
     Ok(())
 }
+
+/// Reasons why no access token could be obtained from IMS
+#[derive(Debug)]
+pub enum AuthError {
+    /// IMS could not be reached, even after retrying
+    Request(reqwest::Error),
+    /// IMS answered, but not with an access token; the body has all credentials masked
+    Rejected { status: StatusCode, body: String },
+}
+
+impl fmt::Display for AuthError {
+    fn fmt(&self, formatter: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            AuthError::Request(error) => write!(formatter, "request to IMS failed: {}", error),
+            AuthError::Rejected { status, body } => write!(
+                formatter,
+                "IMS answered with {} instead of an access token: {}",
+                status,
+                truncate_error_body(body)
+            ),
+        }
+    }
+}
+
+impl From<reqwest::Error> for AuthError {
+    fn from(error: reqwest::Error) -> Self {
+        AuthError::Request(error)
+    }
+}
+
+/// Posts a token request to IMS, retrying transient failures within the retry budget of the run.
+///
+/// # Arguments
+///
+/// * `client` - A reference to a CloudManagerClient instance
+/// * `url` - URL of the IMS endpoint
+/// * `form_params` - The form parameters that are sent to IMS
+async fn request_token(
+    client: &CloudManagerClient,
+    url: String,
+    form_params: &[(&str, String)],
+) -> Result<BearerResponse, AuthError> {
+    let request = client.client.post(url).form(form_params);
+    let response = send_with_retries(request, &client.retry_budget).await?;
+    let status = response.status();
+    let body = response.text().await?;
+    let secrets = [
+        client.config.client_secret.as_str(),
+        client.config.jwt.as_str(),
+    ];
+    parse_token_response(status, &body, &secrets)
+}
+
+/// Reads the access token from an answer of IMS.
+///
+/// # Arguments
+///
+/// * `status` - Status of the answer
+/// * `body` - String slice that holds the body of the answer
+/// * `secrets` - Credentials that were sent with the request and must not show up in errors
+fn parse_token_response(
+    status: StatusCode,
+    body: &str,
+    secrets: &[&str],
+) -> Result<BearerResponse, AuthError> {
+    serde_json::from_str(body).map_err(|_| AuthError::Rejected {
+        status,
+        body: redact_response(body, secrets),
+    })
+}
+
+/// Identity that pippo is authenticated as, printed by `whoami`
+#[derive(Debug, Serialize)]
+pub struct Whoami {
+    pub organization_id: String,
+    /// Client ID with all but its first and last characters masked
+    pub client_id: String,
+    pub auth_strategy: String,
+    pub scope: String,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub token_expires_at: Option<DateTime<Utc>>,
+    pub programs: Vec<WhoamiProgram>,
+}
+
+/// A program that the credentials can see
+#[derive(Debug, PartialEq, Serialize)]
+pub struct WhoamiProgram {
+    pub id: String,
+    pub name: String,
+}
+
+/// Collects the identity of the authenticated client for `whoami`.
+///
+/// # Arguments
+///
+/// * `client` - A reference to an authenticated CloudManagerClient instance
+/// * `programs` - The programs that the credentials can see
+pub fn whoami(client: &CloudManagerClient, programs: &[Program]) -> Whoami {
+    let auth_strategy: &str = (&client.config.auth_strategy).into();
+    let scope: &str = (&client.config.scope).into();
+    Whoami {
+        organization_id: client.config.organization_id.clone(),
+        client_id: redact(&client.config.client_id),
+        auth_strategy: auth_strategy.to_string(),
+        scope: scope.to_string(),
+        token_expires_at: client.config.access_token_expires_at,
+        programs: programs
+            .iter()
+            .map(|program| WhoamiProgram {
+                id: program.id.clone(),
+                name: program.name.clone(),
+            })
+            .collect(),
+    }
+}
+
+/// Returns the identity in human-readable form.
+///
+/// # Arguments
+///
+/// * `whoami` - A reference to the Whoami to describe
+pub fn describe_whoami(whoami: &Whoami) -> String {
+    let mut lines = vec![
+        format!("{:>14} {}", "organization", whoami.organization_id),
+        format!("{:>14} {}", "client", whoami.client_id),
+        format!("{:>14} {}", "auth strategy", whoami.auth_strategy),
+        format!("{:>14} {}", "scope", whoami.scope),
+        format!(
+            "{:>14} {}",
+            "token expiry",
+            whoami
+                .token_expires_at
+                .map(|date| date.to_rfc3339())
+                .unwrap_or_else(|| String::from("unknown"))
+        ),
+        format!("{:>14} {}", "programs", whoami.programs.len()),
+    ];
+    for program in &whoami.programs {
+        lines.push(format!("{:>14} {} ({})", "", program.name, program.id));
+    }
+    lines.join("\n")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn redact_keeps_only_head_and_tail() {
+        assert_eq!(redact("p8e-abcdefghijklmnopqrstuvwxyz"), "p8e-****wxyz");
+        assert_eq!(redact("short-secret"), "****");
+        assert_eq!(redact(""), "****");
+    }
+
+    #[test]
+    fn redact_form_params_masks_credentials() {
+        let form_params = [
+            ("client_id", String::from("my-client-id")),
+            (
+                "client_secret",
+                String::from("p8e-abcdefghijklmnopqrstuvwxyz"),
+            ),
+        ];
+        assert_eq!(
+            redact_form_params(&form_params),
+            "client_id=my-client-id, client_secret=p8e-****wxyz"
+        );
+    }
+
+    #[test]
+    fn redact_response_masks_tokens_and_secrets() {
+        let response =
+            r#"{"error":"invalid_client","access_token":"eyJhbGciOiJSUzI1NiJ9.payload.signature"}"#;
+        let redacted = redact_response(response, &[]);
+        assert!(redacted.contains("invalid_client"));
+        assert!(!redacted.contains("payload"));
+
+        let echoed = "<html>bad request for p8e-abcdefghijklmnopqrstuvwxyz</html>";
+        let redacted = redact_response(echoed, &["p8e-abcdefghijklmnopqrstuvwxyz", ""]);
+        assert_eq!(redacted, "<html>bad request for p8e-****wxyz</html>");
+    }
+
+    fn whoami_fixture() -> Whoami {
+        Whoami {
+            organization_id: String::from("C74F69D7594880280@AdobeOrg"),
+            client_id: redact("4df5gh7a9b1c3d5e7f9a1b3c5d7e9f1a"),
+            auth_strategy: String::from("oauth2"),
+            scope: String::from("ent_cloudmgr_sdk"),
+            token_expires_at: Utc.timestamp_opt(1550001438, 0).single(),
+            programs: vec![WhoamiProgram {
+                id: String::from("22222"),
+                name: String::from("Demo"),
+            }],
+        }
+    }
+
+    #[test]
+    fn whoami_masks_the_client_id() {
+        let whoami = whoami_fixture();
+        assert_eq!(whoami.client_id, "4df5****9f1a");
+        let description = describe_whoami(&whoami);
+        assert!(description.contains("4df5****9f1a"));
+        assert!(!description.contains("4df5gh7a9b1c3d5e7f9a1b3c5d7e9f1a"));
+        assert!(description.contains("2019-02-12T19:57:18+00:00"));
+        assert!(description.contains("Demo (22222)"));
+    }
+
+    #[test]
+    fn whoami_as_json() {
+        let json = serde_json::to_value(whoami_fixture()).unwrap();
+        assert_eq!(
+            json,
+            serde_json::json!({
+                "organization_id": "C74F69D7594880280@AdobeOrg",
+                "client_id": "4df5****9f1a",
+                "auth_strategy": "oauth2",
+                "scope": "ent_cloudmgr_sdk",
+                "token_expires_at": "2019-02-12T19:57:18Z",
+                "programs": [{"id": "22222", "name": "Demo"}]
+            })
+        );
+    }
+
+    #[test]
+    fn whoami_without_expiry() {
+        let mut whoami = whoami_fixture();
+        whoami.token_expires_at = None;
+        assert!(describe_whoami(&whoami).contains("token expiry unknown"));
+        let json = serde_json::to_value(&whoami).unwrap();
+        assert!(json.get("token_expires_at").is_none());
+    }
+
+    #[test]
+    fn token_response_with_access_token() {
+        let body = r#"{"access_token":"ths.is.a.token","token_type":"bearer","expires_in":86399}"#;
+        let bearer = parse_token_response(StatusCode::OK, body, &[]).unwrap();
+        assert_eq!(bearer.access_token, "ths.is.a.token");
+        assert_eq!(bearer.expires_in, Some(86399));
+    }
+
+    #[test]
+    fn token_response_without_access_token_is_a_masked_error() {
+        let body = "<html>503 for p8e-abcdefghijklmnopqrstuvwxyz</html>";
+        let error = parse_token_response(
+            StatusCode::SERVICE_UNAVAILABLE,
+            body,
+            &["p8e-abcdefghijklmnopqrstuvwxyz"],
+        )
+        .unwrap_err();
+        assert_eq!(
+            error.to_string(),
+            "IMS answered with 503 Service Unavailable instead of an access token: <html>503 for p8e-****wxyz</html>"
+        );
+    }
+
+    #[test]
+    fn describe_claims_without_key() {
+        let claims = JwtClaims {
+            exp: 1550001438,
+            iss: String::from("C74F69D7594880280@AdobeOrg"),
+            sub: String::from("6657031C5C095BB40A4@techacct.adobe.com"),
+            aud: String::from("https://ims-na1.adobelogin.com/c/4df5gh"),
+            scope_ent_cloudmgr_sdk: true,
+            scope_ent_aem_cloud_api: false,
+        };
+        let description = describe_jwt_claims(&claims, true);
+        assert!(description.contains("C74F69D7594880280@AdobeOrg"));
+        assert!(description.contains("6657031C5C095BB40A4@techacct.adobe.com"));
+        assert!(description.contains("1550001438 (2019-02-12T19:57:18+00:00)"));
+        assert!(description.contains("ent_cloudmgr_sdk"));
+        assert!(!description.contains("ent_aem_cloud_api"));
+        assert!(description.contains("valid RSA private key"));
+    }
+}
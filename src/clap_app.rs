@@ -1,65 +1,533 @@
 use async_ctrlc::CtrlC;
-use chrono::NaiveDate;
+use chrono::{Local, Utc};
 use clap::Parser;
 use colored::Colorize;
 use futures_lite::FutureExt;
-use std::process;
+use log::debug;
+use serde::Serialize;
+use std::fs::File;
+use std::io::{self, BufWriter, IsTerminal, Write};
+use std::path::{Path, PathBuf};
 use std::str::FromStr;
+use std::time::Duration;
+use tokio::task::JoinSet;
 
-use crate::auth::obtain_access_token;
+use crate::audit::{exit, AuditEntry, AuditOutcome};
+use crate::auth::{
+    describe_jwt_claims, describe_whoami, jwt_claims, obtain_access_token, private_key_is_valid,
+    whoami,
+};
 use crate::clap_models::*;
-use crate::client::CloudManagerClient;
-use crate::config::CloudManagerConfig;
-use crate::encryption::{decrypt, encrypt};
-use crate::logs::{download_log, tail_log};
-use crate::models::domain::Domain;
-use crate::models::log::{LogType, ServiceType};
+use crate::client::{merge_extra_headers, CloudManagerClient};
+use crate::config::{AuthStrategy, CloudManagerConfig};
+use crate::encryption::{
+    decrypt, decrypt_file, encrypt, encrypt_age, encrypt_file, encrypt_file_for_recipients,
+    encrypt_yaml_file, migrate_file, read_new_key, read_recipients, read_value, rotate_key_of_file,
+    set_identity_file, set_key_file, Cipher, EncryptedValue, KeyCipher,
+};
+use crate::environments::{HibernationAction, HibernationOutcome, PurgeTarget};
+use crate::execution::{BuildLogOutcome, StepDecision, StepDecisionOutcome};
+use crate::exit_code::ExitCode;
+use crate::logs::{
+    download_all_logs, download_log, expand_date_range, last_days, tail_log, LineFilter, LogTarget,
+    ProgressMode, TailFormat, TailOptions, TailRecorder, TailRetryPolicy,
+};
+use crate::models::config::{YamlConfig, STDIN_PATH};
+use crate::models::environment::{EnvironmentId, EnvironmentUpdate};
+use crate::models::ip_allowlist::BindingTier;
+use crate::models::log::{LogName, ServiceType};
 use crate::models::variables::{EnvironmentVariableServiceType, PipelineVariableServiceType};
+use crate::output::{render_template, sort_items, OutputFormat, SortKey};
+use crate::report::InventoryFormat;
 
 use crate::variables::{
-    get_env_vars, get_pipeline_vars, set_env_vars_from_file, set_pipeline_vars_from_file,
+    copy_env_vars, get_env_vars, get_pipeline_vars, set_env_vars_from_file,
+    set_pipeline_vars_from_file, validate_vars_file, verify_secrets_file, SyncMode,
+};
+use crate::{
+    apply, audit, config, domains, environments, execution, ip_allowlists, logs, pipelines,
+    programs, report, schema,
 };
-use crate::{domains, environments, execution, pipelines, programs};
 
 pub async fn init_cli() {
-    let cli = Cli::parse();
+    let mut cli = Cli::parse();
+    if let Some(path) = &cli.cryptkey_file {
+        set_key_file(path);
+    }
+    if let Some(path) = &cli.identity_file {
+        set_identity_file(path);
+    }
 
     // Encryption tooling is somewhat extra to pippo, so we handle this at the very beginning since
     // we don't need a Cloud Manager config for this.
     match &cli.command {
-        Some(Commands::Encrypt { input }) => {
-            println!("{}", encrypt(input));
-            process::exit(0);
+        Some(Commands::Encrypt {
+            encrypt_command:
+                Some(EncryptCommands::RotateKey {
+                    input,
+                    output,
+                    new_key_env,
+                    new_key_file,
+                }),
+            ..
+        }) => {
+            read_new_key(new_key_env.as_deref(), new_key_file.as_deref())
+                .and_then(|new_key| {
+                    rotate_key_of_file(
+                        input,
+                        output.as_deref().unwrap_or(input),
+                        &new_key,
+                        cli.dry_run_mode,
+                    )
+                })
+                .unwrap_or_else(|err| {
+                    eprintln!("{} {}", "❌".red(), err.red());
+                    exit(1);
+                });
+            exit(0);
         }
-        Some(Commands::Decrypt { input }) => {
-            println!("{}", decrypt(input.to_string()));
-            process::exit(0);
+        Some(Commands::Encrypt {
+            encrypt_command:
+                Some(EncryptCommands::Yaml {
+                    input,
+                    output,
+                    only,
+                    key_id,
+                    cipher,
+                }),
+            ..
+        }) => {
+            encrypt_yaml_file(
+                input,
+                output.as_deref().unwrap_or(input),
+                only,
+                key_id.as_deref(),
+                *cipher,
+                cli.dry_run_mode,
+            )
+            .unwrap_or_else(|err| {
+                eprintln!("{} {}", "❌".red(), err.red());
+                exit(1);
+            });
+            exit(0);
+        }
+        Some(Commands::Encrypt {
+            encrypt_command: Some(EncryptCommands::Migrate { input, output }),
+            ..
+        }) => {
+            migrate_file(input, output.as_deref().unwrap_or(input), cli.dry_run_mode)
+                .unwrap_or_else(|err| {
+                    eprintln!("{} {}", "❌".red(), err.red());
+                    exit(1);
+                });
+            exit(0);
+        }
+        Some(Commands::Encrypt {
+            encrypt_command: None,
+            input,
+            file,
+            out,
+            force,
+            key_id,
+            cipher,
+            recipients,
+        }) => {
+            let cipher = cipher.unwrap_or(KeyCipher::Gcm1);
+            let recipients = recipients.as_deref().map(|recipients| {
+                read_recipients(recipients).unwrap_or_else(|err| {
+                    eprintln!("{} {}", "❌".red(), err.red());
+                    exit(1);
+                })
+            });
+            match (file, recipients) {
+                (Some(file), Some(recipients)) => encrypt_file_for_recipients(
+                    file,
+                    out.as_deref().unwrap_or(STDIN_PATH),
+                    *force,
+                    &recipients,
+                )
+                .unwrap_or_else(|err| {
+                    eprintln!("{} {}", "❌".red(), err.red());
+                    exit(1);
+                }),
+                (None, Some(recipients)) => {
+                    let input = read_value(input.as_deref()).unwrap_or_else(|err| {
+                        eprintln!("{} {}", "❌".red(), err.red());
+                        exit(1);
+                    });
+                    println!(
+                        "{}",
+                        EncryptedValue {
+                            cipher: Cipher::Age,
+                            key_id: None,
+                            ciphertext: &encrypt_age(&recipients, input.as_bytes()),
+                        }
+                    );
+                }
+                (Some(file), None) => {
+                    encrypt_file(file, out.as_deref().unwrap_or(STDIN_PATH), *force, cipher)
+                        .unwrap_or_else(|err| {
+                            eprintln!("{} {}", "❌".red(), err.red());
+                            exit(1);
+                        })
+                }
+                (None, None) => {
+                    let input = read_value(input.as_deref()).unwrap_or_else(|err| {
+                        eprintln!("{} {}", "❌".red(), err.red());
+                        exit(1);
+                    });
+                    let encrypted = encrypt(&input, key_id.as_deref(), cipher);
+                    // Plain base64 as printed by older versions, unless the format needs the prefix
+                    if cipher == KeyCipher::Cbc && key_id.is_none() {
+                        println!("{}", encrypted);
+                    } else {
+                        println!(
+                            "{}",
+                            EncryptedValue {
+                                cipher: cipher.into(),
+                                key_id: key_id.as_deref(),
+                                ciphertext: &encrypted,
+                            }
+                        );
+                    }
+                }
+            }
+            exit(0);
+        }
+        Some(Commands::Decrypt {
+            input,
+            file,
+            out,
+            force,
+        }) => {
+            match file {
+                Some(file) => decrypt_file(file, out.as_deref().unwrap_or(STDIN_PATH), *force)
+                    .unwrap_or_else(|err| {
+                        eprintln!("{} {}", "❌".red(), err.red());
+                        exit(1);
+                    }),
+                None => {
+                    let input = read_value(input.as_deref()).unwrap_or_else(|err| {
+                        eprintln!("{} {}", "❌".red(), err.red());
+                        exit(1);
+                    });
+                    match EncryptedValue::from_input(&input) {
+                        Ok(value) => match decrypt(&value) {
+                            Ok(decrypted) => println!("{}", decrypted),
+                            Err(err) => {
+                                eprintln!("{} {}", "❌".red(), err.to_string().red());
+                                ExitCode::Undecryptable.exit();
+                            }
+                        },
+                        Err(err) => {
+                            eprintln!("{} {}", "❌".red(), err.red());
+                            exit(1);
+                        }
+                    }
+                }
+            }
+            exit(0);
+        }
+        // Like encrypting, verifying secrets only needs the local key and no credentials.
+        Some(Commands::VerifySecrets { input }) => {
+            exit(if verify_secrets_file(input) { 0 } else { 1 });
+        }
+        Some(Commands::Schema) => {
+            let schema = schema::document::<YamlConfig>("pippo YAML configuration");
+            println!("{}", serde_json::to_string_pretty(&schema).unwrap());
+            exit(0);
+        }
+        // Validating variables is meant for pre-commit hooks, so it works without credentials.
+        Some(Commands::Env {
+            env_command:
+                EnvCommands::Vars {
+                    env_vars_command: EnvVarsCommands::Validate { input },
+                },
+        }) => {
+            exit(if validate_vars_file(input, false) {
+                0
+            } else {
+                1
+            });
+        }
+        Some(Commands::Pipeline {
+            pipeline_command:
+                PipelineCommands::Vars {
+                    pipeline_vars_command: PipelineVarsCommands::Validate { input },
+                },
+        }) => {
+            exit(if validate_vars_file(input, true) {
+                0
+            } else {
+                1
+            });
         }
         // All other match cases will be handled later, move on
         _ => {}
     }
 
     // Read config file
-    let cm_config = CloudManagerConfig::from_file(cli.config.as_str());
+    let config_path = config::discover_config(
+        cli.config.as_deref(),
+        &config::config_candidates(|name| std::env::var(name).ok()),
+        Path::is_file,
+    );
+    debug!("using config file {}", config_path.display());
+    let mut cm_config = CloudManagerConfig::from_file(&config_path.to_string_lossy());
+    if let Some(retry_budget) = cli.retry_budget {
+        cm_config.retry_budget = retry_budget;
+    }
+    merge_extra_headers(&mut cm_config.extra_headers, &cli.headers);
+    if let Some(version) = &cli.min_tls_version {
+        cm_config.min_tls_version = Some(version.clone());
+    }
+    if let Some(ca_bundle) = &cli.ca_bundle {
+        cm_config.ca_bundle = Some(ca_bundle.clone());
+    }
+    if cli.explicit_service {
+        cm_config.explicit_service = true;
+    }
+    // Flags and environment variables were applied by clap, the config only fills the gaps
+    cli.apply_defaults(&cm_config.defaults);
 
     // Initialize HTTP client and get access token
     let mut cm_client = CloudManagerClient::from(cm_config);
-    obtain_access_token(&mut cm_client).await.unwrap();
+    cm_client.dump_request = cli
+        .dump_request
+        .as_deref()
+        .map(|level| level.parse().expect("clap only accepts log levels"));
 
-    match &cli.command {
+    // Access token commands don't need a program, so they are handled before everything else.
+    // Inspecting the JWT must work without contacting IMS, so it is even handled before authentication.
+    let access_token_command = match &cli.command {
         Some(Commands::AccessToken {
             access_token_command,
-        }) => match &access_token_command {
-            AccessTokenCommands::Print => {
-                println!("{}", cm_client.config.access_token);
-                process::exit(0);
+        }) => Some(access_token_command),
+        _ => None,
+    };
+    if let Some(AccessTokenCommands::Jwt) = access_token_command {
+        if cm_client.config.auth_strategy != AuthStrategy::Jwt {
+            eprintln!("❌ The JWT can only be inspected when auth_strategy is set to jwt!");
+            exit(1);
+        }
+        let claims = jwt_claims(&cm_client);
+        if cli.output == Some(OutputFormat::Json) {
+            println!("{}", serde_json::to_string_pretty(&claims).unwrap());
+        } else {
+            println!(
+                "{}",
+                describe_jwt_claims(&claims, private_key_is_valid(&cm_client))
+            );
+        }
+        exit(0);
+    }
+    obtain_access_token(&mut cm_client)
+        .await
+        .unwrap_or_else(|err| {
+            eprintln!("{} {}", "❌ Unable to authenticate:".red(), err);
+            exit(1);
+        });
+    if let Some(AccessTokenCommands::Print) = access_token_command {
+        println!("{}", cm_client.config.access_token);
+        exit(0);
+    }
+
+    // Resolve the program name once, so every command can simply use the program ID.
+    if let Some(program_name) = &cli.program_name {
+        let program_id = programs::resolve_program_id(&mut cm_client, program_name)
+            .await
+            .unwrap_or_else(|err| {
+                eprintln!("{} {}", "❌ Unable to resolve program:".red(), err);
+                exit(1);
+            });
+        cli.program = Some(program_id);
+    }
+
+    // Take the program ID from a YAML config, so it can't disagree with the file
+    if let Some(path) = &cli.program_config {
+        let program_id = programs::read_config(&mut cm_client, path)
+            .await
+            .single_program_id()
+            .unwrap_or_else(|err| {
+                eprintln!(
+                    "{} {}",
+                    "❌ Unable to read program from --program-config:".red(),
+                    err
+                );
+                exit(1);
+            });
+        match cli.program {
+            Some(given) if given != program_id => eprintln!(
+                "⚠ --program {} differs from program {} in {}, using {}",
+                given, program_id, path, given
+            ),
+            Some(_) => {}
+            None => cli.program = Some(program_id),
+        }
+    }
+
+    // Resolve the environment name once, so every command can simply use the environment ID.
+    if let Some(env_name) = &cli.env_name {
+        if let Some(program_id) = cli.program {
+            let env_id = environments::resolve_environment_id(&mut cm_client, program_id, env_name)
+                .await
+                .unwrap_or_else(|err| {
+                    eprintln!("{} {}", "❌ Unable to resolve environment:".red(), err);
+                    exit(1);
+                });
+            cli.envs = vec![env_id];
+        } else {
+            eprintln!("❌ You have to provide a valid Cloud Manager program ID to resolve an environment name!");
+            exit(1);
+        }
+    }
+
+    if cli.envs.len() > 1 && !cli.accepts_multiple_environments() {
+        eprintln!("❌ Only log save and log tail can be run for several environments at once!");
+        exit(1);
+    }
+
+    // Record mutating commands in the audit log before they touch anything
+    if let Some((command, input)) = cli.command.as_ref().and_then(audit::audited_command) {
+        match &cm_client.config.audit_log {
+            Some(path) => {
+                let entry = AuditEntry {
+                    timestamp: audit::now(),
+                    user: audit::current_user(),
+                    command: command.to_string(),
+                    program: cli.program,
+                    environment: cli.env(),
+                    pipeline: cli.pipeline,
+                    input,
+                    reason: cli.reason.clone(),
+                    dry_run: cli.dry_run_mode,
+                    outcome: AuditOutcome::Started,
+                    exit_code: None,
+                };
+                if let Err(err) = audit::start(path, entry) {
+                    eprintln!("{} {}: {}", "❌ Unable to write audit log".red(), path, err);
+                    exit(1);
+                }
             }
-        },
+            None if cli.reason.is_some() => {
+                eprintln!("⚠ --reason is only recorded when audit_log is set in the config");
+            }
+            None => {}
+        }
+    }
+
+    if cli.raw {
+        if cli
+            .command
+            .as_ref()
+            .and_then(audit::audited_command)
+            .is_some()
+        {
+            eprintln!("⚠ --raw only applies to read commands and is ignored");
+        } else {
+            cm_client.raw = true;
+        }
+    }
+    cm_client.allow_empty = cli.allow_empty;
+
+    match &cli.command {
+        Some(Commands::Whoami) => {
+            let programs = programs::get_programs(&mut cm_client, None).await.unwrap();
+            exit_if_raw(&cm_client);
+            let whoami = whoami(&cm_client, &programs.programs);
+            if cli.output == Some(OutputFormat::Json) {
+                println!("{}", serde_json::to_string_pretty(&whoami).unwrap());
+            } else {
+                println!("{}", describe_whoami(&whoami));
+            }
+        }
+
+        Some(Commands::Apply { input, diff: true }) => {
+            let config = programs::read_config(&mut cm_client, input).await;
+            let plan = apply::plan_config(&config, &mut cm_client)
+                .await
+                .unwrap_or_else(|err| {
+                    eprintln!("{} {}", "❌ API error: ".red().bold(), err);
+                    exit(1);
+                });
+            exit_if_raw(&cm_client);
+            println!("📋 Plan for {}\n", input);
+            for line in apply::render_plan(&plan) {
+                println!("{}", line);
+            }
+            if apply::has_drift(&plan) {
+                ExitCode::Drift.exit();
+            }
+        }
+
+        Some(Commands::Apply { input, .. }) => {
+            let applied =
+                apply::apply_config(input, &mut cm_client, cli.ci_mode, cli.dry_run_mode).await;
+            if !applied {
+                ExitCode::Failure.exit();
+            }
+        }
 
         Some(Commands::Program { program_command }) => match &program_command {
-            ProgramCommands::List => {
-                let programs = programs::get_programs(&mut cm_client).await.unwrap();
-                println!("{}", serde_json::to_string_pretty(&programs).unwrap());
+            ProgramCommands::List {
+                enabled_only,
+                status,
+                name_contains,
+                limit,
+            } => {
+                let mut programs = programs::get_programs(&mut cm_client, *limit)
+                    .await
+                    .unwrap();
+                exit_if_raw(&cm_client);
+                programs.programs = programs::filter_programs(
+                    programs.programs,
+                    *enabled_only,
+                    status.as_deref(),
+                    name_contains.as_deref(),
+                );
+                sort_list(&cli.sort, &mut programs.programs);
+                match &cli.output {
+                    Some(OutputFormat::Template(template)) => {
+                        print_template(template, &programs.programs)
+                    }
+                    Some(OutputFormat::Table) => {
+                        for line in programs::render_programs(&programs.programs) {
+                            println!("{}", line);
+                        }
+                    }
+                    _ => println!("{}", serde_json::to_string_pretty(&programs).unwrap()),
+                }
+            }
+            ProgramCommands::Get => {
+                let program_id = cli.program.unwrap_or_else(|| {
+                    eprintln!(
+                        "❌ You have to provide a valid Cloud Manager program ID to run this command!"
+                    );
+                    exit(1);
+                });
+                let program = programs::get_program(&mut cm_client, program_id).await;
+                exit_if_raw(&cm_client);
+                match program {
+                    Ok(Some(program)) => match &cli.output {
+                        Some(OutputFormat::Template(template)) => {
+                            print_template(template, &[program])
+                        }
+                        Some(OutputFormat::Table) => {
+                            for line in programs::render_programs(&[program]) {
+                                println!("{}", line);
+                            }
+                        }
+                        _ => println!("{}", serde_json::to_string_pretty(&program).unwrap()),
+                    },
+                    Ok(None) => {
+                        eprintln!("{} Program {} not found", "❌".red(), program_id);
+                        ExitCode::NotFound.exit();
+                    }
+                    Err(err) => {
+                        eprintln!("{} {}", "❌ API error: ".red().bold(), err);
+                        exit(1);
+                    }
+                }
             }
         },
 
@@ -69,101 +537,894 @@ pub async fn init_cli() {
             #[allow(clippy::collapsible_match)]
             // TODO How can the outer pattern be modified to include the inner pattern?
             if let EnvCommands::Vars { env_vars_command } = &env_command {
-                if let EnvVarsCommands::Set { input } = &env_vars_command {
+                if let EnvVarsCommands::Set {
+                    input,
+                    only_changed,
+                    snapshot_dir,
+                    no_prune,
+                    add_only,
+                } = &env_vars_command
+                {
+                    let mode = if *add_only {
+                        SyncMode::AddOnly
+                    } else if *no_prune {
+                        SyncMode::Additive
+                    } else {
+                        SyncMode::Full
+                    };
                     println!(
                         "🚀 Patching environment variables from input file {}\n",
                         input
                     );
-                    set_env_vars_from_file(input, &mut cm_client, cli.ci_mode, cli.dry_run_mode)
-                        .await;
-                    process::exit(0);
+                    set_env_vars_from_file(
+                        input,
+                        &mut cm_client,
+                        cli.ci_mode,
+                        cli.dry_run_mode,
+                        *only_changed,
+                        snapshot_dir.as_deref(),
+                        mode,
+                    )
+                    .await
+                    .unwrap_or_else(|code| code.exit());
+                    exit(0);
                 }
             }
 
-            // Since all other "env" subcommands need a program ID, we can only run them when it was provided.
-            if let Some(program_id) = cli.program {
-                match &env_command {
-                    EnvCommands::List => {
+            match (&env_command, cli.program) {
+                // The overview spans all programs, so it doesn't need a program ID either.
+                (
+                    EnvCommands::Overview {
+                        strict,
+                        concurrency,
+                    },
+                    _,
+                ) => {
+                    let programs = programs::get_programs(&mut cm_client, None)
+                        .await
+                        .unwrap()
+                        .programs
+                        .into_iter()
+                        .map(|p| (p.id.parse().expect("Program IDs are numeric"), p.name))
+                        .collect();
+                    let overviews = environments::get_environments_overview(
+                        &cm_client,
+                        programs,
+                        *concurrency as usize,
+                    )
+                    .await;
+                    exit_if_raw(&cm_client);
+                    if cli.output == Some(OutputFormat::Json) {
+                        println!("{}", serde_json::to_string_pretty(&overviews).unwrap());
+                    } else {
+                        for line in environments::render_overview(&overviews) {
+                            println!("{}", line);
+                        }
+                    }
+                    if *strict && overviews.iter().any(|o| o.error.is_some()) {
+                        exit(1);
+                    }
+                }
+
+                (EnvCommands::List, Some(program_id)) => {
+                    let mut envs = environments::get_environments(&mut cm_client, program_id)
+                        .await
+                        .unwrap();
+                    exit_if_raw(&cm_client);
+                    sort_list(&cli.sort, &mut envs.environments);
+                    if let Some(OutputFormat::Template(template)) = &cli.output {
+                        print_template(template, &envs.environments);
+                    } else {
+                        println!("{}", serde_json::to_string_pretty(&envs).unwrap());
+                    }
+                }
+
+                (EnvCommands::Regions { check }, Some(program_id)) => {
+                    let regions = environments::get_regions(&mut cm_client, program_id)
+                        .await
+                        .unwrap();
+                    exit_if_raw(&cm_client);
+                    if let Some(region) = check {
+                        match environments::check_region(&regions, region) {
+                            Ok(()) => println!("✔ Region {} is valid", region),
+                            Err(err) => {
+                                eprintln!("{} {}", "❌".red(), err);
+                                exit(1);
+                            }
+                        }
+                    } else if cli.output == Some(OutputFormat::Json) {
+                        println!("{}", serde_json::to_string_pretty(&regions).unwrap());
+                    } else {
+                        println!("{:<10} NAME", "ID");
+                        for region in &regions.regions {
+                            println!("{:<10} {}", region.id, region.name);
+                        }
+                    }
+                }
+
+                (
+                    EnvCommands::Ready {
+                        wait,
+                        max_wait_minutes,
+                        interval,
+                    },
+                    Some(program_id),
+                ) => {
+                    if let Some(env_id) = cli.env() {
+                        let max_wait = if *wait {
+                            Some(Duration::from_secs(max_wait_minutes * 60))
+                        } else {
+                            None
+                        };
+                        let readiness = environments::check_environment_ready(
+                            &mut cm_client,
+                            program_id,
+                            env_id,
+                            max_wait,
+                            Duration::from_secs(*interval),
+                        )
+                        .await
+                        .unwrap_or_else(|err| {
+                            eprintln!("{} {}", "❌ API error: ".red().bold(), err);
+                            exit(1);
+                        });
+                        exit_if_raw(&cm_client);
+                        if cli.output == Some(OutputFormat::Json) {
+                            println!("{}", serde_json::to_string(&readiness).unwrap());
+                        } else if readiness.ready {
+                            println!("✔ Environment {} is ready", env_id);
+                        } else {
+                            println!(
+                                "⏲ Environment {} is not ready, status: {}",
+                                env_id, readiness.status
+                            );
+                        }
+                        match environments::readiness_exit_code(&readiness.status) {
+                            // Waiting ran out while the environment was still on its way
+                            ExitCode::NotReady
+                                if *wait
+                                    && environments::becomes_ready_by_itself(&readiness.status) =>
+                            {
+                                ExitCode::TimedOut.exit()
+                            }
+                            code => code.exit(),
+                        }
+                    } else {
+                        eprintln!("❌ You have to provide a valid Cloud Manager environment ID to run this command!");
+                        exit(1);
+                    }
+                }
+
+                (EnvCommands::Watch { interval, max_wait }, Some(program_id)) => {
+                    if let Some(env_id) = cli.env() {
+                        let ctrlc = CtrlC::new().expect("Could not create Ctrl+C handler");
+                        ctrlc
+                            .race(async {
+                                let ready = environments::watch_environment(
+                                    &mut cm_client,
+                                    program_id,
+                                    env_id,
+                                    Duration::from_secs(*interval),
+                                    max_wait.map(|minutes| Duration::from_secs(minutes * 60)),
+                                )
+                                .await
+                                .unwrap();
+                                if !ready {
+                                    eprintln!(
+                                        "{}",
+                                        "❌ Environment did not become ready within --max-wait"
+                                            .red()
+                                    );
+                                    ExitCode::TimedOut.exit();
+                                }
+                            })
+                            .await;
+                    } else {
+                        eprintln!("❌ You have to provide a valid Cloud Manager environment ID to run this command!");
+                    }
+                }
+
+                (EnvCommands::Update { description }, Some(program_id)) => {
+                    if let Some(env_id) = cli.env() {
+                        let env = environments::get_environment(&mut cm_client, program_id, env_id)
+                            .await
+                            .unwrap();
+                        let old_description = env.description.unwrap_or_default();
+                        println!(
+                            "{:>4} Environment: {} ({})\n{:>8} description: '{}' → '{}'",
+                            "⬛", env_id, env.name, "✍", old_description, description
+                        );
+                        if cli.dry_run_mode {
+                            println!(
+                                "{:>8} --dry-run detected. Not performing any actions.",
+                                "⚠️",
+                            );
+                        } else if old_description == *description {
+                            println!("{:>8} no changes", "✔");
+                        } else {
+                            let update = EnvironmentUpdate {
+                                description: description.clone(),
+                            };
+                            let updated = environments::update_environment(
+                                &mut cm_client,
+                                program_id,
+                                env_id,
+                                &update,
+                            )
+                            .await
+                            .unwrap_or_else(|err| {
+                                eprintln!("{} {}", "❌ API error: ".red().bold(), err);
+                                exit(1);
+                            });
+                            println!(
+                                "{:>8} Success, description is now '{}'",
+                                "✔",
+                                updated.description.unwrap_or_default()
+                            );
+                        }
+                    } else {
+                        eprintln!("❌ You have to provide a valid Cloud Manager environment ID to run this command!");
+                        exit(1);
+                    }
+                }
+
+                (
+                    EnvCommands::Hibernate {
+                        wait,
+                        max_wait_minutes,
+                        all_dev,
+                    }
+                    | EnvCommands::Dehibernate {
+                        wait,
+                        max_wait_minutes,
+                        all_dev,
+                    },
+                    Some(program_id),
+                ) => {
+                    let action = if let EnvCommands::Hibernate { .. } = env_command {
+                        HibernationAction::Hibernate
+                    } else {
+                        HibernationAction::Dehibernate
+                    };
+                    let env_ids: Vec<EnvironmentId> = if *all_dev {
                         let envs = environments::get_environments(&mut cm_client, program_id)
                             .await
                             .unwrap();
-                        println!("{}", serde_json::to_string_pretty(&envs).unwrap());
+                        environments::dev_environments(&envs)
+                            .iter()
+                            .map(|env| env.id)
+                            .collect()
+                    } else if let Some(env_id) = cli.env() {
+                        vec![env_id]
+                    } else {
+                        eprintln!("❌ You have to provide a valid Cloud Manager environment ID or --all-dev to run this command!");
+                        exit(1);
+                    };
+                    if env_ids.is_empty() {
+                        println!("No dev environments found in program {}", program_id);
                     }
+                    let max_wait = if *wait {
+                        Some(Duration::from_secs(max_wait_minutes * 60))
+                    } else {
+                        None
+                    };
 
-                    EnvCommands::Vars { env_vars_command } => {
-                        // Since all other "vars" subcommands need an environment ID, we can only run them when it was provided.
-                        if let Some(env_id) = cli.env {
-                            if let EnvVarsCommands::List = &env_vars_command {
-                                let env_vars = get_env_vars(&mut cm_client, program_id, env_id)
-                                    .await
-                                    .unwrap();
-                                println!("{}", serde_json::to_string_pretty(&env_vars).unwrap());
-                                if let Some(vf) = env_vars.variables.iter().find(|vf| {
-                                    vf.service == EnvironmentVariableServiceType::Invalid
-                                }) {
-                                    eprintln!(
-                                        "{:>8} {}  '{}: {}'",
-                                        "⚠".yellow(),
-                                        "WARN, invalid service type detected for variable".yellow(),
-                                        vf.name,
-                                        vf.service
+                    let mut exit_code = ExitCode::Success;
+                    for env_id in env_ids {
+                        let outcome = environments::change_hibernation(
+                            &mut cm_client,
+                            program_id,
+                            env_id,
+                            action,
+                            max_wait,
+                            Duration::from_secs(30),
+                        )
+                        .await
+                        .unwrap_or_else(|err| {
+                            eprintln!("{} {}", "❌ API error: ".red().bold(), err);
+                            exit(1);
+                        });
+                        match outcome {
+                            HibernationOutcome::Failed => exit_code = ExitCode::Failure,
+                            HibernationOutcome::TimedOut => {
+                                eprintln!(
+                                        "{:>8} {}",
+                                        "❌".red(),
+                                        "Environment did not reach the target status within --max-wait-minutes".red()
                                     );
+                                if exit_code == ExitCode::Success {
+                                    exit_code = ExitCode::TimedOut;
                                 }
                             }
+                            HibernationOutcome::Done | HibernationOutcome::Unchanged => {}
+                        }
+                    }
+                    exit_code.exit();
+                }
+
+                (EnvCommands::Ping { tier, timeout }, Some(program_id)) => {
+                    if let Some(env_id) = cli.env() {
+                        let tier = tier.as_ref().map(|t| BindingTier::from_str(t).unwrap());
+                        let pings = environments::ping_environment(
+                            &mut cm_client,
+                            program_id,
+                            env_id,
+                            tier.as_ref(),
+                            Duration::from_secs(*timeout),
+                        )
+                        .await
+                        .unwrap_or_else(|err| {
+                            eprintln!("{} {}", "❌ API error: ".red().bold(), err);
+                            exit(1);
+                        });
+                        exit_if_raw(&cm_client);
+                        if cli.output == Some(OutputFormat::Json) {
+                            println!("{}", serde_json::to_string_pretty(&pings).unwrap());
                         } else {
-                            eprintln!("❌ You have to provide a valid Cloud Manager environment ID to run this command!");
+                            for ping in &pings {
+                                let tier_name: &str = (&ping.tier).into();
+                                match ping.status {
+                                    Some(status) => println!(
+                                        "{:>4} {:<8} {} {} in {} ms",
+                                        if ping.reachable() { "✔" } else { "❌" },
+                                        tier_name,
+                                        ping.url,
+                                        status,
+                                        ping.latency_ms
+                                    ),
+                                    None => println!(
+                                        "{:>4} {:<8} {} unreachable: {}",
+                                        "❌",
+                                        tier_name,
+                                        ping.url,
+                                        ping.error.as_deref().unwrap_or_default()
+                                    ),
+                                }
+                            }
+                        }
+                        if pings.is_empty() {
+                            eprintln!("❌ The environment has no tier URLs to check");
+                            exit(1);
+                        }
+                        if pings.iter().any(|ping| !ping.reachable()) {
+                            exit(1);
                         }
+                    } else {
+                        eprintln!("❌ You have to provide a valid Cloud Manager environment ID to run this command!");
+                        exit(1);
                     }
                 }
-            } else {
-                eprintln!(
-                    "❌ You have to provide a valid Cloud Manager program ID to run this command!"
-                );
-            }
-        }
 
-        Some(Commands::Log { log_command }) => {
-            // Since all "log" subcommands need program- and environment ID, we can only run them when they were provided.
-            if let Some(program_id) = cli.program {
-                if let Some(env_id) = cli.env {
-                    match log_command {
-                        LogCommands::Save { service, log, date } => {
-                            let downloaded_file = download_log(
+                (
+                    EnvCommands::FlushCache {
+                        path,
+                        surrogate_key,
+                        all,
+                        soft,
+                        domain,
+                    },
+                    Some(program_id),
+                ) => {
+                    if let Some(env_id) = cli.env() {
+                        let mut targets: Vec<PurgeTarget> =
+                            path.iter().cloned().map(PurgeTarget::Path).collect();
+                        if !surrogate_key.is_empty() {
+                            targets.push(PurgeTarget::SurrogateKeys(surrogate_key.clone()));
+                        }
+                        if *all {
+                            targets.push(PurgeTarget::All);
+                        }
+                        if cli.dry_run_mode {
+                            for target in &targets {
+                                println!("{:>4} would purge {}", "✍", target);
+                            }
+                            println!(
+                                "{:>4} --dry-run detected. Not performing any actions.",
+                                "⚠️",
+                            );
+                        } else {
+                            let purge_key = std::env::var(environments::PURGE_KEY_ENV)
+                                .unwrap_or_else(|_| {
+                                    eprintln!(
+                                        "❌ Set {} to the purge key of the CDN to run this command",
+                                        environments::PURGE_KEY_ENV
+                                    );
+                                    exit(1);
+                                });
+                            let purges = environments::flush_content_cache(
                                 &mut cm_client,
                                 program_id,
                                 env_id,
-                                ServiceType::from_str(service).unwrap(),
-                                LogType::from_str(log).unwrap(),
-                                NaiveDate::from_str(date).unwrap_or_else(|err| {
-                                    eprintln!("{}{}", "❌ Cannot parse provided date: ".red(), err);
-                                    process::exit(1);
-                                }),
+                                domain.as_deref(),
+                                &purge_key,
+                                &targets,
+                                *soft,
                             )
                             .await
                             .unwrap();
-                            println!(
-                                "{}{}",
-                                "Log successfully downloaded and saved at ./".green(),
-                                downloaded_file.bold().green()
-                            );
+                            if purges.is_empty() {
+                                eprintln!("❌ The environment has no publish URL, pass --domain to purge its CDN");
+                                exit(1);
+                            }
+                            if cli.output == Some(OutputFormat::Json) {
+                                println!("{}", serde_json::to_string_pretty(&purges).unwrap());
+                            } else {
+                                for purge in &purges {
+                                    match purge.status {
+                                        Some(status) => println!(
+                                            "{:>4} {} {} {}",
+                                            if purge.purged() { "✔" } else { "❌" },
+                                            if purge.soft { "soft purged" } else { "purged" },
+                                            purge.target,
+                                            status
+                                        ),
+                                        None => println!(
+                                            "{:>4} {} failed: {}",
+                                            "❌",
+                                            purge.url,
+                                            purge.error.as_deref().unwrap_or_default()
+                                        ),
+                                    }
+                                }
+                            }
+                            if purges.iter().any(|purge| !purge.purged()) {
+                                exit(1);
+                            }
                         }
+                    } else {
+                        eprintln!("❌ You have to provide a valid Cloud Manager environment ID to run this command!");
+                        exit(1);
+                    }
+                }
 
-                        LogCommands::Tail { service, log } => {
-                            let ctrlc = CtrlC::new().expect("Could not create Ctrl+C handler");
-                            ctrlc
-                                .race(async {
-                                    tail_log(
+                (EnvCommands::Pipelines { check_running }, Some(program_id)) => {
+                    if let Some(env_id) = cli.env() {
+                        let env_pipelines = pipelines::get_environment_pipelines(
+                            &mut cm_client,
+                            program_id,
+                            env_id,
+                        )
+                        .await
+                        .unwrap();
+                        exit_if_raw(&cm_client);
+                        if cli.output == Some(OutputFormat::Json) {
+                            println!("{}", serde_json::to_string_pretty(&env_pipelines).unwrap());
+                        } else if env_pipelines.is_empty() {
+                            println!("No pipeline deploys to environment {}", env_id);
+                        } else {
+                            for p in &env_pipelines {
+                                println!(
+                                    "{:>4} {} ({}) type: {}, last execution: {}",
+                                    "⬛",
+                                    p.name,
+                                    p.id,
+                                    p.pipeline_type.as_deref().unwrap_or("-"),
+                                    p.last_execution_status.as_deref().unwrap_or("-")
+                                );
+                                if *check_running && p.status == "BUSY" {
+                                    println!("{:>8} currently running", "⚠".yellow());
+                                }
+                            }
+                        }
+                    } else {
+                        eprintln!("❌ You have to provide a valid Cloud Manager environment ID to run this command!");
+                    }
+                }
+
+                (EnvCommands::Vars { env_vars_command }, Some(program_id)) => {
+                    // Copying names both environments itself, so it doesn't need an environment ID.
+                    if let EnvVarsCommands::Copy {
+                        from_env,
+                        to_env,
+                        prune,
+                    } = &env_vars_command
+                    {
+                        copy_env_vars(
+                            &mut cm_client,
+                            program_id,
+                            *from_env,
+                            *to_env,
+                            cli.dry_run_mode,
+                            *prune,
+                        )
+                        .await
+                        .unwrap_or_else(|code| code.exit());
+                        exit(0);
+                    }
+
+                    // Since all other "vars" subcommands need an environment ID, we can only run them when it was provided.
+                    if let Some(env_id) = cli.env() {
+                        if let EnvVarsCommands::List = &env_vars_command {
+                            let env_vars = get_env_vars(&mut cm_client, program_id, env_id)
+                                .await
+                                .unwrap();
+                            exit_if_raw(&cm_client);
+                            println!("{}", serde_json::to_string_pretty(&env_vars).unwrap());
+                            if let Some(vf) = env_vars
+                                .variables
+                                .iter()
+                                .find(|vf| vf.service == EnvironmentVariableServiceType::Invalid)
+                            {
+                                eprintln!(
+                                    "{:>8} {}  '{}: {}'",
+                                    "⚠".yellow(),
+                                    "WARN, invalid service type detected for variable".yellow(),
+                                    vf.name,
+                                    vf.service
+                                );
+                            }
+                        }
+                    } else {
+                        eprintln!("❌ You have to provide a valid Cloud Manager environment ID to run this command!");
+                    }
+                }
+
+                // All other "env" subcommands need a program ID, so we can only run them when it was provided.
+                (_, None) => {
+                    eprintln!(
+                        "❌ You have to provide a valid Cloud Manager program ID to run this command!"
+                    );
+                }
+            }
+        }
+
+        Some(Commands::Log { log_command }) => {
+            // Since all "log" subcommands need program- and environment ID, we can only run them when they were provided.
+            if let Some(program_id) = cli.program {
+                if let Some(env_id) = cli.env() {
+                    match log_command {
+                        LogCommands::Save {
+                            service,
+                            log,
+                            date,
+                            from,
+                            to,
+                            days,
+                            all,
+                            output_dir,
+                            filename_template,
+                            overwrite,
+                            no_resume,
+                            verify,
+                            stdout,
+                            decompress,
+                            concurrency,
+                        } => {
+                            // Concurrent downloads and output that is no terminal get a line per milestone
+                            let progress = if cli.quiet {
+                                ProgressMode::Off
+                            } else if cli.envs.len() > 1 || !io::stderr().is_terminal() {
+                                ProgressMode::Milestones
+                            } else {
+                                ProgressMode::Live
+                            };
+                            let target = LogTarget {
+                                dir: PathBuf::from(output_dir),
+                                filename_template: filename_template.clone(),
+                                overwrite: *overwrite,
+                                progress,
+                                resume: !*no_resume,
+                                verify: *verify,
+                            };
+                            // Cloud Manager splits its logfiles by UTC days
+                            let today = Utc::now().date_naive();
+                            let parse_date = |input: &str| {
+                                logs::parse_log_date(input, today).unwrap_or_else(|err| {
+                                    eprintln!("{}{}", "❌ Cannot parse provided date: ".red(), err);
+                                    exit(1);
+                                })
+                            };
+                            // clap ensures both are given when --all is missing
+                            let expand_selection = |services: &[String], lognames: &[String]| {
+                                logs::expand_log_selection(services, lognames).unwrap_or_else(
+                                    |err| {
+                                        eprintln!("{}{}", "❌ Invalid log selection: ".red(), err);
+                                        exit(1);
+                                    },
+                                )
+                            };
+                            // clap ensures that at most one of --date, --from and --days is given
+                            let single_date = from.is_none() && days.is_none();
+                            let dates = match (date, from, days) {
+                                (Some(date), _, _) => vec![parse_date(date)],
+                                (None, None, None) => vec![today],
+                                (None, Some(from), _) => {
+                                    let to = to.as_deref().map_or(today, parse_date);
+                                    expand_date_range(parse_date(from), to).unwrap_or_else(|err| {
+                                        eprintln!("{}{}", "❌ Invalid date range: ".red(), err);
+                                        exit(1);
+                                    })
+                                }
+                                (None, None, days) => last_days(today, days.unwrap()),
+                            };
+                            if *stdout {
+                                // clap rules out --all and date ranges, so only the selection is left to check
+                                let selection = expand_selection(service, log);
+                                if cli.envs.len() > 1 || selection.len() > 1 {
+                                    eprintln!("❌ --stdout only works for a single environment, service and log!");
+                                    exit(1);
+                                }
+                                let (service, logname) = selection[0].clone();
+                                let mut out = BufWriter::new(io::stdout());
+                                logs::stream_log(
+                                    &mut cm_client,
+                                    program_id,
+                                    env_id,
+                                    service,
+                                    logname,
+                                    dates[0],
+                                    &mut out,
+                                    *decompress,
+                                )
+                                .await
+                                .unwrap_or_else(|err| {
+                                    eprintln!("{} {}", "❌".red(), err.to_string().red());
+                                    err.exit_code().exit();
+                                });
+                            } else if cli.envs.len() > 1 {
+                                // Without the environment in the filename, the downloads would overwrite each other
+                                if !filename_template.contains("{env}") {
+                                    eprintln!("❌ The filename template has to contain {{env}} when logs of several environments are saved!");
+                                    exit(1);
+                                }
+                                let selection = if *all {
+                                    None
+                                } else {
+                                    Some(expand_selection(service, log))
+                                };
+                                let complete = logs::download_logs_of_environments(
+                                    &cm_client,
+                                    program_id,
+                                    cli.envs.clone(),
+                                    selection,
+                                    dates,
+                                    target,
+                                    *concurrency as usize,
+                                )
+                                .await;
+                                if !complete {
+                                    exit(1);
+                                }
+                            } else if *all {
+                                let mut complete = true;
+                                let single_day = dates.len() == 1;
+                                for date in dates {
+                                    if !single_day {
+                                        println!("📅 {}", date);
+                                    }
+                                    complete &= download_all_logs(
+                                        &mut cm_client,
+                                        program_id,
+                                        env_id,
+                                        date,
+                                        &target,
+                                        "",
+                                    )
+                                    .await
+                                    .unwrap();
+                                }
+                                if !complete {
+                                    exit(1);
+                                }
+                            } else {
+                                let selection = expand_selection(service, log);
+                                if single_date && selection.len() == 1 {
+                                    let (service, logname) = selection[0].clone();
+                                    let downloaded_file = download_log(
                                         &mut cm_client,
                                         program_id,
                                         env_id,
-                                        ServiceType::from_str(service).unwrap(),
-                                        LogType::from_str(log).unwrap(),
+                                        service,
+                                        logname,
+                                        dates[0],
+                                        &target,
                                     )
+                                    .await
+                                    .unwrap_or_else(|err| {
+                                        eprintln!("{} {}", "❌".red(), err.to_string().red());
+                                        err.exit_code().exit();
+                                    });
+                                    println!(
+                                        "{}{}",
+                                        "Log successfully downloaded and saved at ".green(),
+                                        downloaded_file.bold().green()
+                                    );
+                                } else {
+                                    let complete = logs::download_log_selection(
+                                        &cm_client,
+                                        program_id,
+                                        env_id,
+                                        &selection,
+                                        &dates,
+                                        &target,
+                                        "",
+                                        *concurrency as usize,
+                                    )
+                                    .await;
+                                    if !complete {
+                                        exit(1);
+                                    }
+                                }
+                            }
+                        }
+
+                        LogCommands::List { days } => {
+                            let available =
+                                logs::get_available_logs(&mut cm_client, program_id, env_id, *days)
                                     .await
                                     .unwrap();
+                            exit_if_raw(&cm_client);
+                            let groups = logs::group_logs_by_service(available);
+                            if cli.output == Some(OutputFormat::Json) {
+                                println!("{}", serde_json::to_string_pretty(&groups).unwrap());
+                            } else if groups.is_empty() {
+                                println!("No logfiles available for the last {} days", days);
+                            } else {
+                                for line in logs::render_log_groups(&groups) {
+                                    println!("{}", line);
+                                }
+                            }
+                        }
+
+                        LogCommands::Tail {
+                            service,
+                            log,
+                            spec,
+                            grep,
+                            exclude,
+                            ignore_case,
+                            output_file,
+                            filtered_only,
+                            max_retries,
+                            interval,
+                            backfill_bytes,
+                            since,
+                            output_format,
+                        } => {
+                            // clap only accepts the known formats
+                            let format = TailFormat::from_str(output_format).unwrap();
+                            // clap ensures that either --spec or --service and --log are given
+                            let sources: Vec<(ServiceType, LogName)> = if spec.is_empty() {
+                                vec![(
+                                    ServiceType::from_str(service.as_ref().unwrap()).unwrap(),
+                                    log.clone().unwrap(),
+                                )]
+                            } else {
+                                spec.iter()
+                                    .map(|s| {
+                                        logs::parse_tail_spec(s).unwrap_or_else(|err| {
+                                            eprintln!("{}{}", "❌ Invalid --spec: ".red(), err);
+                                            exit(1);
+                                        })
+                                    })
+                                    .collect()
+                            };
+                            let filter =
+                                LineFilter::new(grep.as_deref(), exclude.as_deref(), *ignore_case)
+                                    .unwrap_or_else(|err| {
+                                        eprintln!(
+                                            "{} {}",
+                                            "❌ Invalid regular expression:".red(),
+                                            err
+                                        );
+                                        exit(1);
+                                    });
+                            let several_envs = cli.envs.len() > 1;
+                            let several_sources = sources.len() > 1;
+                            let mut tails: Vec<(EnvironmentId, usize)> = vec![];
+                            for env_id in &cli.envs {
+                                for index in 0..sources.len() {
+                                    tails.push((*env_id, index));
+                                }
+                            }
+                            if tails.len() > logs::MAX_TAILS {
+                                eprintln!(
+                                    "❌ At most {} logs can be tailed at the same time, not {}!",
+                                    logs::MAX_TAILS,
+                                    tails.len()
+                                );
+                                exit(1);
+                            }
+                            let output_path = |env_id: EnvironmentId, index: usize| {
+                                output_file.as_ref().map(|file| {
+                                    let service: &str = sources[index].0.as_str();
+                                    let log = sources[index].1.as_str();
+                                    file.replace("{env}", &env_id.to_string())
+                                        .replace("{service}", service)
+                                        .replace("{log}", log)
+                                })
+                            };
+                            let mut paths: Vec<String> = tails
+                                .iter()
+                                .filter_map(|(env_id, index)| output_path(*env_id, *index))
+                                .collect();
+                            paths.sort();
+                            paths.dedup();
+                            if output_file.is_some() && paths.len() < tails.len() {
+                                eprintln!("❌ The output file has to contain {{env}}, {{service}} or {{log}}, so every tail gets its own file!");
+                                exit(1);
+                            }
+                            // Open all output files before tailing, so a wrong path doesn't abort a running tail
+                            let started = Local::now();
+                            let tails: Vec<(EnvironmentId, usize, TailOptions)> = tails
+                                .into_iter()
+                                .map(|(env_id, index)| {
+                                    let (service, log) = &sources[index];
+                                    let recorder =
+                                        output_path(env_id, index).map(|path| {
+                                            let header =
+                                                logs::tail_header(service, log, env_id, started);
+                                            TailRecorder::create(
+                                                Path::new(&path),
+                                                &header,
+                                                *filtered_only,
+                                            )
+                                            .unwrap_or_else(|err| {
+                                                eprintln!(
+                                                    "{} {}: {}",
+                                                    "❌ Unable to open output file".red(),
+                                                    path,
+                                                    err
+                                                );
+                                                exit(1);
+                                            })
+                                        });
+                                    let tag = logs::tail_tag(
+                                        several_envs.then_some(env_id),
+                                        several_sources.then_some((service, log)),
+                                        index,
+                                    );
+                                    let options = TailOptions {
+                                        tag,
+                                        filter: filter.clone(),
+                                        recorder,
+                                        retry: TailRetryPolicy {
+                                            max_retries: *max_retries,
+                                            ..TailRetryPolicy::default()
+                                        },
+                                        interval: Duration::from_secs(*interval),
+                                        backfill_bytes: *backfill_bytes,
+                                        since: *since,
+                                        format,
+                                    };
+                                    (env_id, index, options)
                                 })
+                                .collect();
+                            let ctrlc = CtrlC::new().expect("Could not create Ctrl+C handler");
+                            // Every tail gets its own task and client, at most MAX_TAILS of them,
+                            // and their lines are tagged
+                            let mut running = JoinSet::new();
+                            for (env_id, index, mut options) in tails {
+                                let mut client = cm_client.clone();
+                                let (service, log) = sources[index].clone();
+                                running.spawn(async move {
+                                    if let Err(err) = tail_log(
+                                        &mut client,
+                                        program_id,
+                                        env_id,
+                                        service,
+                                        log,
+                                        &mut options,
+                                    )
+                                    .await
+                                    {
+                                        eprintln!(
+                                            "{}{} {}",
+                                            options.tag,
+                                            "❌".red(),
+                                            err.to_string().red()
+                                        );
+                                        err.exit_code().exit();
+                                    }
+                                });
+                            }
+                            ctrlc
+                                .race(async { while running.join_next().await.is_some() {} })
                                 .await;
-                            println!("{}", "👋 Quitting...".magenta());
+                            match format {
+                                TailFormat::Text => println!("{}", "👋 Quitting...".magenta()),
+                                TailFormat::Ndjson => eprintln!("{}", "👋 Quitting...".magenta()),
+                            }
                         }
                     }
                 }
@@ -175,7 +1436,7 @@ pub async fn init_cli() {
             if let DomainCommands::Create { input } = &domain_command {
                 let _ = domains::create_domains(input.to_string(), &mut cm_client).await;
                 println!("🚀 Create Domains succeded. Please Check logs");
-                process::exit(0);
+                exit(0);
             } else {
                 // Since all "domain" subcommands need a program ID, we can only run them when it was provided.
                 if let Some(program_id) = cli.program {
@@ -185,13 +1446,10 @@ pub async fn init_cli() {
                                 domains::get_domains(&mut cm_client, program_id, start, limit)
                                     .await
                                     .unwrap();
-                            if let Some(env_id) = cli.env {
-                                let env_i64 = env_id as i64;
-                                let filtered_domains: Vec<Domain> = domains
-                                    .list
-                                    .into_iter()
-                                    .filter(|object| object.environment_id.eq(&env_i64))
-                                    .collect();
+                            exit_if_raw(&cm_client);
+                            if let Some(env_id) = cli.env() {
+                                let filtered_domains =
+                                    domains::filter_domains_by_environment(domains.list, env_id);
                                 println!(
                                     "{}",
                                     serde_json::to_string_pretty(&filtered_domains).unwrap()
@@ -202,7 +1460,7 @@ pub async fn init_cli() {
                         }
                         DomainCommands::Create { input: _ } => {
                             // must be implemented here, but is already run above in L163...
-                            process::exit(0);
+                            exit(0);
                         }
                     }
                 } else {
@@ -228,27 +1486,50 @@ pub async fn init_cli() {
                         cli.ci_mode,
                         cli.dry_run_mode,
                     )
-                    .await;
-                    process::exit(0);
+                    .await
+                    .unwrap_or_else(|code| code.exit());
+                    exit(0);
                 }
             }
 
             // Since all other "pipeline" subcommands need a program ID, we can only run them when it was provided.
             if let Some(program_id) = cli.program {
                 match &pipeline_command {
-                    PipelineCommands::List => {
-                        let pipelines = pipelines::get_pipelines(&mut cm_client, program_id)
+                    PipelineCommands::List {
+                        status,
+                        pipeline_type,
+                        include_disabled,
+                    } => {
+                        let mut pipelines = pipelines::get_pipelines(&mut cm_client, program_id)
                             .await
                             .unwrap();
-                        println!("{}", serde_json::to_string_pretty(&pipelines).unwrap());
+                        exit_if_raw(&cm_client);
+                        pipelines.pipelines = pipelines::filter_pipelines(
+                            pipelines.pipelines,
+                            status.as_deref(),
+                            pipeline_type.as_deref(),
+                            *include_disabled,
+                        );
+                        sort_list(&cli.sort, &mut pipelines.pipelines);
+                        if let Some(OutputFormat::Template(template)) = &cli.output {
+                            print_template(template, &pipelines.pipelines);
+                        } else {
+                            println!("{}", serde_json::to_string_pretty(&pipelines).unwrap());
+                        }
                     }
 
-                    PipelineCommands::ListExecutions => {
+                    PipelineCommands::ListExecutions { since } => {
                         if let Some(pipeline_id) = cli.pipeline {
-                            let executions =
+                            let mut executions =
                                 execution::get_executions(&mut cm_client, program_id, pipeline_id)
                                     .await
                                     .unwrap();
+                            exit_if_raw(&cm_client);
+                            if let Some(since) = since {
+                                executions.list =
+                                    execution::executions_since(executions.list, *since);
+                            }
+                            sort_list(&cli.sort, &mut executions.list);
 
                             println!("{}", serde_json::to_string_pretty(&executions).unwrap());
                         } else {
@@ -256,6 +1537,82 @@ pub async fn init_cli() {
                         }
                     }
 
+                    PipelineCommands::Watch {
+                        execution_id,
+                        interval,
+                    } => {
+                        if let Some(pipeline_id) = cli.pipeline {
+                            let ctrlc = CtrlC::new().expect("Could not create Ctrl+C handler");
+                            ctrlc
+                                .race(async {
+                                    let execution = execution::watch_execution(
+                                        &mut cm_client,
+                                        program_id,
+                                        pipeline_id,
+                                        *execution_id,
+                                        Duration::from_secs(*interval),
+                                    )
+                                    .await
+                                    .unwrap();
+                                    if execution.status != "FINISHED" {
+                                        exit(1);
+                                    }
+                                })
+                                .await;
+                        } else {
+                            eprintln!("❌ You have to provide a valid Cloud Manager pipeline ID to run this command!");
+                            exit(1);
+                        }
+                    }
+
+                    PipelineCommands::BuildLog { execution_id, out } => {
+                        if let Some(pipeline_id) = cli.pipeline {
+                            let writer: Box<dyn Write> = match out {
+                                Some(path) => Box::new(BufWriter::new(
+                                    File::create(path).unwrap_or_else(|err| {
+                                        eprintln!(
+                                            "{} {}: {}",
+                                            "❌ Unable to create".red(),
+                                            path,
+                                            err
+                                        );
+                                        exit(1);
+                                    }),
+                                )),
+                                None => Box::new(BufWriter::new(io::stdout())),
+                            };
+                            let outcome = execution::stream_build_log(
+                                &mut cm_client,
+                                program_id,
+                                pipeline_id,
+                                *execution_id,
+                                writer,
+                            )
+                            .await
+                            .unwrap_or_else(|err| {
+                                eprintln!("{} {}", "❌".red(), err.to_string().red());
+                                err.exit_code().exit();
+                            });
+                            match outcome {
+                                BuildLogOutcome::Streamed(_) => {}
+                                BuildLogOutcome::NoBuildStep => {
+                                    eprintln!("❌ Execution {} has no build step", execution_id);
+                                    exit(1);
+                                }
+                                BuildLogOutcome::NotStarted => {
+                                    eprintln!(
+                                        "❌ The build step of execution {} hasn't started yet, there is no log",
+                                        execution_id
+                                    );
+                                    exit(1);
+                                }
+                            }
+                        } else {
+                            eprintln!("❌ You have to provide a valid Cloud Manager pipeline ID to run this command!");
+                            exit(1);
+                        }
+                    }
+
                     PipelineCommands::Run => {
                         if let Some(pipeline_id) = cli.pipeline {
                             let execution = pipelines::run_pipeline(
@@ -275,6 +1632,55 @@ pub async fn init_cli() {
                             eprintln!("❌ You have to provide a valid Cloud Manager pipeline ID to run this command!");
                         }
                     }
+                    PipelineCommands::Advance { execution_id }
+                    | PipelineCommands::Reject { execution_id } => {
+                        if let Some(pipeline_id) = cli.pipeline {
+                            let decision = match &pipeline_command {
+                                PipelineCommands::Advance { .. } => StepDecision::Advance,
+                                _ => StepDecision::Reject,
+                            };
+                            match execution::decide_waiting_step(
+                                &mut cm_client,
+                                program_id,
+                                pipeline_id,
+                                *execution_id,
+                                decision,
+                            )
+                            .await
+                            .unwrap()
+                            {
+                                StepDecisionOutcome::Accepted(action) => {
+                                    println!(
+                                        "✔ {} step of execution {} {}",
+                                        action,
+                                        execution_id,
+                                        match decision {
+                                            StepDecision::Advance => "advanced",
+                                            StepDecision::Reject => "rejected",
+                                        }
+                                    );
+                                }
+                                StepDecisionOutcome::NothingWaiting => {
+                                    eprintln!(
+                                        "❌ Execution {} doesn't wait at any step",
+                                        execution_id
+                                    );
+                                    exit(1);
+                                }
+                                StepDecisionOutcome::Unsupported(reason) => {
+                                    eprintln!("❌ {}", reason);
+                                    exit(1);
+                                }
+                                StepDecisionOutcome::Failed(status, body) => {
+                                    eprintln!("{} {}: {}", "❌ API error".red(), status, body);
+                                    exit(1);
+                                }
+                            }
+                        } else {
+                            eprintln!("❌ You have to provide a valid Cloud Manager pipeline ID to run this command!");
+                            exit(1);
+                        }
+                    }
                     PipelineCommands::InvalidateCache => {
                         if let Some(pipeline_id) = cli.pipeline {
                             pipelines::invalidate_pipeline_cache(
@@ -299,6 +1705,7 @@ pub async fn init_cli() {
                                         .await
                                         .unwrap();
 
+                                exit_if_raw(&cm_client);
                                 println!(
                                     "{}",
                                     serde_json::to_string_pretty(&pipeline_vars).unwrap()
@@ -329,6 +1736,155 @@ pub async fn init_cli() {
             }
         }
 
+        Some(Commands::IpAllowlist {
+            ip_allowlist_command,
+        }) => {
+            if let IpAllowlistCommands::BindFromFile { input } = &ip_allowlist_command {
+                println!("🚀 Binding IP allowlists from input file {}\n", input);
+                ip_allowlists::bind_ip_allowlists_from_file(
+                    input,
+                    &mut cm_client,
+                    cli.dry_run_mode,
+                )
+                .await
+                .unwrap_or_else(|code| code.exit());
+                exit(0);
+            }
+
+            // Since all other "ip-allowlist" subcommands need a program ID, we can only run them when it was provided.
+            if let Some(program_id) = cli.program {
+                match &ip_allowlist_command {
+                    IpAllowlistCommands::List => {
+                        let allowlists =
+                            ip_allowlists::get_ip_allowlists(&mut cm_client, program_id)
+                                .await
+                                .unwrap();
+                        exit_if_raw(&cm_client);
+                        println!("{}", serde_json::to_string_pretty(&allowlists).unwrap());
+                    }
+                    IpAllowlistCommands::Bind { allowlist, service }
+                    | IpAllowlistCommands::Unbind { allowlist, service } => {
+                        if let Some(env_id) = cli.env() {
+                            let tier = BindingTier::from_str(service).unwrap();
+                            let result =
+                                if let IpAllowlistCommands::Bind { .. } = &ip_allowlist_command {
+                                    ip_allowlists::bind(
+                                        &mut cm_client,
+                                        program_id,
+                                        env_id,
+                                        allowlist,
+                                        tier,
+                                        cli.dry_run_mode,
+                                    )
+                                    .await
+                                } else {
+                                    ip_allowlists::unbind(
+                                        &mut cm_client,
+                                        program_id,
+                                        env_id,
+                                        allowlist,
+                                        tier,
+                                        cli.dry_run_mode,
+                                    )
+                                    .await
+                                };
+                            if let Err(code) = result {
+                                code.exit();
+                            }
+                        } else {
+                            eprintln!("❌ You have to provide a valid Cloud Manager environment ID to run this command!");
+                            exit(1);
+                        }
+                    }
+                    IpAllowlistCommands::BindFromFile { input: _ } => {
+                        // already handled above
+                        exit(0);
+                    }
+                }
+            } else {
+                eprintln!(
+                    "❌ You have to provide a valid Cloud Manager program ID to run this command!"
+                );
+                exit(1);
+            }
+        }
+
+        Some(Commands::Report { report_command }) => match report_command {
+            ReportCommands::Inventory {
+                format,
+                concurrency,
+            } => {
+                let programs = programs::get_programs(&mut cm_client, None)
+                    .await
+                    .unwrap()
+                    .programs
+                    .into_iter()
+                    .map(|p| (p.id.parse().expect("Program IDs are numeric"), p.name))
+                    .collect();
+                let inventory =
+                    report::get_inventory(&cm_client, programs, *concurrency as usize).await;
+                exit_if_raw(&cm_client);
+                match InventoryFormat::from_str(format).unwrap() {
+                    InventoryFormat::Json => {
+                        println!("{}", serde_json::to_string_pretty(&inventory).unwrap())
+                    }
+                    InventoryFormat::Csv => {
+                        for line in report::render_inventory_csv(&inventory) {
+                            println!("{}", line);
+                        }
+                    }
+                }
+            }
+        },
+
         _ => {}
     }
+    audit::finish(0);
+}
+
+/// Prints one line per item for `--output template:...`, exits if the template doesn't fit the items.
+///
+/// # Arguments
+///
+/// * `template` - String slice that holds the template, e.g. `{id}\t{name}`
+/// * `items` - The items to print
+fn print_template<T: Serialize>(template: &str, items: &[T]) {
+    match render_template(template, items) {
+        Ok(lines) => {
+            for line in lines {
+                println!("{}", line);
+            }
+        }
+        Err(err) => {
+            eprintln!("{} {}", "❌".red(), err);
+            exit(1);
+        }
+    }
+}
+
+/// Sorts the items of a list command by `--sort`, exits if the items don't have the field.
+///
+/// # Arguments
+///
+/// * `sort` - The field to sort by, nothing is sorted without it
+/// * `items` - The items to sort
+fn sort_list<T: Serialize>(sort: &Option<SortKey>, items: &mut Vec<T>) {
+    if let Some(key) = sort {
+        sort_items(items, key).unwrap_or_else(|err| {
+            eprintln!("{} {}", "❌".red(), err);
+            exit(1);
+        });
+    }
+}
+
+/// Ends a read command in raw mode once all its responses were printed unparsed, so they
+/// aren't followed by the formatted output.
+///
+/// # Arguments
+///
+/// * `client` - The client that printed the responses
+fn exit_if_raw(client: &CloudManagerClient) {
+    if client.raw {
+        exit(0);
+    }
 }
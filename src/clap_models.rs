@@ -1,4 +1,11 @@
+use crate::client::parse_header;
+use crate::config::TargetDefaults;
+use crate::encryption::KeyCipher;
+use crate::models::environment::EnvironmentId;
+use crate::models::log::LogName;
+use crate::output::{OutputFormat, SortKey};
 use clap::{ArgAction, Parser, Subcommand};
+use std::str::FromStr;
 
 #[derive(Parser)]
 #[clap(
@@ -9,17 +16,43 @@ long_about = None
 )]
 #[clap(propagate_version = true)]
 pub struct Cli {
-    /// Path to JSON config file
-    #[clap(short, long, value_parser, default_value_t = String::from("./pippo.json"), env = "PIPPO_CONFIG")]
-    pub config: String,
+    /// Path to JSON config file (default: $XDG_CONFIG_HOME/pippo/config.json, ~/.pippo.json or ./pippo.json, whichever exists first)
+    #[clap(short, long, value_parser, env = "PIPPO_CONFIG")]
+    pub config: Option<String>,
 
     /// Cloud Manager program ID
     #[clap(short, long, value_parser, global = true, env = "PIPPO_PROGRAM_ID")]
     pub program: Option<u32>,
 
-    /// Cloud Manager environment ID
-    #[clap(short, long, value_parser, global = true, env = "PIPPO_ENVIRONMENT_ID")]
-    pub env: Option<u32>,
+    /// Cloud Manager program name, resolved to its ID (alternative to --program)
+    #[clap(long, value_parser, global = true, conflicts_with = "program")]
+    pub program_name: Option<String>,
+
+    /// YAML config whose only program is used when --program is not given
+    #[clap(
+        long,
+        value_parser,
+        global = true,
+        value_name = "FILE",
+        conflicts_with = "program-name"
+    )]
+    pub program_config: Option<String>,
+
+    /// Cloud Manager environment ID (log save and log tail accept several, repeated or comma separated)
+    #[clap(
+        short,
+        long = "env",
+        value_parser = EnvironmentId::from_str,
+        global = true,
+        env = "PIPPO_ENVIRONMENT_ID",
+        action = ArgAction::Append,
+        value_delimiter = ','
+    )]
+    pub envs: Vec<EnvironmentId>,
+
+    /// Cloud Manager environment name, resolved to its ID (alternative to --env)
+    #[clap(long, value_parser, global = true, conflicts_with = "envs")]
+    pub env_name: Option<String>,
 
     /// Pipeline ID
     #[clap(
@@ -39,26 +72,207 @@ pub struct Cli {
     #[clap(long = "dry-run", global = true, action = ArgAction::SetTrue )]
     pub dry_run_mode: bool,
 
+    /// Total number of retries on transient API failures for the whole run (overrides config)
+    #[clap(long, value_parser, global = true, env = "PIPPO_RETRY_BUDGET")]
+    pub retry_budget: Option<u32>,
+
+    /// Lowest TLS version that connections may use, 1.2 or 1.3, which switches to rustls (overrides config)
+    #[clap(long, value_parser, global = true, env = "PIPPO_MIN_TLS_VERSION")]
+    pub min_tls_version: Option<String>,
+
+    /// PEM file with additional root certificates, e.g. of a TLS-inspecting proxy (overrides config)
+    #[clap(
+        long,
+        value_parser,
+        value_name = "FILE",
+        global = true,
+        env = "PIPPO_CA_BUNDLE"
+    )]
+    pub ca_bundle: Option<String>,
+
+    /// Extra header that is sent with every request, in the form name:value (can be repeated)
+    #[clap(long = "header", value_parser = parse_header, global = true, action = ArgAction::Append)]
+    pub headers: Vec<(String, String)>,
+
+    /// Why a changing command is run, recorded in the audit log
+    #[clap(long, value_parser, global = true)]
+    pub reason: Option<String>,
+
+    /// Don't report the progress of log downloads
+    #[clap(short, long, global = true, action = ArgAction::SetTrue)]
+    pub quiet: bool,
+
+    /// Print the response of Cloud Manager as it is instead of parsing it (read commands only)
+    #[clap(long, global = true, action = ArgAction::SetTrue)]
+    pub raw: bool,
+
+    /// Treat a 404 answer to listing domains or pipelines as an empty list instead of an error
+    #[clap(long, global = true, action = ArgAction::SetTrue)]
+    pub allow_empty: bool,
+
+    /// Log every request with masked credentials before it is sent, at the given log level
+    #[clap(
+        long,
+        global = true,
+        value_name = "LEVEL",
+        min_values = 0,
+        require_equals = true,
+        default_missing_value = "info",
+        possible_values = ["error", "warn", "info", "debug", "trace"]
+    )]
+    pub dump_request: Option<String>,
+
+    /// Send service "all" of environment variables explicitly instead of omitting it (overrides config)
+    #[clap(long, global = true, action = ArgAction::SetTrue)]
+    pub explicit_service: bool,
+
+    /// Output format of commands that support it (json, table or template:<template>, e.g. template:{id}\t{name})
+    #[clap(short, long, value_parser = OutputFormat::from_str, global = true)]
+    pub output: Option<OutputFormat>,
+
+    /// Sort the items of list commands by a field of their JSON output, prefix it with - for descending order, e.g. --sort=-createdAt
+    #[clap(long, value_parser = SortKey::from_str, value_name = "FIELD", global = true, allow_hyphen_values = true)]
+    pub sort: Option<SortKey>,
+
+    /// File with the encryption key, used instead of ./.cryptkey if PIPPO_CRYPTKEY is not set
+    #[clap(long, value_parser, value_name = "FILE", global = true, env = crate::encryption::KEY_FILE_ENV)]
+    pub cryptkey_file: Option<String>,
+
+    /// File with the age identities that decrypt '$enc:age' values, used if PIPPO_AGE_IDENTITY is not set
+    #[clap(long, value_parser, value_name = "FILE", global = true, env = crate::encryption::AGE_IDENTITY_FILE_ENV)]
+    pub identity_file: Option<String>,
+
     #[clap(subcommand)]
     pub command: Option<Commands>,
 }
 
+impl Cli {
+    /// Fills in the program, environment and pipeline from the config where neither a flag nor an
+    /// environment variable gave one.
+    ///
+    /// The environment and pipeline defaults belong to the program default, so they are only used
+    /// if the program is the default one as well.
+    ///
+    /// # Arguments
+    ///
+    /// * `defaults` - The `defaults` section of the config
+    pub fn apply_defaults(&mut self, defaults: &TargetDefaults) {
+        if self.program.is_none() && self.program_name.is_none() && self.program_config.is_none() {
+            self.program = defaults.program_id;
+        }
+        if defaults.program_id.is_some() && self.program != defaults.program_id {
+            return;
+        }
+        if self.envs.is_empty() && self.env_name.is_none() {
+            self.envs.extend(defaults.environment_id);
+        }
+        if self.pipeline.is_none() {
+            self.pipeline = defaults.pipeline_id;
+        }
+    }
+
+    /// The environment ID for commands that work on a single environment
+    pub fn env(&self) -> Option<EnvironmentId> {
+        self.envs.first().copied()
+    }
+
+    /// Whether the command may run on several environments at once
+    pub fn accepts_multiple_environments(&self) -> bool {
+        matches!(
+            self.command,
+            Some(Commands::Log {
+                log_command: LogCommands::Save { .. } | LogCommands::Tail { .. }
+            })
+        )
+    }
+}
+
 #[derive(Subcommand)]
 pub enum Commands {
-    /// Encrypt a string using the provided .cryptkey file
+    /// Encrypt a string or a file using the provided .cryptkey file
+    #[clap(args_conflicts_with_subcommands = true, subcommand_negates_reqs = true)]
     Encrypt {
-        /// The string you want to encrypt
-        #[clap(value_parser)]
-        input: String,
+        #[clap(subcommand)]
+        encrypt_command: Option<EncryptCommands>,
+
+        /// The string you want to encrypt, - or none reads it from stdin (keeps it out of the shell history)
+        #[clap(value_parser, conflicts_with = "file")]
+        input: Option<String>,
+
+        /// Encrypt this file instead of a string, - reads it from stdin
+        #[clap(long, value_parser, value_name = "FILE")]
+        file: Option<String>,
+
+        /// Write the encrypted file here instead of stdout
+        #[clap(long, value_parser, value_name = "FILE", conflicts_with = "input")]
+        out: Option<String>,
+
+        /// Overwrite the --out file if it exists
+        #[clap(long, action = ArgAction::SetTrue, requires = "out")]
+        force: bool,
+
+        /// Encrypt with the key of this id (PIPPO_CRYPTKEY_<ID> or .cryptkeys) and print the value as '$enc:<id> <base64>'
+        #[clap(long, value_parser = crate::encryption::parse_key_id, value_name = "ID", conflicts_with = "file")]
+        key_id: Option<String>,
+
+        /// How to encrypt: gcm1 (AES-256-GCM, detects modified values, the default) or cbc (the format of older pippo versions)
+        #[clap(long, value_parser = KeyCipher::from_str, possible_values = ["gcm1", "cbc"], env = "PIPPO_CIPHER", conflicts_with = "recipients")]
+        cipher: Option<KeyCipher>,
+
+        /// Encrypt with age for these public keys (age1...) or files with one key per line, comma separated, and print the value as '$enc:age <base64>'. Needs no .cryptkey, only the identities of the recipients can decrypt it
+        #[clap(
+            long,
+            value_parser,
+            value_name = "FILE|KEY,...",
+            env = "PIPPO_AGE_RECIPIENTS",
+            conflicts_with = "key-id"
+        )]
+        recipients: Option<String>,
     },
 
-    /// Decrypt a string using the provided .cryptkey file
+    /// Decrypt a string or a file using the provided .cryptkey file
     Decrypt {
-        /// The string you want to decrypt
-        #[clap(value_parser)]
+        /// The string you want to decrypt, either the base64 or a whole '$enc', '$enc:<key id>' or '$enc:age' value, - or none reads it from stdin
+        #[clap(value_parser, conflicts_with = "file")]
+        input: Option<String>,
+
+        /// Decrypt this file instead of a string, - reads it from stdin
+        #[clap(long, value_parser, value_name = "FILE")]
+        file: Option<String>,
+
+        /// Write the decrypted file here instead of stdout
+        #[clap(long, value_parser, value_name = "FILE", conflicts_with = "input")]
+        out: Option<String>,
+
+        /// Overwrite the --out file if it exists
+        #[clap(long, action = ArgAction::SetTrue, requires = "out")]
+        force: bool,
+    },
+
+    /// Check that every $enc value of a YAML file can be decrypted with the local key
+    VerifySecrets {
+        /// Path to input file, - reads it from stdin
+        #[clap(value_parser, value_name = "FILE")]
         input: String,
     },
 
+    /// Print a JSON Schema for the YAML config, e.g. for validation and autocompletion in editors
+    Schema,
+
+    /// Apply domains, environment variables and pipeline variables from a single YAML file
+    Apply {
+        /// Path to input file, - reads it from stdin
+        #[clap(value_parser, value_name = "FILE")]
+        input: String,
+
+        /// Only compare the file with Cloud Manager and print what would change (exit code 2 on drift)
+        #[clap(long, alias = "plan")]
+        diff: bool,
+    },
+
+    /// Print the organization, client and programs that the configured credentials authenticate as
+    Whoami,
+
     /// Adobe I/O access_token utilities
     AccessToken {
         #[clap(subcommand)]
@@ -94,18 +308,124 @@ pub enum Commands {
         #[clap(subcommand)]
         domain_command: DomainCommands,
     },
+
+    /// Tools to interact with Cloud Manager IP allowlists
+    IpAllowlist {
+        #[clap(subcommand)]
+        ip_allowlist_command: IpAllowlistCommands,
+    },
+
+    /// Reports that span all programs
+    Report {
+        #[clap(subcommand)]
+        report_command: ReportCommands,
+    },
+}
+
+#[derive(Subcommand)]
+pub enum ReportCommands {
+    /// Environments, pipelines, domain counts and earliest certificate expiry of every program
+    Inventory {
+        /// Print one JSON document or one CSV line per program
+        #[clap(long, value_parser, possible_values = vec!["json", "csv"], default_value = "json")]
+        format: String,
+        /// Maximum number of programs that are fetched in parallel
+        #[clap(long, value_parser = clap::value_parser!(u64).range(1..), default_value_t = 4)]
+        concurrency: u64,
+    },
+}
+
+/// Subcommands of `encrypt`
+#[derive(Subcommand)]
+pub enum EncryptCommands {
+    /// Re-encrypt all cbc $enc values of a YAML file with gcm1, keeping their keys (honours --dry-run)
+    Migrate {
+        /// Path to the YAML file, - reads it from stdin
+        #[clap(value_parser, value_name = "FILE")]
+        input: String,
+
+        /// Where the rewritten YAML is written, - for stdout (default: the input file)
+        #[clap(name = "output-file", value_parser, value_name = "FILE")]
+        output: Option<String>,
+    },
+
+    /// Encrypt the plain values of the secretString variables of a YAML file in place (honours --dry-run)
+    Yaml {
+        /// Path to the YAML file, - reads it from stdin
+        #[clap(value_parser, value_name = "FILE")]
+        input: String,
+
+        /// Where the rewritten YAML is written, - for stdout (default: the input file)
+        #[clap(name = "output-file", value_parser, value_name = "FILE")]
+        output: Option<String>,
+
+        /// Only encrypt the variables with these names
+        #[clap(long, value_parser, value_delimiter = ',', value_name = "NAME")]
+        only: Vec<String>,
+
+        /// Encrypt with the key of this id (PIPPO_CRYPTKEY_<ID> or .cryptkeys)
+        #[clap(long, value_parser = crate::encryption::parse_key_id, value_name = "ID")]
+        key_id: Option<String>,
+
+        /// How to encrypt: gcm1 (AES-256-GCM, detects modified values) or cbc (the format of older pippo versions)
+        #[clap(long, value_parser = KeyCipher::from_str, possible_values = ["gcm1", "cbc"], env = "PIPPO_CIPHER", default_value = "gcm1")]
+        cipher: KeyCipher,
+    },
+
+    /// Re-encrypt all $enc values of a YAML file with a new key (honours --dry-run)
+    RotateKey {
+        /// Path to the YAML file, - reads it from stdin
+        #[clap(value_parser, value_name = "FILE")]
+        input: String,
+
+        /// Where the rewritten YAML is written, - for stdout (default: the input file)
+        #[clap(name = "output-file", value_parser, value_name = "FILE")]
+        output: Option<String>,
+
+        /// Environment variable that holds the new key
+        #[clap(
+            long,
+            value_parser,
+            value_name = "VAR",
+            required_unless_present = "new-key-file",
+            conflicts_with = "new-key-file"
+        )]
+        new_key_env: Option<String>,
+
+        /// File that holds the new key
+        #[clap(long, value_parser, value_name = "FILE")]
+        new_key_file: Option<String>,
+    },
 }
 
 #[derive(Subcommand)]
 pub enum AccessTokenCommands {
     /// prints access_token to stdout
     Print,
+    /// prints the claims of the generated JWT without contacting IMS (auth_strategy jwt only)
+    Jwt,
 }
 
 #[derive(Subcommand)]
 pub enum ProgramCommands {
     /// List all programs
-    List,
+    List {
+        /// Only list programs that are enabled
+        #[clap(long, action = ArgAction::SetTrue)]
+        enabled_only: bool,
+        /// Only list programs with this status, e.g. ready (case-insensitive)
+        #[clap(long, value_parser)]
+        status: Option<String>,
+        /// Only list programs whose name contains this text (case-insensitive)
+        #[clap(long, value_parser, value_name = "TEXT")]
+        name_contains: Option<String>,
+        /// Only fetch the first N programs instead of all pages (applied before filtering)
+        #[clap(long, value_parser, value_name = "N")]
+        limit: Option<usize>,
+    },
+
+    /// Show the details of the specified program (exit code 7 if it doesn't exist)
+    Get,
 }
 
 #[derive(Subcommand)]
@@ -113,6 +433,116 @@ pub enum EnvCommands {
     /// List all environments of the specified program
     List,
 
+    /// Show the environments of all programs, grouped by program (no program ID required)
+    Overview {
+        /// Exit with a non-zero code if the environments of a program can't be fetched
+        #[clap(long, action = ArgAction::SetTrue)]
+        strict: bool,
+        /// Maximum number of programs that are fetched in parallel
+        #[clap(long, value_parser = clap::value_parser!(u64).range(1..), default_value_t = 4)]
+        concurrency: u64,
+    },
+
+    /// List the regions that environments of the specified program can be created in
+    Regions {
+        /// Only check that this region is valid for the program (exit code 1 if not)
+        #[clap(long, value_parser, value_name = "REGION")]
+        check: Option<String>,
+    },
+
+    /// Watch the status of the specified environment until it is ready
+    Watch {
+        /// Seconds between two status polls
+        #[clap(long, value_parser = clap::value_parser!(u64).range(1..), default_value_t = 30)]
+        interval: u64,
+        /// Give up and exit with a non-zero code after this many minutes
+        #[clap(long, value_parser, value_name = "MINUTES")]
+        max_wait: Option<u64>,
+    },
+
+    /// Check if the specified environment is ready (exit code 0 ready, 6 busy or hibernated, 5 timed out, 1 error)
+    Ready {
+        /// Poll until the environment is ready instead of checking only once
+        #[clap(long, action = ArgAction::SetTrue)]
+        wait: bool,
+        /// Maximum minutes to wait when --wait is given
+        #[clap(long, value_parser, default_value_t = 30, value_name = "MINUTES")]
+        max_wait_minutes: u64,
+        /// Seconds between two status polls when --wait is given
+        #[clap(long, value_parser = clap::value_parser!(u64).range(1..), default_value_t = 30)]
+        interval: u64,
+    },
+
+    /// Update the description of the specified environment
+    Update {
+        /// New description of the environment
+        #[clap(long, value_parser)]
+        description: String,
+    },
+
+    /// Hibernate the specified dev environment
+    Hibernate {
+        /// Poll until the environment is hibernated
+        #[clap(long, action = ArgAction::SetTrue)]
+        wait: bool,
+        /// Maximum minutes to wait when --wait is given
+        #[clap(long, value_parser, default_value_t = 30, value_name = "MINUTES")]
+        max_wait_minutes: u64,
+        /// Hibernate every dev environment of the program instead of the specified one
+        #[clap(long, action = ArgAction::SetTrue)]
+        all_dev: bool,
+    },
+
+    /// De-hibernate the specified dev environment
+    Dehibernate {
+        /// Poll until the environment is ready again
+        #[clap(long, action = ArgAction::SetTrue)]
+        wait: bool,
+        /// Maximum minutes to wait when --wait is given
+        #[clap(long, value_parser, default_value_t = 30, value_name = "MINUTES")]
+        max_wait_minutes: u64,
+        /// De-hibernate every dev environment of the program instead of the specified one
+        #[clap(long, action = ArgAction::SetTrue)]
+        all_dev: bool,
+    },
+
+    /// Check that the author, publish and preview tiers of the specified environment answer
+    Ping {
+        /// Only check this tier
+        #[clap(long, value_parser, possible_values = vec!["author", "publish", "preview"])]
+        tier: Option<String>,
+        /// Seconds to wait for each tier to answer
+        #[clap(long, value_parser, default_value_t = 10)]
+        timeout: u64,
+    },
+
+    /// Purge the CDN content cache of the specified environment (not the pipeline build cache,
+    /// see `pipeline invalidate-cache`)
+    FlushCache {
+        /// Path of a resource to purge, e.g. /content/site/en.html (repeatable)
+        #[clap(long, value_parser, value_name = "PATH", required_unless_present_any = &["surrogate-key", "all"])]
+        path: Vec<String>,
+        /// Purge every resource that was delivered with this Surrogate-Key (repeatable)
+        #[clap(long, value_parser, value_name = "KEY")]
+        surrogate_key: Vec<String>,
+        /// Purge the whole CDN cache of the environment
+        #[clap(long, action = ArgAction::SetTrue, conflicts_with_all = &["path", "surrogate-key", "soft"])]
+        all: bool,
+        /// Mark the content as stale instead of removing it
+        #[clap(long, action = ArgAction::SetTrue)]
+        soft: bool,
+        /// Send the purge requests to this domain instead of the publish tier
+        #[clap(long, value_parser)]
+        domain: Option<String>,
+    },
+
+    /// List the pipelines that deploy to the specified environment
+    Pipelines {
+        /// Flag pipelines that are currently running (status BUSY)
+        #[clap(long, action = ArgAction::SetTrue)]
+        check_running: bool,
+    },
+
     /// Read or update Cloud Manager environment variables
     Vars {
         #[clap(subcommand)]
@@ -126,55 +556,240 @@ pub enum EnvVarsCommands {
     List,
     /// Update environment variables read from YAML file
     Set {
-        /// Path to input file
+        /// Path to input file, - reads it from stdin
+        #[clap(value_parser, value_name = "FILE")]
+        input: String,
+        /// Only send variables that differ from Cloud Manager and print `changed=true|false`
+        #[clap(long, action = ArgAction::SetTrue)]
+        only_changed: bool,
+        /// Save the current variables of each environment to this directory before changing them
+        #[clap(long, value_parser, value_name = "DIR")]
+        snapshot_dir: Option<String>,
+        /// Add and update variables, but keep variables that only Cloud Manager has instead of deleting them
+        #[clap(long, action = ArgAction::SetTrue)]
+        no_prune: bool,
+        /// Only add variables that Cloud Manager doesn't have yet, never update or delete any
+        #[clap(long, action = ArgAction::SetTrue, conflicts_with = "no-prune")]
+        add_only: bool,
+    },
+    /// Check environment variables of a YAML file offline, without credentials
+    Validate {
+        /// Path to input file, - reads it from stdin
         #[clap(value_parser, value_name = "FILE")]
         input: String,
     },
+    /// Copy the variables of one environment to another, secrets have to be set manually
+    Copy {
+        /// ID of the environment to copy the variables from
+        #[clap(long, value_parser = EnvironmentId::from_str, value_name = "ENV_ID")]
+        from_env: EnvironmentId,
+        /// ID of the environment to copy the variables to
+        #[clap(long, value_parser = EnvironmentId::from_str, value_name = "ENV_ID")]
+        to_env: EnvironmentId,
+        /// Delete variables of the target environment that the source environment doesn't have
+        #[clap(long, action = ArgAction::SetTrue)]
+        prune: bool,
+    },
 }
 
 #[derive(Subcommand)]
 pub enum LogCommands {
+    /// List the logfiles that are available for download, grouped by service
+    List {
+        /// Number of days, counting back from today
+        #[clap(long, value_parser = clap::value_parser!(u32).range(1..), default_value_t = 2)]
+        days: u32,
+    },
+
     /// Download the specified logfile
     Save {
-        /// Name of service
-        #[clap(short, long, value_parser, possible_values = vec!["author", "publish", "dispatcher", "preview_dispatcher"])]
-        service: String,
+        /// Name of service, several can be separated by commas, "all" selects every service
+        #[clap(short, long, value_parser, value_delimiter = ',', possible_values = vec!["author", "publish", "dispatcher", "preview_dispatcher", "preview_publish", "all"], required_unless_present = "all")]
+        service: Vec<String>,
 
-        /// Name of log file
-        #[clap(short, long, value_parser, possible_values = vec!["aemaccess", "aemdispatcher", "aemerror", "aemrequest", "cdn", "httpdaccess", "httpderror"])]
-        log: String,
+        /// Name of log file (aemaccess, aemdispatcher, aemerror, aemrequest, cdn, httpdaccess, httpderror or custom:<name>), several can be separated by commas, "all" selects every known log
+        #[clap(short, long, value_parser = crate::logs::parse_log_arg, value_delimiter = ',', required_unless_present = "all")]
+        log: Vec<String>,
 
-        /// Date of which specified log file will be downloaded
-        #[clap(short, long, value_parser, value_name = "YYYY-MM-DD")]
-        date: String,
+        /// Date of which specified log file will be downloaded, also today, yesterday or -N for N days ago (default: today in UTC)
+        #[clap(short, long, value_parser, value_name = "DATE", allow_hyphen_values = true, conflicts_with_all = &["from", "days"])]
+        date: Option<String>,
+
+        /// First date of a range of days to download, in the same forms as --date
+        #[clap(
+            long,
+            value_parser,
+            value_name = "DATE",
+            allow_hyphen_values = true,
+            conflicts_with = "days"
+        )]
+        from: Option<String>,
+
+        /// Last date of the range started with --from, defaults to today
+        #[clap(
+            long,
+            value_parser,
+            value_name = "DATE",
+            allow_hyphen_values = true,
+            requires = "from"
+        )]
+        to: Option<String>,
+
+        /// Download the last N days, including today
+        #[clap(long, value_parser = clap::value_parser!(u32).range(1..))]
+        days: Option<u32>,
+
+        /// Download every log of every service that the environment offers
+        #[clap(long, action = ArgAction::SetTrue, conflicts_with_all = &["service", "log"])]
+        all: bool,
+
+        /// Directory the logfiles are written to, created if missing
+        #[clap(long, value_parser, value_name = "DIR", default_value = ".")]
+        output_dir: String,
+
+        /// Template for the filenames, with the placeholders {date}, {env}, {program}, {service} and {log}
+        #[clap(long, value_parser, default_value = crate::logs::DEFAULT_LOG_FILENAME_TEMPLATE)]
+        filename_template: String,
+
+        /// Replace logfiles that already exist
+        #[clap(long, action = ArgAction::SetTrue)]
+        overwrite: bool,
+
+        /// Start interrupted downloads over instead of continuing their .part files
+        #[clap(long, action = ArgAction::SetTrue)]
+        no_resume: bool,
+
+        /// Compare downloads with the MD5 sent by the server (Content-MD5 or ETag) and download them again once if they don't match
+        #[clap(long, action = ArgAction::SetTrue)]
+        verify: bool,
+
+        /// Write the logfile to stdout instead of a file, e.g. to pipe it into other tools
+        #[clap(long, action = ArgAction::SetTrue, conflicts_with_all = &["output-dir", "filename-template", "overwrite", "no-resume", "verify", "all", "from", "days"])]
+        stdout: bool,
+
+        /// Decompress the logfile that is written to stdout
+        #[clap(long, action = ArgAction::SetTrue, requires = "stdout")]
+        decompress: bool,
+
+        /// Maximum number of environments to download at the same time when several are given
+        #[clap(long, value_parser = clap::value_parser!(u64).range(1..), default_value_t = 4)]
+        concurrency: u64,
     },
 
     /// Tail the latest of the specified logfile
     Tail {
         /// Name of service
-        #[clap(short, long, value_parser, possible_values = vec!["author", "publish", "dispatcher", "preview_dispatcher"])]
-        service: String,
+        #[clap(short, long, value_parser, possible_values = vec!["author", "publish", "dispatcher", "preview_dispatcher", "preview_publish"], required_unless_present = "spec")]
+        service: Option<String>,
 
-        /// Name of log file
-        #[clap(short, long, value_parser, possible_values = vec!["aemaccess", "aemdispatcher", "aemerror", "aemrequest", "cdn", "httpdaccess", "httpderror"])]
-        log: String,
+        /// Name of log file (aemaccess, aemdispatcher, aemerror, aemrequest, cdn, httpdaccess, httpderror or custom:<name>)
+        #[clap(short, long, value_parser = LogName::from_str, required_unless_present = "spec")]
+        log: Option<LogName>,
+
+        /// Tail several logs merged into one output, e.g. dispatcher:httpderror,publish:aemerror
+        #[clap(long, value_parser, value_name = "SERVICE:LOG", action = ArgAction::Append, value_delimiter = ',', conflicts_with_all = &["service", "log"])]
+        spec: Vec<String>,
+
+        /// Only print lines that match this regular expression
+        #[clap(long, value_parser)]
+        grep: Option<String>,
+
+        /// Don't print lines that match this regular expression
+        #[clap(long, value_parser)]
+        exclude: Option<String>,
+
+        /// Match --grep and --exclude case-insensitively
+        #[clap(long, action = ArgAction::SetTrue)]
+        ignore_case: bool,
+
+        /// Also append the received lines to this file ({env}, {service} and {log} are replaced)
+        #[clap(long, value_parser)]
+        output_file: Option<String>,
+
+        /// Only write the lines that pass --grep and --exclude to the output file
+        #[clap(long, action = ArgAction::SetTrue, requires = "output-file")]
+        filtered_only: bool,
+
+        /// Number of failed polls in a row after which tailing is given up
+        #[clap(long, value_parser, default_value_t = 5)]
+        max_retries: u32,
+
+        /// Seconds to wait between two polls of the logfile
+        #[clap(long, value_parser = clap::value_parser!(u64).range(1..), default_value_t = 5)]
+        interval: u64,
+
+        /// Start this many bytes before the current end of the logfile to print recent lines first
+        #[clap(long, value_parser, default_value_t = 0)]
+        backfill_bytes: u64,
+
+        /// Print the lines of this last period first, e.g. 5m or 1h; logs without timestamps use --backfill-bytes
+        #[clap(long, value_parser = crate::logs::parse_since, value_name = "PERIOD")]
+        since: Option<chrono::Duration>,
+
+        /// Print the lines as they are or as JSON objects with their origin, one per line
+        #[clap(long, value_parser, possible_values = vec!["text", "ndjson"], default_value = "text")]
+        output_format: String,
     },
 }
 
 #[derive(Subcommand)]
 pub enum PipelineCommands {
     /// List all pipelines of the specified program
-    List,
+    List {
+        /// Only list pipelines with this status, e.g. IDLE, BUSY or WAITING
+        #[clap(long, value_parser)]
+        status: Option<String>,
+        /// Only list pipelines of this type, e.g. CI_CD, CODE_QUALITY or WEB_TIER
+        #[clap(long = "type", value_parser)]
+        pipeline_type: Option<String>,
+        /// Also list pipelines that are disabled
+        #[clap(long, action = ArgAction::SetTrue)]
+        include_disabled: bool,
+    },
     /// Runs a pipeline
     Run,
+    /// Advance the step that an execution waits at, e.g. approve a production deployment
+    Advance {
+        /// ID of the waiting execution
+        #[clap(value_parser)]
+        execution_id: u32,
+    },
+    /// Reject the step that an execution waits at, which ends the execution
+    Reject {
+        /// ID of the waiting execution
+        #[clap(value_parser)]
+        execution_id: u32,
+    },
     /// Prints all executions
-    ListExecutions,
+    ListExecutions {
+        /// Only print executions that are newer than the execution with this ID
+        #[clap(long, value_parser, value_name = "EXECUTION_ID")]
+        since: Option<u32>,
+    },
+    /// Watch an execution and show the status of its steps until it is done
+    Watch {
+        /// ID of the execution to watch
+        #[clap(value_parser)]
+        execution_id: u32,
+        /// Seconds between two status polls
+        #[clap(long, value_parser = clap::value_parser!(u64).range(1..), default_value_t = 10)]
+        interval: u64,
+    },
+    /// Print the log of the build step of an execution, e.g. to page through it with less
+    BuildLog {
+        /// ID of the execution
+        #[clap(long = "execution", value_parser, value_name = "EXECUTION_ID")]
+        execution_id: u32,
+        /// Write the log to this file instead of stdout
+        #[clap(long, value_parser, value_name = "FILE")]
+        out: Option<String>,
+    },
     /// Read or update Cloud Manager environment variables
     Vars {
         #[clap(subcommand)]
         pipeline_vars_command: PipelineVarsCommands,
     },
-    /// Invalidate pipeline cache,
+    /// Invalidate the build cache of the pipeline (not the CDN content cache, see `env flush-cache`)
     InvalidateCache,
 }
 
@@ -184,7 +799,13 @@ pub enum PipelineVarsCommands {
     List,
     /// Update pipeline variables read from YAML file
     Set {
-        /// Path to input file
+        /// Path to input file, - reads it from stdin
+        #[clap(value_parser, value_name = "FILE")]
+        input: String,
+    },
+    /// Check pipeline variables of a YAML file offline, without credentials
+    Validate {
+        /// Path to input file, - reads it from stdin
         #[clap(value_parser, value_name = "FILE")]
         input: String,
     },
@@ -203,7 +824,359 @@ pub enum DomainCommands {
     },
     /// Creates domains based upon a provided file
     Create {
+        /// Path to input file, - reads it from stdin
+        #[clap(value_parser, value_name = "FILE")]
+        input: String,
+    },
+}
+
+#[derive(Subcommand)]
+pub enum IpAllowlistCommands {
+    /// List all IP allowlists of the specified program, including their bindings
+    List,
+    /// Bind an IP allowlist to a service of the specified environment
+    Bind {
+        /// Name or ID of the IP allowlist
+        #[clap(short, long, value_parser)]
+        allowlist: String,
+        /// Name of service
+        #[clap(short, long, value_parser, possible_values = vec!["author", "publish", "preview"])]
+        service: String,
+    },
+    /// Unbind an IP allowlist from a service of the specified environment
+    Unbind {
+        /// Name or ID of the IP allowlist
+        #[clap(short, long, value_parser)]
+        allowlist: String,
+        /// Name of service
+        #[clap(short, long, value_parser, possible_values = vec!["author", "publish", "preview"])]
+        service: String,
+    },
+    /// Bind IP allowlists as declared in the bindings of a YAML file
+    BindFromFile {
+        /// Path to input file, - reads it from stdin
         #[clap(value_parser, value_name = "FILE")]
         input: String,
     },
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn defaults() -> TargetDefaults {
+        TargetDefaults {
+            program_id: Some(11111),
+            environment_id: Some(EnvironmentId(22222)),
+            pipeline_id: Some(33333),
+        }
+    }
+
+    #[test]
+    fn config_defaults_fill_missing_targets() {
+        let mut cli = Cli::try_parse_from(["pippo", "env", "vars", "list"]).unwrap();
+        cli.apply_defaults(&defaults());
+        assert_eq!(cli.program, Some(11111));
+        assert_eq!(cli.env(), Some(EnvironmentId(22222)));
+        assert_eq!(cli.pipeline, Some(33333));
+
+        let mut cli = Cli::try_parse_from(["pippo", "-e", "44444", "env", "vars", "list"]).unwrap();
+        cli.apply_defaults(&defaults());
+        assert_eq!(cli.program, Some(11111));
+        assert_eq!(cli.env(), Some(EnvironmentId(44444)));
+    }
+
+    #[test]
+    fn config_defaults_only_apply_to_their_program() {
+        let mut cli = Cli::try_parse_from(["pippo", "-p", "55555", "env", "vars", "list"]).unwrap();
+        cli.apply_defaults(&defaults());
+        assert_eq!(cli.program, Some(55555));
+        assert_eq!(cli.env(), None);
+        assert_eq!(cli.pipeline, None);
+
+        let mut cli =
+            Cli::try_parse_from(["pippo", "--program-name", "Intranet", "env", "list"]).unwrap();
+        cli.apply_defaults(&defaults());
+        assert_eq!(cli.program, None);
+        assert_eq!(cli.env(), None);
+
+        // Without a program default, the environment and pipeline defaults apply to any program
+        let mut cli = Cli::try_parse_from(["pippo", "-p", "55555", "pipeline", "run"]).unwrap();
+        cli.apply_defaults(&TargetDefaults {
+            program_id: None,
+            ..defaults()
+        });
+        assert_eq!(cli.pipeline, Some(33333));
+    }
+
+    #[test]
+    fn repeated_and_comma_separated_environments() {
+        let cli = Cli::try_parse_from([
+            "pippo", "-e", "111", "--env", "222,333", "log", "tail", "-s", "author", "-l",
+            "aemerror",
+        ])
+        .unwrap();
+        assert_eq!(
+            cli.envs,
+            vec![EnvironmentId(111), EnvironmentId(222), EnvironmentId(333)]
+        );
+        assert_eq!(cli.env(), Some(EnvironmentId(111)));
+        assert!(cli.accepts_multiple_environments());
+    }
+
+    #[test]
+    fn only_log_save_and_tail_accept_multiple_environments() {
+        let cli = Cli::try_parse_from(["pippo", "-e", "111,222", "env", "vars", "list"]).unwrap();
+        assert!(!cli.accepts_multiple_environments());
+
+        let cli = Cli::try_parse_from(["pippo", "log", "list"]).unwrap();
+        assert!(!cli.accepts_multiple_environments());
+        assert_eq!(cli.env(), None);
+    }
+
+    #[test]
+    fn log_save_date_is_optional_and_may_be_relative() {
+        let date_of = |args: &[&str]| match Cli::try_parse_from(args).unwrap().command {
+            Some(Commands::Log {
+                log_command: LogCommands::Save { date, .. },
+            }) => date,
+            _ => panic!("not a log save"),
+        };
+        let save = ["pippo", "log", "save", "-s", "author", "-l", "aemerror"];
+        assert_eq!(date_of(&save), None);
+        assert_eq!(
+            date_of(&[&save[..], &["--date", "-3"]].concat()),
+            Some(String::from("-3"))
+        );
+        assert_eq!(
+            date_of(&[&save[..], &["-d", "yesterday"]].concat()),
+            Some(String::from("yesterday"))
+        );
+    }
+
+    #[test]
+    fn log_save_to_stdout_excludes_file_options() {
+        let save = ["pippo", "log", "save", "-s", "author", "-l", "aemerror"];
+        assert!(Cli::try_parse_from([&save[..], &["--stdout", "--decompress"]].concat()).is_ok());
+        assert!(
+            Cli::try_parse_from([&save[..], &["--stdout", "--output-dir", "logs"]].concat())
+                .is_err()
+        );
+        assert!(Cli::try_parse_from([&save[..], &["--stdout", "--days", "2"]].concat()).is_err());
+        assert!(Cli::try_parse_from([&save[..], &["--decompress"]].concat()).is_err());
+    }
+
+    #[test]
+    fn logs_may_be_custom() {
+        let tailed = |args: &[&str]| match Cli::try_parse_from(args).map(|cli| cli.command) {
+            Ok(Some(Commands::Log {
+                log_command: LogCommands::Tail { log, .. },
+            })) => log,
+            _ => None,
+        };
+        assert_eq!(
+            tailed(&[
+                "pippo",
+                "log",
+                "tail",
+                "-s",
+                "author",
+                "-l",
+                "custom:aemsidecar"
+            ]),
+            Some(LogName::Custom(String::from("aemsidecar")))
+        );
+        assert_eq!(
+            tailed(&["pippo", "log", "tail", "-s", "author", "-l", "aemsidecar"]),
+            None
+        );
+        assert!(Cli::try_parse_from([
+            "pippo",
+            "log",
+            "save",
+            "-s",
+            "author",
+            "-l",
+            "aemerror,custom:aemsidecar"
+        ])
+        .is_ok());
+    }
+
+    #[test]
+    fn input_may_be_stdin() {
+        for args in [
+            &["pippo", "env", "vars", "set", "-"][..],
+            &["pippo", "pipeline", "vars", "set", "-"],
+            &["pippo", "domain", "create", "-"],
+            &["pippo", "apply", "-"],
+        ] {
+            let cli = Cli::try_parse_from(args).unwrap();
+            let input = crate::audit::audited_command(cli.command.as_ref().unwrap())
+                .and_then(|(_, input)| input);
+            assert_eq!(input.as_deref(), Some("-"), "{:?}", args);
+        }
+    }
+
+    #[test]
+    fn program_name_excludes_program_id() {
+        let cli = Cli::try_parse_from(["pippo", "--program-name", "Demo", "env", "list"]).unwrap();
+        assert_eq!(cli.program_name.as_deref(), Some("Demo"));
+        assert!(Cli::try_parse_from([
+            "pippo",
+            "-p",
+            "22222",
+            "--program-name",
+            "Demo",
+            "env",
+            "list"
+        ])
+        .is_err());
+    }
+
+    #[test]
+    fn sort_accepts_descending_fields() {
+        let cli =
+            Cli::try_parse_from(["pippo", "pipeline", "list", "--sort", "-createdAt"]).unwrap();
+        assert_eq!(cli.sort, Some(SortKey::from_str("-createdAt").unwrap()));
+        assert!(Cli::try_parse_from(["pippo", "program", "list", "--sort="]).is_err());
+    }
+
+    #[test]
+    fn program_config_excludes_program_name() {
+        let cli =
+            Cli::try_parse_from(["pippo", "--program-config", "pippo.yml", "env", "list"]).unwrap();
+        assert_eq!(cli.program_config.as_deref(), Some("pippo.yml"));
+        assert!(Cli::try_parse_from([
+            "pippo",
+            "--program-config",
+            "pippo.yml",
+            "--program-name",
+            "Demo",
+            "env",
+            "list"
+        ])
+        .is_err());
+    }
+
+    #[test]
+    fn encrypt_takes_a_string_or_a_file() {
+        assert!(Cli::try_parse_from(["pippo", "encrypt", "secret"]).is_ok());
+        assert!(Cli::try_parse_from(["pippo", "decrypt", "--file", "-", "--out", "a.yml"]).is_ok());
+        // Without a string, the value is read from stdin
+        assert!(Cli::try_parse_from(["pippo", "encrypt"]).is_ok());
+        assert!(Cli::try_parse_from(["pippo", "decrypt", "-"]).is_ok());
+        assert!(Cli::try_parse_from(["pippo", "encrypt", "secret", "--file", "a.yml"]).is_err());
+        assert!(Cli::try_parse_from(["pippo", "encrypt", "-", "--file", "a.yml"]).is_err());
+        assert!(Cli::try_parse_from(["pippo", "encrypt", "secret", "--out", "a.yml"]).is_err());
+        assert!(Cli::try_parse_from(["pippo", "encrypt", "--file", "a.yml", "--force"]).is_err());
+    }
+
+    #[test]
+    fn recipients_conflict_with_cipher() {
+        let recipients = "age1ql3z7hjy54pw3hyww5ayyfg7zqgvc7w3j2elw8zmrj2kg5sfn9aqmcac8p";
+        assert!(Cli::try_parse_from(["pippo", "encrypt", "--recipients", recipients]).is_ok());
+        assert!(Cli::try_parse_from(["pippo", "encrypt", "--cipher", "cbc"]).is_ok());
+        assert!(Cli::try_parse_from([
+            "pippo",
+            "encrypt",
+            "--cipher",
+            "cbc",
+            "--recipients",
+            recipients
+        ])
+        .is_err());
+        assert!(Cli::try_parse_from(["pippo", "encrypt", "--cipher", "age"]).is_err());
+    }
+
+    #[test]
+    fn dump_request_level_is_optional() {
+        let level = |args: &[&str]| Cli::try_parse_from(args).unwrap().dump_request;
+        assert_eq!(level(&["pippo", "env", "list"]), None);
+        assert_eq!(
+            level(&["pippo", "--dump-request", "env", "list"]).as_deref(),
+            Some("info")
+        );
+        assert_eq!(
+            level(&["pippo", "--dump-request=debug", "env", "list"]).as_deref(),
+            Some("debug")
+        );
+        assert!(Cli::try_parse_from(["pippo", "--dump-request=loud", "env", "list"]).is_err());
+    }
+
+    #[test]
+    fn rotate_key_is_a_subcommand_of_encrypt() {
+        let cli = Cli::try_parse_from([
+            "pippo",
+            "encrypt",
+            "rotate-key",
+            "vars.yml",
+            "--new-key-env",
+            "NEW_KEY",
+        ])
+        .unwrap();
+        match cli.command {
+            Some(Commands::Encrypt {
+                encrypt_command:
+                    Some(EncryptCommands::RotateKey {
+                        input,
+                        output,
+                        new_key_env,
+                        ..
+                    }),
+                ..
+            }) => {
+                assert_eq!(input, "vars.yml");
+                assert_eq!(output, None);
+                assert_eq!(new_key_env.as_deref(), Some("NEW_KEY"));
+            }
+            _ => panic!("rotate-key wasn't parsed"),
+        }
+        assert!(Cli::try_parse_from(["pippo", "encrypt", "rotate-key", "vars.yml"]).is_err());
+        assert!(Cli::try_parse_from(["pippo", "encrypt", "hello world"]).is_ok());
+    }
+
+    #[test]
+    fn rotate_key_with_an_output_path() {
+        // The output path must not clash with the global --output
+        let cli = Cli::try_parse_from([
+            "pippo",
+            "-o",
+            "json",
+            "encrypt",
+            "rotate-key",
+            "in.yml",
+            "out.yml",
+            "--new-key-env",
+            "NEW_KEY",
+        ])
+        .unwrap();
+        assert_eq!(cli.output, Some(OutputFormat::Json));
+        match cli.command {
+            Some(Commands::Encrypt {
+                encrypt_command: Some(EncryptCommands::RotateKey { input, output, .. }),
+                ..
+            }) => {
+                assert_eq!(input, "in.yml");
+                assert_eq!(output.as_deref(), Some("out.yml"));
+            }
+            _ => panic!("rotate-key wasn't parsed"),
+        }
+    }
+
+    #[test]
+    fn migrate_with_an_output_path() {
+        let cli =
+            Cli::try_parse_from(["pippo", "encrypt", "migrate", "in.yml", "out.yml"]).unwrap();
+        assert_eq!(cli.output, None);
+        match cli.command {
+            Some(Commands::Encrypt {
+                encrypt_command: Some(EncryptCommands::Migrate { input, output }),
+                ..
+            }) => {
+                assert_eq!(input, "in.yml");
+                assert_eq!(output.as_deref(), Some("out.yml"));
+            }
+            _ => panic!("migrate wasn't parsed"),
+        }
+    }
+}
@@ -1,12 +1,21 @@
+use crate::keyring::{self, read_secret, resolve_secret};
+use crate::models::environment::EnvironmentId;
+use chrono::{DateTime, Utc};
+use colored::Colorize;
 use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
 use std::fs;
+use std::path::{Path, PathBuf};
 use strum_macros::{EnumString, IntoStaticStr};
 
 /// Model for a Cloud Manager connection configuration
-#[derive(Debug, Deserialize)]
+#[derive(Clone, Debug, Deserialize)]
 pub struct CloudManagerConfig {
     #[serde(skip_deserializing)]
     pub access_token: String,
+    /// When the access token expires, if IMS told
+    #[serde(skip_deserializing)]
+    pub access_token_expires_at: Option<DateTime<Utc>>,
     pub client_id: String,
     pub client_secret: String,
     #[serde(skip_deserializing)]
@@ -18,23 +27,59 @@ pub struct CloudManagerConfig {
     pub scope: Scope,
     #[serde(default = "default_auth")]
     pub auth_strategy: AuthStrategy,
+    #[serde(default = "default_retry_budget")]
+    pub retry_budget: u32,
+    #[serde(default)]
+    pub extra_headers: HashMap<String, String>,
+    pub pool_max_idle_per_host: Option<usize>,
+    pub pool_idle_timeout_secs: Option<u64>,
+    pub tcp_keepalive_secs: Option<u64>,
+    /// Proxy that all requests are sent through, e.g. `http://proxy.example.com:3128`
+    pub proxy: Option<String>,
+    pub connect_timeout_secs: Option<u64>,
+    pub read_timeout_secs: Option<u64>,
+    /// Lowest TLS version that connections may use, `1.2` or `1.3`. `1.3` switches from the system
+    /// TLS library to rustls, which is the only backend that can enforce it.
+    pub min_tls_version: Option<String>,
+    /// PEM file with root certificates that are trusted in addition to the system ones
+    pub ca_bundle: Option<String>,
+    /// File that changing commands are recorded in
+    pub audit_log: Option<String>,
+    /// Send `service: all` for environment variables instead of leaving it to Cloud Manager's default
+    #[serde(default)]
+    pub explicit_service: bool,
+    /// Program, environment and pipeline that commands target if none is given
+    #[serde(default)]
+    pub defaults: TargetDefaults,
+}
+
+/// Targets from the config that are used when neither a flag nor an environment variable gives one
+#[derive(Clone, Debug, Default, Deserialize, PartialEq)]
+pub struct TargetDefaults {
+    pub program_id: Option<u32>,
+    pub environment_id: Option<EnvironmentId>,
+    pub pipeline_id: Option<u32>,
 }
 
 /// Possible types that the AuthStrategy can have
 #[derive(Debug, Clone, Deserialize, Serialize, IntoStaticStr, EnumString, PartialEq)]
 pub enum AuthStrategy {
     #[serde(rename(deserialize = "oauth2", serialize = "oauth2"))]
+    #[strum(serialize = "oauth2")]
     OAuth2,
     #[serde(rename(deserialize = "jwt", serialize = "jwt"))]
-    JWT,
+    #[strum(serialize = "jwt")]
+    Jwt,
 }
 
 /// Possible types that the scope can have
 #[derive(Debug, Clone, Deserialize, Serialize, IntoStaticStr, EnumString, PartialEq)]
 pub enum Scope {
     #[serde(rename(deserialize = "ent_cloudmgr_sdk", serialize = "ent_cloudmgr_sdk"))]
+    #[strum(serialize = "ent_cloudmgr_sdk")]
     EntCloudmgrSdk,
     #[serde(rename(deserialize = "ent_aem_cloud_api", serialize = "ent_aem_cloud_api"))]
+    #[strum(serialize = "ent_aem_cloud_api")]
     EntAemCloudApi,
 }
 
@@ -46,10 +91,18 @@ fn default_scope() -> Scope {
 fn default_auth() -> AuthStrategy {
     AuthStrategy::OAuth2
 }
+/// default amount of retries that may be spent across a whole pippo run
+fn default_retry_budget() -> u32 {
+    10
+}
 
 impl CloudManagerConfig {
     /// Reads a Cloud Manager configuration from a JSON file
     ///
+    /// `client_secret` and `private_key` may reference an OS keyring entry (`keyring:<entry>`)
+    /// instead of holding the secret. Without a keyring service, `PIPPO_CLIENT_SECRET` and
+    /// `PIPPO_PRIVATE_KEY` are used.
+    ///
     /// # Arguments
     ///
     /// * `path` - String slice that holds the path to the JSON config file
@@ -57,9 +110,167 @@ impl CloudManagerConfig {
         let data = fs::read_to_string(path)
             .unwrap_or_else(|_| {
                 eprintln!("[ERROR] Unable to find config at path '{}'. The documentation is available at https://github.com/wcm-io-devops/pippo", path);
-                std::process::exit(1)
+                crate::audit::exit(1)
             });
-        let config: Self = serde_json::from_str(data.as_str()).expect("Invalid JSON format");
+        let mut config: Self = serde_json::from_str(data.as_str()).expect("Invalid JSON format");
+        config.client_secret = resolve_config_secret(
+            "client_secret",
+            &config.client_secret,
+            "PIPPO_CLIENT_SECRET",
+        );
+        config.private_key =
+            resolve_config_secret("private_key", &config.private_key, "PIPPO_PRIVATE_KEY");
         config
     }
 }
+
+/// Config file that is used when none of the discovered ones exists
+pub const DEFAULT_CONFIG_PATH: &str = "./pippo.json";
+
+/// Returns the places where pippo looks for its config, in the order they are tried.
+///
+/// These are `$XDG_CONFIG_HOME/pippo/config.json` (`~/.config/pippo/config.json` if
+/// `XDG_CONFIG_HOME` isn't set), `~/.pippo.json` and `./pippo.json`.
+///
+/// # Arguments
+///
+/// * `var` - Returns the value of an environment variable
+pub fn config_candidates<V>(var: V) -> Vec<PathBuf>
+where
+    V: Fn(&str) -> Option<String>,
+{
+    let non_empty = |name: &str| var(name).filter(|value| !value.is_empty());
+    let home = non_empty("HOME").or_else(|| non_empty("USERPROFILE"));
+    let mut candidates = vec![];
+    match (non_empty("XDG_CONFIG_HOME"), &home) {
+        (Some(xdg), _) => candidates.push(Path::new(&xdg).join("pippo").join("config.json")),
+        (None, Some(home)) => candidates.push(
+            Path::new(home)
+                .join(".config")
+                .join("pippo")
+                .join("config.json"),
+        ),
+        (None, None) => {}
+    }
+    if let Some(home) = &home {
+        candidates.push(Path::new(home).join(".pippo.json"));
+    }
+    candidates.push(PathBuf::from(DEFAULT_CONFIG_PATH));
+    candidates
+}
+
+/// Picks the config file to read.
+///
+/// A path given with `--config` or `PIPPO_CONFIG` always wins. Otherwise the first of
+/// [`config_candidates`] that exists is used, or `./pippo.json` if none does.
+///
+/// # Arguments
+///
+/// * `explicit` - The path given on the command line or in `PIPPO_CONFIG`
+/// * `candidates` - The places to look at, see [`config_candidates`]
+/// * `exists` - Tells whether a file exists
+pub fn discover_config<E>(explicit: Option<&str>, candidates: &[PathBuf], exists: E) -> PathBuf
+where
+    E: Fn(&Path) -> bool,
+{
+    if let Some(path) = explicit {
+        return PathBuf::from(path);
+    }
+    candidates
+        .iter()
+        .find(|candidate| exists(candidate))
+        .cloned()
+        .unwrap_or_else(|| PathBuf::from(DEFAULT_CONFIG_PATH))
+}
+
+/// Resolves a secret of the config file and exits if it can't be read.
+///
+/// # Arguments
+///
+/// * `field` - String slice that holds the name of the config field
+/// * `value` - String slice that holds the value from the config file
+/// * `fallback_var` - Name of the environment variable used when no keyring is available
+fn resolve_config_secret(field: &str, value: &str, fallback_var: &str) -> String {
+    resolve_secret(field, value, std::env::var(fallback_var).ok(), read_secret).unwrap_or_else(
+        |err| {
+            eprintln!("{} {}", "❌".red(), err);
+            if keyring::is_supported() {
+                eprintln!(
+                    "   Store the secret in the keyring or set {} instead.",
+                    fallback_var
+                );
+            } else {
+                eprintln!(
+                    "   Put the secret into the config file instead of '{}'.",
+                    value
+                );
+            }
+            crate::audit::exit(1)
+        },
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn vars(pairs: &[(&str, &str)]) -> impl Fn(&str) -> Option<String> {
+        let pairs: Vec<(String, String)> = pairs
+            .iter()
+            .map(|(k, v)| (k.to_string(), v.to_string()))
+            .collect();
+        move |name| {
+            pairs
+                .iter()
+                .find(|(k, _)| k == name)
+                .map(|(_, v)| v.clone())
+        }
+    }
+
+    #[test]
+    fn config_candidates_in_order() {
+        assert_eq!(
+            config_candidates(vars(&[("XDG_CONFIG_HOME", "/xdg"), ("HOME", "/home/jdoe")])),
+            vec![
+                PathBuf::from("/xdg/pippo/config.json"),
+                PathBuf::from("/home/jdoe/.pippo.json"),
+                PathBuf::from("./pippo.json"),
+            ]
+        );
+        assert_eq!(
+            config_candidates(vars(&[("XDG_CONFIG_HOME", ""), ("HOME", "/home/jdoe")]))[0],
+            PathBuf::from("/home/jdoe/.config/pippo/config.json")
+        );
+        assert_eq!(
+            config_candidates(vars(&[])),
+            vec![PathBuf::from("./pippo.json")]
+        );
+    }
+
+    #[test]
+    fn explicit_config_wins_over_discovery() {
+        let candidates = config_candidates(vars(&[("HOME", "/home/jdoe")]));
+        let everything = |_: &Path| true;
+        assert_eq!(
+            discover_config(Some("other.json"), &candidates, everything),
+            PathBuf::from("other.json")
+        );
+        assert_eq!(
+            discover_config(None, &candidates, everything),
+            PathBuf::from("/home/jdoe/.config/pippo/config.json")
+        );
+    }
+
+    #[test]
+    fn discovery_uses_first_existing_config() {
+        let candidates = config_candidates(vars(&[("HOME", "/home/jdoe")]));
+        assert_eq!(
+            discover_config(None, &candidates, |path| path.ends_with(".pippo.json")),
+            PathBuf::from("/home/jdoe/.pippo.json")
+        );
+        assert_eq!(
+            discover_config(None, &candidates, |_| false),
+            PathBuf::from("./pippo.json")
+        );
+    }
+}
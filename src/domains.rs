@@ -1,12 +1,16 @@
+use crate::audit;
 use crate::client::{AdobeConnector, CloudManagerClient};
 use crate::errors::throw_adobe_api_error;
+use crate::exit_code::ExitCode;
 use crate::models::config::YamlConfig;
-use crate::models::domain::{CreateDomainResponse, DomainList, DomainResponse, MinimumDomain};
-use crate::HOST_NAME;
+use crate::models::domain::{
+    CreateDomainResponse, Domain, DomainList, DomainResponse, MinimumDomain,
+};
+use crate::models::environment::EnvironmentId;
+use crate::programs::read_config;
 extern crate uuid;
 use colored::Colorize;
 use reqwest::{Error, Method, StatusCode};
-use std::process;
 use std::str;
 use uuid::Uuid;
 
@@ -28,7 +32,10 @@ pub async fn get_domains(
     start: &u32,
     limit: &u32,
 ) -> Result<DomainList, Error> {
-    let request_path = format!("{}/api/program/{}/domainNames", HOST_NAME, program_id);
+    let request_path = format!(
+        "{}/api/program/{}/domainNames",
+        client.host_name, program_id
+    );
     let query_start: &str = &start.to_string();
     let query_limit: &str = &limit.to_string();
     let query_parameters = vec![("start", query_start), ("limit", query_limit)];
@@ -39,17 +46,33 @@ pub async fn get_domains(
             None::<()>,
             Some(query_parameters),
         )
-        .await?
-        .text()
         .await?;
+    let response = match client.read_list_response(response).await? {
+        Some(response) => response,
+        None => return Ok(DomainList { list: vec![] }),
+    };
+    client.print_raw_response(&response);
     let domains: DomainResponse = serde_json::from_str(response.as_str()).unwrap_or_else(|_| {
         throw_adobe_api_error(response);
-        process::exit(1);
+        audit::exit(1);
     });
 
     Ok(domains.domain_list)
 }
 
+/// Keeps only the domains that belong to the given environment.
+///
+/// # Arguments
+///
+/// * `domains` - A vector of domains
+/// * `env_id` - An EnvironmentId that holds the environment ID
+pub fn filter_domains_by_environment(domains: Vec<Domain>, env_id: EnvironmentId) -> Vec<Domain> {
+    domains
+        .into_iter()
+        .filter(|domain| domain.environment_id == env_id)
+        .collect()
+}
+
 /// Sets pipeline variables that are read from a given YAML file.
 ///
 /// When the target pipeline is currently updating, the function will retry until its state
@@ -63,15 +86,34 @@ pub async fn create_domains(
     file_path: String,
     client: &mut CloudManagerClient,
 ) -> Result<StatusCode, Error> {
-    let input = std::fs::read_to_string(file_path).expect("Unable to read file");
-    let input: YamlConfig = serde_yaml::from_str(input.as_str()).unwrap_or_else(|err| {
-        eprintln!("{} {}", "❌ Malformed YAML: ".red(), err);
-        process::exit(1);
-    });
+    let input = read_config(client, &file_path).await;
+
+    // Creating the same domain twice only fails halfway through the run, so check the whole file first
+    let duplicates = find_duplicate_domains(&input);
+    if !duplicates.is_empty() {
+        for dup in &duplicates {
+            eprintln!(
+                "{:>4} {}  '{}' in {}",
+                "❌".red(),
+                "ERROR, duplicate domain definition found, please check your file!".red(),
+                dup.name,
+                dup.locations
+                    .iter()
+                    .map(|(program_id, env_id)| format!(
+                        "program {} / environment {}",
+                        program_id, env_id
+                    ))
+                    .collect::<Vec<_>>()
+                    .join(", ")
+            );
+        }
+        ExitCode::Duplicate.exit();
+    }
+
     let mut ret_value = 0;
     let programs: Vec<crate::models::config::ProgramsConfig> = input.programs;
     for d in &programs {
-        println!("☁ Program: {}", d.id,);
+        println!("☁ Program: {}", d.program_id(),);
         if let Some(environments_vec) = &d.environments {
             for e in environments_vec {
                 if let Some(domain_vec) = &e.domains {
@@ -82,15 +124,17 @@ pub async fn create_domains(
                             name: dom.domainname.clone(),
                             dns_txt_record: generate_txt_record(
                                 dom.domainname.clone(),
-                                d.id,
-                                e.id.into(),
+                                d.program_id(),
+                                e.id,
                             ),
-                            certificate_id: dom.certificate_id.clone(),
-                            environment_id: e.id.into(),
+                            certificate_id: dom.certificate_id,
+                            environment_id: e.id,
                             dns_zone: String::from("adobe.com."),
                         };
 
-                        match create_singledomain(client, d.id, domain_to_be_created).await {
+                        match create_singledomain(client, d.program_id(), domain_to_be_created)
+                            .await
+                        {
                             Ok(status) => match status {
                                 StatusCode::OK => {
                                     println!("{:>8} Success", "✔");
@@ -101,12 +145,12 @@ pub async fn create_domains(
                                         "Warning, check output above".yellow(),
                                         "⚠".yellow()
                                     );
-                                    ret_value = ret_value + 1;
+                                    ret_value += 1;
                                 }
                             },
                             Err(error) => {
                                 eprintln!("{} {}", "❌ API error: ".red().bold(), error);
-                                process::exit(1);
+                                ExitCode::Failure.exit();
                             }
                         }
                     }
@@ -121,12 +165,52 @@ pub async fn create_domains(
     }
 }
 
+/// A domain name that is defined more than once in the configuration YAML
+#[derive(Debug, PartialEq)]
+pub struct DuplicateDomain {
+    pub name: String,
+    /// Program and environment of every definition, in file order
+    pub locations: Vec<(u32, EnvironmentId)>,
+}
+
+/// Finds domain names that are defined more than once across all programs and environments.
+///
+/// Domain names are compared case-insensitively, like DNS does.
+///
+/// # Arguments
+///
+/// * `config` - The configuration YAML
+pub fn find_duplicate_domains(config: &YamlConfig) -> Vec<DuplicateDomain> {
+    let mut definitions: Vec<DuplicateDomain> = vec![];
+    for p in &config.programs {
+        for e in p.environments.iter().flatten() {
+            for dom in e.domains.iter().flatten() {
+                match definitions
+                    .iter_mut()
+                    .find(|d| d.name.eq_ignore_ascii_case(&dom.domainname))
+                {
+                    Some(definition) => definition.locations.push((p.program_id(), e.id)),
+                    None => definitions.push(DuplicateDomain {
+                        name: dom.domainname.clone(),
+                        locations: vec![(p.program_id(), e.id)],
+                    }),
+                }
+            }
+        }
+    }
+    definitions.retain(|d| d.locations.len() > 1);
+    definitions
+}
+
 async fn create_singledomain(
     client: &mut CloudManagerClient,
     program_id: u32,
     domain: &MinimumDomain,
 ) -> Result<StatusCode, Error> {
-    let request_path = format!("{}/api/program/{}/domainNames", HOST_NAME, program_id);
+    let request_path = format!(
+        "{}/api/program/{}/domainNames",
+        client.host_name, program_id
+    );
 
     let response = client
         .perform_request(Method::POST, request_path, Some(domain), None)
@@ -137,7 +221,7 @@ async fn create_singledomain(
         let create_domain_response: CreateDomainResponse =
             serde_json::from_str(response_text.as_str()).unwrap_or_else(|_| {
                 throw_adobe_api_error(response_text.clone());
-                process::exit(1);
+                audit::exit(1);
             });
         if let Some(error_vec) = &create_domain_response.errors {
             for error in error_vec {
@@ -161,10 +245,10 @@ async fn create_singledomain(
 ///
 /// * `domain` - String of the domain
 /// * `program_id` - A u32 that holds the program ID
-/// * `env_id` - A i64 that holds the environment ID
+/// * `env_id` - An EnvironmentId that holds the environment ID
 /// * returns a String
 /// ```
-fn generate_txt_record(domain: String, program_id: u32, env_id: i64) -> String {
+fn generate_txt_record(domain: String, program_id: u32, env_id: EnvironmentId) -> String {
     let uuid = Uuid::new_v4();
     // adobe-aem-verification=<domain-name>/<program-id>/<environment-id>/<random-8-4-4-4-12-guid>\
     let txt_record = format!(
@@ -174,5 +258,63 @@ fn generate_txt_record(domain: String, program_id: u32, env_id: i64) -> String {
         &env_id,
         &uuid.hyphenated().to_string(),
     );
-    return txt_record;
+    txt_record
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::models::tests::{read_json_from_file, read_yaml_from_file};
+    use std::str::FromStr;
+
+    #[test]
+    fn find_duplicate_domains_across_programs_and_environments() {
+        let config: YamlConfig = read_yaml_from_file("test/test_yaml_config_domains.yml").unwrap();
+        assert_eq!(
+            find_duplicate_domains(&config),
+            vec![
+                DuplicateDomain {
+                    name: String::from("www.example.com"),
+                    locations: vec![
+                        (222222, EnvironmentId(333333)),
+                        (222222, EnvironmentId(444444))
+                    ],
+                },
+                DuplicateDomain {
+                    name: String::from("shop.example.com"),
+                    locations: vec![
+                        (222222, EnvironmentId(333333)),
+                        (555555, EnvironmentId(666666))
+                    ],
+                },
+            ]
+        );
+    }
+
+    #[test]
+    fn find_no_duplicate_domains() {
+        let config: YamlConfig = read_yaml_from_file("test/test_yaml_config.yml").unwrap();
+        assert!(find_duplicate_domains(&config).is_empty());
+    }
+
+    #[test]
+    fn filter_domains_by_environment_id() {
+        let response: DomainResponse =
+            read_json_from_file("test/test_domain_response.json").unwrap();
+        let total = response.domain_list.list.len();
+        // The domain API returns numeric environment IDs, the CLI passes them as string
+        let env_id = EnvironmentId::from_str("54321").unwrap();
+
+        let filtered = filter_domains_by_environment(response.domain_list.list, env_id);
+        assert_eq!(filtered.len(), total);
+    }
+
+    #[test]
+    fn filter_domains_by_unknown_environment_id() {
+        let response: DomainResponse =
+            read_json_from_file("test/test_domain_response.json").unwrap();
+        assert!(
+            filter_domains_by_environment(response.domain_list.list, EnvironmentId(1)).is_empty()
+        );
+    }
 }
@@ -1,58 +1,2087 @@
-use magic_crypt::{new_magic_crypt, MagicCryptTrait};
+use crate::audit;
+use crate::models::config::{YamlConfig, STDIN_PATH};
+use crate::models::variables::VariableType;
+use aes_gcm::aead::{Aead, AeadCore, OsRng, Payload};
+use aes_gcm::{Aes256Gcm, Key, KeyInit, Nonce};
+use age::x25519;
+use base64::prelude::*;
+use hkdf::Hkdf;
+use magic_crypt::{new_magic_crypt, MagicCryptError, MagicCryptTrait};
+use regex::Regex;
+use sha2::Sha256;
+use std::collections::BTreeMap;
+use std::fmt;
+use std::fs::OpenOptions;
+use std::io::{self, IsTerminal, Read, Write};
+use std::path::Path;
+use std::str::FromStr;
+use std::sync::OnceLock;
 use std::{env, process};
+use strum_macros::EnumString;
 
-/// Reads the encryption key either from `PIPPO_CRYPTKEY` environment variable or from the `./.cryptkey` file.
-fn provide_secret_key() -> String {
-    // Read secret key from PIPPO_CRYPTKEY environment variable; if not provided, use .cryptkey file
-    match env::var("PIPPO_CRYPTKEY") {
-        Ok(key_from_envvar) => key_from_envvar,
-        Err(_) => {
-            match std::fs::read_to_string(".cryptkey") {
-                Ok(key_from_file) => key_from_file.trim_end().to_string(),
-                Err(_) => {
-                    eprintln!("❌ PIPPO_CRYPTKEY not set and .cryptkey file not found. Can't do any crypto!");
-                    process::exit(1);
+/// File with the default key, used if neither `PIPPO_CRYPTKEY` nor a key file is given
+pub const DEFAULT_KEY_FILE: &str = ".cryptkey";
+
+/// Environment variable that holds the path of the key file, see `--cryptkey-file`
+pub const KEY_FILE_ENV: &str = "PIPPO_CRYPTKEY_FILE";
+
+/// Key file given with `--cryptkey-file` or `PIPPO_CRYPTKEY_FILE`, set once at startup
+static KEY_FILE: OnceLock<String> = OnceLock::new();
+
+/// Makes [`read_secret_key`] read the default key from this file instead of `./.cryptkey`.
+///
+/// # Arguments
+///
+/// * `path` - String slice that holds the path to the key file
+pub fn set_key_file(path: &str) {
+    let _ = KEY_FILE.set(path.to_string());
+}
+
+/// Reads the default encryption key from the `PIPPO_CRYPTKEY` environment variable, the key file
+/// set with [`set_key_file`] or the `./.cryptkey` file, in this order.
+pub fn read_secret_key() -> Result<String, DecryptError> {
+    find_secret_key(
+        |name| env::var(name).ok(),
+        |path| std::fs::read_to_string(path).ok(),
+        KEY_FILE.get().map(String::as_str),
+    )
+}
+
+/// Looks up the default encryption key. A given key file replaces `./.cryptkey`, so a missing
+/// key file is never silently replaced by another key.
+///
+/// # Arguments
+///
+/// * `env_var` - Returns the value of an environment variable
+/// * `read_file` - Returns the content of a file
+/// * `key_file` - Path of the key file given with `--cryptkey-file`
+pub fn find_secret_key<E, F>(
+    env_var: E,
+    read_file: F,
+    key_file: Option<&str>,
+) -> Result<String, DecryptError>
+where
+    E: Fn(&str) -> Option<String>,
+    F: Fn(&str) -> Option<String>,
+{
+    if let Some(key) = env_var("PIPPO_CRYPTKEY") {
+        return Ok(key);
+    }
+    let path = key_file.unwrap_or(DEFAULT_KEY_FILE);
+    read_file(path)
+        .map(|key| key.trim_end().to_string())
+        .ok_or_else(|| {
+            DecryptError::MissingKey(vec![String::from("PIPPO_CRYPTKEY"), path.to_string()])
+        })
+}
+
+/// Reads the key that `encrypt rotate-key` re-encrypts values with, from an environment variable
+/// or a file.
+///
+/// # Arguments
+///
+/// * `env_var` - Name of the environment variable that holds the new key
+/// * `file` - Path of the file that holds the new key
+pub fn read_new_key(env_var: Option<&str>, file: Option<&str>) -> Result<String, String> {
+    let key = match (env_var, file) {
+        (Some(name), _) => env::var(name).map_err(|_| format!("{} is not set", name))?,
+        (None, Some(path)) => std::fs::read_to_string(path)
+            .map_err(|err| format!("Unable to read {}: {}", path, err))?
+            .trim_end()
+            .to_string(),
+        (None, None) => return Err(String::from("no new key given")),
+    };
+    if key.is_empty() {
+        return Err(String::from("the new key is empty"));
+    }
+    Ok(key)
+}
+
+/// File with the keys that are referenced by a key id, a YAML map from key id to key
+pub const KEYS_FILE: &str = ".cryptkeys";
+
+/// Returns the environment variable that holds the key with the given id, e.g.
+/// `PIPPO_CRYPTKEY_V2` for `v2`.
+///
+/// # Arguments
+///
+/// * `key_id` - Id of the key
+pub fn key_env_var(key_id: &str) -> String {
+    format!(
+        "PIPPO_CRYPTKEY_{}",
+        key_id.to_ascii_uppercase().replace('-', "_")
+    )
+}
+
+/// Checks that a key id only consists of letters, digits, `-` and `_`, so it can be written after
+/// `$enc:` and be part of an environment variable name.
+///
+/// # Arguments
+///
+/// * `key_id` - Id of the key
+pub fn parse_key_id(key_id: &str) -> Result<String, String> {
+    if key_id == GCM1_TAG || key_id == AGE_TAG {
+        return Err(format!(
+            "'{}' names the encryption format and can't be a key id",
+            key_id
+        ));
+    }
+    if key_env_var(key_id) == KEY_FILE_ENV {
+        return Err(format!(
+            "'{}' can't be a key id, {} names the key file",
+            key_id, KEY_FILE_ENV
+        ));
+    }
+    if !key_id.is_empty()
+        && key_id
+            .chars()
+            .all(|c| c.is_ascii_alphanumeric() || c == '-' || c == '_')
+    {
+        Ok(key_id.to_string())
+    } else {
+        Err(format!(
+            "invalid key id '{}', only letters, digits, - and _ are allowed",
+            key_id
+        ))
+    }
+}
+
+/// Parses the content of a [`KEYS_FILE`].
+///
+/// # Arguments
+///
+/// * `input` - String slice that holds the YAML map from key id to key
+pub fn parse_keys(input: &str) -> Result<BTreeMap<String, String>, String> {
+    if input.trim().is_empty() {
+        return Ok(BTreeMap::new());
+    }
+    serde_yaml::from_str(input).map_err(|err| format!("{} is malformed: {}", KEYS_FILE, err))
+}
+
+/// Reads the key with the given id, or the default key if there is no id.
+///
+/// The default key comes from `PIPPO_CRYPTKEY` or a key file, see [`read_secret_key`]. Other
+/// keys come from their environment variable, see [`key_env_var`], or from `./.cryptkeys`.
+///
+/// # Arguments
+///
+/// * `key_id` - Id of the key, `None` for the default key
+pub fn read_key(key_id: Option<&str>) -> Result<String, DecryptError> {
+    let key_id = match key_id {
+        Some(key_id) => key_id,
+        None => return read_secret_key(),
+    };
+    if let Ok(key) = env::var(key_env_var(key_id)) {
+        return Ok(key);
+    }
+    let keys = match std::fs::read_to_string(KEYS_FILE) {
+        Ok(input) => parse_keys(&input).map_err(DecryptError::InvalidKeysFile)?,
+        Err(_) => BTreeMap::new(),
+    };
+    keys.get(key_id)
+        .cloned()
+        .ok_or_else(|| DecryptError::UnknownKeyId(key_id.to_string()))
+}
+
+/// Like [`read_key`], but exits if the key is not available.
+fn provide_key(key_id: Option<&str>) -> String {
+    read_key(key_id).unwrap_or_else(|err| {
+        eprintln!("❌ {}. Can't do any crypto!", err);
+        audit::exit(1);
+    })
+}
+
+/// Encrypts a string and returns the ciphertext, see [`EncryptedValue::ciphertext`]
+///
+/// # Arguments
+///
+///  * `input` - The string you want to encrypt
+///  * `key_id` - Id of the key to encrypt with, `None` for the default key
+///  * `cipher` - How the string is encrypted
+pub fn encrypt(input: &str, key_id: Option<&str>, cipher: KeyCipher) -> String {
+    let key = provide_key(key_id);
+    match cipher {
+        KeyCipher::Cbc => encrypt_with_key(&key, input),
+        KeyCipher::Gcm1 => encrypt_gcm_with_key(&key, key_id, input.as_bytes()),
+    }
+}
+
+/// Encrypts bytes with the given key and cipher and returns the ciphertext. The key id is only
+/// sealed into [`Cipher::Gcm1`] values.
+fn encrypt_with(key: &str, key_id: Option<&str>, cipher: KeyCipher, input: &[u8]) -> String {
+    match cipher {
+        KeyCipher::Cbc => encrypt_bytes_with_key(key, input),
+        KeyCipher::Gcm1 => encrypt_gcm_with_key(key, key_id, input),
+    }
+}
+
+/// Decrypts the ciphertext of a value with the given key and returns the original bytes.
+fn decrypt_with(key: &str, value: &EncryptedValue) -> Result<Vec<u8>, DecryptError> {
+    match value.cipher {
+        Cipher::Cbc => decrypt_bytes_with_key(key, value.ciphertext),
+        Cipher::Gcm1 => decrypt_gcm_with_key(key, value.key_id, value.ciphertext),
+        // Not encrypted with a key at all, see decrypt_locally
+        Cipher::Age => Err(DecryptError::WrongKey),
+    }
+}
+
+/// Decrypts a value with the local key or, for [`Cipher::Age`], the local identities and returns
+/// the original bytes.
+fn decrypt_locally(value: &EncryptedValue) -> Result<Vec<u8>, DecryptError> {
+    match value.cipher {
+        Cipher::Age => decrypt_age(&read_identities()?, value.ciphertext),
+        _ => read_key(value.key_id).and_then(|key| decrypt_with(&key, value)),
+    }
+}
+
+/// Encrypts a string with the given key and returns base64
+///
+/// # Arguments
+///
+/// * `key` - The encryption key
+/// * `input` - The string you want to encrypt
+pub fn encrypt_with_key(key: &str, input: &str) -> String {
+    let magic_crypt = new_magic_crypt!(key, 256);
+    magic_crypt.encrypt_str_to_base64(input)
+}
+
+/// Prefix of the encrypted values of a YAML file
+pub const ENCRYPTED_PREFIX: &str = "$enc";
+
+/// Marks values that are encrypted with [`Cipher::Gcm1`], e.g. `$enc:gcm1 <ciphertext>`
+pub const GCM1_TAG: &str = "gcm1";
+
+/// Marks values that are encrypted with [`Cipher::Age`], e.g. `$enc:age <ciphertext>`
+pub const AGE_TAG: &str = "age";
+
+/// How values are encrypted
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Cipher {
+    /// AES-256-CBC of magic-crypt, the ciphertext is plain base64. A modified value can't be
+    /// detected and may decrypt to garbage.
+    Cbc,
+    /// AES-256-GCM, the ciphertext is `<nonce>.<encrypted value>.<tag>` in base64. A modified
+    /// value fails to decrypt.
+    Gcm1,
+    /// age with X25519 recipients, the ciphertext is the binary age file in base64. Only the
+    /// identities of the recipients can decrypt it, see `encrypt --recipients`.
+    Age,
+}
+
+impl Cipher {
+    /// The cipher that encrypts values of this kind with a key, `None` for [`Cipher::Age`]
+    pub fn key_cipher(self) -> Option<KeyCipher> {
+        match self {
+            Cipher::Cbc => Some(KeyCipher::Cbc),
+            Cipher::Gcm1 => Some(KeyCipher::Gcm1),
+            Cipher::Age => None,
+        }
+    }
+}
+
+/// The ciphers that encrypt values with a key, i.e. every [`Cipher`] but age
+#[derive(Clone, Copy, Debug, PartialEq, EnumString)]
+#[strum(serialize_all = "lowercase")]
+pub enum KeyCipher {
+    Cbc,
+    Gcm1,
+}
+
+impl From<KeyCipher> for Cipher {
+    fn from(cipher: KeyCipher) -> Self {
+        match cipher {
+            KeyCipher::Cbc => Cipher::Cbc,
+            KeyCipher::Gcm1 => Cipher::Gcm1,
+        }
+    }
+}
+
+/// An encrypted value of a YAML file, `$enc[:gcm1][:<key id>] <ciphertext>` or
+/// `$enc:age <ciphertext>`.
+///
+/// Without `gcm1` the value is encrypted with [`Cipher::Cbc`], without a key id with the default
+/// key, so `$enc <base64>` keeps its meaning. `age` values are not encrypted with a key, so they
+/// never have a key id.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct EncryptedValue<'a> {
+    /// How the value is encrypted
+    pub cipher: Cipher,
+    /// Id of the key the value is encrypted with, `None` for the default key
+    pub key_id: Option<&'a str>,
+    /// The encrypted value, its form depends on the cipher
+    pub ciphertext: &'a str,
+}
+
+impl<'a> EncryptedValue<'a> {
+    /// Whether a value of a YAML file is meant to be encrypted
+    pub fn is_encrypted(value: &str) -> bool {
+        value.starts_with(ENCRYPTED_PREFIX)
+    }
+
+    /// Parses an encrypted value of a YAML file.
+    ///
+    /// # Arguments
+    ///
+    /// * `value` - String slice that holds the value, e.g. `$enc:gcm1:v2 <ciphertext>`
+    pub fn parse(value: &'a str) -> Result<Self, String> {
+        let malformed = || {
+            String::from(
+                "encrypted value must have the form '$enc[:gcm1][:<key id>] <ciphertext>' or '$enc:age <ciphertext>'",
+            )
+        };
+        let mut parts = value.split_whitespace();
+        let (prefix, ciphertext) = match (parts.next(), parts.next(), parts.next()) {
+            (Some(prefix), Some(ciphertext), None) => (prefix, ciphertext),
+            _ => return Err(malformed()),
+        };
+        let mut segments: Vec<&str> = match prefix.strip_prefix(ENCRYPTED_PREFIX) {
+            Some("") => vec![],
+            Some(rest) => match rest.strip_prefix(':') {
+                Some(rest) => rest.split(':').collect(),
+                None => return Err(malformed()),
+            },
+            None => return Err(malformed()),
+        };
+        let cipher = match segments.first() {
+            Some(&GCM1_TAG) => Cipher::Gcm1,
+            Some(&AGE_TAG) => Cipher::Age,
+            _ => Cipher::Cbc,
+        };
+        if cipher != Cipher::Cbc {
+            segments.remove(0);
+        }
+        let key_id = match segments[..] {
+            [] => None,
+            [key_id] if cipher != Cipher::Age && parse_key_id(key_id).is_ok() => Some(key_id),
+            _ => return Err(malformed()),
+        };
+        Ok(EncryptedValue {
+            cipher,
+            key_id,
+            ciphertext,
+        })
+    }
+
+    /// Parses a value given on the command line, which is either a whole encrypted value or only
+    /// the base64 of a value that is encrypted with [`Cipher::Cbc`] and the default key.
+    ///
+    /// # Arguments
+    ///
+    /// * `input` - String slice that holds the value
+    pub fn from_input(input: &'a str) -> Result<Self, String> {
+        if EncryptedValue::is_encrypted(input) {
+            EncryptedValue::parse(input)
+        } else {
+            Ok(EncryptedValue {
+                cipher: Cipher::Cbc,
+                key_id: None,
+                ciphertext: input,
+            })
+        }
+    }
+}
+
+impl fmt::Display for EncryptedValue<'_> {
+    fn fmt(&self, formatter: &mut fmt::Formatter) -> fmt::Result {
+        write!(formatter, "{}", ENCRYPTED_PREFIX)?;
+        match self.cipher {
+            Cipher::Cbc => {}
+            Cipher::Gcm1 => write!(formatter, ":{}", GCM1_TAG)?,
+            Cipher::Age => write!(formatter, ":{}", AGE_TAG)?,
+        }
+        if let Some(key_id) = self.key_id {
+            write!(formatter, ":{}", key_id)?;
+        }
+        write!(formatter, " {}", self.ciphertext)
+    }
+}
+
+/// Error that occurs when an encrypted value can't be decrypted
+#[derive(Debug, PartialEq)]
+pub enum DecryptError {
+    /// None of the sources of the default key is available, holds the sources that were tried
+    MissingKey(Vec<String>),
+    /// Neither the environment variable nor `./.cryptkeys` has a key with this id
+    UnknownKeyId(String),
+    /// `./.cryptkeys` can't be parsed
+    InvalidKeysFile(String),
+    /// The value isn't valid base64, e.g. because it was cut off when it was copied
+    InvalidBase64(String),
+    /// The value wasn't encrypted with this key
+    WrongKey,
+    /// The value was modified after it was encrypted, or encrypted with another key
+    Tampered,
+    /// Neither `PIPPO_AGE_IDENTITY` nor an identity file is available to decrypt an age value
+    MissingIdentity,
+    /// The age identity can't be parsed
+    InvalidIdentity(String),
+    /// The age value wasn't encrypted for any of the local identities
+    NoMatchingIdentity,
+    /// The decrypted value isn't UTF-8 text, e.g. because it holds a whole binary file
+    NotText,
+}
+
+impl fmt::Display for DecryptError {
+    fn fmt(&self, formatter: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            DecryptError::MissingKey(sources) => {
+                write!(formatter, "no key found, tried {}", sources.join(", "))
+            }
+            DecryptError::UnknownKeyId(key_id) => write!(
+                formatter,
+                "key '{}' not found, set {} or add it to {}",
+                key_id,
+                key_env_var(key_id),
+                KEYS_FILE
+            ),
+            DecryptError::InvalidKeysFile(err) => write!(formatter, "{}", err),
+            DecryptError::InvalidBase64(err) => {
+                write!(formatter, "the value is not valid base64 ({})", err)
+            }
+            DecryptError::WrongKey => write!(
+                formatter,
+                "the value was not encrypted with this key - wrong key?"
+            ),
+            DecryptError::Tampered => write!(
+                formatter,
+                "the value was modified after it was encrypted or was not encrypted with this key"
+            ),
+            DecryptError::MissingIdentity => write!(
+                formatter,
+                "no age identity found, set {} or use --identity-file",
+                AGE_IDENTITY_ENV
+            ),
+            DecryptError::InvalidIdentity(err) => write!(formatter, "{}", err),
+            DecryptError::NoMatchingIdentity => write!(
+                formatter,
+                "the value was not encrypted for this identity - wrong identity?"
+            ),
+            DecryptError::NotText => write!(
+                formatter,
+                "the decrypted value is not UTF-8 text, decrypt binary files with --file"
+            ),
+        }
+    }
+}
+
+impl From<MagicCryptError> for DecryptError {
+    fn from(err: MagicCryptError) -> Self {
+        match err {
+            MagicCryptError::Base64Error(err) => DecryptError::InvalidBase64(err.to_string()),
+            _ => DecryptError::WrongKey,
+        }
+    }
+}
+
+/// Decrypts an encrypted value with the key that it names and returns it
+///
+/// Fails if the key is not available or the value wasn't encrypted with it.
+///
+/// # Arguments
+///
+/// * `value` The value you want to decrypt
+pub fn decrypt(value: &EncryptedValue) -> Result<String, DecryptError> {
+    match value.cipher {
+        Cipher::Cbc => decrypt_with_key(&read_key(value.key_id)?, value.ciphertext),
+        _ => String::from_utf8(decrypt_locally(value)?).map_err(|_| DecryptError::NotText),
+    }
+}
+
+/// Decrypts a string with the given key, failing instead of panicking if the key doesn't fit.
+///
+/// Surrounding whitespace and line breaks, which values copied from files often carry, are
+/// ignored.
+///
+/// # Arguments
+///
+/// * `key` - The encryption key
+/// * `input` - The base64 string you want to decrypt
+pub fn decrypt_with_key(key: &str, input: &str) -> Result<String, DecryptError> {
+    let magic_crypt = new_magic_crypt!(key, 256);
+    Ok(magic_crypt.decrypt_base64_to_string(input.trim())?)
+}
+
+/// Encrypts bytes with the given key and returns base64
+///
+/// # Arguments
+///
+/// * `key` - The encryption key
+/// * `input` - The bytes you want to encrypt
+pub fn encrypt_bytes_with_key(key: &str, input: &[u8]) -> String {
+    let magic_crypt = new_magic_crypt!(key, 256);
+    magic_crypt.encrypt_bytes_to_base64(input)
+}
+
+/// Decrypts base64 with the given key and returns the original bytes. Surrounding whitespace,
+/// e.g. the line break at the end of an encrypted file, is ignored.
+///
+/// # Arguments
+///
+/// * `key` - The encryption key
+/// * `input` - The base64 string you want to decrypt
+pub fn decrypt_bytes_with_key(key: &str, input: &str) -> Result<Vec<u8>, DecryptError> {
+    let magic_crypt = new_magic_crypt!(key, 256);
+    Ok(magic_crypt.decrypt_base64_to_bytes(input.trim())?)
+}
+
+/// Derives the AES-256 key of [`Cipher::Gcm1`] from a key as it is configured, with HKDF-SHA256.
+///
+/// HKDF spreads the key over the 256 bits and keeps it apart from the cbc key, but doesn't
+/// stretch it, so the configured key itself has to be random, e.g. `openssl rand -base64 32`.
+fn gcm_key(key: &str) -> Key<Aes256Gcm> {
+    let mut derived = Key::<Aes256Gcm>::default();
+    Hkdf::<Sha256>::new(None, key.as_bytes())
+        .expand(b"pippo $enc:gcm1", &mut derived)
+        .expect("32 bytes are a valid HKDF-SHA256 output length");
+    derived
+}
+
+/// Returns the associated data that a [`Cipher::Gcm1`] value is sealed with: the tag and the key
+/// id as they are written after `$enc`, e.g. `gcm1:v2`. Moving a value to another key id fails
+/// to decrypt instead of being read with the wrong key.
+fn gcm_aad(key_id: Option<&str>) -> String {
+    match key_id {
+        Some(key_id) => format!("{}:{}", GCM1_TAG, key_id),
+        None => GCM1_TAG.to_string(),
+    }
+}
+
+/// Encrypts bytes with AES-256-GCM and a random nonce and returns `<nonce>.<encrypted bytes>.<tag>`
+/// in base64.
+///
+/// # Arguments
+///
+/// * `key` - The encryption key
+/// * `key_id` - Id of the key that is written after `$enc:gcm1`, `None` for the default key
+/// * `input` - The bytes you want to encrypt
+pub fn encrypt_gcm_with_key(key: &str, key_id: Option<&str>, input: &[u8]) -> String {
+    let cipher = Aes256Gcm::new(&gcm_key(key));
+    let nonce = Aes256Gcm::generate_nonce(&mut OsRng);
+    let aad = gcm_aad(key_id);
+    let payload = Payload {
+        msg: input,
+        aad: aad.as_bytes(),
+    };
+    // Only fails for inputs of many gigabytes
+    let sealed = cipher
+        .encrypt(&nonce, payload)
+        .expect("Unable to encrypt with AES-GCM");
+    let (encrypted, tag) = sealed.split_at(sealed.len() - GCM_TAG_LENGTH);
+    [nonce.as_slice(), encrypted, tag]
+        .iter()
+        .map(|part| BASE64_STANDARD.encode(part))
+        .collect::<Vec<_>>()
+        .join(".")
+}
+
+/// Length of the authentication tag of AES-GCM in bytes
+const GCM_TAG_LENGTH: usize = 16;
+
+/// Length of the nonce of AES-GCM in bytes
+const GCM_NONCE_LENGTH: usize = 12;
+
+/// Decrypts the output of [`encrypt_gcm_with_key`] and returns the original bytes, failing if it
+/// was modified. Surrounding whitespace is ignored.
+///
+/// # Arguments
+///
+/// * `key` - The encryption key
+/// * `key_id` - Id of the key that is written after `$enc:gcm1`, `None` for the default key
+/// * `input` - String slice that holds `<nonce>.<encrypted bytes>.<tag>`
+pub fn decrypt_gcm_with_key(
+    key: &str,
+    key_id: Option<&str>,
+    input: &str,
+) -> Result<Vec<u8>, DecryptError> {
+    let parts = input
+        .trim()
+        .split('.')
+        .map(|part| BASE64_STANDARD.decode(part))
+        .collect::<Result<Vec<_>, _>>()
+        .map_err(|err| DecryptError::InvalidBase64(err.to_string()))?;
+    let (nonce, encrypted, tag) = match &parts[..] {
+        [nonce, encrypted, tag]
+            if nonce.len() == GCM_NONCE_LENGTH && tag.len() == GCM_TAG_LENGTH =>
+        {
+            (nonce, encrypted, tag)
+        }
+        _ => {
+            return Err(DecryptError::InvalidBase64(String::from(
+                "expected <nonce>.<encrypted value>.<tag>",
+            )))
+        }
+    };
+    let sealed = [encrypted.as_slice(), tag.as_slice()].concat();
+    let aad = gcm_aad(key_id);
+    let payload = Payload {
+        msg: &sealed,
+        aad: aad.as_bytes(),
+    };
+    Aes256Gcm::new(&gcm_key(key))
+        .decrypt(Nonce::from_slice(nonce), payload)
+        .map_err(|_| DecryptError::Tampered)
+}
+
+/// Environment variable that holds the age identities, used instead of `--identity-file`
+pub const AGE_IDENTITY_ENV: &str = "PIPPO_AGE_IDENTITY";
+
+/// Environment variable that holds the path of the age identity file, see `--identity-file`
+pub const AGE_IDENTITY_FILE_ENV: &str = "PIPPO_AGE_IDENTITY_FILE";
+
+/// Identity file given with `--identity-file` or `PIPPO_AGE_IDENTITY_FILE`, set once at startup
+static IDENTITY_FILE: OnceLock<String> = OnceLock::new();
+
+/// Makes [`read_identities`] read the age identities from this file if `PIPPO_AGE_IDENTITY` is
+/// not set.
+///
+/// # Arguments
+///
+/// * `path` - String slice that holds the path to the identity file
+pub fn set_identity_file(path: &str) {
+    let _ = IDENTITY_FILE.set(path.to_string());
+}
+
+/// Returns the lines of an age key list that hold a key, skipping blank lines and `#` comments.
+fn key_lines(input: &str) -> impl Iterator<Item = &str> {
+    input
+        .lines()
+        .map(str::trim)
+        .filter(|line| !line.is_empty() && !line.starts_with('#'))
+}
+
+/// Parses the age identities of an identity file, one `AGE-SECRET-KEY-1...` per line as written
+/// by `age-keygen`.
+///
+/// # Arguments
+///
+/// * `input` - String slice that holds the identities
+pub fn parse_identities(input: &str) -> Result<Vec<x25519::Identity>, DecryptError> {
+    let identities = key_lines(input)
+        .map(|line| {
+            x25519::Identity::from_str(line).map_err(|err| {
+                DecryptError::InvalidIdentity(format!("invalid age identity: {}", err))
+            })
+        })
+        .collect::<Result<Vec<_>, _>>()?;
+    if identities.is_empty() {
+        return Err(DecryptError::InvalidIdentity(String::from(
+            "the age identity is empty",
+        )));
+    }
+    Ok(identities)
+}
+
+/// Reads the age identities from the `PIPPO_AGE_IDENTITY` environment variable or the identity
+/// file set with [`set_identity_file`], in this order.
+pub fn read_identities() -> Result<Vec<x25519::Identity>, DecryptError> {
+    if let Ok(identities) = env::var(AGE_IDENTITY_ENV) {
+        return parse_identities(&identities);
+    }
+    let path = IDENTITY_FILE.get().ok_or(DecryptError::MissingIdentity)?;
+    let identities = std::fs::read_to_string(path).map_err(|err| {
+        DecryptError::InvalidIdentity(format!("Unable to read identity file {}: {}", path, err))
+    })?;
+    parse_identities(&identities)
+}
+
+/// Parses the value of `--recipients`, a comma separated list of age public keys (`age1...`) and
+/// files with one public key per line.
+///
+/// # Arguments
+///
+/// * `input` - String slice that holds the recipients
+pub fn read_recipients(input: &str) -> Result<Vec<x25519::Recipient>, String> {
+    let mut recipients = vec![];
+    for item in input
+        .split(',')
+        .map(str::trim)
+        .filter(|item| !item.is_empty())
+    {
+        if item.starts_with("age1") {
+            recipients.push(
+                x25519::Recipient::from_str(item)
+                    .map_err(|err| format!("invalid recipient '{}': {}", item, err))?,
+            );
+            continue;
+        }
+        let keys = std::fs::read_to_string(item)
+            .map_err(|err| format!("Unable to read recipients file {}: {}", item, err))?;
+        for key in key_lines(&keys) {
+            recipients.push(
+                x25519::Recipient::from_str(key)
+                    .map_err(|err| format!("invalid recipient '{}' in {}: {}", key, item, err))?,
+            );
+        }
+    }
+    if recipients.is_empty() {
+        return Err(String::from("no recipients given"));
+    }
+    Ok(recipients)
+}
+
+/// Encrypts bytes for age recipients and returns the binary age file in base64, so it fits on a
+/// single line after `$enc:age`.
+///
+/// # Arguments
+///
+/// * `recipients` - Public keys of everyone who can decrypt the value, must not be empty
+/// * `input` - The bytes you want to encrypt
+pub fn encrypt_age(recipients: &[x25519::Recipient], input: &[u8]) -> String {
+    let encryptor =
+        age::Encryptor::with_recipients(recipients.iter().map(|r| r as &dyn age::Recipient))
+            .expect("at least one recipient is required");
+    let mut encrypted = vec![];
+    let mut writer = encryptor
+        .wrap_output(&mut encrypted)
+        .expect("writing to memory can't fail");
+    writer
+        .write_all(input)
+        .and_then(|_| writer.finish())
+        .expect("writing to memory can't fail");
+    BASE64_STANDARD.encode(encrypted)
+}
+
+/// Decrypts the output of [`encrypt_age`] with any of the given identities and returns the
+/// original bytes. Surrounding whitespace is ignored.
+///
+/// # Arguments
+///
+/// * `identities` - The local age identities
+/// * `input` - String slice that holds the base64 of the age file
+pub fn decrypt_age(identities: &[x25519::Identity], input: &str) -> Result<Vec<u8>, DecryptError> {
+    let encrypted = BASE64_STANDARD
+        .decode(input.trim())
+        .map_err(|err| DecryptError::InvalidBase64(err.to_string()))?;
+    let decryptor = age::Decryptor::new(&encrypted[..]).map_err(|_| DecryptError::Tampered)?;
+    let mut reader = decryptor
+        .decrypt(identities.iter().map(|i| i as &dyn age::Identity))
+        .map_err(|err| match err {
+            age::DecryptError::NoMatchingKeys => DecryptError::NoMatchingIdentity,
+            _ => DecryptError::Tampered,
+        })?;
+    let mut decrypted = vec![];
+    reader
+        .read_to_end(&mut decrypted)
+        .map_err(|_| DecryptError::Tampered)?;
+    Ok(decrypted)
+}
+
+/// Encrypts a whole file. With [`Cipher::Cbc`] the output holds the base64 of the file content,
+/// with [`Cipher::Gcm1`] a whole `$enc:gcm1` value, and a line break.
+///
+/// # Arguments
+///
+/// * `input_path` - Path of the file to encrypt, `-` reads stdin
+/// * `output_path` - Path of the encrypted file, `-` writes to stdout
+/// * `force` - Overwrite the output file if it exists
+/// * `cipher` - How the file is encrypted
+pub fn encrypt_file(
+    input_path: &str,
+    output_path: &str,
+    force: bool,
+    cipher: KeyCipher,
+) -> Result<(), String> {
+    let input = read_bytes(input_path)?;
+    let ciphertext = encrypt_with(&provide_key(None), None, cipher, &input);
+    let mut encrypted = match cipher {
+        KeyCipher::Cbc => ciphertext,
+        KeyCipher::Gcm1 => EncryptedValue {
+            cipher: cipher.into(),
+            key_id: None,
+            ciphertext: &ciphertext,
+        }
+        .to_string(),
+    };
+    encrypted.push('\n');
+    write_bytes(output_path, encrypted.as_bytes(), force)
+}
+
+/// Encrypts a whole file for age recipients, the output holds a whole `$enc:age` value and a
+/// line break.
+///
+/// # Arguments
+///
+/// * `input_path` - Path of the file to encrypt, `-` reads stdin
+/// * `output_path` - Path of the encrypted file, `-` writes to stdout
+/// * `force` - Overwrite the output file if it exists
+/// * `recipients` - Public keys of everyone who can decrypt the file
+pub fn encrypt_file_for_recipients(
+    input_path: &str,
+    output_path: &str,
+    force: bool,
+    recipients: &[x25519::Recipient],
+) -> Result<(), String> {
+    let input = read_bytes(input_path)?;
+    let ciphertext = encrypt_age(recipients, &input);
+    let encrypted = EncryptedValue {
+        cipher: Cipher::Age,
+        key_id: None,
+        ciphertext: &ciphertext,
+    };
+    write_bytes(output_path, format!("{}\n", encrypted).as_bytes(), force)
+}
+
+/// Decrypts a file written by [`encrypt_file`] or [`encrypt_file_for_recipients`], restoring the
+/// original content byte by byte.
+///
+/// # Arguments
+///
+/// * `input_path` - Path of the encrypted file, `-` reads stdin
+/// * `output_path` - Path of the decrypted file, `-` writes to stdout
+/// * `force` - Overwrite the output file if it exists
+pub fn decrypt_file(input_path: &str, output_path: &str, force: bool) -> Result<(), String> {
+    let input = read_bytes(input_path)?;
+    let input =
+        String::from_utf8(input).map_err(|_| format!("{} is not an encrypted file", input_path))?;
+    let value = EncryptedValue::from_input(input.trim())
+        .map_err(|err| format!("Could not decrypt {}: {}", input_path, err))?;
+    let decrypted = decrypt_locally(&value)
+        .map_err(|err| format!("Could not decrypt {}: {}", input_path, err))?;
+    write_bytes(output_path, &decrypted, force)
+}
+
+/// Result of re-encrypting the `$enc` values of a YAML file
+#[derive(Debug, PartialEq)]
+pub struct Rotation {
+    /// The YAML with the `$enc` values re-encrypted, otherwise unchanged
+    pub output: String,
+    /// Line numbers (starting at 1) of the values that were re-encrypted
+    pub lines: Vec<usize>,
+}
+
+/// Rewrites the `$enc` values of a YAML file as text, so comments, quoting and indentation stay
+/// as they are. Only the `value` of a variable is rewritten, never a comment or other text that
+/// happens to contain `$enc`.
+///
+/// `rewrite` returns the new value, `None` to keep a value as it is, or why it can't be
+/// decrypted. Nothing is returned unless every value could be rewritten.
+///
+/// # Arguments
+///
+/// * `input` - String slice that holds the YAML
+/// * `failure` - Start of the error message if values can't be decrypted
+/// * `rewrite` - Re-encrypts a single value
+fn rewrite_encrypted_values<F>(
+    input: &str,
+    failure: &str,
+    mut rewrite: F,
+) -> Result<Rotation, String>
+where
+    F: FnMut(&EncryptedValue) -> Option<Result<String, DecryptError>>,
+{
+    let key_line = variable_key_line();
+    let input_lines: Vec<&str> = input.split_inclusive('\n').collect();
+    let value_lines: Vec<usize> = scan_variables(&input_lines, &key_line)
+        .into_iter()
+        .filter_map(|v| v.value_line)
+        .collect();
+    let mut output = String::with_capacity(input.len());
+    let mut lines = vec![];
+    let mut failures = vec![];
+    for (index, line) in input_lines.iter().enumerate() {
+        let captures = match key_line.captures(line) {
+            Some(captures) if value_lines.contains(&index) => captures,
+            _ => {
+                output.push_str(line);
+                continue;
+            }
+        };
+        let value = captures.get(4).unwrap();
+        let (scalar, rest) = split_scalar(value.as_str());
+        let rewritten = serde_yaml::from_str::<Option<String>>(scalar)
+            .ok()
+            .flatten()
+            .and_then(|text| EncryptedValue::parse(&text).ok().and_then(|v| rewrite(&v)));
+        let rewritten = match rewritten {
+            Some(Ok(rewritten)) => rewritten,
+            Some(Err(err)) => {
+                failures.push(format!("line {}: {}", index + 1, err));
+                output.push_str(line);
+                continue;
+            }
+            None => {
+                output.push_str(line);
+                continue;
+            }
+        };
+        // Encrypted values need no escaping, so a quoted value keeps its quotes
+        let quote = match scalar.chars().next() {
+            Some(quote) if quote == '\'' || quote == '"' => quote.to_string(),
+            _ => String::new(),
+        };
+        output.push_str(&line[..value.start()]);
+        output.push_str(&format!("{}{}{}", quote, rewritten, quote));
+        output.push_str(rest);
+        output.push_str(captures.get(5).map_or("", |newline| newline.as_str()));
+        lines.push(index + 1);
+    }
+    if !failures.is_empty() {
+        return Err(format!(
+            "{}, nothing was written: {}",
+            failure,
+            failures.join(", ")
+        ));
+    }
+    Ok(Rotation { output, lines })
+}
+
+/// Re-encrypts every `$enc` value of the default key in a YAML file with a new key, keeping its
+/// cipher. Values with a key id and age values are left alone.
+///
+/// The file is rewritten as text, so comments, quoting and indentation stay as they are. Nothing
+/// is returned unless every value can be decrypted with the old key.
+///
+/// # Arguments
+///
+/// * `input` - String slice that holds the YAML
+/// * `old_key` - The key the values are encrypted with
+/// * `new_key` - The key the values are re-encrypted with
+pub fn rotate_encrypted_values(
+    input: &str,
+    old_key: &str,
+    new_key: &str,
+) -> Result<Rotation, String> {
+    rewrite_encrypted_values(input, "Could not decrypt with the current key", |value| {
+        let cipher = value
+            .cipher
+            .key_cipher()
+            .filter(|_| value.key_id.is_none())?;
+        Some(decrypt_with(old_key, value).map(|plain| {
+            let ciphertext = encrypt_with(new_key, None, cipher, &plain);
+            EncryptedValue {
+                ciphertext: &ciphertext,
+                ..*value
+            }
+            .to_string()
+        }))
+    })
+}
+
+/// Re-encrypts every `$enc` value of a YAML file that still uses [`Cipher::Cbc`] with
+/// [`Cipher::Gcm1`], using the same key.
+///
+/// # Arguments
+///
+/// * `input` - String slice that holds the YAML
+/// * `read_key` - Returns the key of a key id, `None` stands for the default key
+pub fn migrate_encrypted_values<K>(input: &str, read_key: K) -> Result<Rotation, String>
+where
+    K: Fn(Option<&str>) -> Result<String, DecryptError>,
+{
+    rewrite_encrypted_values(input, "Could not decrypt with the local keys", |value| {
+        if value.cipher != Cipher::Cbc {
+            return None;
+        }
+        Some(
+            read_key(value.key_id)
+                .and_then(|key| decrypt_with(&key, value).map(|plain| (key, plain)))
+                .map(|(key, plain)| {
+                    let ciphertext = encrypt_with(&key, value.key_id, KeyCipher::Gcm1, &plain);
+                    EncryptedValue {
+                        cipher: Cipher::Gcm1,
+                        ciphertext: &ciphertext,
+                        ..*value
+                    }
+                    .to_string()
+                }),
+        )
+    })
+}
+
+/// A variable of a YAML file while it is scanned as text
+#[derive(Default)]
+struct ScannedVariable {
+    /// Column of the `-` that starts the list item
+    dash_column: usize,
+    /// Column of the keys of the list item
+    key_column: usize,
+    name: Option<String>,
+    secret: bool,
+    /// Index of the `value:` line
+    value_line: Option<usize>,
+}
+
+/// Splits the rest of a `value:` line into the scalar and what follows it, e.g. a comment.
+fn split_scalar(rest: &str) -> (&str, &str) {
+    let bytes = rest.as_bytes();
+    let mut end = rest.len();
+    match bytes.first() {
+        Some(b'\'') => {
+            // A quote is escaped by doubling it
+            let mut index = 1;
+            while index < bytes.len() {
+                if bytes[index] == b'\'' {
+                    if bytes.get(index + 1) == Some(&b'\'') {
+                        index += 2;
+                        continue;
+                    }
+                    end = index + 1;
+                    break;
                 }
+                index += 1;
             }
         }
+        Some(b'"') => {
+            let mut index = 1;
+            while index < bytes.len() {
+                match bytes[index] {
+                    b'\\' => index += 2,
+                    b'"' => {
+                        end = index + 1;
+                        break;
+                    }
+                    _ => index += 1,
+                }
+            }
+        }
+        _ => end = rest.find(" #").unwrap_or(rest.len()),
     }
+    let scalar = rest[..end.min(rest.len())].trim_end();
+    (scalar, &rest[scalar.len()..])
 }
 
-/// Encrypts a string and returns base64
+/// Matches the `name`, `type` and `value` keys of a variable, the value is in the fourth group.
+fn variable_key_line() -> Regex {
+    Regex::new(r"^( *)(- +)?(name|type|value):[ \t]*(.*?)(\r?\n)?$").unwrap()
+}
+
+/// Finds the variables of a YAML file by scanning its lines as text, so the `value:` line of
+/// every variable is known.
 ///
 /// # Arguments
 ///
-///  * `input` - The string you want to encrypt
-pub fn encrypt(input: &str) -> String {
-    let secret_key = provide_secret_key();
-    let magic_crypt = new_magic_crypt!(secret_key, 256);
-    magic_crypt.encrypt_str_to_base64(input)
+/// * `lines` - Lines of the YAML, including their line breaks
+/// * `key_line` - The regex of [`variable_key_line`]
+fn scan_variables(lines: &[&str], key_line: &Regex) -> Vec<ScannedVariable> {
+    let mut variables = vec![];
+    let mut current: Option<ScannedVariable> = None;
+    for (index, line) in lines.iter().enumerate() {
+        let content = line.trim_start();
+        let column = line.len() - content.len();
+        if content.trim().is_empty() || content.starts_with('#') {
+            continue;
+        }
+        if current.as_ref().is_some_and(|v| column <= v.dash_column) {
+            variables.extend(current.take());
+        }
+        if let Some(rest) = content.strip_prefix('-') {
+            if rest.is_empty() || rest.starts_with(' ') {
+                variables.extend(current.take());
+                current = Some(ScannedVariable {
+                    dash_column: column,
+                    key_column: column + 1 + (rest.len() - rest.trim_start().len()),
+                    ..Default::default()
+                });
+            }
+        }
+        let (variable, captures) = match (current.as_mut(), key_line.captures(line)) {
+            (Some(variable), Some(captures)) => (variable, captures),
+            _ => continue,
+        };
+        // Keys of nested mappings, e.g. unknown fields, don't belong to the variable
+        if captures[1].len() + captures.get(2).map_or(0, |dash| dash.len()) != variable.key_column {
+            continue;
+        }
+        let (scalar, _) = split_scalar(&captures[4]);
+        match &captures[3] {
+            "name" => variable.name = serde_yaml::from_str(scalar).ok(),
+            "type" => {
+                variable.secret =
+                    serde_yaml::from_str::<String>(scalar).is_ok_and(|t| t == "secretString")
+            }
+            _ => variable.value_line = Some(index),
+        }
+    }
+    variables.extend(current);
+    variables
 }
 
-/// Decrypts a string and returns it
+/// Returns the name, whether it is a secret and the value of every variable of a YAML config, in
+/// the order the config is read.
+fn config_variables(config: &YamlConfig) -> Vec<(&str, bool, Option<&str>)> {
+    let mut variables = vec![];
+    for program in &config.programs {
+        for v in program.defaults.iter().flat_map(|d| &d.variables) {
+            let secret = v.variable_type == VariableType::SecretString;
+            variables.push((v.name.as_str(), secret, v.value.as_deref()));
+        }
+        for v in program
+            .environments
+            .iter()
+            .flatten()
+            .flat_map(|e| &e.variables)
+        {
+            let secret = v.variable_type == VariableType::SecretString;
+            variables.push((v.name.as_str(), secret, v.value.as_deref()));
+        }
+        for v in program
+            .pipelines
+            .iter()
+            .flatten()
+            .flat_map(|p| &p.variables)
+        {
+            let secret = v.variable_type == VariableType::SecretString;
+            variables.push((v.name.as_str(), secret, v.value.as_deref()));
+        }
+    }
+    variables
+}
+
+/// Encrypts the plain `value` of every `secretString` variable of a YAML config.
+///
+/// The file is rewritten as text, so comments, quoting and indentation stay as they are. Values
+/// that are already encrypted are left alone. The result is parsed again and every value is
+/// decrypted to check it, so nothing is returned unless every value was encrypted correctly.
 ///
 /// # Arguments
 ///
-/// * `input` The string you want to decrypt
-pub fn decrypt(input: String) -> String {
-    let secret_key = provide_secret_key();
-    let magic_crypt = new_magic_crypt!(secret_key, 256);
-    magic_crypt
-        .decrypt_base64_to_string(input)
-        .expect("Could not decrypt string - wrong key?")
+/// * `input` - String slice that holds the YAML
+/// * `only` - Names of the variables to encrypt, all secret variables if empty
+/// * `key` - The key the values are encrypted with
+/// * `key_id` - Id of the key, written after `$enc:`
+/// * `cipher` - How the values are encrypted
+pub fn encrypt_secret_values(
+    input: &str,
+    only: &[String],
+    key: &str,
+    key_id: Option<&str>,
+    cipher: KeyCipher,
+) -> Result<Rotation, String> {
+    let config = YamlConfig::parse(input)?;
+    let variables = config_variables(&config);
+    let unknown: Vec<&str> = only
+        .iter()
+        .map(String::as_str)
+        .filter(|name| !variables.iter().any(|(n, secret, _)| n == name && *secret))
+        .collect();
+    if !unknown.is_empty() {
+        return Err(format!(
+            "No secretString variable named {}",
+            unknown.join(", ")
+        ));
+    }
+
+    let key_line = variable_key_line();
+    let lines: Vec<&str> = input.split_inclusive('\n').collect();
+    let value_lines: Vec<usize> = scan_variables(&lines, &key_line)
+        .into_iter()
+        .filter(|v| {
+            v.secret
+                && v.name
+                    .as_ref()
+                    .is_some_and(|n| only.is_empty() || only.contains(n))
+        })
+        .filter_map(|v| v.value_line)
+        .collect();
+
+    let mut output = String::with_capacity(input.len());
+    let mut encrypted_lines = vec![];
+    for (index, line) in lines.iter().enumerate() {
+        let captures = match key_line.captures(line) {
+            Some(captures) if value_lines.contains(&index) => captures,
+            _ => {
+                output.push_str(line);
+                continue;
+            }
+        };
+        let value = captures.get(4).unwrap();
+        let (scalar, rest) = split_scalar(value.as_str());
+        let plain = match serde_yaml::from_str::<Option<String>>(scalar) {
+            Ok(Some(plain)) if !EncryptedValue::is_encrypted(&plain) => plain,
+            _ => {
+                output.push_str(line);
+                continue;
+            }
+        };
+        let ciphertext = encrypt_with(key, key_id, cipher, plain.as_bytes());
+        let encrypted = EncryptedValue {
+            cipher: cipher.into(),
+            key_id,
+            ciphertext: &ciphertext,
+        };
+        output.push_str(&line[..value.start()]);
+        output.push_str(&encrypted.to_string());
+        output.push_str(rest);
+        output.push_str(captures.get(5).map_or("", |newline| newline.as_str()));
+        encrypted_lines.push(index + 1);
+    }
+
+    // Every value must read back as before, either encrypted or untouched
+    let rewritten = YamlConfig::parse(&output)
+        .map_err(|err| format!("Could not encrypt the values in place: {}", err))?;
+    let wanted = |name: &str, secret: bool, value: Option<&str>| {
+        secret
+            && value.is_some_and(|value| !EncryptedValue::is_encrypted(value))
+            && (only.is_empty() || only.iter().any(|n| n == name))
+    };
+    let mut missed = vec![];
+    for ((name, secret, before), (_, _, after)) in
+        variables.iter().zip(config_variables(&rewritten))
+    {
+        let matches = match (wanted(name, *secret, *before), after) {
+            (true, Some(after)) => EncryptedValue::parse(after)
+                .ok()
+                .filter(|value| value.key_id == key_id)
+                .and_then(|value| decrypt_with(key, &value).ok())
+                .is_some_and(|plain| Some(plain.as_slice()) == before.map(str::as_bytes)),
+            (_, after) => after == *before,
+        };
+        if !matches {
+            missed.push(name.to_string());
+        }
+    }
+    if !missed.is_empty() {
+        return Err(format!(
+            "Could not encrypt {} in place, encrypt multi-line or flow style values with 'pippo encrypt'",
+            missed.join(", ")
+        ));
+    }
+    Ok(Rotation {
+        output,
+        lines: encrypted_lines,
+    })
+}
+
+/// Re-encrypts every `$enc` value of a YAML file with a new key, see [`rotate_encrypted_values`].
+///
+/// # Arguments
+///
+/// * `input_path` - Path of the YAML file, `-` reads stdin
+/// * `output_path` - Path the rewritten YAML is written to, `-` writes to stdout
+/// * `new_key` - The key the values are re-encrypted with
+/// * `dry_run` - Only list the values that would be re-encrypted
+pub fn rotate_key_of_file(
+    input_path: &str,
+    output_path: &str,
+    new_key: &str,
+    dry_run: bool,
+) -> Result<(), String> {
+    let input = read_text(input_path)?;
+    let rotation = rotate_encrypted_values(&input, &provide_key(None), new_key)?;
+    write_rotation(input_path, output_path, &rotation, dry_run, "re-encrypted")
+}
+
+/// Moves every `$enc` value of a YAML file to [`Cipher::Gcm1`], see
+/// [`migrate_encrypted_values`].
+///
+/// # Arguments
+///
+/// * `input_path` - Path of the YAML file, `-` reads stdin
+/// * `output_path` - Path the rewritten YAML is written to, `-` writes to stdout
+/// * `dry_run` - Only list the values that would be re-encrypted
+pub fn migrate_file(input_path: &str, output_path: &str, dry_run: bool) -> Result<(), String> {
+    let input = read_text(input_path)?;
+    let rotation = migrate_encrypted_values(&input, read_key)?;
+    write_rotation(input_path, output_path, &rotation, dry_run, "re-encrypted")
+}
+
+/// Encrypts the plain secret values of a YAML file, see [`encrypt_secret_values`].
+///
+/// # Arguments
+///
+/// * `input_path` - Path of the YAML file, `-` reads stdin
+/// * `output_path` - Path the rewritten YAML is written to, `-` writes to stdout
+/// * `only` - Names of the variables to encrypt, all secret variables if empty
+/// * `key_id` - Id of the key to encrypt with, `None` for the default key
+/// * `cipher` - How the values are encrypted
+/// * `dry_run` - Only list the values that would be encrypted
+pub fn encrypt_yaml_file(
+    input_path: &str,
+    output_path: &str,
+    only: &[String],
+    key_id: Option<&str>,
+    cipher: KeyCipher,
+    dry_run: bool,
+) -> Result<(), String> {
+    let input = read_text(input_path)?;
+    let rotation = encrypt_secret_values(&input, only, &provide_key(key_id), key_id, cipher)
+        .map_err(|err| format!("{}, nothing was written: {}", input_path, err))?;
+    write_rotation(input_path, output_path, &rotation, dry_run, "encrypted")
+}
+
+/// Lists the rewritten lines and writes the rewritten YAML unless it's a dry run.
+///
+/// `done` says what happened to the values, e.g. `re-encrypted`.
+fn write_rotation(
+    input_path: &str,
+    output_path: &str,
+    rotation: &Rotation,
+    dry_run: bool,
+    done: &str,
+) -> Result<(), String> {
+    for line in &rotation.lines {
+        eprintln!("{:>4} {}:{}", "🔑", input_path, line);
+    }
+    if dry_run {
+        eprintln!(
+            "{} values would be {}, --dry-run doesn't write anything",
+            rotation.lines.len(),
+            done
+        );
+        return Ok(());
+    }
+    replace_file(output_path, rotation.output.as_bytes())?;
+    eprintln!("{} values {}", rotation.lines.len(), done);
+    Ok(())
+}
+
+/// Returns the value that `encrypt` or `decrypt` work on: the argument, or stdin if the argument
+/// is `-` or missing. Reading stdin keeps secrets out of the shell history and `ps`.
+///
+/// Without an argument, stdin is only read if it isn't a terminal, so pippo doesn't wait for
+/// input that nobody is going to type.
+///
+/// # Arguments
+///
+/// * `input` - The argument, if one was given
+pub fn read_value(input: Option<&str>) -> Result<String, String> {
+    match input {
+        Some(input) if input != STDIN_PATH => Ok(input.to_string()),
+        None if io::stdin().is_terminal() => Err(String::from(
+            "No value given, pass it as argument or pipe it to stdin",
+        )),
+        _ => read_text(STDIN_PATH).map(|value| trim_newline(&value).to_string()),
+    }
+}
+
+/// Removes exactly one trailing line break, as added by `echo` or a here-string.
+///
+/// # Arguments
+///
+/// * `value` - String slice that holds the value read from stdin
+pub fn trim_newline(value: &str) -> &str {
+    value
+        .strip_suffix("\r\n")
+        .or_else(|| value.strip_suffix('\n'))
+        .unwrap_or(value)
+}
+
+/// Reads a text file, or stdin if the path is `-`.
+fn read_text(path: &str) -> Result<String, String> {
+    String::from_utf8(read_bytes(path)?).map_err(|_| format!("{} is not a text file", path))
+}
+
+/// Reads a file, or stdin if the path is `-`.
+fn read_bytes(path: &str) -> Result<Vec<u8>, String> {
+    let mut input = vec![];
+    if path == STDIN_PATH {
+        io::stdin()
+            .read_to_end(&mut input)
+            .map_err(|err| format!("Unable to read stdin: {}", err))?;
+    } else {
+        input = std::fs::read(path).map_err(|err| format!("Unable to read {}: {}", path, err))?;
+    }
+    Ok(input)
+}
+
+/// Replaces a file, or writes to stdout if the path is `-`.
+///
+/// The content is written to a temporary file next to it, which is then renamed, so the file
+/// holds either the old or the new content even if pippo is interrupted. The permissions of an
+/// existing file are kept.
+fn replace_file(path: &str, content: &[u8]) -> Result<(), String> {
+    if path == STDIN_PATH {
+        return write_bytes(path, content, true);
+    }
+    let target = Path::new(path);
+    let file_name = target
+        .file_name()
+        .ok_or_else(|| format!("Unable to write {}: not a file", path))?;
+    let temp = target.with_file_name(format!(
+        ".{}.{}.tmp",
+        file_name.to_string_lossy(),
+        process::id()
+    ));
+    let written = (|| {
+        let mut file = OpenOptions::new()
+            .write(true)
+            .create_new(true)
+            .open(&temp)?;
+        if let Ok(metadata) = std::fs::metadata(target) {
+            file.set_permissions(metadata.permissions())?;
+        }
+        file.write_all(content)?;
+        file.sync_all()?;
+        std::fs::rename(&temp, target)
+    })();
+    written.map_err(|err| {
+        let _ = std::fs::remove_file(&temp);
+        format!("Unable to write {}: {}", path, err)
+    })
+}
+
+/// Writes to a file, or to stdout if the path is `-`. An existing file is only replaced with
+/// `force`.
+fn write_bytes(path: &str, content: &[u8], force: bool) -> Result<(), String> {
+    if path == STDIN_PATH {
+        return io::stdout()
+            .write_all(content)
+            .map_err(|err| format!("Unable to write to stdout: {}", err));
+    }
+    let mut options = OpenOptions::new();
+    options.write(true);
+    if force {
+        options.create(true).truncate(true);
+    } else {
+        options.create_new(true);
+    }
+    let mut file = options.open(path).map_err(|err| {
+        if err.kind() == io::ErrorKind::AlreadyExists {
+            format!("{} already exists, use --force to overwrite it", path)
+        } else {
+            format!("Unable to write {}: {}", path, err)
+        }
+    })?;
+    file.write_all(content)
+        .map_err(|err| format!("Unable to write {}: {}", path, err))
 }
 
 #[cfg(test)]
 mod tests {
     use super::*;
+    use crate::variables::{validate_env_vars, validate_pipeline_vars};
 
     #[test]
     fn encryption_workflow() {
         env::set_var("PIPPO_CRYPTKEY", "Test 123@!");
         let test_string = "th!s i$ a 'TEST`";
-        let encrypted_value = encrypt(test_string);
-        let decrypted_value = decrypt(encrypted_value);
+        let encrypted_value = encrypt(test_string, None, KeyCipher::Cbc);
+        let decrypted_value =
+            decrypt(&EncryptedValue::from_input(&encrypted_value).unwrap()).unwrap();
 
         assert_eq!(test_string, decrypted_value);
     }
+
+    #[test]
+    fn encrypted_values_are_parsed() {
+        assert_eq!(
+            EncryptedValue::parse("$enc abc=="),
+            Ok(EncryptedValue {
+                cipher: Cipher::Cbc,
+                key_id: None,
+                ciphertext: "abc==",
+            })
+        );
+        assert_eq!(
+            EncryptedValue::parse("  $enc:v2\tabc==\n"),
+            Ok(EncryptedValue {
+                cipher: Cipher::Cbc,
+                key_id: Some("v2"),
+                ciphertext: "abc==",
+            })
+        );
+        for malformed in [
+            "$enc",
+            "$enc a b",
+            "$enc: abc",
+            "$enc:v 2 abc",
+            "$encrypted abc",
+            "$enc:v2:gcm1 abc",
+            "$enc:gcm1:gcm1 abc",
+        ] {
+            assert!(EncryptedValue::parse(malformed).is_err(), "{}", malformed);
+        }
+        let value = EncryptedValue::parse("$enc:gcm1:key-2 a.b.c").unwrap();
+        assert_eq!(value.cipher, Cipher::Gcm1);
+        assert_eq!(value.key_id, Some("key-2"));
+        assert_eq!(value.to_string(), "$enc:gcm1:key-2 a.b.c");
+        assert_eq!(
+            EncryptedValue::parse("$enc:gcm1 a.b.c").unwrap().key_id,
+            None
+        );
+        assert_eq!(
+            EncryptedValue::from_input("abc"),
+            Ok(EncryptedValue {
+                cipher: Cipher::Cbc,
+                key_id: None,
+                ciphertext: "abc",
+            })
+        );
+    }
+
+    #[test]
+    fn values_of_two_keys_round_trip() {
+        env::set_var("PIPPO_CRYPTKEY", "Test 123@!");
+        env::set_var(key_env_var("round-trip-v2"), "Second key");
+        let old = format!("$enc {}", encrypt("first", None, KeyCipher::Cbc));
+        let new = format!(
+            "$enc:round-trip-v2 {}",
+            encrypt("second", Some("round-trip-v2"), KeyCipher::Cbc)
+        );
+
+        let decrypt_input = |input: &str| decrypt(&EncryptedValue::parse(input).unwrap());
+        assert_eq!(decrypt_input(&old), Ok(String::from("first")));
+        assert_eq!(decrypt_input(&new), Ok(String::from("second")));
+        // The key id decides which key is used
+        let swapped = new.replacen("$enc:round-trip-v2", "$enc", 1);
+        assert_eq!(decrypt_input(&swapped), Err(DecryptError::WrongKey));
+        assert_eq!(
+            decrypt_input("$enc:missing-key abc"),
+            Err(DecryptError::UnknownKeyId(String::from("missing-key")))
+        );
+    }
+
+    #[test]
+    fn gcm_round_trip() {
+        let encrypted = encrypt_gcm_with_key("right key", None, "sëcret".as_bytes());
+        assert_eq!(encrypted.split('.').count(), 3);
+        assert_ne!(
+            encrypted,
+            encrypt_gcm_with_key("right key", None, "sëcret".as_bytes()),
+            "every value gets its own nonce"
+        );
+        assert_eq!(
+            decrypt_gcm_with_key("right key", None, &format!(" {}\n", encrypted)),
+            Ok("sëcret".as_bytes().to_vec())
+        );
+        assert_eq!(
+            decrypt_gcm_with_key("wrong key", None, &encrypted),
+            Err(DecryptError::Tampered)
+        );
+    }
+
+    #[test]
+    fn gcm_binds_the_key_id() {
+        let encrypted = encrypt_gcm_with_key("same key", Some("v2"), b"s3cret");
+        assert_eq!(
+            decrypt_gcm_with_key("same key", Some("v2"), &encrypted),
+            Ok(b"s3cret".to_vec())
+        );
+        // Renaming $enc:gcm1:v2 to $enc:gcm1:v3 or $enc:gcm1, even if the keys are the same
+        assert_eq!(
+            decrypt_gcm_with_key("same key", Some("v3"), &encrypted),
+            Err(DecryptError::Tampered)
+        );
+        assert_eq!(
+            decrypt_gcm_with_key("same key", None, &encrypted),
+            Err(DecryptError::Tampered)
+        );
+    }
+
+    #[test]
+    fn gcm_detects_modified_values() {
+        let encrypted = encrypt_gcm_with_key("right key", None, b"s3cret value");
+        let parts: Vec<&str> = encrypted.split('.').collect();
+        let mut bytes = BASE64_STANDARD.decode(parts[1]).unwrap();
+        bytes[0] ^= 1;
+        let flipped = format!(
+            "{}.{}.{}",
+            parts[0],
+            BASE64_STANDARD.encode(&bytes),
+            parts[2]
+        );
+        assert_eq!(
+            decrypt_gcm_with_key("right key", None, &flipped),
+            Err(DecryptError::Tampered)
+        );
+        // A shortened value, e.g. cut off while copying
+        let shortened = format!("{}.{}.{}", parts[0], parts[1], &parts[2][..8]);
+        assert!(matches!(
+            decrypt_gcm_with_key("right key", None, &shortened),
+            Err(DecryptError::InvalidBase64(_))
+        ));
+        assert!(matches!(
+            decrypt_gcm_with_key("right key", None, "no.dots"),
+            Err(DecryptError::InvalidBase64(_))
+        ));
+        // The same flip goes unnoticed by cbc
+        let cbc = encrypt_with_key("right key", "a s3cret value that spans three blocks");
+        let mut bytes = BASE64_STANDARD.decode(&cbc).unwrap();
+        bytes[0] ^= 1;
+        assert!(decrypt_bytes_with_key("right key", &BASE64_STANDARD.encode(&bytes)).is_ok());
+    }
+
+    #[test]
+    fn decryption_dispatches_on_the_cipher() {
+        env::set_var("PIPPO_CRYPTKEY", "Test 123@!");
+        let cbc = format!("$enc {}", encrypt("cbc value", None, KeyCipher::Cbc));
+        let gcm = format!("$enc:gcm1 {}", encrypt("gcm value", None, KeyCipher::Gcm1));
+        let decrypt_input = |input: &str| decrypt(&EncryptedValue::parse(input).unwrap());
+        assert_eq!(decrypt_input(&cbc), Ok(String::from("cbc value")));
+        assert_eq!(decrypt_input(&gcm), Ok(String::from("gcm value")));
+        assert!(decrypt_input(&gcm.replacen(":gcm1", "", 1)).is_err());
+        let binary = EncryptedValue {
+            cipher: Cipher::Gcm1,
+            key_id: None,
+            ciphertext: &encrypt_gcm_with_key("Test 123@!", None, &[0xff, 0xfe]),
+        };
+        assert_eq!(decrypt(&binary), Err(DecryptError::NotText));
+    }
+
+    #[test]
+    fn migration_moves_cbc_values_to_gcm() {
+        let keys = |key_id: Option<&str>| match key_id {
+            None => Ok(String::from("old")),
+            Some("v2") => Ok(String::from("second")),
+            Some(key_id) => Err(DecryptError::UnknownKeyId(key_id.to_string())),
+        };
+        let already = format!("$enc:gcm1 {}", encrypt_gcm_with_key("old", None, b"third"));
+        let input = format!(
+            "- name: A\n  value: $enc {}\n- name: B\n  value: \"$enc:v2 {}\" # quoted\n- name: C\n  value: {}\n- name: D\n  value: plain\n",
+            encrypt_with_key("old", "first"),
+            encrypt_with_key("second", "sëcond"),
+            already
+        );
+        let migration = migrate_encrypted_values(&input, keys).unwrap();
+        assert_eq!(migration.lines, vec![2, 4]);
+
+        let lines: Vec<&str> = migration.output.lines().collect();
+        assert!(lines[1].starts_with("  value: $enc:gcm1 "));
+        assert!(
+            lines[3].starts_with("  value: \"$enc:gcm1:v2 ") && lines[3].ends_with("\" # quoted")
+        );
+        assert_eq!(lines[5], format!("  value: {}", already));
+        assert_eq!(lines[7], "  value: plain");
+        let decrypt_line = |line: &str, key: &str| {
+            let value = line
+                .split_once(": ")
+                .unwrap()
+                .1
+                .split('"')
+                .find(|v| !v.is_empty());
+            let value = EncryptedValue::parse(value.unwrap()).unwrap();
+            decrypt_with(key, &value).map(|plain| String::from_utf8(plain).unwrap())
+        };
+        assert_eq!(decrypt_line(lines[1], "old"), Ok(String::from("first")));
+        assert_eq!(decrypt_line(lines[3], "second"), Ok(String::from("sëcond")));
+
+        let unknown = format!("- value: $enc:v3 {}\n", encrypt_with_key("third", "x"));
+        let err = migrate_encrypted_values(&unknown, keys).unwrap_err();
+        assert!(
+            err.starts_with("Could not decrypt with the local keys, nothing was written: line 1:")
+        );
+    }
+
+    /// Looks up the default key in a fake environment with the given variables and files
+    fn find_key(
+        vars: &[(&str, &str)],
+        files: &[(&str, &str)],
+        key_file: Option<&str>,
+    ) -> Result<String, DecryptError> {
+        let lookup = |entries: &[(&str, &str)], name: &str| {
+            entries
+                .iter()
+                .find(|(entry, _)| *entry == name)
+                .map(|(_, value)| value.to_string())
+        };
+        find_secret_key(
+            |name| lookup(vars, name),
+            |path| lookup(files, path),
+            key_file,
+        )
+    }
+
+    #[test]
+    fn secret_key_precedence() {
+        let vars = [("PIPPO_CRYPTKEY", "from env")];
+        let files = [
+            ("ci/key", "from key file\n"),
+            (DEFAULT_KEY_FILE, "from default file\n"),
+        ];
+        assert_eq!(
+            find_key(&vars, &files, Some("ci/key")),
+            Ok(String::from("from env"))
+        );
+        assert_eq!(
+            find_key(&[], &files, Some("ci/key")),
+            Ok(String::from("from key file"))
+        );
+        assert_eq!(
+            find_key(&[], &files, None),
+            Ok(String::from("from default file"))
+        );
+    }
+
+    #[test]
+    fn missing_secret_key_names_the_sources() {
+        let files = [(DEFAULT_KEY_FILE, "from default file")];
+        let err = find_key(&[], &files, Some("ci/key")).unwrap_err();
+        assert_eq!(
+            err.to_string(),
+            "no key found, tried PIPPO_CRYPTKEY, ci/key"
+        );
+        let err = find_key(&[], &[], None).unwrap_err();
+        assert_eq!(
+            err.to_string(),
+            "no key found, tried PIPPO_CRYPTKEY, .cryptkey"
+        );
+    }
+
+    /// Decrypts a value of a YAML config with the key of the secrets fixture
+    fn decrypt_fixture_value(value: &EncryptedValue) -> Result<String, DecryptError> {
+        decrypt_with("Test 123@!", value).map(|plain| String::from_utf8(plain).unwrap())
+    }
+
+    /// Returns the decrypted value of a variable of a YAML config
+    fn secret_of(config: &YamlConfig, name: &str) -> String {
+        let (_, _, value) = config_variables(config)
+            .into_iter()
+            .find(|(n, _, _)| *n == name)
+            .unwrap();
+        decrypt_fixture_value(&EncryptedValue::parse(value.unwrap()).unwrap()).unwrap()
+    }
+
+    #[test]
+    fn encrypt_secret_values_in_place() {
+        let input = std::fs::read_to_string("test/test_yaml_config_secrets.yml").unwrap();
+        let encryption =
+            encrypt_secret_values(&input, &[], "Test 123@!", None, KeyCipher::Gcm1).unwrap();
+        assert_eq!(encryption.lines, vec![6, 13, 17, 28]);
+        assert!(encryption.output.contains("# DEV\n"));
+        assert!(encryption.output.contains("== # from the vault\n"));
+        assert!(encryption.output.contains("            value: debug\n"));
+
+        // The result feeds into `env vars set` and `pipeline vars set` like a hand-written file
+        let config = YamlConfig::parse(&encryption.output).unwrap();
+        assert!(validate_env_vars(&config, decrypt_fixture_value).is_empty());
+        assert!(validate_pipeline_vars(&config, decrypt_fixture_value).is_empty());
+        assert_eq!(secret_of(&config, "SHARED_TOKEN"), "shared-token");
+        assert_eq!(secret_of(&config, "API_KEY"), "s3cr#t: key");
+        assert_eq!(secret_of(&config, "DB_PASSWORD"), "it's secret");
+        assert_eq!(secret_of(&config, "ALREADY_ENCRYPTED"), "already encrypted");
+        assert_eq!(secret_of(&config, "NPM_TOKEN"), "npm-token");
+
+        // Encrypted values are left alone
+        let again =
+            encrypt_secret_values(&encryption.output, &[], "Test 123@!", None, KeyCipher::Gcm1)
+                .unwrap();
+        assert!(again.lines.is_empty());
+        assert_eq!(again.output, encryption.output);
+    }
+
+    #[test]
+    fn encrypt_only_some_secret_values() {
+        let input = std::fs::read_to_string("test/test_yaml_config_secrets.yml").unwrap();
+        let only = vec![String::from("NPM_TOKEN")];
+        let encryption =
+            encrypt_secret_values(&input, &only, "second key", Some("v2"), KeyCipher::Cbc).unwrap();
+        assert_eq!(encryption.lines, vec![28]);
+        assert!(encryption.output.contains("value: $enc:v2 "));
+        assert!(encryption.output.contains("value: shared-token\n"));
+
+        let only = vec![String::from("LOG_LEVEL"), String::from("MISSING")];
+        assert_eq!(
+            encrypt_secret_values(&input, &only, "second key", None, KeyCipher::Gcm1).unwrap_err(),
+            "No secretString variable named LOG_LEVEL, MISSING"
+        );
+    }
+
+    #[test]
+    fn encrypt_secret_values_refuses_what_it_cant_rewrite() {
+        assert!(
+            encrypt_secret_values("programs: [", &[], "key", None, KeyCipher::Gcm1)
+                .unwrap_err()
+                .starts_with("Malformed YAML")
+        );
+        let multi_line = "programs:\n  - id: 1\n    environments:\n      - id: 2\n        variables:\n          - name: CERT\n            type: secretString\n            value: |\n              line 1\n              line 2\n";
+        assert_eq!(
+            encrypt_secret_values(multi_line, &[], "key", None, KeyCipher::Gcm1).unwrap_err(),
+            "Could not encrypt CERT in place, encrypt multi-line or flow style values with 'pippo encrypt'"
+        );
+    }
+
+    #[test]
+    fn trim_exactly_one_newline() {
+        assert_eq!(trim_newline("secret\n"), "secret");
+        assert_eq!(trim_newline("secret\r\n"), "secret");
+        assert_eq!(trim_newline("secret\n\n"), "secret\n");
+        assert_eq!(trim_newline(" secret "), " secret ");
+        assert_eq!(read_value(Some("secret")), Ok(String::from("secret")));
+    }
+
+    #[test]
+    fn key_ids_and_keys_file() {
+        assert_eq!(key_env_var("v2"), "PIPPO_CRYPTKEY_V2");
+        assert_eq!(key_env_var("team-b"), "PIPPO_CRYPTKEY_TEAM_B");
+        assert!(parse_key_id("v2").is_ok());
+        assert!(parse_key_id("v:2").is_err());
+        assert!(parse_key_id("").is_err());
+        assert!(parse_key_id("gcm1").is_err());
+        assert!(parse_key_id("file").is_err());
+
+        let keys = parse_keys("v2: second key\n'2025': 'third: key'\n").unwrap();
+        assert_eq!(keys.get("v2").map(String::as_str), Some("second key"));
+        assert_eq!(keys.get("2025").map(String::as_str), Some("third: key"));
+        assert!(parse_keys("").unwrap().is_empty());
+        assert!(parse_keys("- not a map").is_err());
+    }
+
+    #[test]
+    fn decryption_with_wrong_key_fails() {
+        let encrypted_value = encrypt_with_key("right key", "secret");
+        assert_eq!(
+            decrypt_with_key("right key", &encrypted_value),
+            Ok(String::from("secret"))
+        );
+        assert_eq!(
+            decrypt_with_key("wrong key", &encrypted_value),
+            Err(DecryptError::WrongKey)
+        );
+    }
+
+    #[test]
+    fn decryption_of_corrupted_base64_fails() {
+        let encrypted_value = encrypt_with_key("right key", "secret");
+        assert!(matches!(
+            decrypt_with_key("right key", "not base64!"),
+            Err(DecryptError::InvalidBase64(_))
+        ));
+        // A value that was cut off when it was copied
+        assert!(
+            decrypt_with_key("right key", &encrypted_value[..encrypted_value.len() - 3]).is_err()
+        );
+    }
+
+    #[test]
+    fn decryption_ignores_surrounding_whitespace() {
+        let encrypted_value = encrypt_with_key("right key", "secret");
+        for padded in [
+            format!("{}\n", encrypted_value),
+            format!("  {}\r\n", encrypted_value),
+            format!("\t{} ", encrypted_value),
+        ] {
+            assert_eq!(
+                decrypt_with_key("right key", &padded),
+                Ok(String::from("secret"))
+            );
+        }
+    }
+
+    #[test]
+    fn file_encryption_round_trip() {
+        env::set_var("PIPPO_CRYPTKEY", "Test 123@!");
+        let dir = env::temp_dir();
+        let id = uuid::Uuid::new_v4();
+        let plain = dir.join(format!("pippo-plain-{}.yml", id));
+        let encrypted = dir.join(format!("pippo-encrypted-{}.yml.enc", id));
+        let decrypted = dir.join(format!("pippo-decrypted-{}.yml", id));
+        let (plain, encrypted, decrypted) = (
+            plain.to_str().unwrap(),
+            encrypted.to_str().unwrap(),
+            decrypted.to_str().unwrap(),
+        );
+        let content: String = (0..200)
+            .map(|i| format!("- name: SECRET_{}\n  value: Grüße, 秘密 №{} ✔\n", i, i))
+            .collect();
+        assert!(content.len() > 8 * 1024);
+        std::fs::write(plain, &content).unwrap();
+
+        encrypt_file(plain, encrypted, false, KeyCipher::Gcm1).unwrap();
+        let written = std::fs::read_to_string(encrypted).unwrap();
+        assert!(written.ends_with('\n') && !written.contains("SECRET"));
+        decrypt_file(encrypted, decrypted, false).unwrap();
+        assert_eq!(std::fs::read_to_string(decrypted).unwrap(), content);
+
+        // Existing outputs are only replaced with force
+        assert_eq!(
+            decrypt_file(encrypted, decrypted, false),
+            Err(format!(
+                "{} already exists, use --force to overwrite it",
+                decrypted
+            ))
+        );
+        std::fs::write(plain, "no trailing newline").unwrap();
+        encrypt_file(plain, encrypted, true, KeyCipher::Cbc).unwrap();
+        decrypt_file(encrypted, decrypted, true).unwrap();
+        assert_eq!(
+            std::fs::read_to_string(decrypted).unwrap(),
+            "no trailing newline"
+        );
+
+        for path in [plain, encrypted, decrypted] {
+            std::fs::remove_file(path).unwrap();
+        }
+    }
+
+    #[test]
+    fn replacing_a_file_keeps_its_permissions() {
+        let dir = env::temp_dir().join(format!("pippo-replace-{}", uuid::Uuid::new_v4()));
+        std::fs::create_dir(&dir).unwrap();
+        let path = dir.join("variables.yml");
+        std::fs::write(&path, "old").unwrap();
+        #[cfg(unix)]
+        {
+            use std::os::unix::fs::PermissionsExt;
+            std::fs::set_permissions(&path, std::fs::Permissions::from_mode(0o600)).unwrap();
+        }
+
+        replace_file(path.to_str().unwrap(), b"new").unwrap();
+        assert_eq!(std::fs::read_to_string(&path).unwrap(), "new");
+        #[cfg(unix)]
+        {
+            use std::os::unix::fs::PermissionsExt;
+            let mode = std::fs::metadata(&path).unwrap().permissions().mode();
+            assert_eq!(mode & 0o777, 0o600);
+        }
+        // Nothing but the file is left behind
+        assert_eq!(std::fs::read_dir(&dir).unwrap().count(), 1);
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn rotation_keeps_everything_but_the_encrypted_values() {
+        let input = format!(
+            "# secrets of intranet\nprograms:\n  - id: 1\n    environments:\n      - id: 2\n        variables:\n          - name: A\n            value: $enc {}\n            type: secretString\n          - name: B\n            value: \"$enc {}\" # quoted\n            type: secretString\n",
+            encrypt_with_key("old", "first"),
+            encrypt_with_key("old", "sëcond")
+        );
+        let rotation = rotate_encrypted_values(&input, "old", "new").unwrap();
+        assert_eq!(rotation.lines, vec![8, 11]);
+
+        let old_lines: Vec<&str> = input.lines().collect();
+        let new_lines: Vec<&str> = rotation.output.lines().collect();
+        assert_eq!(old_lines.len(), new_lines.len());
+        for (index, (old, new)) in old_lines.iter().zip(&new_lines).enumerate() {
+            if rotation.lines.contains(&(index + 1)) {
+                assert_ne!(old, new);
+            } else {
+                assert_eq!(old, new);
+            }
+        }
+        assert!(new_lines[10].ends_with("\" # quoted"));
+        let rotated = |line: &str| {
+            let base64 = line
+                .split("$enc ")
+                .nth(1)
+                .unwrap()
+                .split('"')
+                .next()
+                .unwrap()
+                .to_string();
+            decrypt_with_key("new", &base64)
+        };
+        assert_eq!(rotated(new_lines[7]), Ok(String::from("first")));
+        assert_eq!(rotated(new_lines[10]), Ok(String::from("sëcond")));
+    }
+
+    #[test]
+    fn rotation_fails_if_a_value_has_another_key() {
+        let input = format!(
+            "- value: $enc {}\n- value: $enc {}\n",
+            encrypt_with_key("old", "first"),
+            encrypt_with_key("foreign", "second")
+        );
+        let err = rotate_encrypted_values(&input, "old", "new").unwrap_err();
+        assert!(
+            err.starts_with("Could not decrypt with the current key, nothing was written: line 2:")
+        );
+    }
+
+    #[test]
+    fn rotation_only_rewrites_variable_values() {
+        let encrypted = format!("$enc {}", encrypt_with_key("old", "first"));
+        let input = format!(
+            "# was {0}\n- name: A\n  value: '{0}'\n- name: B\n  value: not {0}\n  description: {0}\n",
+            encrypted
+        );
+        let rotation = rotate_encrypted_values(&input, "old", "new").unwrap();
+        assert_eq!(rotation.lines, vec![3]);
+
+        let old_lines: Vec<&str> = input.lines().collect();
+        let new_lines: Vec<&str> = rotation.output.lines().collect();
+        assert_eq!(new_lines[0], old_lines[0]);
+        assert_eq!(&new_lines[3..], &old_lines[3..]);
+        let rotated = new_lines[2]
+            .strip_prefix("  value: '$enc ")
+            .and_then(|value| value.strip_suffix('\''))
+            .unwrap();
+        assert_eq!(decrypt_with_key("new", rotated), Ok(String::from("first")));
+    }
+
+    /// Reads the identity of the age fixtures
+    fn test_identities() -> Vec<x25519::Identity> {
+        parse_identities(&std::fs::read_to_string("test/test_age_identity.txt").unwrap()).unwrap()
+    }
+
+    #[test]
+    fn age_values_are_parsed() {
+        let value = EncryptedValue::parse("$enc:age YWdl").unwrap();
+        assert_eq!(value.cipher, Cipher::Age);
+        assert_eq!(value.key_id, None);
+        assert_eq!(value.to_string(), "$enc:age YWdl");
+        assert!(EncryptedValue::parse("$enc:age:v2 YWdl").is_err());
+        assert!(EncryptedValue::parse("$enc:v2:age YWdl").is_err());
+        assert!(parse_key_id("age").is_err());
+        assert!(KeyCipher::from_str("age").is_err());
+        assert_eq!(value.cipher.key_cipher(), None);
+    }
+
+    #[test]
+    fn age_round_trip() {
+        let recipients = read_recipients("test/test_age_recipients.txt").unwrap();
+        assert_eq!(recipients.len(), 2);
+        let encrypted = encrypt_age(&recipients, "s3cret ünicode".as_bytes());
+        assert_eq!(
+            decrypt_age(&test_identities(), &encrypted),
+            Ok("s3cret ünicode".as_bytes().to_vec())
+        );
+
+        // No other test reads PIPPO_AGE_IDENTITY
+        env::set_var(
+            AGE_IDENTITY_ENV,
+            std::fs::read_to_string("test/test_age_identity.txt").unwrap(),
+        );
+        let value = EncryptedValue {
+            cipher: Cipher::Age,
+            key_id: None,
+            ciphertext: &encrypted,
+        }
+        .to_string();
+        assert_eq!(
+            decrypt(&EncryptedValue::parse(&value).unwrap()),
+            Ok(String::from("s3cret ünicode"))
+        );
+    }
+
+    #[test]
+    fn age_fails_with_the_wrong_identity() {
+        let recipients =
+            read_recipients("age17whgzual5fz4tfcg65n5xgcjrr5ve08kr3msalkatyfkapg7kqhqkpkukd")
+                .unwrap();
+        let encrypted = encrypt_age(&recipients, b"s3cret");
+        let other = vec![x25519::Identity::generate()];
+        assert_eq!(
+            decrypt_age(&other, &encrypted),
+            Err(DecryptError::NoMatchingIdentity)
+        );
+        let mut modified = BASE64_STANDARD.decode(&encrypted).unwrap();
+        let last = modified.len() - 1;
+        modified[last] ^= 1;
+        assert_eq!(
+            decrypt_age(&test_identities(), &BASE64_STANDARD.encode(modified)),
+            Err(DecryptError::Tampered)
+        );
+    }
+
+    #[test]
+    fn age_keys_are_validated() {
+        assert!(read_recipients("age1invalid").is_err());
+        assert!(read_recipients(" , ").is_err());
+        assert!(read_recipients("test/missing_recipients.txt")
+            .unwrap_err()
+            .starts_with("Unable to read recipients file test/missing_recipients.txt"));
+        assert!(matches!(
+            parse_identities("# only a comment\n"),
+            Err(DecryptError::InvalidIdentity(_))
+        ));
+        assert!(matches!(
+            parse_identities("AGE-SECRET-KEY-1INVALID"),
+            Err(DecryptError::InvalidIdentity(_))
+        ));
+    }
+
+    #[test]
+    fn rotation_leaves_age_values_alone() {
+        let recipients =
+            read_recipients("age17whgzual5fz4tfcg65n5xgcjrr5ve08kr3msalkatyfkapg7kqhqkpkukd")
+                .unwrap();
+        let input = format!(
+            "- value: $enc {}\n- value: $enc:age {}\n",
+            encrypt_with_key("old", "first"),
+            encrypt_age(&recipients, b"second")
+        );
+        let rotation = rotate_encrypted_values(&input, "old", "new").unwrap();
+        assert_eq!(rotation.lines, vec![1]);
+        assert_eq!(rotation.output.lines().nth(1), input.lines().nth(1));
+    }
 }
@@ -1,10 +1,22 @@
-use crate::client::{AdobeConnector, CloudManagerClient};
+use crate::audit;
+use crate::client::{build_http_client, AdobeConnector, CloudManagerClient};
 use crate::errors::throw_adobe_api_error;
-use crate::models::environment::{Environment, EnvironmentsList, EnvironmentsResponse};
-
-use crate::HOST_NAME;
+use crate::exit_code::ExitCode;
+use crate::models::environment::{
+    Environment, EnvironmentId, EnvironmentReadiness, EnvironmentUpdate, EnvironmentsList,
+    EnvironmentsResponse,
+};
+use crate::models::ip_allowlist::BindingTier;
+use crate::models::region::{RegionsList, RegionsResponse};
+use crate::resolve::{resolve_by_name, ResolveError};
+use chrono::Local;
+use colored::Colorize;
 use reqwest::{Error, Method};
-use std::process;
+use serde::Serialize;
+use std::sync::Arc;
+use std::time::{Duration, Instant};
+use tokio::sync::Semaphore;
+use tokio::task::JoinSet;
 
 /// Retrieves all environments of a given program ID.
 ///
@@ -22,16 +34,20 @@ pub async fn get_environments(
     client: &mut CloudManagerClient,
     program_id: u32,
 ) -> Result<EnvironmentsList, Error> {
-    let request_path = format!("{}/api/program/{}/environments", HOST_NAME, program_id);
+    let request_path = format!(
+        "{}/api/program/{}/environments",
+        client.host_name, program_id
+    );
     let response = client
         .perform_request(Method::GET, request_path, None::<()>, None)
         .await?
         .text()
         .await?;
+    client.print_raw_response(&response);
     let environments: EnvironmentsResponse = serde_json::from_str(response.as_str())
         .unwrap_or_else(|_| {
             throw_adobe_api_error(response);
-            process::exit(1);
+            audit::exit(1);
         });
     Ok(environments.environments_list)
 }
@@ -42,7 +58,7 @@ pub async fn get_environments(
 ///
 /// * `client` - A mutable reference to a CloudManagerClient instance
 /// * `program_id` - A u32 that holds the program ID
-/// * `env_id` - A u32 that holds the environment ID
+/// * `env_id` - An EnvironmentId that holds the environment ID
 ///
 /// # Performed API Request
 ///
@@ -52,20 +68,1501 @@ pub async fn get_environments(
 pub async fn get_environment(
     client: &mut CloudManagerClient,
     program_id: u32,
-    env_id: u32,
+    env_id: EnvironmentId,
 ) -> Result<Environment, Error> {
     let request_path = format!(
         "{}/api/program/{}/environment/{}",
-        HOST_NAME, program_id, env_id
+        client.host_name, program_id, env_id
     );
     let response = client
         .perform_request(Method::GET, request_path, None::<()>, None)
         .await?
         .text()
         .await?;
+    client.print_raw_response(&response);
+    let environment: Environment = serde_json::from_str(response.as_str()).unwrap_or_else(|_| {
+        throw_adobe_api_error(response);
+        audit::exit(1);
+    });
+    Ok(environment)
+}
+
+/// Updates the description of an environment and returns the updated environment.
+///
+/// # Arguments
+///
+/// * `client` - A mutable reference to a CloudManagerClient instance
+/// * `program_id` - A u32 that holds the program ID
+/// * `env_id` - An EnvironmentId that holds the environment ID
+/// * `update` - A reference to the EnvironmentUpdate that holds the new description
+///
+/// # Performed API Request
+///
+/// ```
+/// PATCH https://cloudmanager.adobe.io/api/program/{program_id}/environment/{env_id}
+/// ```
+pub async fn update_environment(
+    client: &mut CloudManagerClient,
+    program_id: u32,
+    env_id: EnvironmentId,
+    update: &EnvironmentUpdate,
+) -> Result<Environment, Error> {
+    let request_path = format!(
+        "{}/api/program/{}/environment/{}",
+        client.host_name, program_id, env_id
+    );
+    let response = client
+        .perform_request(Method::PATCH, request_path, Some(update), None)
+        .await?
+        .text()
+        .await?;
     let environment: Environment = serde_json::from_str(response.as_str()).unwrap_or_else(|_| {
         throw_adobe_api_error(response);
-        process::exit(1);
+        audit::exit(1);
     });
     Ok(environment)
 }
+
+/// Retrieves the regions that environments of a program can be created in.
+///
+/// # Arguments
+///
+/// * `client` - A mutable reference to a CloudManagerClient instance
+/// * `program_id` - A u32 that holds the program ID
+///
+/// # Performed API Request
+///
+/// ```
+/// GET https://cloudmanager.adobe.io/api/program/{program_id}/regions
+/// ```
+pub async fn get_regions(
+    client: &mut CloudManagerClient,
+    program_id: u32,
+) -> Result<RegionsList, Error> {
+    let request_path = format!("{}/api/program/{}/regions", client.host_name, program_id);
+    let response = client
+        .perform_request(Method::GET, request_path, None::<()>, None)
+        .await?
+        .text()
+        .await?;
+    client.print_raw_response(&response);
+    let regions: RegionsResponse = serde_json::from_str(response.as_str()).unwrap_or_else(|_| {
+        throw_adobe_api_error(response);
+        audit::exit(1);
+    });
+    Ok(regions.regions_list)
+}
+
+/// Checks that a region is one of the regions of a program, so environment creation can fail
+/// early with the list of valid values.
+///
+/// # Arguments
+///
+/// * `regions` - A reference to the regions of the program
+/// * `region` - String slice that holds the requested region ID
+pub fn check_region(regions: &RegionsList, region: &str) -> Result<(), String> {
+    if regions.regions.iter().any(|r| r.id == region) {
+        Ok(())
+    } else {
+        Err(format!(
+            "unknown region '{}', valid regions are: {}",
+            region,
+            regions
+                .regions
+                .iter()
+                .map(|r| r.id.as_str())
+                .collect::<Vec<_>>()
+                .join(", ")
+        ))
+    }
+}
+
+/// Resolves the ID of an environment by its name.
+///
+/// # Arguments
+///
+/// * `client` - A mutable reference to a CloudManagerClient instance
+/// * `program_id` - A u32 that holds the program ID
+/// * `env_name` - String slice that holds the name of the environment (case-insensitive)
+pub async fn resolve_environment_id(
+    client: &mut CloudManagerClient,
+    program_id: u32,
+    env_name: &str,
+) -> Result<EnvironmentId, ResolveError> {
+    let environments = get_environments(client, program_id).await?;
+    find_environment_id(&environments, env_name)
+}
+
+/// Finds the ID of the environment with the given name in a list of environments.
+fn find_environment_id(
+    environments: &EnvironmentsList,
+    env_name: &str,
+) -> Result<EnvironmentId, ResolveError> {
+    let env = resolve_by_name(&environments.environments, env_name, |e| e.name.as_str())?;
+    Ok(env.id)
+}
+
+/// Watches an environment's status until it is ready, printing a line on every status change.
+///
+/// Returns `true` when the environment became ready and `false` when `max_wait` ran out before.
+///
+/// # Arguments
+///
+/// * `client` - A mutable reference to a CloudManagerClient instance
+/// * `program_id` - A u32 that holds the program ID
+/// * `env_id` - An EnvironmentId that holds the environment ID
+/// * `interval` - Seconds to wait between two polls
+/// * `max_wait` - Optional maximum duration to watch
+pub async fn watch_environment(
+    client: &mut CloudManagerClient,
+    program_id: u32,
+    env_id: EnvironmentId,
+    interval: Duration,
+    max_wait: Option<Duration>,
+) -> Result<bool, Error> {
+    watch_environment_until(client, program_id, env_id, "ready", interval, max_wait).await
+}
+
+/// Watches an environment's status until it reaches `target_status`, printing a line on every
+/// status change.
+///
+/// Returns `true` when the status was reached and `false` when `max_wait` ran out before.
+async fn watch_environment_until(
+    client: &mut CloudManagerClient,
+    program_id: u32,
+    env_id: EnvironmentId,
+    target_status: &str,
+    interval: Duration,
+    max_wait: Option<Duration>,
+) -> Result<bool, Error> {
+    let started = Instant::now();
+    let mut watcher = StatusWatcher::default();
+    loop {
+        let env = get_environment(client, program_id, env_id).await?;
+        if let Some(change) = watcher.observe(&env.status) {
+            let timestamp = Local::now().format("%Y-%m-%d %H:%M:%S");
+            match change.from {
+                None => println!("{} {}: {}", timestamp, env.name, change.to),
+                Some(from) => println!("{} {}: {} → {}", timestamp, env.name, from, change.to),
+            }
+        }
+        if env.status == target_status {
+            return Ok(true);
+        }
+        if let Some(max_wait) = max_wait {
+            if started.elapsed() >= max_wait {
+                return Ok(false);
+            }
+        }
+        tokio::time::sleep(interval).await;
+    }
+}
+
+/// Checks if an environment is ready, optionally waiting for it to become ready.
+///
+/// Waiting stops early once the environment is in a status it doesn't leave for `ready` on its
+/// own, e.g. `failed` or `hibernated`.
+///
+/// # Arguments
+///
+/// * `client` - A mutable reference to a CloudManagerClient instance
+/// * `program_id` - A u32 that holds the program ID
+/// * `env_id` - An EnvironmentId that holds the environment ID
+/// * `max_wait` - If set, poll until the environment is ready or this duration ran out
+/// * `interval` - Time between two polls while waiting
+pub async fn check_environment_ready(
+    client: &mut CloudManagerClient,
+    program_id: u32,
+    env_id: EnvironmentId,
+    max_wait: Option<Duration>,
+    interval: Duration,
+) -> Result<EnvironmentReadiness, Error> {
+    let started = Instant::now();
+    loop {
+        let env = get_environment(client, program_id, env_id).await?;
+        let ready = env.status == "ready";
+        let waited_enough = match max_wait {
+            None => true,
+            Some(max_wait) => started.elapsed() >= max_wait,
+        };
+        if ready || waited_enough || !becomes_ready_by_itself(&env.status) {
+            return Ok(EnvironmentReadiness {
+                status: env.status,
+                ready,
+            });
+        }
+        tokio::time::sleep(interval).await;
+    }
+}
+
+/// Returns whether an environment in this status turns `ready` without anybody acting on it.
+///
+/// # Arguments
+///
+/// * `status` - String slice that holds the status of the environment
+pub fn becomes_ready_by_itself(status: &str) -> bool {
+    matches!(status, "creating" | "updating" | "dehibernating")
+}
+
+/// Maps the status of an environment to the exit code of `env ready`.
+///
+/// * `Success` (0) - the environment is ready
+/// * `NotReady` (6) - the environment is busy or hibernated and will (or can) become ready later
+/// * `Failure` (1) - every other status, e.g. failed or deleting
+pub fn readiness_exit_code(status: &str) -> ExitCode {
+    match status {
+        "ready" => ExitCode::Success,
+        "creating" | "updating" | "hibernating" | "hibernated" | "dehibernating" => {
+            ExitCode::NotReady
+        }
+        _ => ExitCode::Failure,
+    }
+}
+
+/// Moving an environment into or out of hibernation
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum HibernationAction {
+    Hibernate,
+    Dehibernate,
+}
+
+impl HibernationAction {
+    /// Last segment of the API path that triggers the action
+    fn endpoint(&self) -> &'static str {
+        match self {
+            HibernationAction::Hibernate => "hibernate",
+            HibernationAction::Dehibernate => "dehibernate",
+        }
+    }
+
+    /// Status the environment has once the action is done
+    pub fn target_status(&self) -> &'static str {
+        match self {
+            HibernationAction::Hibernate => "hibernated",
+            HibernationAction::Dehibernate => "ready",
+        }
+    }
+}
+
+/// Outcome of hibernating or de-hibernating an environment
+#[derive(Debug, PartialEq)]
+pub enum HibernationOutcome {
+    /// The environment already had the target status, nothing was done
+    Unchanged,
+    /// The action was triggered (and, with `--wait`, the target status was reached)
+    Done,
+    /// The target status was not reached within the maximum wait time
+    TimedOut,
+    /// The environment can't be hibernated or the API rejected the request
+    Failed,
+}
+
+/// Checks whether an environment supports hibernation, which only dev environments do.
+///
+/// # Arguments
+///
+/// * `env` - A reference to the environment
+pub fn check_hibernation_supported(env: &Environment) -> Result<(), String> {
+    if env.env_type == "dev" {
+        Ok(())
+    } else {
+        Err(format!(
+            "environment {} ({}) is of type '{}', only dev environments can be hibernated",
+            env.name, env.id, env.env_type
+        ))
+    }
+}
+
+/// Returns all dev environments of a list of environments.
+///
+/// # Arguments
+///
+/// * `environments` - A reference to the environments of a program
+pub fn dev_environments(environments: &EnvironmentsList) -> Vec<&Environment> {
+    environments
+        .environments
+        .iter()
+        .filter(|env| check_hibernation_supported(env).is_ok())
+        .collect()
+}
+
+/// Hibernates or de-hibernates an environment and optionally waits for the target status.
+///
+/// # Arguments
+///
+/// * `client` - A mutable reference to a CloudManagerClient instance
+/// * `program_id` - A u32 that holds the program ID
+/// * `env_id` - An EnvironmentId that holds the environment ID
+/// * `action` - Whether to hibernate or de-hibernate
+/// * `max_wait` - If set, poll until the target status is reached or this duration ran out
+/// * `interval` - Time between two polls while waiting
+///
+/// # Performed API Request
+///
+/// ```
+/// POST https://cloudmanager.adobe.io/api/program/{program_id}/environment/{env_id}/hibernate
+/// POST https://cloudmanager.adobe.io/api/program/{program_id}/environment/{env_id}/dehibernate
+/// ```
+pub async fn change_hibernation(
+    client: &mut CloudManagerClient,
+    program_id: u32,
+    env_id: EnvironmentId,
+    action: HibernationAction,
+    max_wait: Option<Duration>,
+    interval: Duration,
+) -> Result<HibernationOutcome, Error> {
+    let env = get_environment(client, program_id, env_id).await?;
+    println!("{:>4} Environment: {} ({})", "⬛", env.id, env.name);
+    if let Err(err) = check_hibernation_supported(&env) {
+        eprintln!("{:>8} {}", "❌".red(), err.red());
+        return Ok(HibernationOutcome::Failed);
+    }
+    if env.status == action.target_status() {
+        println!("{:>8} already {}", "✔", env.status);
+        return Ok(HibernationOutcome::Unchanged);
+    }
+
+    let request_path = format!(
+        "{}/api/program/{}/environment/{}/{}",
+        client.host_name,
+        program_id,
+        env_id,
+        action.endpoint()
+    );
+    let response = client
+        .perform_request(Method::POST, request_path, None::<()>, None)
+        .await?;
+    let status_code = response.status();
+    if !status_code.is_success() {
+        let response_text = response.text().await?;
+        eprintln!(
+            "{:>8} {} failed ({}): {}",
+            "❌".red(),
+            action.endpoint(),
+            status_code,
+            response_text
+        );
+        return Ok(HibernationOutcome::Failed);
+    }
+    println!("{:>8} {} triggered", "✔", action.endpoint());
+
+    if let Some(max_wait) = max_wait {
+        let reached = watch_environment_until(
+            client,
+            program_id,
+            env_id,
+            action.target_status(),
+            interval,
+            Some(max_wait),
+        )
+        .await?;
+        if !reached {
+            return Ok(HibernationOutcome::TimedOut);
+        }
+    }
+    Ok(HibernationOutcome::Done)
+}
+
+/// Result of checking whether a tier of an environment answers HTTP requests
+#[derive(Debug, Serialize)]
+pub struct TierPing {
+    pub tier: BindingTier,
+    pub url: String,
+    pub status: Option<u16>,
+    pub latency_ms: u128,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub error: Option<String>,
+}
+
+impl TierPing {
+    /// A tier counts as reachable when it answers with anything but a server error
+    pub fn reachable(&self) -> bool {
+        matches!(self.status, Some(status) if status < 500)
+    }
+}
+
+/// Returns the URLs of the tiers of an environment, optionally restricted to a single tier.
+///
+/// # Arguments
+///
+/// * `env` - A reference to the environment
+/// * `tier` - If set, only the URL of this tier is returned
+pub fn tier_urls(env: &Environment, tier: Option<&BindingTier>) -> Vec<(BindingTier, String)> {
+    let links = match &env.links {
+        Some(links) => links,
+        None => return vec![],
+    };
+    vec![
+        (BindingTier::Author, &links.author),
+        (BindingTier::Publish, &links.publish),
+        (BindingTier::Preview, &links.preview),
+    ]
+    .into_iter()
+    .filter(|(t, _)| tier.is_none_or(|wanted| wanted == t))
+    .filter_map(|(t, link)| link.as_ref().map(|link| (t, link.href.clone())))
+    .collect()
+}
+
+/// Sends an unauthenticated GET request to a tier URL and measures how long the answer took.
+///
+/// The given HTTP client must not carry the Cloud Manager credentials, since the request goes
+/// straight to the AEM host.
+///
+/// # Arguments
+///
+/// * `http` - An HTTP client without the Adobe auth headers
+/// * `tier` - The tier that is checked
+/// * `url` - String slice that holds the URL of the tier
+pub async fn ping_tier(http: &reqwest::Client, tier: BindingTier, url: &str) -> TierPing {
+    let started = Instant::now();
+    let result = http.get(url).send().await;
+    let latency_ms = started.elapsed().as_millis();
+    match result {
+        Ok(response) => TierPing {
+            tier,
+            url: url.to_string(),
+            status: Some(response.status().as_u16()),
+            latency_ms,
+            error: None,
+        },
+        Err(err) => TierPing {
+            tier,
+            url: url.to_string(),
+            status: None,
+            latency_ms,
+            error: Some(err.to_string()),
+        },
+    }
+}
+
+/// Checks all (or a single) tiers of an environment for reachability.
+///
+/// # Arguments
+///
+/// * `client` - A mutable reference to a CloudManagerClient instance
+/// * `program_id` - A u32 that holds the program ID
+/// * `env_id` - An EnvironmentId that holds the environment ID
+/// * `tier` - If set, only this tier is checked
+/// * `timeout` - Maximum time to wait for each tier to answer
+pub async fn ping_environment(
+    client: &mut CloudManagerClient,
+    program_id: u32,
+    env_id: EnvironmentId,
+    tier: Option<&BindingTier>,
+    timeout: Duration,
+) -> Result<Vec<TierPing>, String> {
+    let env = get_environment(client, program_id, env_id)
+        .await
+        .map_err(|err| err.to_string())?;
+    // Built from the config like the shared client, so proxy and TLS settings apply, but with a
+    // total timeout. The Adobe auth headers are only added to API requests.
+    let http = build_http_client(&client.config, Some(timeout))?;
+    let mut pings = vec![];
+    for (tier, url) in tier_urls(&env, tier) {
+        pings.push(ping_tier(&http, tier, &url).await);
+    }
+    Ok(pings)
+}
+
+/// Environment variable that holds the purge key of the CDN, see `env flush-cache`
+pub const PURGE_KEY_ENV: &str = "PIPPO_PURGE_KEY";
+
+/// What `env flush-cache` removes from the CDN cache of an environment
+#[derive(Debug, PartialEq)]
+pub enum PurgeTarget {
+    /// A single resource, e.g. `/content/site/en.html`
+    Path(String),
+    /// Every resource that was delivered with one of these `Surrogate-Key` values
+    SurrogateKeys(Vec<String>),
+    /// The whole CDN cache of the environment
+    All,
+}
+
+impl PurgeTarget {
+    /// Returns the URL that the purge request for this target is sent to.
+    ///
+    /// # Arguments
+    ///
+    /// * `base_url` - String slice that holds the URL of the publish tier or of a custom domain
+    pub fn url(&self, base_url: &str) -> String {
+        let base_url = base_url.trim_end_matches('/');
+        match self {
+            PurgeTarget::Path(path) => format!("{}/{}", base_url, path.trim_start_matches('/')),
+            PurgeTarget::SurrogateKeys(_) | PurgeTarget::All => format!("{}/", base_url),
+        }
+    }
+}
+
+impl std::fmt::Display for PurgeTarget {
+    fn fmt(&self, formatter: &mut std::fmt::Formatter) -> std::fmt::Result {
+        match self {
+            PurgeTarget::Path(path) => write!(formatter, "{}", path),
+            PurgeTarget::SurrogateKeys(keys) => {
+                write!(formatter, "surrogate keys {}", keys.join(" "))
+            }
+            PurgeTarget::All => write!(formatter, "everything"),
+        }
+    }
+}
+
+/// Result of a single purge request against the CDN of an environment
+#[derive(Debug, Serialize)]
+pub struct ContentPurge {
+    pub target: String,
+    pub url: String,
+    pub soft: bool,
+    pub status: Option<u16>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub error: Option<String>,
+}
+
+impl ContentPurge {
+    /// The CDN confirms a purge with a 2xx status
+    pub fn purged(&self) -> bool {
+        matches!(self.status, Some(status) if (200..300).contains(&status))
+    }
+}
+
+/// Returns the URL that purge requests are sent to: the given domain or, without one, the
+/// publish tier of the environment.
+///
+/// # Arguments
+///
+/// * `env` - A reference to the environment
+/// * `domain` - A custom domain that is served by the CDN of the environment, with or without scheme
+pub fn purge_base_url(env: &Environment, domain: Option<&str>) -> Option<String> {
+    match domain {
+        Some(domain) if domain.contains("://") => Some(domain.to_string()),
+        Some(domain) => Some(format!("https://{}", domain)),
+        None => tier_urls(env, Some(&BindingTier::Publish))
+            .into_iter()
+            .next()
+            .map(|(_, url)| url),
+    }
+}
+
+/// Sends a `PURGE` request for one target to the CDN in front of an environment.
+///
+/// This purges the content cache of the CDN. It has nothing to do with the build cache of a
+/// pipeline, which `pipeline invalidate-cache` clears via Cloud Manager.
+///
+/// # Arguments
+///
+/// * `http` - The HTTP client of the run, without the Adobe auth headers
+/// * `base_url` - String slice that holds the URL of the publish tier or of a custom domain
+/// * `purge_key` - The purge key that the CDN of the environment is configured with
+/// * `target` - What to purge
+/// * `soft` - Mark the cached content as stale instead of removing it
+pub async fn purge_content(
+    http: &reqwest::Client,
+    base_url: &str,
+    purge_key: &str,
+    target: &PurgeTarget,
+    soft: bool,
+) -> ContentPurge {
+    let url = target.url(base_url);
+    let mut request = http
+        .request(Method::from_bytes(b"PURGE").unwrap(), &url)
+        .header("X-AEM-Purge-Key", purge_key);
+    match target {
+        PurgeTarget::SurrogateKeys(keys) => {
+            request = request.header("Surrogate-Key", keys.join(" "));
+        }
+        PurgeTarget::All => request = request.header("X-AEM-Purge", "all"),
+        PurgeTarget::Path(_) => {}
+    }
+    if soft {
+        request = request.header("X-AEM-Purge", "soft");
+    }
+    let (status, error) = match request.send().await {
+        Ok(response) => (Some(response.status().as_u16()), None),
+        Err(err) => (None, Some(err.to_string())),
+    };
+    ContentPurge {
+        target: target.to_string(),
+        url,
+        soft,
+        status,
+        error,
+    }
+}
+
+/// Purges the CDN content cache of an environment, one request per target. Without a domain
+/// and without a publish tier nothing is purged and the result is empty.
+///
+/// # Arguments
+///
+/// * `client` - A mutable reference to a CloudManagerClient instance
+/// * `program_id` - A u32 that holds the program ID
+/// * `env_id` - An EnvironmentId that holds the environment ID
+/// * `domain` - If set, the purge requests go to this domain instead of the publish tier
+/// * `purge_key` - The purge key that the CDN of the environment is configured with
+/// * `targets` - What to purge
+/// * `soft` - Mark the cached content as stale instead of removing it
+///
+/// # Performed Requests
+///
+/// ```
+/// GET https://cloudmanager.adobe.io/api/program/{program_id}/environment/{env_id}
+/// PURGE https://publish-p{program_id}-e{env_id}.adobeaemcloud.com/{path}
+/// ```
+pub async fn flush_content_cache(
+    client: &mut CloudManagerClient,
+    program_id: u32,
+    env_id: EnvironmentId,
+    domain: Option<&str>,
+    purge_key: &str,
+    targets: &[PurgeTarget],
+    soft: bool,
+) -> Result<Vec<ContentPurge>, Error> {
+    let env = get_environment(client, program_id, env_id).await?;
+    let base_url = match purge_base_url(&env, domain) {
+        Some(base_url) => base_url,
+        None => return Ok(vec![]),
+    };
+    // The shared client keeps the proxy and TLS settings, the Adobe auth headers are only added
+    // to API requests
+    let mut purges = vec![];
+    for target in targets {
+        purges.push(purge_content(&client.client, &base_url, purge_key, target, soft).await);
+    }
+    Ok(purges)
+}
+
+/// Environments of a single program as shown by `env overview`
+#[derive(Debug, Serialize)]
+pub struct ProgramOverview {
+    pub program_id: String,
+    pub program_name: String,
+    pub environments: Vec<EnvironmentOverview>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub error: Option<String>,
+}
+
+/// A single environment as shown by `env overview`
+#[derive(Debug, Serialize)]
+pub struct EnvironmentOverview {
+    pub id: EnvironmentId,
+    pub name: String,
+    #[serde(rename = "type")]
+    pub env_type: String,
+    pub status: String,
+    pub region: Option<String>,
+    pub ready: bool,
+}
+
+/// Retrieves all environments of a program and reports failures instead of exiting.
+///
+/// # Arguments
+///
+/// * `client` - A mutable reference to a CloudManagerClient instance
+/// * `program_id` - A u32 that holds the program ID
+pub async fn try_get_environments(
+    client: &mut CloudManagerClient,
+    program_id: u32,
+) -> Result<EnvironmentsList, String> {
+    let request_path = format!(
+        "{}/api/program/{}/environments",
+        client.host_name, program_id
+    );
+    client
+        .try_get::<EnvironmentsResponse>(request_path, None)
+        .await
+        .map(|response| response.environments_list)
+}
+
+/// Collects the environments of several programs, fetching at most `concurrency` programs at the
+/// same time. The result keeps the order of `programs`.
+///
+/// # Arguments
+///
+/// * `client` - A reference to a CloudManagerClient instance, clones of it are used per program
+/// * `programs` - IDs and names of the programs
+/// * `concurrency` - Maximum number of programs that are fetched in parallel
+pub async fn get_environments_overview(
+    client: &CloudManagerClient,
+    programs: Vec<(u32, String)>,
+    concurrency: usize,
+) -> Vec<ProgramOverview> {
+    let semaphore = Arc::new(Semaphore::new(concurrency.max(1)));
+    let mut tasks = JoinSet::new();
+    for (index, (program_id, program_name)) in programs.into_iter().enumerate() {
+        let mut client = client.clone();
+        let semaphore = semaphore.clone();
+        tasks.spawn(async move {
+            let _permit = semaphore.acquire_owned().await.unwrap();
+            let result = try_get_environments(&mut client, program_id).await;
+            (index, summarize_program(program_id, program_name, result))
+        });
+    }
+    let mut overviews = vec![];
+    while let Some(joined) = tasks.join_next().await {
+        overviews.push(joined.expect("Fetching environments panicked"));
+    }
+    overviews.sort_by_key(|(index, _)| *index);
+    overviews
+        .into_iter()
+        .map(|(_, overview)| overview)
+        .collect()
+}
+
+/// Builds the overview of a program from the result of fetching its environments.
+///
+/// # Arguments
+///
+/// * `program_id` - A u32 that holds the program ID
+/// * `program_name` - Name of the program
+/// * `result` - The environments of the program or the reason why they couldn't be fetched
+pub fn summarize_program(
+    program_id: u32,
+    program_name: String,
+    result: Result<EnvironmentsList, String>,
+) -> ProgramOverview {
+    let (environments, error) = match result {
+        Ok(list) => (
+            list.environments
+                .into_iter()
+                .map(|env| EnvironmentOverview {
+                    ready: env.status == "ready",
+                    id: env.id,
+                    name: env.name,
+                    env_type: env.env_type,
+                    status: env.status,
+                    region: env.region,
+                })
+                .collect(),
+            None,
+        ),
+        Err(err) => (vec![], Some(err)),
+    };
+    ProgramOverview {
+        program_id: program_id.to_string(),
+        program_name,
+        environments,
+        error,
+    }
+}
+
+/// Renders the overview as lines grouped by program, flagging environments that are not ready.
+///
+/// # Arguments
+///
+/// * `overviews` - The overviews of all programs
+pub fn render_overview(overviews: &[ProgramOverview]) -> Vec<String> {
+    let mut lines = vec![];
+    for program in overviews {
+        lines.push(format!(
+            "☁ Program: {} ({})",
+            program.program_name, program.program_id
+        ));
+        if let Some(err) = &program.error {
+            lines.push(format!("{:>4} unable to fetch environments: {}", "❌", err));
+        }
+        for env in &program.environments {
+            lines.push(format!(
+                "{:>4} {:<24} {:<6} {:<14} {}",
+                if env.ready { "✔" } else { "⚠" },
+                env.name,
+                env.env_type,
+                env.status,
+                env.region.as_deref().unwrap_or("-")
+            ));
+        }
+    }
+    lines
+}
+
+/// A change of an environment's status between two polls
+#[derive(Debug, PartialEq)]
+pub struct StatusChange {
+    pub from: Option<String>,
+    pub to: String,
+}
+
+/// Keeps track of the last seen status of an environment to detect changes between polls
+#[derive(Default)]
+pub struct StatusWatcher {
+    last_status: Option<String>,
+}
+
+impl StatusWatcher {
+    /// Records a polled status and returns the change if it differs from the last one.
+    /// The very first observation always counts as a change.
+    pub fn observe(&mut self, status: &str) -> Option<StatusChange> {
+        if self.last_status.as_deref() == Some(status) {
+            return None;
+        }
+        let from = self.last_status.replace(status.to_string());
+        Some(StatusChange {
+            from,
+            to: status.to_string(),
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::client::mock::{MockResponse, MockServer};
+    use crate::models::tests::read_json_from_file;
+
+    #[test]
+    fn find_environment_id_by_name() {
+        let response: EnvironmentsResponse =
+            read_json_from_file("test/test_environment_response.json").unwrap();
+        let environments = response.environments_list;
+
+        assert_eq!(
+            find_environment_id(&environments, "demo-stage"),
+            Ok(EnvironmentId(117454))
+        );
+        assert_eq!(
+            find_environment_id(&environments, "Development"),
+            Ok(EnvironmentId(33333))
+        );
+        assert!(matches!(
+            find_environment_id(&environments, "demo-dev"),
+            Err(ResolveError::NotFound { .. })
+        ));
+    }
+
+    #[test]
+    fn hibernation_is_only_supported_for_dev_environments() {
+        let response: EnvironmentsResponse =
+            read_json_from_file("test/test_environment_response.json").unwrap();
+        let environments = response.environments_list;
+
+        for env in &environments.environments {
+            let supported = check_hibernation_supported(env);
+            if env.env_type == "dev" {
+                assert!(supported.is_ok());
+            } else {
+                assert!(supported
+                    .unwrap_err()
+                    .contains("only dev environments can be hibernated"));
+            }
+        }
+    }
+
+    #[test]
+    fn dev_environments_of_program() {
+        let response: EnvironmentsResponse =
+            read_json_from_file("test/test_environment_response.json").unwrap();
+        let environments = response.environments_list;
+
+        let dev = dev_environments(&environments);
+        assert_eq!(dev.len(), 1);
+        assert_eq!(dev[0].env_type, "dev");
+    }
+
+    #[test]
+    fn hibernation_target_status() {
+        assert_eq!(HibernationAction::Hibernate.target_status(), "hibernated");
+        assert_eq!(HibernationAction::Dehibernate.target_status(), "ready");
+        assert_eq!(HibernationAction::Hibernate.endpoint(), "hibernate");
+        assert_eq!(HibernationAction::Dehibernate.endpoint(), "dehibernate");
+    }
+
+    #[test]
+    fn check_region_against_program_regions() {
+        let response: RegionsResponse =
+            read_json_from_file("test/test_regions_response.json").unwrap();
+        let regions = response.regions_list;
+
+        assert!(check_region(&regions, "nld2").is_ok());
+        assert_eq!(
+            check_region(&regions, "mars1"),
+            Err(String::from(
+                "unknown region 'mars1', valid regions are: va7, nld2, aus5"
+            ))
+        );
+    }
+
+    #[test]
+    fn tier_urls_from_environment_links() {
+        let response: EnvironmentsResponse =
+            read_json_from_file("test/test_environment_response.json").unwrap();
+        let env = &response.environments_list.environments[0];
+
+        let urls = tier_urls(env, None);
+        assert_eq!(
+            urls,
+            vec![
+                (
+                    BindingTier::Author,
+                    String::from("https://author-p11111-e222222.adobeaemcloud.com")
+                ),
+                (
+                    BindingTier::Publish,
+                    String::from("https://publish-p11111-e222222.adobeaemcloud.com")
+                ),
+                (
+                    BindingTier::Preview,
+                    String::from("https://preview-p11111-e222222.adobeaemcloud.com")
+                ),
+            ]
+        );
+        assert_eq!(tier_urls(env, Some(&BindingTier::Publish)).len(), 1);
+    }
+
+    /// Starts a server on a random local port that answers every request with the given status line
+    fn serve(status_line: &'static str) -> String {
+        use std::io::{Read, Write};
+        let listener = std::net::TcpListener::bind("127.0.0.1:0").unwrap();
+        let address = listener.local_addr().unwrap();
+        std::thread::spawn(move || {
+            for mut stream in listener.incoming().flatten() {
+                let mut buffer = [0; 1024];
+                let _ = stream.read(&mut buffer);
+                let _ = write!(
+                    stream,
+                    "HTTP/1.1 {}\r\nContent-Length: 0\r\nConnection: close\r\n\r\n",
+                    status_line
+                );
+            }
+        });
+        format!("http://{}", address)
+    }
+
+    #[tokio::test]
+    async fn ping_reachable_tier() {
+        let url = serve("200 OK");
+        let ping = ping_tier(&reqwest::Client::new(), BindingTier::Author, &url).await;
+        assert_eq!(ping.status, Some(200));
+        assert!(ping.reachable());
+    }
+
+    #[tokio::test]
+    async fn ping_tier_with_server_error() {
+        let url = serve("503 Service Unavailable");
+        let ping = ping_tier(&reqwest::Client::new(), BindingTier::Publish, &url).await;
+        assert_eq!(ping.status, Some(503));
+        assert!(!ping.reachable());
+    }
+
+    #[tokio::test]
+    async fn ping_unreachable_tier() {
+        // Bind and release a port, so nothing is listening on it
+        let address = std::net::TcpListener::bind("127.0.0.1:0")
+            .unwrap()
+            .local_addr()
+            .unwrap();
+        let url = format!("http://{}", address);
+        let ping = ping_tier(&reqwest::Client::new(), BindingTier::Preview, &url).await;
+        assert_eq!(ping.status, None);
+        assert!(ping.error.is_some());
+        assert!(!ping.reachable());
+    }
+
+    /// Starts a server that answers a single request with 200 and hands its head to the receiver
+    fn record_request() -> (String, std::sync::mpsc::Receiver<String>) {
+        use std::io::{Read, Write};
+        let listener = std::net::TcpListener::bind("127.0.0.1:0").unwrap();
+        let address = listener.local_addr().unwrap();
+        let (sender, receiver) = std::sync::mpsc::channel();
+        std::thread::spawn(move || {
+            if let Some(mut stream) = listener.incoming().flatten().next() {
+                let mut buffer = [0; 4096];
+                let read = stream.read(&mut buffer).unwrap_or(0);
+                let _ = sender.send(String::from_utf8_lossy(&buffer[..read]).to_lowercase());
+                let _ = write!(
+                    stream,
+                    "HTTP/1.1 200 OK\r\nContent-Length: 0\r\nConnection: close\r\n\r\n"
+                );
+            }
+        });
+        (format!("http://{}", address), receiver)
+    }
+
+    /// A dev environment with the given status as Cloud Manager returns it
+    fn dev_environment(status: &str) -> MockResponse {
+        MockResponse::json(serde_json::json!({
+            "id": "222222",
+            "programId": "11111",
+            "name": "demo-dev",
+            "type": "dev",
+            "status": status
+        }))
+    }
+
+    #[tokio::test]
+    async fn update_sends_the_description_and_returns_the_environment() {
+        let server = MockServer::start();
+        server.route(
+            "PATCH",
+            "/api/program/11111/environment/222222",
+            vec![MockResponse::json(serde_json::json!({
+                "id": "222222",
+                "programId": "11111",
+                "name": "demo-dev",
+                "description": "Sprint 42 QA",
+                "type": "dev",
+                "status": "ready"
+            }))],
+        );
+        let update = EnvironmentUpdate {
+            description: String::from("Sprint 42 QA"),
+        };
+
+        let env = update_environment(&mut server.client(), 11111, EnvironmentId(222222), &update)
+            .await
+            .unwrap();
+
+        assert_eq!(env.description.as_deref(), Some("Sprint 42 QA"));
+        let requests = server.requests();
+        assert_eq!(requests.len(), 1);
+        assert_eq!(requests[0].method, "PATCH");
+        assert_eq!(requests[0].target, "/api/program/11111/environment/222222");
+        assert_eq!(
+            serde_json::from_str::<serde_json::Value>(&requests[0].body).unwrap(),
+            serde_json::json!({"description": "Sprint 42 QA"})
+        );
+    }
+
+    #[tokio::test]
+    async fn hibernate_and_wait_until_hibernated() {
+        let server = MockServer::start();
+        server
+            .route(
+                "GET",
+                "/api/program/11111/environment/222222",
+                vec![
+                    dev_environment("ready"),
+                    dev_environment("hibernating"),
+                    dev_environment("hibernated"),
+                ],
+            )
+            .route(
+                "POST",
+                "/api/program/11111/environment/222222/hibernate",
+                vec![MockResponse::new(202, "")],
+            );
+        let outcome = change_hibernation(
+            &mut server.client(),
+            11111,
+            EnvironmentId(222222),
+            HibernationAction::Hibernate,
+            Some(Duration::from_secs(60)),
+            Duration::from_millis(1),
+        )
+        .await
+        .unwrap();
+        assert_eq!(outcome, HibernationOutcome::Done);
+        let requests = server.requests();
+        let methods: Vec<&str> = requests.iter().map(|r| r.method.as_str()).collect();
+        assert_eq!(methods, vec!["GET", "POST", "GET", "GET"]);
+        assert_eq!(
+            requests[1].target,
+            "/api/program/11111/environment/222222/hibernate"
+        );
+        assert_eq!(requests[1].header("X-Api-Key"), Some("id"));
+        assert_eq!(requests[1].body, "");
+    }
+
+    #[tokio::test]
+    async fn ready_wait_polls_until_ready() {
+        let server = MockServer::start();
+        server.route(
+            "GET",
+            "/api/program/11111/environment/222222",
+            vec![dev_environment("updating"), dev_environment("ready")],
+        );
+        let readiness = check_environment_ready(
+            &mut server.client(),
+            11111,
+            EnvironmentId(222222),
+            Some(Duration::from_secs(60)),
+            Duration::from_millis(1),
+        )
+        .await
+        .unwrap();
+        assert!(readiness.ready);
+        assert_eq!(server.requests().len(), 2);
+    }
+
+    #[tokio::test]
+    async fn ready_wait_stops_when_the_environment_failed() {
+        let server = MockServer::start();
+        server.route(
+            "GET",
+            "/api/program/11111/environment/222222",
+            vec![
+                dev_environment("updating"),
+                dev_environment("failed"),
+                dev_environment("ready"),
+            ],
+        );
+        let readiness = check_environment_ready(
+            &mut server.client(),
+            11111,
+            EnvironmentId(222222),
+            Some(Duration::from_secs(60)),
+            Duration::from_millis(1),
+        )
+        .await
+        .unwrap();
+        assert!(!readiness.ready);
+        assert_eq!(readiness.status, "failed");
+        assert_eq!(server.requests().len(), 2);
+        assert_eq!(readiness_exit_code(&readiness.status), ExitCode::Failure);
+    }
+
+    #[test]
+    fn only_transitions_become_ready_by_themselves() {
+        assert!(becomes_ready_by_itself("updating"));
+        assert!(becomes_ready_by_itself("dehibernating"));
+        assert!(!becomes_ready_by_itself("hibernated"));
+        assert!(!becomes_ready_by_itself("failed"));
+        assert!(!becomes_ready_by_itself("deleting"));
+    }
+
+    #[tokio::test]
+    async fn dehibernate_times_out() {
+        let server = MockServer::start();
+        server
+            .route(
+                "GET",
+                "/api/program/11111/environment/222222",
+                vec![dev_environment("hibernated"), dev_environment("starting")],
+            )
+            .route(
+                "POST",
+                "/api/program/11111/environment/222222/dehibernate",
+                vec![MockResponse::new(202, "")],
+            );
+        let outcome = change_hibernation(
+            &mut server.client(),
+            11111,
+            EnvironmentId(222222),
+            HibernationAction::Dehibernate,
+            Some(Duration::ZERO),
+            Duration::from_millis(1),
+        )
+        .await
+        .unwrap();
+        assert_eq!(outcome, HibernationOutcome::TimedOut);
+    }
+
+    #[tokio::test]
+    async fn rejected_hibernation_fails() {
+        let server = MockServer::start();
+        server
+            .route(
+                "GET",
+                "/api/program/11111/environment/222222",
+                vec![dev_environment("ready")],
+            )
+            .route(
+                "POST",
+                "/api/program/11111/environment/222222/hibernate",
+                vec![MockResponse::new(400, r#"{"title":"not allowed"}"#)],
+            );
+        let outcome = change_hibernation(
+            &mut server.client(),
+            11111,
+            EnvironmentId(222222),
+            HibernationAction::Hibernate,
+            None,
+            Duration::from_millis(1),
+        )
+        .await
+        .unwrap();
+        assert_eq!(outcome, HibernationOutcome::Failed);
+    }
+
+    #[test]
+    fn purge_urls() {
+        let base_url = "https://publish-p11111-e222222.adobeaemcloud.com/";
+        assert_eq!(
+            PurgeTarget::Path(String::from("/content/site/en.html")).url(base_url),
+            "https://publish-p11111-e222222.adobeaemcloud.com/content/site/en.html"
+        );
+        assert_eq!(
+            PurgeTarget::All.url(base_url),
+            "https://publish-p11111-e222222.adobeaemcloud.com/"
+        );
+    }
+
+    #[test]
+    fn purge_base_url_prefers_the_domain() {
+        let response: EnvironmentsResponse =
+            read_json_from_file("test/test_environment_response.json").unwrap();
+        let env = &response.environments_list.environments[0];
+        assert_eq!(
+            purge_base_url(env, None).as_deref(),
+            Some("https://publish-p11111-e222222.adobeaemcloud.com")
+        );
+        assert_eq!(
+            purge_base_url(env, Some("www.example.com")).as_deref(),
+            Some("https://www.example.com")
+        );
+        assert_eq!(
+            purge_base_url(env, Some("http://localhost:8080")).as_deref(),
+            Some("http://localhost:8080")
+        );
+    }
+
+    #[tokio::test]
+    async fn ping_uses_the_http_settings_of_the_config() {
+        let server = MockServer::start();
+        server
+            .route(
+                "GET",
+                "/api/program/11111/environment/222222",
+                vec![MockResponse::json(serde_json::json!({
+                    "id": "222222",
+                    "programId": "11111",
+                    "name": "demo-dev",
+                    "type": "dev",
+                    "status": "ready",
+                    "_links": {
+                        "http://ns.adobe.com/adobecloud/rel/author": {
+                            "href": format!("{}/author", server.url)
+                        }
+                    }
+                }))],
+            )
+            .route("GET", "/author", vec![MockResponse::new(200, "{}")]);
+        let mut client = server.client();
+        client
+            .config
+            .extra_headers
+            .insert(String::from("X-Cost-Center"), String::from("4711"));
+        let pings = ping_environment(
+            &mut client,
+            11111,
+            EnvironmentId(222222),
+            None,
+            Duration::from_secs(5),
+        )
+        .await
+        .unwrap();
+        assert_eq!(pings.len(), 1);
+        assert!(pings[0].reachable());
+        let ping = server.requests().pop().unwrap();
+        assert_eq!(ping.target, "/author");
+        assert_eq!(ping.header("x-cost-center"), Some("4711"));
+        assert_eq!(ping.header("authorization"), None);
+    }
+
+    #[tokio::test]
+    async fn flush_cache_purges_through_the_shared_client() {
+        let server = MockServer::start();
+        server
+            .route(
+                "GET",
+                "/api/program/11111/environment/222222",
+                vec![dev_environment("ready")],
+            )
+            .route(
+                "PURGE",
+                "/content/site/en.html",
+                vec![MockResponse::new(200, "{}")],
+            );
+        let mut client = server.client();
+        let purges = flush_content_cache(
+            &mut client,
+            11111,
+            EnvironmentId(222222),
+            Some(&server.url),
+            "secret",
+            &[PurgeTarget::Path(String::from("/content/site/en.html"))],
+            false,
+        )
+        .await
+        .unwrap();
+        assert!(purges[0].purged());
+        let purge = server.requests().pop().unwrap();
+        assert_eq!(purge.method, "PURGE");
+        assert_eq!(purge.header("x-aem-purge-key"), Some("secret"));
+        assert_eq!(purge.header("authorization"), None);
+    }
+
+    #[tokio::test]
+    async fn purge_a_path_softly() {
+        let (url, request) = record_request();
+        let purge = purge_content(
+            &reqwest::Client::new(),
+            &url,
+            "secret",
+            &PurgeTarget::Path(String::from("/content/site/en.html")),
+            true,
+        )
+        .await;
+        assert!(purge.purged());
+        let request = request.recv().unwrap();
+        assert!(request.starts_with("purge /content/site/en.html http/1.1"));
+        assert!(request.contains("x-aem-purge-key: secret"));
+        assert!(request.contains("x-aem-purge: soft"));
+    }
+
+    #[tokio::test]
+    async fn purge_by_surrogate_keys() {
+        let (url, request) = record_request();
+        let target = PurgeTarget::SurrogateKeys(vec![String::from("nav"), String::from("footer")]);
+        let purge = purge_content(&reqwest::Client::new(), &url, "secret", &target, false).await;
+        assert!(purge.purged());
+        assert_eq!(purge.target, "surrogate keys nav footer");
+        let request = request.recv().unwrap();
+        assert!(request.starts_with("purge / http/1.1"));
+        assert!(request.contains("surrogate-key: nav footer"));
+        assert!(!request.contains("x-aem-purge:"));
+    }
+
+    #[tokio::test]
+    async fn failed_purge() {
+        let url = serve("403 Forbidden");
+        let purge = purge_content(
+            &reqwest::Client::new(),
+            &url,
+            "wrong",
+            &PurgeTarget::All,
+            false,
+        )
+        .await;
+        assert_eq!(purge.status, Some(403));
+        assert!(!purge.purged());
+    }
+
+    #[test]
+    fn overview_of_multiple_programs() {
+        let response: EnvironmentsResponse =
+            read_json_from_file("test/test_environment_response.json").unwrap();
+        let overviews = vec![
+            summarize_program(11111, String::from("demo"), Ok(response.environments_list)),
+            summarize_program(
+                22222,
+                String::from("broken"),
+                Err(String::from("403 Forbidden: {}")),
+            ),
+        ];
+
+        assert_eq!(overviews[0].environments.len(), 4);
+        assert!(overviews[0].error.is_none());
+        assert_eq!(overviews[0].environments[2].region.as_deref(), Some("deu6"));
+        assert!(overviews[1].environments.is_empty());
+
+        let lines = render_overview(&overviews);
+        assert_eq!(lines[0], "☁ Program: demo (11111)");
+        assert!(lines[1].contains("demo-prod") && lines[1].contains("nld2"));
+        assert_eq!(lines[5], "☁ Program: broken (22222)");
+        assert_eq!(
+            lines[6],
+            "   ❌ unable to fetch environments: 403 Forbidden: {}"
+        );
+    }
+
+    #[tokio::test]
+    async fn overview_fetches_every_program() {
+        let server = MockServer::start();
+        server
+            .route(
+                "GET",
+                "/api/program/11111/environments",
+                vec![MockResponse::json(
+                    read_json_from_file("test/test_environment_response.json").unwrap(),
+                )],
+            )
+            .route(
+                "GET",
+                "/api/program/22222/environments",
+                vec![MockResponse::new(403, "{}")],
+            );
+        let programs = vec![
+            (11111, String::from("demo")),
+            (22222, String::from("broken")),
+            (33333, String::from("empty")),
+        ];
+
+        let overviews = get_environments_overview(&server.client(), programs, 2).await;
+
+        let ids: Vec<&str> = overviews.iter().map(|o| o.program_id.as_str()).collect();
+        assert_eq!(ids, vec!["11111", "22222", "33333"]);
+        assert_eq!(overviews[0].environments.len(), 4);
+        assert!(overviews[0].error.is_none());
+        assert!(overviews[1]
+            .error
+            .as_deref()
+            .is_some_and(|error| error.contains("403")));
+        assert!(overviews[2].error.is_some());
+        let mut paths: Vec<String> = server
+            .requests()
+            .into_iter()
+            .map(|r| r.target.split('?').next().unwrap().to_string())
+            .collect();
+        paths.sort();
+        assert_eq!(
+            paths,
+            vec![
+                "/api/program/11111/environments",
+                "/api/program/22222/environments",
+                "/api/program/33333/environments"
+            ]
+        );
+    }
+
+    #[test]
+    fn overview_flags_environments_that_are_not_ready() {
+        let response: EnvironmentsResponse =
+            read_json_from_file("test/test_environment_response.json").unwrap();
+        let overview =
+            summarize_program(11111, String::from("demo"), Ok(response.environments_list));
+
+        for (env, line) in overview.environments.iter().zip(
+            render_overview(std::slice::from_ref(&overview))
+                .iter()
+                .skip(1),
+        ) {
+            assert_eq!(env.ready, env.status == "ready");
+            assert_eq!(line.trim_start().starts_with('✔'), env.ready);
+        }
+    }
+
+    #[test]
+    fn readiness_exit_code_for_ready() {
+        assert_eq!(readiness_exit_code("ready"), ExitCode::Success);
+        assert_eq!(readiness_exit_code("ready").code(), 0);
+    }
+
+    #[test]
+    fn readiness_exit_code_for_busy_or_hibernated() {
+        assert_eq!(readiness_exit_code("updating"), ExitCode::NotReady);
+        assert_eq!(readiness_exit_code("hibernated"), ExitCode::NotReady);
+        assert_eq!(readiness_exit_code("creating"), ExitCode::NotReady);
+        // The CI contract of env ready
+        assert_eq!(readiness_exit_code("updating").code(), 6);
+    }
+
+    #[test]
+    fn readiness_exit_code_for_errors() {
+        assert_eq!(readiness_exit_code("failed"), ExitCode::Failure);
+        assert_eq!(readiness_exit_code("deleting"), ExitCode::Failure);
+        assert_eq!(readiness_exit_code(""), ExitCode::Failure);
+        assert_eq!(readiness_exit_code("failed").code(), 1);
+    }
+
+    #[test]
+    fn status_watcher_detects_transitions() {
+        let mut watcher = StatusWatcher::default();
+        let changes: Vec<Option<StatusChange>> = ["updating", "updating", "ready", "ready"]
+            .iter()
+            .map(|status| watcher.observe(status))
+            .collect();
+
+        assert_eq!(
+            changes,
+            vec![
+                Some(StatusChange {
+                    from: None,
+                    to: String::from("updating")
+                }),
+                None,
+                Some(StatusChange {
+                    from: Some(String::from("updating")),
+                    to: String::from("ready")
+                }),
+                None,
+            ]
+        );
+    }
+}
@@ -1,5 +1,11 @@
+use crate::exit_code::ExitCode;
 use colored::*;
+use reqwest::Response;
 use serde::{Deserialize, Serialize};
+use std::borrow::Cow;
+
+/// Maximum number of bytes of an error response that are read and printed
+pub const MAX_ERROR_BODY_BYTES: usize = 8 * 1024;
 
 /// Struct that's used to deserialize Adobe API errors
 #[derive(Debug, Deserialize, Serialize)]
@@ -37,16 +43,128 @@ pub struct AdobeApiErrorMissingParams {
     pub parameter_type: String,
 }
 
+/// Cuts an error response down to [`MAX_ERROR_BODY_BYTES`], marking that it was truncated.
+///
+/// # Arguments
+///
+/// * `body` - String slice that holds the error response
+pub fn truncate_error_body(body: &str) -> Cow<'_, str> {
+    truncate_body(body, MAX_ERROR_BODY_BYTES, body.len())
+}
+
+/// Cuts a body down to `limit` bytes without splitting a character.
+///
+/// # Arguments
+///
+/// * `body` - String slice that holds the (possibly already shortened) body
+/// * `limit` - Maximum number of bytes to keep
+/// * `total` - Size of the complete body in bytes, as shown in the marker
+fn truncate_body(body: &str, limit: usize, total: usize) -> Cow<'_, str> {
+    if body.len() <= limit && total <= limit {
+        return Cow::Borrowed(body);
+    }
+    let mut end = limit.min(body.len());
+    while !body.is_char_boundary(end) {
+        end -= 1;
+    }
+    Cow::Owned(format!(
+        "{}... (truncated, {} bytes total)",
+        &body[..end],
+        total
+    ))
+}
+
+/// Reads at most [`MAX_ERROR_BODY_BYTES`] of an error response.
+///
+/// Unlike `Response::text`, pathological responses such as huge HTML error pages are not
+/// buffered completely.
+///
+/// # Arguments
+///
+/// * `response` - The error response to read
+pub async fn read_error_body(mut response: Response) -> Result<String, reqwest::Error> {
+    let mut body = Vec::new();
+    let mut total = 0;
+    while let Some(chunk) = response.chunk().await? {
+        total += chunk.len();
+        if body.len() < MAX_ERROR_BODY_BYTES {
+            let remaining = MAX_ERROR_BODY_BYTES - body.len();
+            body.extend_from_slice(&chunk[..chunk.len().min(remaining)]);
+        }
+        if total > MAX_ERROR_BODY_BYTES {
+            break;
+        }
+    }
+    // The cut may split a character, which is replaced rather than failing
+    let text = String::from_utf8_lossy(&body);
+    Ok(truncate_body(&text, MAX_ERROR_BODY_BYTES, total).into_owned())
+}
+
 /// Throws an AdobeApiError.
 ///
+/// Responses that aren't an Adobe API error, e.g. HTML error pages of a proxy, are printed as
+/// they are. Either way, at most [`MAX_ERROR_BODY_BYTES`] are printed.
+///
 /// # Arguments
 ///
 /// * `error_response` - String that contains the returned error message from Adobe's API
 pub fn throw_adobe_api_error(error_response: String) {
-    let api_error = serde_json::from_str::<AdobeApiError>(error_response.as_str()).unwrap();
+    let details = match serde_json::from_str::<AdobeApiError>(error_response.as_str()) {
+        Ok(api_error) => serde_json::to_string_pretty(&api_error).unwrap(),
+        Err(_) => error_response,
+    };
     eprintln!(
         "{}\n{}",
         "❌ API Error; check output below.".red().bold(),
-        serde_json::to_string_pretty(&api_error).unwrap().magenta()
+        truncate_error_body(&details).magenta()
     );
 }
+
+/// Prints the error of a request and returns the exit code of an aborted run, for functions that
+/// leave exiting to their caller.
+///
+/// # Arguments
+///
+/// * `error` - The error of the request
+pub fn report_api_error(error: reqwest::Error) -> ExitCode {
+    eprintln!("{} {}", "❌ API error: ".red().bold(), error);
+    ExitCode::Failure
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn short_error_bodies_are_kept() {
+        let body = r#"{"status":404,"type":"about:blank","title":"Not Found"}"#;
+        assert_eq!(truncate_error_body(body), body);
+    }
+
+    #[test]
+    fn long_error_bodies_are_truncated() {
+        let body = "<html>".to_string() + &"x".repeat(MAX_ERROR_BODY_BYTES * 100);
+        let truncated = truncate_error_body(&body);
+        assert!(truncated.starts_with("<html>xxx"));
+        assert!(truncated.ends_with(&format!("... (truncated, {} bytes total)", body.len())));
+        assert!(truncated.len() < MAX_ERROR_BODY_BYTES + 64);
+    }
+
+    #[test]
+    fn truncation_keeps_characters_intact() {
+        // "ä" takes two bytes, so the limit falls into the middle of one
+        let body = "ä".repeat(MAX_ERROR_BODY_BYTES);
+        let truncated = truncate_error_body(&body);
+        let kept = truncated.split("...").next().unwrap();
+        assert_eq!(kept.len(), MAX_ERROR_BODY_BYTES);
+        assert!(kept.chars().all(|c| c == 'ä'));
+    }
+
+    #[test]
+    fn already_shortened_bodies_are_marked() {
+        assert_eq!(
+            truncate_body("abc", 3, 10),
+            "abc... (truncated, 10 bytes total)"
+        );
+    }
+}
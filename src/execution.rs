@@ -1,9 +1,16 @@
+use crate::audit;
 use crate::client::{AdobeConnector, CloudManagerClient};
-use crate::errors::throw_adobe_api_error;
-use crate::models::execution::{ExecutionList, ExecutionResponse};
-use crate::HOST_NAME;
-use reqwest::{Error, Method};
-use std::process;
+use crate::errors::{read_error_body, throw_adobe_api_error};
+use crate::logs::{stream_log_file, LogError, LogFileSource};
+use crate::models::execution::{
+    Execution, ExecutionList, ExecutionResponse, StepLogRedirect, StepState,
+};
+use async_trait::async_trait;
+use colored::*;
+use reqwest::{Error, Method, Response, StatusCode};
+use serde_json::{json, Value};
+use std::io::Write;
+use std::time::Duration;
 
 /// Retrieves all Executions of a pipeline.
 ///
@@ -25,19 +32,575 @@ pub async fn get_executions(
 ) -> Result<ExecutionList, Error> {
     let request_path = format!(
         "{}/api/program/{}/pipeline/{}/executions",
-        HOST_NAME, program_id, pipeline_id
+        client.host_name, program_id, pipeline_id
     );
     let response = client
         .perform_request(Method::GET, request_path, None::<()>, None)
         .await?
         .text()
         .await?;
+    client.print_raw_response(&response);
 
     let execution_response: ExecutionResponse = serde_json::from_str(response.as_str())
         .unwrap_or_else(|_| {
             throw_adobe_api_error(response);
-            process::exit(1);
+            audit::exit(1);
         });
 
     Ok(execution_response.execution_list)
 }
+
+/// Returns the executions that are newer than the given one.
+///
+/// Cloud Manager lists executions newest first, so the list is cut off at the first execution
+/// that isn't newer, whether or not `since` itself is still part of the list.
+///
+/// # Arguments
+///
+/// * `executions` - Executions as returned by Cloud Manager, newest first
+/// * `since` - ID of the last execution that is already known
+pub fn executions_since(executions: Vec<Execution>, since: u32) -> Vec<Execution> {
+    executions
+        .into_iter()
+        .take_while(|execution| {
+            execution
+                .id
+                .parse::<u64>()
+                .ok()
+                .is_none_or(|id| id > u64::from(since))
+        })
+        .collect()
+}
+
+/// Retrieves a single execution of a pipeline including the states of its steps.
+///
+/// # Arguments
+///
+/// * `client` - A mutable reference to a CloudManagerClient instance
+/// * `program_id` - A u32 that holds the program ID
+/// * `pipeline_id` - A u32 that holds the pipeline ID
+/// * `execution_id` - A u32 that holds the execution ID
+///
+/// # Performed API Request
+///
+/// ```
+/// GET https://cloudmanager.adobe.io/api/program/{}/pipeline/{}/execution/{}
+/// ```
+pub async fn get_execution(
+    client: &mut CloudManagerClient,
+    program_id: u32,
+    pipeline_id: u32,
+    execution_id: u32,
+) -> Result<Execution, Error> {
+    let request_path = format!(
+        "{}/api/program/{}/pipeline/{}/execution/{}",
+        client.host_name, program_id, pipeline_id, execution_id
+    );
+    let response = client
+        .perform_request(Method::GET, request_path, None::<()>, None)
+        .await?
+        .text()
+        .await?;
+    client.print_raw_response(&response);
+
+    let execution: Execution = serde_json::from_str(response.as_str()).unwrap_or_else(|_| {
+        throw_adobe_api_error(response);
+        audit::exit(1);
+    });
+
+    Ok(execution)
+}
+
+/// Polls an execution and redraws a per-step status view in place until the execution reaches a
+/// terminal state. Returns the final execution.
+///
+/// # Arguments
+///
+/// * `client` - A mutable reference to a CloudManagerClient instance
+/// * `program_id` - A u32 that holds the program ID
+/// * `pipeline_id` - A u32 that holds the pipeline ID
+/// * `execution_id` - A u32 that holds the execution ID
+/// * `interval` - Time to wait between two polls
+pub async fn watch_execution(
+    client: &mut CloudManagerClient,
+    program_id: u32,
+    pipeline_id: u32,
+    execution_id: u32,
+    interval: Duration,
+) -> Result<Execution, Error> {
+    let mut drawn_lines = 0;
+    loop {
+        let execution = get_execution(client, program_id, pipeline_id, execution_id).await?;
+        let lines = render_execution(&execution);
+
+        // Move the cursor back to the start of the previous view and overwrite it
+        let mut stdout = std::io::stdout();
+        if drawn_lines > 0 {
+            print!("\x1b[{}A", drawn_lines);
+        }
+        for line in &lines {
+            println!("\x1b[2K{}", line);
+        }
+        stdout.flush().unwrap();
+        drawn_lines = lines.len();
+
+        if is_terminal_status(&execution.status) {
+            return Ok(execution);
+        }
+        tokio::time::sleep(interval).await;
+    }
+}
+
+/// Renders an execution as one header line and one line per step.
+///
+/// # Arguments
+///
+/// * `execution` - A reference to the execution
+pub fn render_execution(execution: &Execution) -> Vec<String> {
+    let mut lines = vec![format!(
+        "Execution {} of pipeline {}: {}",
+        execution.id, execution.pipeline_id, execution.status
+    )];
+    for step in execution.step_states() {
+        let target = step
+            .environment
+            .as_ref()
+            .map(|env| format!(" ({})", env))
+            .unwrap_or_default();
+        lines.push(format!(
+            "{:>4} {:<16} {}{}",
+            step_symbol(&step.status),
+            step.action,
+            step.status,
+            target
+        ));
+    }
+    lines
+}
+
+/// What to do with a step that waits for a decision
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum StepDecision {
+    Advance,
+    Reject,
+}
+
+impl StepDecision {
+    /// Last segment of the step endpoint for the decision
+    fn endpoint(self) -> &'static str {
+        match self {
+            StepDecision::Advance => "advance",
+            StepDecision::Reject => "reject",
+        }
+    }
+}
+
+/// Result of deciding on the waiting step of an execution
+#[derive(Debug, PartialEq)]
+pub enum StepDecisionOutcome {
+    /// Cloud Manager accepted the decision for the contained step action
+    Accepted(String),
+    /// The execution has no step that waits for a decision
+    NothingWaiting,
+    /// The waiting step can't be advanced or rejected by pippo
+    Unsupported(String),
+    /// Cloud Manager answered with an unexpected status
+    Failed(StatusCode, String),
+}
+
+/// Finds the step of an execution that waits for a decision, e.g. an approval gate.
+///
+/// # Arguments
+///
+/// * `execution` - A reference to the execution
+pub fn waiting_step(execution: &Execution) -> Option<&StepState> {
+    execution
+        .step_states()
+        .iter()
+        .find(|step| step.status == "WAITING")
+}
+
+/// Builds the request body for advancing or rejecting a waiting step.
+///
+/// # Arguments
+///
+/// * `step` - The waiting step
+/// * `decision` - Whether the step is advanced or rejected
+pub fn decision_body(step: &StepState, decision: StepDecision) -> Result<Value, String> {
+    let proceed = decision == StepDecision::Advance;
+    match step.action.as_str() {
+        "approval" => Ok(json!({ "approved": proceed })),
+        // Advancing a failed quality gate overrides the failed metrics
+        "codeQuality" | "security" | "performance" => Ok(json!({ "override": proceed })),
+        other => Err(format!(
+            "steps of type '{}' can't be advanced or rejected",
+            other
+        )),
+    }
+}
+
+/// Advances or rejects the step that an execution is waiting at.
+///
+/// # Arguments
+///
+/// * `client` - A mutable reference to a CloudManagerClient instance
+/// * `program_id` - A u32 that holds the program ID
+/// * `pipeline_id` - A u32 that holds the pipeline ID
+/// * `execution_id` - A u32 that holds the execution ID
+/// * `decision` - Whether the waiting step is advanced or rejected
+///
+/// # Performed API Requests
+///
+/// ```
+/// GET https://cloudmanager.adobe.io/api/program/{}/pipeline/{}/execution/{}
+/// PUT https://cloudmanager.adobe.io/api/program/{}/pipeline/{}/execution/{}/phase/{}/step/{}/advance
+/// PUT https://cloudmanager.adobe.io/api/program/{}/pipeline/{}/execution/{}/phase/{}/step/{}/reject
+/// ```
+pub async fn decide_waiting_step(
+    client: &mut CloudManagerClient,
+    program_id: u32,
+    pipeline_id: u32,
+    execution_id: u32,
+    decision: StepDecision,
+) -> Result<StepDecisionOutcome, Error> {
+    let execution = get_execution(client, program_id, pipeline_id, execution_id).await?;
+    let step = match waiting_step(&execution) {
+        Some(step) => step,
+        None => return Ok(StepDecisionOutcome::NothingWaiting),
+    };
+    let body = match decision_body(step, decision) {
+        Ok(body) => body,
+        Err(reason) => return Ok(StepDecisionOutcome::Unsupported(reason)),
+    };
+
+    let request_path = format!(
+        "{}/api/program/{}/pipeline/{}/execution/{}/phase/{}/step/{}/{}",
+        client.host_name,
+        program_id,
+        pipeline_id,
+        execution_id,
+        step.phase_id,
+        step.id,
+        decision.endpoint()
+    );
+    let response = client
+        .perform_request(Method::PUT, request_path, Some(body), None)
+        .await?;
+    let status = response.status();
+    if status.is_success() {
+        Ok(StepDecisionOutcome::Accepted(step.action.clone()))
+    } else {
+        Ok(StepDecisionOutcome::Failed(
+            status,
+            read_error_body(response).await?,
+        ))
+    }
+}
+
+/// Result of streaming the log of the build step of an execution
+#[derive(Debug, PartialEq)]
+pub enum BuildLogOutcome {
+    /// The log was written, holds the number of received bytes
+    Streamed(u64),
+    /// The execution has no build step, e.g. because the pipeline only deploys
+    NoBuildStep,
+    /// The build step hasn't started yet
+    NotStarted,
+}
+
+/// Finds the build step of an execution.
+///
+/// # Arguments
+///
+/// * `execution` - A reference to the execution
+pub fn build_step(execution: &Execution) -> Option<&StepState> {
+    execution
+        .step_states()
+        .iter()
+        .find(|step| step.action == "build")
+}
+
+/// Downloads a step log from the URL that Cloud Manager redirects to
+struct StepLogSource<'a> {
+    http: &'a reqwest::Client,
+    url: String,
+}
+
+#[async_trait]
+impl LogFileSource for StepLogSource<'_> {
+    async fn fetch(&mut self, _resume: Option<(u64, &str)>) -> Result<Response, Error> {
+        self.http.get(&self.url).send().await
+    }
+}
+
+/// Writes the log of a step of an execution to `out` as it is received.
+///
+/// Returns the number of bytes that were received.
+///
+/// # Arguments
+///
+/// * `client` - A mutable reference to a CloudManagerClient instance
+/// * `program_id` - A u32 that holds the program ID
+/// * `pipeline_id` - A u32 that holds the pipeline ID
+/// * `execution_id` - A u32 that holds the execution ID
+/// * `step` - The step whose log is written
+/// * `out` - Where the log is written to
+///
+/// # Performed API Request
+///
+/// ```
+/// GET https://cloudmanager.adobe.io/api/program/{}/pipeline/{}/execution/{}/phase/{}/step/{}/logs
+/// ```
+pub async fn stream_step_log<W: Write>(
+    client: &mut CloudManagerClient,
+    program_id: u32,
+    pipeline_id: u32,
+    execution_id: u32,
+    step: &StepState,
+    out: W,
+) -> Result<u64, LogError> {
+    let request_path = format!(
+        "{}/api/program/{}/pipeline/{}/execution/{}/phase/{}/step/{}/logs",
+        client.host_name, program_id, pipeline_id, execution_id, step.phase_id, step.id
+    );
+    let response = client
+        .perform_request(Method::GET, request_path, None::<()>, None)
+        .await?;
+    let status = response.status();
+    if !status.is_success() {
+        let body = read_error_body(response).await.unwrap_or_default();
+        return Err(LogError::from_status(status, body));
+    }
+    let body = response.text().await?;
+    let redirect: StepLogRedirect = serde_json::from_str(&body)
+        .map_err(|_| LogError::UnexpectedStatus { code: status, body })?;
+    // The redirect is a pre-signed URL, so the shared client is used without the credentials,
+    // which keeps the proxy and TLS settings of the config
+    let mut source = StepLogSource {
+        http: &client.client,
+        url: redirect.redirect,
+    };
+    stream_log_file(&mut source, out, false).await
+}
+
+/// Writes the log of the build step of an execution to `out`, e.g. to page through it.
+///
+/// # Arguments
+///
+/// * `client` - A mutable reference to a CloudManagerClient instance
+/// * `program_id` - A u32 that holds the program ID
+/// * `pipeline_id` - A u32 that holds the pipeline ID
+/// * `execution_id` - A u32 that holds the execution ID
+/// * `out` - Where the log is written to
+pub async fn stream_build_log<W: Write>(
+    client: &mut CloudManagerClient,
+    program_id: u32,
+    pipeline_id: u32,
+    execution_id: u32,
+    out: W,
+) -> Result<BuildLogOutcome, LogError> {
+    let execution = get_execution(client, program_id, pipeline_id, execution_id).await?;
+    let step = match build_step(&execution) {
+        Some(step) => step,
+        None => return Ok(BuildLogOutcome::NoBuildStep),
+    };
+    if step.status == "NOT_STARTED" {
+        return Ok(BuildLogOutcome::NotStarted);
+    }
+    stream_step_log(client, program_id, pipeline_id, execution_id, step, out)
+        .await
+        .map(BuildLogOutcome::Streamed)
+}
+
+/// Whether an execution status means that the execution won't change anymore
+pub fn is_terminal_status(status: &str) -> bool {
+    matches!(status, "FINISHED" | "ERROR" | "FAILED" | "CANCELLED")
+}
+
+/// Symbol that represents the status of a step in the watch view
+fn step_symbol(status: &str) -> ColoredString {
+    match status {
+        "FINISHED" => "✔".green(),
+        "RUNNING" | "ROLLING_BACK" => "▶".yellow(),
+        "WAITING" => "⏸".yellow(),
+        "ERROR" | "FAILED" | "CANCELLED" | "ROLLED_BACK" => "✖".red(),
+        _ => "·".normal(),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::client::mock::{MockResponse, MockServer};
+    use crate::models::tests::read_json_from_file;
+
+    #[tokio::test]
+    async fn build_log_is_downloaded_with_the_shared_client() {
+        let server = MockServer::start();
+        let execution =
+            std::fs::read_to_string("test/test_execution_detail_response.json").unwrap();
+        server
+            .route(
+                "GET",
+                "/api/program/1/pipeline/2/execution/3",
+                vec![MockResponse::new(200, &execution)],
+            )
+            .route(
+                "GET",
+                "/api/program/1/pipeline/2/execution/3/phase/2002/step/88882/logs",
+                vec![MockResponse::json(serde_json::json!({
+                    "redirect": format!("{}/logs/build.log", server.url)
+                }))],
+            )
+            .route(
+                "GET",
+                "/logs/build.log",
+                vec![MockResponse::new(200, "line 1\nline 2\n")],
+            );
+        let mut client = server.client();
+        let mut out = vec![];
+        let outcome = stream_build_log(&mut client, 1, 2, 3, &mut out)
+            .await
+            .unwrap();
+        assert_eq!(outcome, BuildLogOutcome::Streamed(14));
+        assert_eq!(String::from_utf8(out).unwrap(), "line 1\nline 2\n");
+        // The pre-signed URL gets no credentials
+        let download = server.requests().pop().unwrap();
+        assert_eq!(download.target, "/logs/build.log");
+        assert_eq!(download.header("authorization"), None);
+    }
+
+    fn executions_with_ids(ids: &[&str]) -> Vec<Execution> {
+        let response: ExecutionResponse =
+            read_json_from_file("test/test_execution_response.json").unwrap();
+        response
+            .execution_list
+            .list
+            .into_iter()
+            .zip(ids)
+            .map(|(mut execution, id)| {
+                execution.id = id.to_string();
+                execution
+            })
+            .collect()
+    }
+
+    fn ids(executions: &[Execution]) -> Vec<&str> {
+        executions.iter().map(|e| e.id.as_str()).collect()
+    }
+
+    #[test]
+    fn executions_since_stops_at_known_execution() {
+        let executions = executions_with_ids(&["105", "104", "103", "102", "101"]);
+        assert_eq!(ids(&executions_since(executions, 103)), vec!["105", "104"]);
+
+        let executions = executions_with_ids(&["105", "104"]);
+        assert!(executions_since(executions, 105).is_empty());
+    }
+
+    #[test]
+    fn executions_since_an_execution_that_is_no_longer_listed() {
+        // The known execution dropped out of the page, older ones are still cut off
+        let executions = executions_with_ids(&["105", "104", "101", "100"]);
+        assert_eq!(ids(&executions_since(executions, 102)), vec!["105", "104"]);
+
+        let executions = executions_with_ids(&["105", "104"]);
+        assert_eq!(ids(&executions_since(executions, 90)), vec!["105", "104"]);
+    }
+
+    #[test]
+    fn render_execution_per_step() {
+        colored::control::set_override(false);
+        let execution: Execution =
+            read_json_from_file("test/test_execution_detail_response.json").unwrap();
+
+        assert_eq!(
+            render_execution(&execution),
+            vec![
+                "Execution 77777 of pipeline 55555555: RUNNING",
+                "   ✔ validate         FINISHED",
+                "   ▶ build            RUNNING",
+                "   · deploy           NOT_STARTED (development)",
+            ]
+        );
+    }
+
+    #[test]
+    fn find_waiting_approval_step() {
+        let execution: Execution =
+            read_json_from_file("test/test_execution_waiting_response.json").unwrap();
+        let step = waiting_step(&execution).unwrap();
+
+        assert_eq!(step.action, "approval");
+        assert_eq!(step.phase_id, "2004");
+        assert_eq!(step.id, "88884");
+    }
+
+    #[test]
+    fn no_waiting_step_while_building() {
+        let execution: Execution =
+            read_json_from_file("test/test_execution_detail_response.json").unwrap();
+        assert!(waiting_step(&execution).is_none());
+    }
+
+    #[test]
+    fn decision_bodies_per_step_action() {
+        let execution: Execution =
+            read_json_from_file("test/test_execution_waiting_response.json").unwrap();
+        let steps = execution.step_states();
+        let approval = waiting_step(&execution).unwrap();
+
+        assert_eq!(
+            decision_body(approval, StepDecision::Advance),
+            Ok(json!({ "approved": true }))
+        );
+        assert_eq!(
+            decision_body(approval, StepDecision::Reject),
+            Ok(json!({ "approved": false }))
+        );
+        // the build step can't be decided on
+        assert!(decision_body(&steps[1], StepDecision::Advance).is_err());
+    }
+
+    #[test]
+    fn find_build_step() {
+        let execution: Execution =
+            read_json_from_file("test/test_execution_detail_response.json").unwrap();
+        let step = build_step(&execution).unwrap();
+        assert_eq!(step.status, "RUNNING");
+
+        let execution: Execution =
+            read_json_from_file("test/test_execution_waiting_response.json").unwrap();
+        assert_eq!(
+            build_step(&execution).map(|step| step.id.as_str()),
+            Some("88882")
+        );
+
+        let mut execution: Execution =
+            read_json_from_file("test/test_execution_detail_response.json").unwrap();
+        if let Some(embedded) = execution.embedded.as_mut() {
+            embedded.step_states.retain(|step| step.action != "build");
+        }
+        assert!(build_step(&execution).is_none());
+    }
+
+    #[test]
+    fn deserialize_step_log_redirect() {
+        let redirect: StepLogRedirect =
+            serde_json::from_str(r#"{"redirect": "https://logs.example.com/build.txt?sig=1"}"#)
+                .unwrap();
+        assert_eq!(
+            redirect.redirect,
+            "https://logs.example.com/build.txt?sig=1"
+        );
+    }
+
+    #[test]
+    fn terminal_execution_states() {
+        assert!(is_terminal_status("FINISHED"));
+        assert!(is_terminal_status("FAILED"));
+        assert!(is_terminal_status("CANCELLED"));
+        assert!(!is_terminal_status("RUNNING"));
+        assert!(!is_terminal_status("NOT_STARTED"));
+    }
+}
@@ -0,0 +1,119 @@
+use crate::audit;
+use std::fmt;
+
+/// Exit codes that pippo ends with, so every number has a single meaning across commands.
+///
+/// The only exception is [`ExitCode::Drift`]: `apply --diff` reports drift with `2`, like
+/// `terraform plan -detailed-exitcode`, and no other command uses it.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum ExitCode {
+    /// Everything was done
+    Success,
+    /// The run was aborted, e.g. because of an API error or an invalid input file
+    Failure,
+    /// Not all targets were changed, e.g. because they were busy in --ci mode or rejected the change
+    Incomplete,
+    /// The input file contains a variable with an invalid service type
+    InvalidService,
+    /// The input file defines the same variable or domain more than once
+    Duplicate,
+    /// An environment did not reach the expected status in time
+    TimedOut,
+    /// An encrypted value of the input file can't be decrypted with the local key
+    Undecryptable,
+    /// The requested program or logfile doesn't exist
+    NotFound,
+    /// Cloud Manager denied access to the requested resource
+    Unauthorized,
+    /// Cloud Manager offers no tail link for the requested log
+    NoTailLink,
+    /// Cloud Manager answered with a status the command didn't expect
+    UnexpectedStatus,
+    /// The environment is busy or hibernated and can become ready later
+    NotReady,
+    /// Cloud Manager differs from the input file (`apply --diff` only)
+    Drift,
+}
+
+impl ExitCode {
+    /// The numeric code the process ends with
+    pub fn code(self) -> i32 {
+        match self {
+            ExitCode::Success => 0,
+            ExitCode::Failure => 1,
+            ExitCode::Incomplete => 2,
+            ExitCode::InvalidService => 3,
+            ExitCode::Duplicate => 4,
+            ExitCode::TimedOut => 5,
+            ExitCode::Undecryptable => 11,
+            ExitCode::NotFound => 7,
+            ExitCode::Unauthorized => 8,
+            ExitCode::NoTailLink => 9,
+            ExitCode::UnexpectedStatus => 10,
+            ExitCode::NotReady => 6,
+            ExitCode::Drift => 2,
+        }
+    }
+
+    /// Prints a one-line explanation of a non-zero exit code, records the outcome of an audited
+    /// command and ends the process with the code.
+    pub fn exit(self) -> ! {
+        if self != ExitCode::Success {
+            eprintln!("\nℹ Exit code {}: {}", self.code(), self);
+        }
+        audit::exit(self.code())
+    }
+}
+
+impl fmt::Display for ExitCode {
+    fn fmt(&self, formatter: &mut fmt::Formatter) -> fmt::Result {
+        let meaning = match self {
+            ExitCode::Success => "all changes were applied",
+            ExitCode::Failure => "the run was aborted, check the error above",
+            ExitCode::Incomplete => {
+                "not all targets were changed, because they were busy (--ci) or the API rejected the change"
+            }
+            ExitCode::InvalidService => "the input file contains an invalid service type",
+            ExitCode::Duplicate => "the input file contains duplicate definitions",
+            ExitCode::TimedOut => "the environment did not reach the target status in time",
+            ExitCode::Undecryptable => "an encrypted value can't be decrypted with the local key",
+            ExitCode::NotFound => "the requested program or logfile doesn't exist",
+            ExitCode::Unauthorized => "Cloud Manager denied access, check the credentials",
+            ExitCode::NoTailLink => "Cloud Manager offers no tail link for the log",
+            ExitCode::UnexpectedStatus => "Cloud Manager answered with an unexpected status",
+            ExitCode::NotReady => "the environment is busy or hibernated and not ready yet",
+            ExitCode::Drift => "Cloud Manager differs from the input file",
+        };
+        write!(formatter, "{}", meaning)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn exit_codes_are_stable() {
+        assert_eq!(ExitCode::Success.code(), 0);
+        assert_eq!(ExitCode::Failure.code(), 1);
+        assert_eq!(ExitCode::Incomplete.code(), 2);
+        assert_eq!(ExitCode::InvalidService.code(), 3);
+        assert_eq!(ExitCode::Duplicate.code(), 4);
+        assert_eq!(ExitCode::TimedOut.code(), 5);
+        assert_eq!(ExitCode::Undecryptable.code(), 11);
+        assert_eq!(ExitCode::NotFound.code(), 7);
+        assert_eq!(ExitCode::Unauthorized.code(), 8);
+        assert_eq!(ExitCode::NoTailLink.code(), 9);
+        assert_eq!(ExitCode::UnexpectedStatus.code(), 10);
+        assert_eq!(ExitCode::NotReady.code(), 6);
+        assert_eq!(ExitCode::Drift.code(), 2);
+    }
+
+    #[test]
+    fn exit_codes_explain_themselves() {
+        assert_eq!(
+            ExitCode::Duplicate.to_string(),
+            "the input file contains duplicate definitions"
+        );
+    }
+}
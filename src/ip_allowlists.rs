@@ -0,0 +1,581 @@
+use crate::audit;
+use crate::client::{AdobeConnector, CloudManagerClient};
+use crate::errors::{report_api_error, throw_adobe_api_error};
+use crate::exit_code::ExitCode;
+use crate::models::environment::EnvironmentId;
+use crate::models::ip_allowlist::{
+    BindingTier, IpAllowlist, IpAllowlistBinding, IpAllowlistsList, IpAllowlistsResponse,
+    MinimumIpAllowlistBinding,
+};
+use crate::programs::read_config;
+use colored::*;
+use reqwest::{Error, Method, StatusCode};
+
+/// Retrieves all IP allowlists of a given program ID, including their bindings.
+///
+/// # Arguments
+///
+/// * `client` - A mutable reference to a CloudManagerClient instance
+/// * `program_id` - A u32 that holds the program ID
+///
+/// # Performed API Request
+///
+/// ```
+/// GET https://cloudmanager.adobe.io/api/program/{program_id}/ipAllowlists
+/// ```
+pub async fn get_ip_allowlists(
+    client: &mut CloudManagerClient,
+    program_id: u32,
+) -> Result<IpAllowlistsList, Error> {
+    let request_path = format!(
+        "{}/api/program/{}/ipAllowlists",
+        client.host_name, program_id
+    );
+    let response = client
+        .perform_request(Method::GET, request_path, None::<()>, None)
+        .await?
+        .text()
+        .await?;
+    client.print_raw_response(&response);
+    let ip_allowlists: IpAllowlistsResponse = serde_json::from_str(response.as_str())
+        .unwrap_or_else(|_| {
+            throw_adobe_api_error(response);
+            audit::exit(1);
+        });
+    Ok(ip_allowlists.ip_allowlists_list)
+}
+
+/// Binds an IP allowlist to a tier of an environment.
+///
+/// # Arguments
+///
+/// * `client` - A mutable reference to a CloudManagerClient instance
+/// * `program_id` - A u32 that holds the program ID
+/// * `allowlist_id` - String slice that holds the IP allowlist ID
+/// * `env_id` - An EnvironmentId that holds the environment ID
+/// * `tier` - The tier the allowlist will be bound to
+///
+/// # Performed API Request
+///
+/// ```
+/// POST https://cloudmanager.adobe.io/api/program/{program_id}/ipAllowlist/{allowlist_id}/bindings
+/// ```
+pub async fn bind_ip_allowlist(
+    client: &mut CloudManagerClient,
+    program_id: u32,
+    allowlist_id: &str,
+    env_id: EnvironmentId,
+    tier: BindingTier,
+) -> Result<StatusCode, Error> {
+    let request_path = format!(
+        "{}/api/program/{}/ipAllowlist/{}/bindings",
+        client.host_name, program_id, allowlist_id
+    );
+    let binding = MinimumIpAllowlistBinding {
+        environment_id: env_id.to_string(),
+        tier,
+    };
+    let response = client
+        .perform_request(Method::POST, request_path, Some(binding), None)
+        .await?;
+    let response_code = response.status();
+    // Print out additional info if request failed
+    let response_text = response.text().await?;
+    if !response_code.is_success() && response_code != StatusCode::CONFLICT {
+        eprintln!("⚠ POST failed: {}", response_text);
+    }
+
+    Ok(response_code)
+}
+
+/// Removes the binding of an IP allowlist.
+///
+/// # Arguments
+///
+/// * `client` - A mutable reference to a CloudManagerClient instance
+/// * `program_id` - A u32 that holds the program ID
+/// * `allowlist_id` - String slice that holds the IP allowlist ID
+/// * `binding_id` - String slice that holds the ID of the binding
+///
+/// # Performed API Request
+///
+/// ```
+/// DELETE https://cloudmanager.adobe.io/api/program/{program_id}/ipAllowlist/{allowlist_id}/binding/{binding_id}
+/// ```
+pub async fn unbind_ip_allowlist(
+    client: &mut CloudManagerClient,
+    program_id: u32,
+    allowlist_id: &str,
+    binding_id: &str,
+) -> Result<StatusCode, Error> {
+    let request_path = format!(
+        "{}/api/program/{}/ipAllowlist/{}/binding/{}",
+        client.host_name, program_id, allowlist_id, binding_id
+    );
+    let response = client
+        .perform_request(Method::DELETE, request_path, None::<()>, None)
+        .await?;
+    let response_code = response.status();
+    // Print out additional info if request failed
+    let response_text = response.text().await?;
+    if !response_code.is_success() && response_code != StatusCode::NOT_FOUND {
+        eprintln!("⚠ DELETE failed: {}", response_text);
+    }
+
+    Ok(response_code)
+}
+
+/// Binds an IP allowlist, identified by its name or ID, to a tier of an environment.
+/// Nothing is done when the allowlist is already bound, so it is safe to run this repeatedly.
+///
+/// # Arguments
+///
+/// * `client` - A mutable reference to a CloudManagerClient instance
+/// * `program_id` - A u32 that holds the program ID
+/// * `env_id` - An EnvironmentId that holds the environment ID
+/// * `allowlist` - String slice that holds the name or ID of the IP allowlist
+/// * `tier` - The tier the allowlist will be bound to
+/// * `dry_run` - Only print what would be done
+pub async fn bind(
+    client: &mut CloudManagerClient,
+    program_id: u32,
+    env_id: EnvironmentId,
+    allowlist: &str,
+    tier: BindingTier,
+    dry_run: bool,
+) -> Result<(), ExitCode> {
+    let allowlists = get_ip_allowlists(client, program_id)
+        .await
+        .map_err(report_api_error)?;
+    let ip_allowlist = resolve_ip_allowlist(&allowlists, allowlist)?;
+
+    if find_binding(ip_allowlist, env_id, &tier).is_some() {
+        println!(
+            "{:>8} '{}' is already bound to {} of environment {}, nothing to do",
+            "✔",
+            ip_allowlist.name,
+            Into::<&str>::into(&tier),
+            env_id
+        );
+        return Ok(());
+    }
+
+    println!(
+        "{:>8} BINDING '{}' to {} of environment {}",
+        "✍",
+        ip_allowlist.name,
+        Into::<&str>::into(&tier),
+        env_id
+    );
+    if dry_run {
+        println!(
+            "{:>8} --dry-run detected. Not performing any actions.",
+            "⚠️",
+        );
+        return Ok(());
+    }
+
+    let allowlist_id = ip_allowlist.id.clone();
+    match bind_ip_allowlist(client, program_id, &allowlist_id, env_id, tier).await {
+        Ok(status) if status.is_success() => {
+            println!("{:>8} Success", "✔");
+            Ok(())
+        }
+        Ok(StatusCode::CONFLICT) => {
+            println!("{:>8} Already bound, nothing to do", "✔");
+            Ok(())
+        }
+        Ok(_) => {
+            eprintln!("{:>8} {}", "Error, check output above".red(), "❌".red());
+            Err(ExitCode::Incomplete)
+        }
+        Err(error) => Err(report_api_error(error)),
+    }
+}
+
+/// Removes the binding of an IP allowlist, identified by its name or ID, from a tier of an environment.
+/// Nothing is done when the allowlist is not bound.
+///
+/// # Arguments
+///
+/// * `client` - A mutable reference to a CloudManagerClient instance
+/// * `program_id` - A u32 that holds the program ID
+/// * `env_id` - An EnvironmentId that holds the environment ID
+/// * `allowlist` - String slice that holds the name or ID of the IP allowlist
+/// * `tier` - The tier the allowlist will be unbound from
+/// * `dry_run` - Only print what would be done
+pub async fn unbind(
+    client: &mut CloudManagerClient,
+    program_id: u32,
+    env_id: EnvironmentId,
+    allowlist: &str,
+    tier: BindingTier,
+    dry_run: bool,
+) -> Result<(), ExitCode> {
+    let allowlists = get_ip_allowlists(client, program_id)
+        .await
+        .map_err(report_api_error)?;
+    let ip_allowlist = resolve_ip_allowlist(&allowlists, allowlist)?;
+
+    let binding = match find_binding(ip_allowlist, env_id, &tier) {
+        Some(binding) => binding,
+        None => {
+            println!(
+                "{:>8} '{}' is not bound to {} of environment {}, nothing to do",
+                "✔",
+                ip_allowlist.name,
+                Into::<&str>::into(&tier),
+                env_id
+            );
+            return Ok(());
+        }
+    };
+
+    println!(
+        "{:>8} UNBINDING '{}' from {} of environment {}",
+        "✍",
+        ip_allowlist.name,
+        Into::<&str>::into(&tier),
+        env_id
+    );
+    if dry_run {
+        println!(
+            "{:>8} --dry-run detected. Not performing any actions.",
+            "⚠️",
+        );
+        return Ok(());
+    }
+
+    let allowlist_id = ip_allowlist.id.clone();
+    let binding_id = binding.id.clone();
+    match unbind_ip_allowlist(client, program_id, &allowlist_id, &binding_id).await {
+        Ok(status) if status.is_success() => {
+            println!("{:>8} Success", "✔");
+            Ok(())
+        }
+        Ok(StatusCode::NOT_FOUND) => {
+            println!("{:>8} Not bound anymore, nothing to do", "✔");
+            Ok(())
+        }
+        Ok(_) => {
+            eprintln!("{:>8} {}", "Error, check output above".red(), "❌".red());
+            Err(ExitCode::Incomplete)
+        }
+        Err(error) => Err(report_api_error(error)),
+    }
+}
+
+/// Binds IP allowlists as declared in the `bindings` sections of a given YAML file.
+///
+/// # Arguments
+///
+/// * `file_path` - String slice that holds the path to the YAML config
+/// * `client` - A mutable reference to a CloudManagerClient instance
+/// * `dry_run` - Only print what would be done
+pub async fn bind_ip_allowlists_from_file(
+    file_path: &str,
+    client: &mut CloudManagerClient,
+    dry_run: bool,
+) -> Result<(), ExitCode> {
+    let input = read_config(client, file_path).await;
+
+    for p in &input.programs {
+        println!("☁ Program: {}", p.program_id(),);
+        if let Some(environments) = &p.environments {
+            for e in environments {
+                if let Some(bindings) = &e.bindings {
+                    println!("{:>4} Environment: {}", "⬛", e.id);
+                    for b in bindings {
+                        bind(
+                            client,
+                            p.program_id(),
+                            e.id,
+                            &b.allowlist,
+                            b.service.clone(),
+                            dry_run,
+                        )
+                        .await?;
+                    }
+                }
+            }
+        }
+    }
+    Ok(())
+}
+
+/// Looks up an IP allowlist by its ID or name and fails if there is no such allowlist.
+fn resolve_ip_allowlist<'a>(
+    allowlists: &'a IpAllowlistsList,
+    allowlist: &str,
+) -> Result<&'a IpAllowlist, ExitCode> {
+    find_ip_allowlist(allowlists, allowlist).ok_or_else(|| {
+        eprintln!(
+            "{} '{}'",
+            "❌ No IP allowlist found with ID or name".red(),
+            allowlist
+        );
+        ExitCode::Failure
+    })
+}
+
+/// Finds an IP allowlist by its ID or name.
+///
+/// # Arguments
+///
+/// * `allowlists` - The IP allowlists of a program
+/// * `allowlist` - String slice that holds the ID or name of the IP allowlist
+pub fn find_ip_allowlist<'a>(
+    allowlists: &'a IpAllowlistsList,
+    allowlist: &str,
+) -> Option<&'a IpAllowlist> {
+    allowlists
+        .ip_allowlists
+        .iter()
+        .find(|a| a.id == allowlist)
+        .or_else(|| {
+            allowlists
+                .ip_allowlists
+                .iter()
+                .find(|a| a.name == allowlist)
+        })
+}
+
+/// Finds the binding of an IP allowlist to a tier of an environment.
+///
+/// # Arguments
+///
+/// * `allowlist` - The IP allowlist
+/// * `env_id` - An EnvironmentId that holds the environment ID
+/// * `tier` - The tier of the environment
+pub fn find_binding<'a>(
+    allowlist: &'a IpAllowlist,
+    env_id: EnvironmentId,
+    tier: &BindingTier,
+) -> Option<&'a IpAllowlistBinding> {
+    let env_id = env_id.to_string();
+    allowlist
+        .bindings
+        .iter()
+        .find(|b| b.environment_id == env_id && &b.tier == tier)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::client::mock::{MockResponse, MockServer};
+    use crate::models::tests::read_json_from_file;
+
+    fn allowlists() -> IpAllowlistsList {
+        let response: IpAllowlistsResponse =
+            read_json_from_file("test/test_ip_allowlists_response.json").unwrap();
+        response.ip_allowlists_list
+    }
+
+    #[test]
+    fn find_ip_allowlist_by_name_or_id() {
+        let allowlists = allowlists();
+        assert_eq!(find_ip_allowlist(&allowlists, "vpn").unwrap().id, "1112");
+        assert_eq!(
+            find_ip_allowlist(&allowlists, "1111").unwrap().name,
+            "office"
+        );
+        assert!(find_ip_allowlist(&allowlists, "unknown").is_none());
+    }
+
+    #[test]
+    fn find_existing_binding_for_idempotent_rebind() {
+        let allowlists = allowlists();
+        let office = find_ip_allowlist(&allowlists, "office").unwrap();
+        assert_eq!(
+            find_binding(office, EnvironmentId(33333), &BindingTier::Author)
+                .unwrap()
+                .id,
+            "5555"
+        );
+        assert!(find_binding(office, EnvironmentId(33333), &BindingTier::Publish).is_none());
+        assert!(find_binding(office, EnvironmentId(44444), &BindingTier::Author).is_none());
+    }
+
+    /// A mock server that lists the allowlists of the test response for program 22222
+    fn allowlist_server() -> MockServer {
+        let server = MockServer::start();
+        server.route(
+            "GET",
+            "/api/program/22222/ipAllowlists",
+            vec![MockResponse::json(
+                read_json_from_file("test/test_ip_allowlists_response.json").unwrap(),
+            )],
+        );
+        server
+    }
+
+    fn changes(server: &MockServer) -> Vec<(String, String)> {
+        server
+            .requests()
+            .into_iter()
+            .filter(|r| r.method != "GET")
+            .map(|r| (r.method, r.target))
+            .collect()
+    }
+
+    #[tokio::test]
+    async fn bind_creates_a_missing_binding() {
+        let server = allowlist_server();
+        server.route(
+            "POST",
+            "/api/program/22222/ipAllowlist/1112/bindings",
+            vec![MockResponse::new(201, "{}")],
+        );
+        let mut client = server.client();
+
+        let result = bind(
+            &mut client,
+            22222,
+            EnvironmentId(33333),
+            "vpn",
+            BindingTier::Publish,
+            false,
+        )
+        .await;
+
+        assert_eq!(result, Ok(()));
+        let post = server
+            .requests()
+            .into_iter()
+            .find(|r| r.method == "POST")
+            .unwrap();
+        assert_eq!(post.target, "/api/program/22222/ipAllowlist/1112/bindings");
+        let body: serde_json::Value = serde_json::from_str(&post.body).unwrap();
+        assert_eq!(body["environmentId"], "33333");
+        assert_eq!(body["tier"], "publish");
+    }
+
+    #[tokio::test]
+    async fn bind_leaves_an_existing_binding_alone() {
+        let server = allowlist_server();
+        let mut client = server.client();
+
+        let result = bind(
+            &mut client,
+            22222,
+            EnvironmentId(33333),
+            "office",
+            BindingTier::Author,
+            false,
+        )
+        .await;
+
+        assert_eq!(result, Ok(()));
+        assert!(changes(&server).is_empty());
+    }
+
+    #[tokio::test]
+    async fn bind_in_dry_run_sends_nothing() {
+        let server = allowlist_server();
+        let mut client = server.client();
+
+        let result = bind(
+            &mut client,
+            22222,
+            EnvironmentId(33333),
+            "vpn",
+            BindingTier::Publish,
+            true,
+        )
+        .await;
+
+        assert_eq!(result, Ok(()));
+        assert!(changes(&server).is_empty());
+    }
+
+    #[tokio::test]
+    async fn rejected_bind_is_incomplete() {
+        let server = allowlist_server();
+        server.route(
+            "POST",
+            "/api/program/22222/ipAllowlist/1112/bindings",
+            vec![MockResponse::new(400, r#"{"title":"invalid tier"}"#)],
+        );
+        let mut client = server.client();
+
+        let result = bind(
+            &mut client,
+            22222,
+            EnvironmentId(33333),
+            "vpn",
+            BindingTier::Preview,
+            false,
+        )
+        .await;
+
+        assert_eq!(result, Err(ExitCode::Incomplete));
+    }
+
+    #[tokio::test]
+    async fn bind_of_unknown_allowlist_fails() {
+        let server = allowlist_server();
+        let mut client = server.client();
+
+        let result = bind(
+            &mut client,
+            22222,
+            EnvironmentId(33333),
+            "unknown",
+            BindingTier::Author,
+            false,
+        )
+        .await;
+
+        assert_eq!(result, Err(ExitCode::Failure));
+        assert!(changes(&server).is_empty());
+    }
+
+    #[tokio::test]
+    async fn unbind_deletes_the_binding() {
+        let server = allowlist_server();
+        server.route(
+            "DELETE",
+            "/api/program/22222/ipAllowlist/1111/binding/5555",
+            vec![MockResponse::new(204, "")],
+        );
+        let mut client = server.client();
+
+        let result = unbind(
+            &mut client,
+            22222,
+            EnvironmentId(33333),
+            "office",
+            BindingTier::Author,
+            false,
+        )
+        .await;
+
+        assert_eq!(result, Ok(()));
+        assert_eq!(
+            changes(&server),
+            vec![(
+                String::from("DELETE"),
+                String::from("/api/program/22222/ipAllowlist/1111/binding/5555")
+            )]
+        );
+    }
+
+    #[tokio::test]
+    async fn unbind_without_binding_sends_nothing() {
+        let server = allowlist_server();
+        let mut client = server.client();
+
+        let result = unbind(
+            &mut client,
+            22222,
+            EnvironmentId(33333),
+            "vpn",
+            BindingTier::Author,
+            false,
+        )
+        .await;
+
+        assert_eq!(result, Ok(()));
+        assert!(changes(&server).is_empty());
+    }
+}
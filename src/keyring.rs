@@ -0,0 +1,237 @@
+use std::fmt;
+use std::io::ErrorKind;
+use std::process::Command;
+
+/// Prefix of config values that reference an entry in the OS keyring instead of holding a secret
+pub const KEYRING_PREFIX: &str = "keyring:";
+
+/// Service name under which pippo's secrets are stored in the OS keyring
+pub const KEYRING_SERVICE: &str = "pippo";
+
+/// Error that occurs when a secret can't be read from the OS keyring
+#[derive(Debug, PartialEq)]
+pub enum KeyringError {
+    /// There is no keyring service that pippo can talk to on this system
+    Unavailable(String),
+    /// pippo can't use the keyring of this operating system at all, holds its name
+    Unsupported(&'static str),
+    /// The keyring is available, but has no entry with the given name
+    NotFound(String),
+}
+
+impl fmt::Display for KeyringError {
+    fn fmt(&self, formatter: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            KeyringError::Unavailable(reason) => {
+                write!(formatter, "no keyring service available ({})", reason)
+            }
+            KeyringError::Unsupported(os) => write!(
+                formatter,
+                "pippo only supports the keyring on macOS and Linux, not on {}",
+                os
+            ),
+            KeyringError::NotFound(entry) => write!(
+                formatter,
+                "no keyring entry '{}' for service '{}'",
+                entry, KEYRING_SERVICE
+            ),
+        }
+    }
+}
+
+/// Returns the name of the keyring entry if the value references one, e.g. `keyring:client-secret`.
+///
+/// # Arguments
+///
+/// * `value` - String slice that holds the value from the config file
+pub fn keyring_entry(value: &str) -> Option<&str> {
+    value
+        .strip_prefix(KEYRING_PREFIX)
+        .map(str::trim)
+        .filter(|entry| !entry.is_empty())
+}
+
+/// Whether pippo can read secrets from the keyring of this operating system
+pub fn is_supported() -> bool {
+    cfg!(any(target_os = "macos", target_os = "linux"))
+}
+
+/// Reads a secret from the OS keyring.
+///
+/// Uses the `security` tool on macOS and `secret-tool` (libsecret) on Linux, so no extra native
+/// libraries are needed. Only the entry name is passed as an argument, the secret comes back on
+/// stdout, so it never shows up in the process list. Other systems fail with
+/// [`KeyringError::Unsupported`].
+///
+/// # Arguments
+///
+/// * `entry` - String slice that holds the name of the keyring entry
+pub fn read_secret(entry: &str) -> Result<String, KeyringError> {
+    let mut command = if cfg!(target_os = "macos") {
+        let mut command = Command::new("security");
+        command.args([
+            "find-generic-password",
+            "-s",
+            KEYRING_SERVICE,
+            "-a",
+            entry,
+            "-w",
+        ]);
+        command
+    } else if cfg!(target_os = "linux") {
+        let mut command = Command::new("secret-tool");
+        command.args(["lookup", "service", KEYRING_SERVICE, "account", entry]);
+        command
+    } else {
+        return Err(KeyringError::Unsupported(std::env::consts::OS));
+    };
+
+    let output = command.output().map_err(|err| match err.kind() {
+        ErrorKind::NotFound => {
+            KeyringError::Unavailable(format!("{:?} is not installed", command.get_program()))
+        }
+        _ => KeyringError::Unavailable(err.to_string()),
+    })?;
+    let secret = String::from_utf8_lossy(&output.stdout);
+    let secret = secret.trim_end_matches(['\r', '\n']);
+    if !output.status.success() || secret.is_empty() {
+        return Err(KeyringError::NotFound(entry.to_string()));
+    }
+    Ok(secret.to_string())
+}
+
+/// Resolves a secret config value.
+///
+/// Inline values are returned as they are. Keyring references are looked up with `lookup`; if no
+/// keyring service is available, `fallback` (e.g. from an environment variable) is used instead.
+/// On an operating system without keyring support, a keyring reference is always an error, so a
+/// config that relies on it doesn't quietly run with another secret.
+///
+/// # Arguments
+///
+/// * `field` - String slice that holds the name of the config field, used in error messages
+/// * `value` - String slice that holds the value from the config file
+/// * `fallback` - Value to use when the keyring is unavailable
+/// * `lookup` - Reads an entry from the keyring
+pub fn resolve_secret<F>(
+    field: &str,
+    value: &str,
+    fallback: Option<String>,
+    lookup: F,
+) -> Result<String, String>
+where
+    F: Fn(&str) -> Result<String, KeyringError>,
+{
+    let entry = match keyring_entry(value) {
+        Some(entry) => entry,
+        None => return Ok(value.to_string()),
+    };
+    match lookup(entry) {
+        Ok(secret) => Ok(secret),
+        Err(KeyringError::Unavailable(reason)) => fallback.ok_or_else(|| {
+            format!(
+                "unable to read '{}' from the OS keyring: {}",
+                field,
+                KeyringError::Unavailable(reason)
+            )
+        }),
+        Err(err) => Err(format!(
+            "unable to read '{}' from the OS keyring: {}",
+            field, err
+        )),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn keyring(entry: &str) -> Result<String, KeyringError> {
+        match entry {
+            "client-secret" => Ok(String::from("s3cr3t")),
+            _ => Err(KeyringError::NotFound(entry.to_string())),
+        }
+    }
+
+    fn no_keyring(_: &str) -> Result<String, KeyringError> {
+        Err(KeyringError::Unavailable(String::from("no dbus session")))
+    }
+
+    fn unsupported_keyring(_: &str) -> Result<String, KeyringError> {
+        Err(KeyringError::Unsupported("windows"))
+    }
+
+    #[test]
+    fn keyring_entry_from_value() {
+        assert_eq!(
+            keyring_entry("keyring:client-secret"),
+            Some("client-secret")
+        );
+        assert_eq!(
+            keyring_entry("keyring: client-secret "),
+            Some("client-secret")
+        );
+        assert_eq!(keyring_entry("keyring:"), None);
+        assert_eq!(keyring_entry("XXX"), None);
+    }
+
+    #[test]
+    fn resolve_inline_secret() {
+        assert_eq!(
+            resolve_secret("client_secret", "XXX", None, no_keyring),
+            Ok(String::from("XXX"))
+        );
+    }
+
+    #[test]
+    fn resolve_secret_from_keyring() {
+        assert_eq!(
+            resolve_secret("client_secret", "keyring:client-secret", None, keyring),
+            Ok(String::from("s3cr3t"))
+        );
+        assert_eq!(
+            resolve_secret("private_key", "keyring:private-key", None, keyring),
+            Err(String::from(
+                "unable to read 'private_key' from the OS keyring: no keyring entry 'private-key' for service 'pippo'"
+            ))
+        );
+    }
+
+    #[test]
+    fn resolve_secret_without_keyring_uses_fallback() {
+        assert_eq!(
+            resolve_secret(
+                "client_secret",
+                "keyring:client-secret",
+                Some(String::from("from-env")),
+                no_keyring
+            ),
+            Ok(String::from("from-env"))
+        );
+        assert_eq!(
+            resolve_secret("client_secret", "keyring:client-secret", None, no_keyring),
+            Err(String::from(
+                "unable to read 'client_secret' from the OS keyring: no keyring service available (no dbus session)"
+            ))
+        );
+    }
+
+    #[test]
+    fn resolve_secret_on_unsupported_os_fails() {
+        assert_eq!(
+            resolve_secret(
+                "client_secret",
+                "keyring:client-secret",
+                Some(String::from("from-env")),
+                unsupported_keyring
+            ),
+            Err(String::from(
+                "unable to read 'client_secret' from the OS keyring: pippo only supports the keyring on macOS and Linux, not on windows"
+            ))
+        );
+        assert_eq!(
+            resolve_secret("client_secret", "XXX", None, unsupported_keyring),
+            Ok(String::from("XXX"))
+        );
+    }
+}
@@ -1,258 +1,4646 @@
-use std::io::Cursor;
-use std::thread::sleep;
-use std::time::Duration;
-use std::{i64, process};
+use std::convert::TryFrom;
+use std::fmt;
+use std::fs::{File, OpenOptions};
+use std::io::{self, Read, Write};
+use std::path::{Path, PathBuf};
+use std::str::FromStr;
+use std::sync::Arc;
+use std::time::{Duration, Instant};
+use tokio::sync::Semaphore;
+use tokio::task::JoinSet;
+use tokio::time::sleep;
 
-use chrono::NaiveDate;
+use base64::prelude::*;
+use chrono::{
+    DateTime, Duration as ChronoDuration, Local, NaiveDate, NaiveDateTime, SecondsFormat, Utc,
+};
 use colored::*;
+use flate2::write::GzDecoder;
 use log::debug;
-use reqwest::{Error, Method, StatusCode};
+use md5::{Digest, Md5};
+use regex::{Regex, RegexBuilder};
+use reqwest::header::{
+    HeaderMap, HeaderValue, CONTENT_LENGTH, CONTENT_RANGE, ETAG, IF_RANGE, LAST_MODIFIED, RANGE,
+};
+use reqwest::{Error, Method, Response, StatusCode};
 
+use crate::audit;
 use crate::client::{AdobeConnector, CloudManagerClient};
-use crate::errors::throw_adobe_api_error;
-use crate::models::log::{LogTailResponse, LogType, ServiceType};
-use crate::HOST_NAME;
+use crate::environments::get_environment;
+use crate::errors::{read_error_body, throw_adobe_api_error, truncate_error_body};
+use crate::exit_code::ExitCode;
+use crate::models::environment::EnvironmentId;
+use crate::models::log::{
+    Log, LogName, LogOption, LogTailResponse, LogType, LogsResponse, ServiceType, TailEvent,
+};
+use async_trait::async_trait;
+use serde::Serialize;
+use strum::IntoEnumIterator;
+use strum_macros::EnumString;
 
-/// Downloads the specified log.
+/// Result of trying to download a logfile
+#[derive(Debug, PartialEq)]
+pub enum LogDownload {
+    /// The logfile was saved under the contained filename
+    Saved(String),
+    /// Cloud Manager has no such logfile for the requested date
+    NotFound,
+    /// Cloud Manager answered with an unexpected status and body
+    Failed(StatusCode, String),
+    /// The download ended early; the contained partial file, its size and the expected size
+    Incomplete(String, u64, u64),
+    /// The target file already exists and overwriting was not requested
+    Exists(String),
+    /// The downloaded logfile doesn't match the hash sent by the server; the target file, the
+    /// expected and the actual MD5
+    ChecksumMismatch(String, String, String),
+}
+
+/// Errors of the log commands that are reported to the user instead of panicking
+#[derive(Debug, PartialEq)]
+pub enum LogError {
+    /// Cloud Manager has no such logfile
+    NotFound,
+    /// Cloud Manager rejected the credentials or permissions of the technical account
+    Unauthorized,
+    /// The log exists, but Cloud Manager offers no link to tail it
+    NoTailLink,
+    UnexpectedStatus {
+        code: StatusCode,
+        body: String,
+    },
+    /// The request didn't get an answer, e.g. because the connection broke
+    Request(String),
+}
+
+impl fmt::Display for LogError {
+    fn fmt(&self, formatter: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            LogError::NotFound => write!(
+                formatter,
+                "The requested logfile was not found. Check your parameters."
+            ),
+            LogError::Unauthorized => write!(
+                formatter,
+                "Cloud Manager denied access to the logs. Check the credentials and permissions of the technical account."
+            ),
+            LogError::NoTailLink => write!(
+                formatter,
+                "Cloud Manager offers no tail link for the requested log."
+            ),
+            LogError::UnexpectedStatus { code, body } if body.is_empty() => {
+                write!(formatter, "Unexpected answer from Cloud Manager: {}", code)
+            }
+            LogError::UnexpectedStatus { code, body } => write!(
+                formatter,
+                "Unexpected answer from Cloud Manager: {}: {}",
+                code,
+                truncate_error_body(body)
+            ),
+            LogError::Request(reason) => write!(formatter, "Request failed: {}", reason),
+        }
+    }
+}
+
+impl From<Error> for LogError {
+    fn from(error: Error) -> Self {
+        LogError::Request(error.to_string())
+    }
+}
+
+impl LogError {
+    /// Classifies an answer of Cloud Manager that isn't what a log command expected.
+    ///
+    /// # Arguments
+    ///
+    /// * `code` - Status of the answer
+    /// * `body` - Body of the answer
+    pub fn from_status(code: StatusCode, body: String) -> Self {
+        match code {
+            StatusCode::NOT_FOUND => LogError::NotFound,
+            StatusCode::UNAUTHORIZED | StatusCode::FORBIDDEN => LogError::Unauthorized,
+            code => LogError::UnexpectedStatus { code, body },
+        }
+    }
+
+    /// Exit code that pippo ends with when a log command fails with this error
+    pub fn exit_code(&self) -> ExitCode {
+        match self {
+            LogError::Request(_) => ExitCode::Failure,
+            LogError::NotFound => ExitCode::NotFound,
+            LogError::Unauthorized => ExitCode::Unauthorized,
+            LogError::NoTailLink => ExitCode::NoTailLink,
+            LogError::UnexpectedStatus { .. } => ExitCode::UnexpectedStatus,
+        }
+    }
+}
+
+/// Decides which lines of a tailed logfile are printed
+#[derive(Clone, Debug, Default)]
+pub struct LineFilter {
+    /// Only lines matching this expression are printed
+    grep: Option<Regex>,
+    /// Lines matching this expression are never printed
+    exclude: Option<Regex>,
+}
+
+impl LineFilter {
+    /// Compiles the expressions of `--grep` and `--exclude`.
+    ///
+    /// # Arguments
+    ///
+    /// * `grep` - Expression that lines must match to be printed
+    /// * `exclude` - Expression of lines that are dropped
+    /// * `ignore_case` - Whether both expressions match case-insensitively
+    pub fn new(
+        grep: Option<&str>,
+        exclude: Option<&str>,
+        ignore_case: bool,
+    ) -> Result<LineFilter, regex::Error> {
+        let compile = |pattern: &str| {
+            RegexBuilder::new(pattern)
+                .case_insensitive(ignore_case)
+                .build()
+        };
+        Ok(LineFilter {
+            grep: grep.map(compile).transpose()?,
+            exclude: exclude.map(compile).transpose()?,
+        })
+    }
+
+    /// Returns whether the line is printed
+    ///
+    /// # Arguments
+    ///
+    /// * `line` - String slice that holds a line of the logfile
+    pub fn accepts(&self, line: &str) -> bool {
+        self.grep.as_ref().is_none_or(|grep| grep.is_match(line))
+            && !self.exclude.as_ref().is_some_and(|ex| ex.is_match(line))
+    }
+
+    /// Marks every match of `--grep` in the line, e.g. by coloring it.
+    ///
+    /// # Arguments
+    ///
+    /// * `line` - String slice that holds a line of the logfile
+    /// * `paint` - Turns a match into its highlighted form
+    pub fn highlight<F>(&self, line: &str, paint: F) -> String
+    where
+        F: Fn(&str) -> String,
+    {
+        match &self.grep {
+            Some(grep) => grep
+                .replace_all(line, |captures: &regex::Captures| paint(&captures[0]))
+                .into_owned(),
+            None => line.to_string(),
+        }
+    }
+}
+
+/// Returns the lines of a tailed chunk that are printed, dropping empty lines such as the trailing
+/// newline of the logfile.
 ///
 /// # Arguments
 ///
-/// * `client` - A mutable reference to a CloudManagerClient instance
-/// * `program_id` - A u32 that holds the program ID
-/// * `env_id` - A u32 that holds the environment ID
-/// * `service` - Name of the service type - can be either author, publish, dispatcher, or preview_dispatcher
-/// * `logname` - Name of the logfile - can be either aemaccess, aemdispatcher, aemerror, aemrequest, cdn, httpdaccess, or httpderror
-/// * `date` - Date you want to retrieve the logs from, in the format YYYY-MM-DD
+/// * `lines` - The lines of the chunk
+/// * `filter` - Decides which lines are printed
+pub fn filter_log_lines<'a>(lines: &[&'a str], filter: &LineFilter) -> Vec<&'a str> {
+    lines
+        .iter()
+        .copied()
+        .filter(|line| !line.is_empty() && filter.accepts(line))
+        .collect()
+}
+
+/// Copy of a tailed log that is written to a file while the log is printed
+#[derive(Debug)]
+pub struct TailRecorder<W: Write> {
+    writer: W,
+    /// Only record the lines that pass the filter instead of every received line
+    filtered_only: bool,
+}
+
+impl TailRecorder<File> {
+    /// Opens the file for appending, creating it if needed, and writes the header.
+    ///
+    /// # Arguments
+    ///
+    /// * `path` - Path of the file
+    /// * `header` - First line to write, see [`tail_header`]
+    /// * `filtered_only` - Whether only the filtered lines are recorded
+    pub fn create(path: &Path, header: &str, filtered_only: bool) -> io::Result<Self> {
+        let file = OpenOptions::new().create(true).append(true).open(path)?;
+        TailRecorder::new(file, header, filtered_only)
+    }
+}
+
+impl<W: Write> TailRecorder<W> {
+    /// Wraps a writer and writes the header to it.
+    ///
+    /// # Arguments
+    ///
+    /// * `writer` - Where the lines are written to
+    /// * `header` - First line to write, see [`tail_header`]
+    /// * `filtered_only` - Whether only the filtered lines are recorded
+    pub fn new(mut writer: W, header: &str, filtered_only: bool) -> io::Result<Self> {
+        writeln!(writer, "{}", header)?;
+        writer.flush()?;
+        Ok(TailRecorder {
+            writer,
+            filtered_only,
+        })
+    }
+
+    /// Writes the lines of one poll cycle and flushes them, so nothing is lost if pippo is killed.
+    ///
+    /// # Arguments
+    ///
+    /// * `received` - All non-empty lines of the cycle
+    /// * `printed` - The lines of the cycle that passed the filter
+    pub fn record(&mut self, received: &[&str], printed: &[&str]) -> io::Result<()> {
+        let lines = if self.filtered_only {
+            printed
+        } else {
+            received
+        };
+        for line in lines {
+            writeln!(self.writer, "{}", line)?;
+        }
+        self.writer.flush()
+    }
+}
+
+/// Header line of a recorded tail.
 ///
-/// # Performed API Request
+/// # Arguments
 ///
-/// ```
-/// GET https://cloudmanager.adobe.io/api/program/{program_id}/environment/{env_id}/logs/download
-/// ```
-pub async fn download_log(
-    client: &mut CloudManagerClient,
-    program_id: u32,
-    env_id: u32,
-    service: ServiceType,
-    logname: LogType,
-    date: NaiveDate,
-) -> Result<String, Error> {
-    // Convert date to String, since query parameters must be all of the same type
-    let naive_date = date.to_string();
+/// * `service` - The service that is tailed
+/// * `logname` - The log that is tailed
+/// * `env_id` - The environment that is tailed
+/// * `started` - When the tail started
+pub fn tail_header(
+    service: &ServiceType,
+    logname: &LogName,
+    env_id: EnvironmentId,
+    started: DateTime<Local>,
+) -> String {
+    let service: &str = service.as_str();
+    let logname = logname.as_str();
+    format!(
+        "# pippo log tail service={} log={} env={} started={}",
+        service,
+        logname,
+        env_id,
+        started.to_rfc3339_opts(SecondsFormat::Secs, false)
+    )
+}
 
-    let query_parameters = vec![
-        ("service", service.clone().into()),
-        ("name", logname.clone().into()),
-        ("date", naive_date.as_str()),
-    ];
+/// Seconds between two polls of a tailed logfile, unless `--interval` says otherwise
+pub const DEFAULT_TAIL_INTERVAL_SECS: u64 = 5;
 
-    let request_path = format!(
-        "{}/api/program/{}/environment/{}/logs/download",
-        HOST_NAME, program_id, env_id
-    );
+/// How the lines of a tailed log are printed on stdout
+#[derive(Clone, Copy, Debug, Default, PartialEq, EnumString)]
+#[strum(serialize_all = "lowercase")]
+pub enum TailFormat {
+    /// The bare lines, prefixed with the tag of the tail
+    #[default]
+    Text,
+    /// One [`TailEvent`] JSON object per line, while banners go to stderr
+    Ndjson,
+}
 
-    let response = client
-        .perform_request(
-            Method::GET,
-            request_path,
-            None::<()>,
-            Some(query_parameters),
-        )
-        .await?;
+/// Where the lines of a tail come from
+#[derive(Clone, Debug, PartialEq)]
+pub struct TailOrigin {
+    pub program_id: u32,
+    pub env_id: EnvironmentId,
+    pub service: ServiceType,
+    pub log: LogName,
+}
 
-    match response.status() {
-        StatusCode::NOT_FOUND => {
-            eprintln!(
-                "{}",
-                "❌ The requested logfile was not found. Check your parameters.".red()
-            );
-            process::exit(1);
+/// Formats a line that passed the filter of a tail for stdout.
+///
+/// # Arguments
+///
+/// * `line` - String slice that holds the line of the logfile
+/// * `options` - How the log is printed, only tag, filter and format are used
+/// * `highlight` - Whether matches of `--grep` are colored in text output
+/// * `origin` - Where the line comes from
+/// * `received` - When the line was received
+pub fn format_tail_line(
+    line: &str,
+    options: &TailOptions,
+    highlight: bool,
+    origin: &TailOrigin,
+    received: DateTime<Utc>,
+) -> String {
+    match options.format {
+        TailFormat::Text if highlight => format!(
+            "{}{}",
+            options.tag,
+            options
+                .filter
+                .highlight(line, |m| m.red().bold().to_string())
+        ),
+        TailFormat::Text => format!("{}{}", options.tag, line),
+        TailFormat::Ndjson => {
+            let event = TailEvent {
+                ts_received: received.to_rfc3339_opts(SecondsFormat::Millis, true),
+                program: origin.program_id,
+                env: origin.env_id,
+                service: origin.service.clone(),
+                log: origin.log.clone(),
+                line: line.to_string(),
+            };
+            // A struct of strings and numbers always serializes
+            serde_json::to_string(&event).unwrap()
         }
-        StatusCode::OK => {
-            let download = response.bytes().await?;
-            // Save archive to file in working directory
-            let filename = format!(
-                "{}_{}-{}_{}.log.gz",
-                date,
-                env_id,
-                Into::<&str>::into(&service),
-                Into::<&str>::into(&logname),
-            );
-            let mut file = std::fs::File::create(&filename).unwrap();
-            let mut content = Cursor::new(download);
-            std::io::copy(&mut content, &mut file).unwrap();
+    }
+}
 
-            Ok(filename)
-        }
-        _ => {
-            eprintln!("wtf? -> {}", response.status());
-            unreachable!();
+/// How a tailed log is printed and recorded
+#[derive(Debug)]
+pub struct TailOptions {
+    /// Prefix of every printed line, see [`environment_tag`]
+    pub tag: String,
+    /// Decides which lines are printed
+    pub filter: LineFilter,
+    /// Writes the received lines to a file as well
+    pub recorder: Option<TailRecorder<File>>,
+    /// How failing polls are retried
+    pub retry: TailRetryPolicy,
+    /// Time between two polls
+    pub interval: Duration,
+    /// Number of bytes before the current end of the logfile that are printed first
+    pub backfill_bytes: u64,
+    /// Print the lines of this last period first, see [`scan_since`]
+    pub since: Option<ChronoDuration>,
+    /// How the lines are printed on stdout
+    pub format: TailFormat,
+}
+
+impl Default for TailOptions {
+    fn default() -> Self {
+        TailOptions {
+            tag: String::new(),
+            filter: LineFilter::default(),
+            recorder: None,
+            retry: TailRetryPolicy::default(),
+            interval: Duration::from_secs(DEFAULT_TAIL_INTERVAL_SECS),
+            backfill_bytes: 0,
+            since: None,
+            format: TailFormat::default(),
         }
     }
 }
 
-/// Tails the specified log.
+/// Returns the offset that a tail starts at, so that up to `backfill_bytes` of the existing
+/// content are printed first.
 ///
 /// # Arguments
 ///
-/// * `client` - A mutable reference to a CloudManagerClient instance
-/// * `program_id` - A u32 that holds the program ID
-/// * `env_id` - A u32 that holds the environment ID
-/// * `service` - Name of the service type - can be either author, publish, dispatcher, or preview_dispatcher
-/// * `logname` - Name of the logfile - can be either aemaccess, aemdispatcher, aemerror, aemrequest, cdn, httpdaccess, or httpderror
+/// * `content_length` - Current length of the logfile in bytes
+/// * `backfill_bytes` - Number of bytes before the end of the logfile to start at
+pub fn initial_tail_offset(content_length: i64, backfill_bytes: u64) -> i64 {
+    let backfill = i64::try_from(backfill_bytes).unwrap_or(i64::MAX);
+    content_length.saturating_sub(backfill).max(0)
+}
+
+/// Parses the period of `log tail --since`, e.g. `30s`, `5m`, `1h` or `2d`.
 ///
-/// # Performed API Request
+/// # Arguments
 ///
-/// ```
-/// GET https://cloudmanager.adobe.io/api/program/{program_id}/environment/{env_id}/logs/download
-/// ```
-pub async fn tail_log(
-    client: &mut CloudManagerClient,
-    program_id: u32,
-    env_id: u32,
-    service: ServiceType,
-    logname: LogType,
-) -> Result<(), Error> {
-    println!("{}", "Tailing requested log (exit with Ctrl-C)".yellow());
-    println!(
-        "{}", "⚠ Be aware that Adobe doesn't provide logs in realtime, so it might take a couple of seconds before logs start showing up.".yellow()
-    );
+/// * `input` - String slice that holds the period
+pub fn parse_since(input: &str) -> Result<ChronoDuration, String> {
+    let invalid = || format!("'{}' is no period like 30s, 5m, 1h or 2d", input);
+    let split = input
+        .find(|c: char| !c.is_ascii_digit())
+        .ok_or_else(invalid)?;
+    let (number, unit) = input.split_at(split);
+    let number: i64 = number.parse().map_err(|_| invalid())?;
+    let period = match unit {
+        "s" => ChronoDuration::try_seconds(number),
+        "m" => ChronoDuration::try_minutes(number),
+        "h" => ChronoDuration::try_hours(number),
+        "d" => ChronoDuration::try_days(number),
+        _ => None,
+    };
+    period
+        .filter(|period| *period > ChronoDuration::zero())
+        .ok_or_else(invalid)
+}
 
-    // -> get log path from API
+/// Layout of the timestamps in the lines of a logfile
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum LineTimestamp {
+    /// `01.03.2025 10:00:00.001 *INFO* ...` of the AEM error log
+    AemError,
+    /// `01/Mar/2025:10:00:00 +0000 [42] -> GET ...` of the AEM request log
+    AemRequest,
+    /// `host - user 01/Mar/2025:10:00:00 +0000 "GET ..."` of the AEM and httpd access logs
+    AccessLog,
+    /// `[01/Mar/2025:10:00:00 +0000] [I] ...` of the dispatcher log
+    Dispatcher,
+    /// `Sat Mar 01 10:00:00.123456 2025 [...] ...` of the httpd error log
+    HttpdError,
+    /// JSON objects with a `timestamp` field, one per line, of the CDN log
+    Cdn,
+}
 
-    let tail_url = get_tail_log_url(client, program_id, env_id, service, logname)
-        .await
-        .unwrap();
+/// Number of leading words of an access log line in which its timestamp is looked for
+const ACCESS_LOG_TIMESTAMP_WORDS: usize = 8;
 
-    let reqwest_client = reqwest::Client::new();
-    let init_response = reqwest_client.head(&tail_url).send().await?;
+impl LineTimestamp {
+    /// Returns the layout of the timestamps of a log, or `None` for logs that pippo doesn't know.
+    ///
+    /// # Arguments
+    ///
+    /// * `logname` - The log
+    pub fn of(logname: &LogName) -> Option<LineTimestamp> {
+        match logname {
+            LogName::Known(LogType::AemError) => Some(LineTimestamp::AemError),
+            LogName::Known(LogType::AemRequest) => Some(LineTimestamp::AemRequest),
+            LogName::Known(LogType::AemAccess) | LogName::Known(LogType::HttpdAccess) => {
+                Some(LineTimestamp::AccessLog)
+            }
+            LogName::Known(LogType::AemDispatcher) => Some(LineTimestamp::Dispatcher),
+            LogName::Known(LogType::HttpdError) => Some(LineTimestamp::HttpdError),
+            LogName::Known(LogType::Cdn) => Some(LineTimestamp::Cdn),
+            LogName::Custom(_) => None,
+        }
+    }
 
-    let mut last_content_length: i64 = 0;
+    /// Returns the timestamp of a line, or `None` if it has none, e.g. a line of a stack trace.
+    ///
+    /// Timestamps without a timezone are in UTC, like all logs of AEM as a Cloud Service.
+    ///
+    /// # Arguments
+    ///
+    /// * `line` - String slice that holds the line
+    pub fn parse(&self, line: &str) -> Option<DateTime<Utc>> {
+        let words: Vec<&str> = line.split_whitespace().collect();
+        let common_log = |date: &str, zone: &str| {
+            DateTime::parse_from_str(&format!("{} {}", date, zone), "%d/%b/%Y:%H:%M:%S %z")
+                .ok()
+                .map(|ts| ts.with_timezone(&Utc))
+        };
+        match self {
+            LineTimestamp::AemError => {
+                let stamp = words.get(..2)?.join(" ");
+                NaiveDateTime::parse_from_str(&stamp, "%d.%m.%Y %H:%M:%S%.f")
+                    .ok()
+                    .map(|ts| ts.and_utc())
+            }
+            LineTimestamp::AemRequest => common_log(words.first()?, words.get(1)?),
+            LineTimestamp::AccessLog => words
+                .iter()
+                .take(ACCESS_LOG_TIMESTAMP_WORDS)
+                .zip(words.iter().skip(1))
+                .find_map(|(date, zone)| common_log(date, zone)),
+            LineTimestamp::Dispatcher => {
+                let (stamp, _) = line.strip_prefix('[')?.split_once(']')?;
+                let (date, zone) = stamp.split_once(' ')?;
+                common_log(date, zone)
+            }
+            LineTimestamp::HttpdError => {
+                let stamp = words.get(..5)?.join(" ");
+                NaiveDateTime::parse_from_str(&stamp, "%a %b %d %H:%M:%S%.f %Y")
+                    .ok()
+                    .map(|ts| ts.and_utc())
+            }
+            LineTimestamp::Cdn => {
+                let event: serde_json::Value = serde_json::from_str(line).ok()?;
+                let stamp = event.get("timestamp")?.as_str()?;
+                DateTime::parse_from_rfc3339(stamp)
+                    .or_else(|_| DateTime::parse_from_str(stamp, "%Y-%m-%dT%H:%M:%S%z"))
+                    .ok()
+                    .map(|ts| ts.with_timezone(&Utc))
+            }
+        }
+    }
+}
 
-    match init_response.status() {
-        StatusCode::NOT_FOUND => {
-            eprintln!(
-                "{}",
-                "❌ The requested logfile was not found. Check your parameters.".red()
-            );
-            process::exit(1);
+/// Outcome of looking for the start of a period in the end of a logfile
+#[derive(Debug, PartialEq)]
+pub enum SinceScan {
+    /// The period starts at this offset
+    Found(i64),
+    /// No line of the scanned bytes is older than the period, so more have to be read
+    NeedMore,
+    /// None of the scanned lines has a timestamp
+    NoTimestamps,
+}
+
+/// Looks for the offset of the first line of a period in the end of a logfile.
+///
+/// The period starts after the last line that is older than `cutoff`; lines without timestamp
+/// belong to the line before them. If no line is older, the period starts at the first line with a
+/// timestamp once `exhausted` says that nothing more can be read.
+///
+/// # Arguments
+///
+/// * `buffer` - The end of the logfile that was read so far
+/// * `buffer_start` - Offset of `buffer` in the logfile
+/// * `exhausted` - Whether `buffer` starts at the beginning of the file or as far back as pippo reads
+/// * `cutoff` - Start of the period
+/// * `layout` - Layout of the timestamps of the lines
+pub fn since_offset(
+    buffer: &[u8],
+    buffer_start: i64,
+    exhausted: bool,
+    cutoff: DateTime<Utc>,
+    layout: LineTimestamp,
+) -> SinceScan {
+    let mut position = buffer_start;
+    let mut stamped: Vec<(i64, DateTime<Utc>)> = vec![];
+    for (index, line) in buffer.split_inclusive(|byte| *byte == b'\n').enumerate() {
+        // The first line is cut off unless the buffer starts at the beginning of the file
+        if index > 0 || buffer_start == 0 {
+            if let Some(ts) = layout.parse(&String::from_utf8_lossy(line)) {
+                stamped.push((position, ts));
+            }
         }
-        StatusCode::OK => {
-            debug!("Init response: {:?}", init_response);
-            let content_length = init_response
-                .headers()
-                .get("content-length")
-                .unwrap()
-                .to_str();
-            last_content_length = content_length.unwrap().to_owned().parse::<i64>().unwrap();
-            debug!("initial Content Length: {:?}", last_content_length);
+        position += line.len() as i64;
+    }
+
+    match stamped.iter().rposition(|(_, ts)| *ts < cutoff) {
+        Some(last_old) => SinceScan::Found(
+            stamped
+                .get(last_old + 1)
+                .map_or(position, |(offset, _)| *offset),
+        ),
+        None if !exhausted => SinceScan::NeedMore,
+        None if buffer_start == 0 && !stamped.is_empty() => SinceScan::Found(0),
+        None => stamped
+            .first()
+            .map_or(SinceScan::NoTimestamps, |(offset, _)| {
+                SinceScan::Found(*offset)
+            }),
+    }
+}
+
+/// Number of bytes that `--since` reads per request while going back in a logfile
+pub const SINCE_CHUNK_BYTES: i64 = 256 * 1024;
+
+/// Number of bytes before the end of a logfile that `--since` goes back at most
+pub const SINCE_MAX_BYTES: i64 = 16 * 1024 * 1024;
+
+/// Reads a logfile backwards in chunks until it finds the first line of the period that starts
+/// at `cutoff`, see [`since_offset`].
+///
+/// Returns `None` if the lines have no timestamps, so the caller can fall back to a fixed backfill.
+///
+/// # Arguments
+///
+/// * `source` - Where the logfile is read from
+/// * `url` - The tail URL of the logfile
+/// * `content_length` - Current length of the logfile in bytes
+/// * `cutoff` - Start of the period
+/// * `layout` - Layout of the timestamps of the lines
+/// * `chunk_bytes` - Number of bytes per request
+/// * `max_bytes` - Number of bytes before the end to go back at most
+pub async fn scan_since<S: TailSource + Send>(
+    source: &mut S,
+    url: &str,
+    content_length: i64,
+    cutoff: DateTime<Utc>,
+    layout: LineTimestamp,
+    chunk_bytes: i64,
+    max_bytes: i64,
+) -> Result<Option<i64>, TailPollError> {
+    let mut buffer: Vec<u8> = vec![];
+    let mut start = content_length.max(0);
+    loop {
+        let end = start;
+        start = end
+            .saturating_sub(chunk_bytes)
+            .max(content_length - max_bytes)
+            .max(0);
+        if start < end {
+            let mut chunk = source.read_range(url, start, end - 1).await?;
+            if chunk.len() as i64 != end - start {
+                return Err(TailPollError::Transient(format!(
+                    "asked for {} bytes, got {}",
+                    end - start,
+                    chunk.len()
+                )));
+            }
+            chunk.extend_from_slice(&buffer);
+            buffer = chunk;
         }
-        _ => {
-            eprintln!("{}: {}", "❌ API Error".red(), init_response.status());
+        let exhausted = start == 0 || content_length - start >= max_bytes;
+        match since_offset(&buffer, start, exhausted, cutoff, layout) {
+            SinceScan::Found(offset) => return Ok(Some(offset)),
+            SinceScan::NoTimestamps => return Ok(None),
+            SinceScan::NeedMore => {}
         }
     }
+}
 
-    // Now we can start printing what's being added to the logfile.
-    loop {
-        let range_header_value = format!("bytes={}-", last_content_length);
+/// Filename template that matches the names pippo has always used
+pub const DEFAULT_LOG_FILENAME_TEMPLATE: &str = "{date}_{env}-{service}_{log}.log.gz";
 
-        debug!("range_header_value: {:?}", range_header_value);
-        let response = reqwest_client
-            .get(&tail_url)
-            .header("Range", range_header_value)
-            .send()
-            .await?;
-        let current_content_length: i64 = response.content_length().unwrap() as i64;
+/// Where downloaded logfiles are written to
+#[derive(Clone, Debug, PartialEq)]
+pub struct LogTarget {
+    /// Directory the files are written to
+    pub dir: PathBuf,
+    /// Template for the filename, see [`render_log_filename`]
+    pub filename_template: String,
+    /// Replace files that already exist
+    pub overwrite: bool,
+    /// How the progress of a download is reported
+    pub progress: ProgressMode,
+    /// Continue partial downloads instead of starting over
+    pub resume: bool,
+    /// Compare downloads with the hash sent by the server, see [`expected_md5`]
+    pub verify: bool,
+}
 
-        debug!("Content Length: {:?}", current_content_length);
-        debug!("response.status(): {:?}", response.status());
+impl Default for LogTarget {
+    fn default() -> Self {
+        LogTarget {
+            dir: PathBuf::from("."),
+            filename_template: String::from(DEFAULT_LOG_FILENAME_TEMPLATE),
+            overwrite: false,
+            progress: ProgressMode::Off,
+            resume: true,
+            verify: false,
+        }
+    }
+}
 
-        match response.status() {
-            StatusCode::PARTIAL_CONTENT => {
-                let buffer: String = response.text().await?;
-                let current_log_lines = buffer.split('\n').collect::<Vec<_>>();
+/// How the progress of a log download is reported
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum ProgressMode {
+    Off,
+    /// A line at 25, 50 and 75 percent, for output that isn't a terminal
+    Milestones,
+    /// A line that is updated at most once per second
+    Live,
+}
 
-                for line in current_log_lines {
-                    // Don't print the trailing \n of the logfile
-                    if line.is_empty() {
-                        continue;
-                    }
-                    println!("{}", line);
+/// Source of the time that the throughput of a download is measured with
+pub trait Clock {
+    /// Time since the download started
+    fn elapsed(&self) -> Duration;
+}
+
+/// Measures the real time since it was created
+pub struct SystemClock(Instant);
+
+impl SystemClock {
+    pub fn new() -> Self {
+        SystemClock(Instant::now())
+    }
+}
+
+impl Clock for SystemClock {
+    fn elapsed(&self) -> Duration {
+        self.0.elapsed()
+    }
+}
+
+/// Formats a number of bytes for humans, e.g. `1.5 MB`.
+///
+/// # Arguments
+///
+/// * `bytes` - The number of bytes
+pub fn format_bytes(bytes: u64) -> String {
+    const UNITS: [&str; 4] = ["KB", "MB", "GB", "TB"];
+    if bytes < 1024 {
+        return format!("{} B", bytes);
+    }
+    let mut value = bytes as f64 / 1024.0;
+    let mut unit = 0;
+    while value >= 1024.0 && unit < UNITS.len() - 1 {
+        value /= 1024.0;
+        unit += 1;
+    }
+    format!("{:.1} {}", value, UNITS[unit])
+}
+
+/// Writer that passes everything on to `inner` and reports how much of a download was written.
+///
+/// Failing to report progress never fails the download.
+pub struct ProgressWriter<W: Write, R: Write, C: Clock> {
+    inner: W,
+    report: R,
+    clock: C,
+    mode: ProgressMode,
+    /// Name of the downloaded file, so concurrent reports can be told apart
+    name: String,
+    total: Option<u64>,
+    done: u64,
+    last_report: Duration,
+    reported: bool,
+    next_milestone: u64,
+}
+
+impl<W: Write, R: Write, C: Clock> ProgressWriter<W, R, C> {
+    /// # Arguments
+    ///
+    /// * `inner` - Where the download is written to
+    /// * `report` - Where progress is reported to, e.g. stderr
+    /// * `clock` - Measures the time since the download started
+    /// * `mode` - How progress is reported
+    /// * `name` - Name of the downloaded file
+    /// * `total` - Size of the download, if the server sent it
+    pub fn new(
+        inner: W,
+        report: R,
+        clock: C,
+        mode: ProgressMode,
+        name: &str,
+        total: Option<u64>,
+    ) -> Self {
+        ProgressWriter {
+            inner,
+            report,
+            clock,
+            mode,
+            name: name.to_string(),
+            total,
+            done: 0,
+            last_report: Duration::ZERO,
+            reported: false,
+            next_milestone: 25,
+        }
+    }
+
+    /// The current progress, e.g. `1.0 GB / 4.0 GB (25%), 12.0 MB/s`
+    fn progress_line(&self) -> String {
+        let seconds = self.clock.elapsed().as_secs_f64();
+        let throughput = if seconds > 0.0 {
+            format!(", {}/s", format_bytes((self.done as f64 / seconds) as u64))
+        } else {
+            String::new()
+        };
+        match self.total.filter(|total| *total > 0) {
+            Some(total) => format!(
+                "{:>4} {}: {} / {} ({}%){}",
+                "⏬",
+                self.name,
+                format_bytes(self.done),
+                format_bytes(total),
+                self.done * 100 / total,
+                throughput
+            ),
+            None => format!(
+                "{:>4} {}: {} downloaded{}",
+                "⏬",
+                self.name,
+                format_bytes(self.done),
+                throughput
+            ),
+        }
+    }
+
+    fn report_progress(&mut self) {
+        match self.mode {
+            ProgressMode::Off => {}
+            ProgressMode::Live => {
+                let now = self.clock.elapsed();
+                if now >= self.last_report + Duration::from_secs(1) {
+                    self.last_report = now;
+                    self.reported = true;
+                    let line = self.progress_line();
+                    let _ = write!(self.report, "\r{}", line);
+                    let _ = self.report.flush();
                 }
-                // sum with current content length because we need a new range start value
-                // for our next request
-                last_content_length = current_content_length + last_content_length;
-                sleep(Duration::from_secs(5));
             }
-            StatusCode::RANGE_NOT_SATISFIABLE => {
-                // no new content
-                sleep(Duration::from_secs(5));
+            ProgressMode::Milestones => {
+                let total = match self.total.filter(|total| *total > 0) {
+                    Some(total) => total,
+                    None => return,
+                };
+                let percent = self.done * 100 / total;
+                while self.next_milestone <= 75 && percent >= self.next_milestone {
+                    let _ = writeln!(
+                        self.report,
+                        "{:>4} {}: {}% of {}",
+                        "⏬",
+                        self.name,
+                        self.next_milestone,
+                        format_bytes(total)
+                    );
+                    self.next_milestone += 25;
+                }
             }
-            _ => {
-                eprintln!("{}: {}", "❌ API Error".red(), response.status());
+        }
+    }
+
+    /// Ends the live progress line, if one was printed.
+    pub fn finish(&mut self) -> io::Result<()> {
+        if self.reported {
+            let line = self.progress_line();
+            let _ = writeln!(self.report, "\r{}", line);
+        }
+        self.inner.flush()
+    }
+}
+
+impl<W: Write, R: Write, C: Clock> Write for ProgressWriter<W, R, C> {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        let written = self.inner.write(buf)?;
+        self.done += written as u64;
+        self.report_progress();
+        Ok(written)
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        self.inner.flush()
+    }
+}
+
+impl LogTarget {
+    /// Returns the path a logfile is written to.
+    ///
+    /// # Arguments
+    ///
+    /// * `date` - Date of the logfile
+    /// * `program_id` - A u32 that holds the program ID
+    /// * `env_id` - An EnvironmentId that holds the environment ID
+    /// * `service` - Service type of the logfile
+    /// * `logname` - Name of the logfile
+    pub fn path_for(
+        &self,
+        date: NaiveDate,
+        program_id: u32,
+        env_id: EnvironmentId,
+        service: &ServiceType,
+        logname: &LogName,
+    ) -> Result<PathBuf, String> {
+        let filename = render_log_filename(
+            &self.filename_template,
+            date,
+            program_id,
+            env_id,
+            service,
+            logname,
+        )?;
+        Ok(self.dir.join(filename))
+    }
+}
+
+/// Renders a logfile name from a template with the placeholders `{date}`, `{env}`, `{program}`,
+/// `{service}` and `{log}`.
+///
+/// # Arguments
+///
+/// * `template` - String slice that holds the template
+/// * `date` - Date of the logfile
+/// * `program_id` - A u32 that holds the program ID
+/// * `env_id` - An EnvironmentId that holds the environment ID
+/// * `service` - Service type of the logfile
+/// * `logname` - Name of the logfile
+pub fn render_log_filename(
+    template: &str,
+    date: NaiveDate,
+    program_id: u32,
+    env_id: EnvironmentId,
+    service: &ServiceType,
+    logname: &LogName,
+) -> Result<String, String> {
+    let mut rendered = String::new();
+    let mut rest = template;
+    while let Some(start) = rest.find('{') {
+        rendered.push_str(&rest[..start]);
+        let end = rest[start..]
+            .find('}')
+            .ok_or_else(|| format!("unclosed placeholder in '{}'", template))?;
+        match &rest[start + 1..start + end] {
+            "date" => rendered.push_str(&date.to_string()),
+            "env" => rendered.push_str(&env_id.to_string()),
+            "program" => rendered.push_str(&program_id.to_string()),
+            "service" => rendered.push_str(service.into()),
+            "log" => rendered.push_str(logname.as_str()),
+            other => {
+                return Err(format!(
+                    "unknown placeholder '{{{}}}', possible values: {{date}}, {{env}}, {{program}}, {{service}}, {{log}}",
+                    other
+                ))
             }
         }
+        rest = &rest[start + end + 1..];
     }
+    rendered.push_str(rest);
+    if rendered.trim().is_empty() {
+        return Err(String::from(
+            "the filename template renders to an empty name",
+        ));
+    }
+    Ok(rendered)
 }
 
-///  Gets the Url of the log we want to tail
+/// Downloads the specified log.
 ///
 /// # Arguments
 ///
 /// * `client` - A mutable reference to a CloudManagerClient instance
 /// * `program_id` - A u32 that holds the program ID
-/// * `env_id` - A u32 that holds the environment ID
-/// * `service` - Name of the service type - can be either author, publish, dispatcher, or preview_dispatcher
+/// * `env_id` - An EnvironmentId that holds the environment ID
+/// * `service` - Name of the service type - can be either author, publish, dispatcher, preview_dispatcher, or preview_publish
 /// * `logname` - Name of the logfile - can be either aemaccess, aemdispatcher, aemerror, aemrequest, cdn, httpdaccess, or httpderror
+/// * `date` - Date you want to retrieve the logs from, in the format YYYY-MM-DD
+/// * `target` - Where the logfile is written to
+///
+/// Returns the path of the written file.
 ///
 /// # Performed API Request
 ///
 /// ```
-/// GET https://cloudmanager.adobe.io/api/program/{program_id}/environment/{env_id}/logs/
+/// GET https://cloudmanager.adobe.io/api/program/{program_id}/environment/{env_id}/logs/download
 /// ```
-pub async fn get_tail_log_url(
+pub async fn download_log(
     client: &mut CloudManagerClient,
     program_id: u32,
-    env_id: u32,
+    env_id: EnvironmentId,
     service: ServiceType,
-    logname: LogType,
-) -> Result<String, Error> {
-    let query_parameters = vec![
-        ("service", service.clone().into()),
-        ("name", logname.clone().into()),
-        ("days", "2"),
-    ];
-
-    let request_path = format!(
-        "{}/api/program/{}/environment/{}/logs",
-        HOST_NAME, program_id, env_id
-    );
+    logname: LogName,
+    date: NaiveDate,
+    target: &LogTarget,
+) -> Result<String, LogError> {
+    match try_download_log(client, program_id, env_id, service, logname, date, target).await? {
+        LogDownload::Saved(path) => Ok(path),
+        LogDownload::Exists(path) => {
+            eprintln!(
+                "{} {}",
+                "❌ File already exists, use --overwrite to replace it:".red(),
+                path
+            );
+            audit::exit(1);
+        }
+        LogDownload::NotFound => Err(LogError::NotFound),
+        LogDownload::Failed(status, body) => Err(LogError::from_status(status, body)),
+        LogDownload::Incomplete(partial, size, expected) => Err(LogError::Request(
+            incomplete_message(&partial, size, expected),
+        )),
+        LogDownload::ChecksumMismatch(path, expected, actual) => Err(LogError::Request(
+            checksum_message(&path, &expected, &actual),
+        )),
+    }
+}
 
-    let response_obj = client
-        .perform_request(
-            Method::GET,
-            request_path.clone(),
-            None::<()>,
-            Some(query_parameters.clone()),
-        )
-        .await?
-        .text()
-        .await?;
-    let response: LogTailResponse =
-        serde_json::from_str(&response_obj.as_str()).unwrap_or_else(|_| {
-            throw_adobe_api_error(response_obj);
-            process::exit(1);
+/// Downloads the specified log and reports a missing logfile instead of exiting.
+///
+/// Takes the same arguments as [`download_log`].
+pub async fn try_download_log(
+    client: &mut CloudManagerClient,
+    program_id: u32,
+    env_id: EnvironmentId,
+    service: ServiceType,
+    logname: LogName,
+    date: NaiveDate,
+    target: &LogTarget,
+) -> Result<LogDownload, Error> {
+    let path = target
+        .path_for(date, program_id, env_id, &service, &logname)
+        .unwrap_or_else(|err| {
+            eprintln!("{} {}", "❌ Invalid filename template:".red(), err);
+            audit::exit(1);
         });
-    match &response.embedded.downloads[0]
-        .links
-        .http_ns_adobe_com_adobecloud_rel_logs_tail
-    {
-        Some(value) => {
-            // returning the log tail url
-            Ok(value.href.to_owned())
-        }
-        None => {
-            unreachable!();
-        }
+    // Don't download anything that would be thrown away
+    if path.exists() && !target.overwrite {
+        return Ok(LogDownload::Exists(path.display().to_string()));
+    }
+
+    let mut source = CloudLogFileSource {
+        client,
+        request_path: format!(
+            "{}/api/program/{}/environment/{}/logs/download",
+            client.host_name, program_id, env_id
+        ),
+        service,
+        logname,
+        date: date.to_string(),
+    };
+    save_log_file(&mut source, &path, target).await
+}
+
+/// Suffix of the file that a logfile is downloaded to until it is complete
+pub const PARTIAL_DOWNLOAD_SUFFIX: &str = ".part";
+
+/// Suffix of the file that holds the `ETag` or `Last-Modified` date of a partial download
+pub const PARTIAL_VALIDATOR_SUFFIX: &str = ".part.validator";
+
+/// Where a logfile is downloaded from
+#[async_trait]
+pub trait LogFileSource {
+    /// Requests the logfile. If `resume` holds an offset and a validator, only the bytes from the
+    /// offset on are requested, as long as the logfile still matches the validator.
+    async fn fetch(&mut self, resume: Option<(u64, &str)>) -> Result<Response, Error>;
+}
+
+/// Downloads a logfile from Cloud Manager
+struct CloudLogFileSource<'a> {
+    client: &'a CloudManagerClient,
+    request_path: String,
+    service: ServiceType,
+    logname: LogName,
+    date: String,
+}
+
+#[async_trait]
+impl LogFileSource for CloudLogFileSource<'_> {
+    async fn fetch(&mut self, resume: Option<(u64, &str)>) -> Result<Response, Error> {
+        let mut headers = HeaderMap::new();
+        if let Some((offset, validator)) = resume {
+            // The validator was read from a header, so it is a valid header value again
+            if let Ok(validator) = HeaderValue::from_str(validator) {
+                // A range of digits is always a valid header value
+                let range = HeaderValue::from_str(&format!("bytes={}-", offset)).unwrap();
+                headers.insert(RANGE, range);
+                headers.insert(IF_RANGE, validator);
+            }
+        }
+        let query_parameters = vec![
+            ("service", self.service.as_str()),
+            ("name", self.logname.as_str()),
+            ("date", self.date.as_str()),
+        ];
+        self.client
+            .get_with_headers(self.request_path.clone(), query_parameters, headers)
+            .await
+    }
+}
+
+/// Returns the path that a logfile is downloaded to until it is complete.
+///
+/// # Arguments
+///
+/// * `path` - Path of the finished logfile
+pub fn partial_path(path: &Path) -> PathBuf {
+    let mut partial = path.as_os_str().to_owned();
+    partial.push(PARTIAL_DOWNLOAD_SUFFIX);
+    PathBuf::from(partial)
+}
+
+/// Returns the path of the file that holds the validator of a partial download.
+///
+/// # Arguments
+///
+/// * `path` - Path of the finished logfile
+pub fn validator_path(path: &Path) -> PathBuf {
+    let mut validator = path.as_os_str().to_owned();
+    validator.push(PARTIAL_VALIDATOR_SUFFIX);
+    PathBuf::from(validator)
+}
+
+/// Returns the validator that a download can be resumed with: a strong `ETag` or, if the server
+/// sends none, the `Last-Modified` date. Weak ETags can't be used for ranges.
+///
+/// # Arguments
+///
+/// * `headers` - Headers of the download response
+pub fn range_validator(headers: &HeaderMap) -> Option<String> {
+    let header = |name| {
+        headers
+            .get(name)
+            .and_then(|value: &HeaderValue| value.to_str().ok())
+            .map(str::trim)
+            .filter(|value| !value.is_empty())
+    };
+    header(ETAG)
+        .filter(|etag| !etag.starts_with("W/"))
+        .or_else(|| header(LAST_MODIFIED))
+        .map(String::from)
+}
+
+/// Downloads a logfile to `path`, resuming an earlier partial download if the target allows it.
+///
+/// The logfile is written to its [`partial_path`] first and only renamed once its size matches
+/// the size announced by the server. The validator of the download is kept next to it, see
+/// [`range_validator`]. If a partial file and its validator exist, only the rest is requested with
+/// `Range` and `If-Range` headers and appended on `206`. Servers that ignore the range or have a
+/// newer logfile (`200`) or can't satisfy the range (`416`) send the whole logfile, which then
+/// replaces the partial file. Without a validator the download starts over.
+///
+/// # Arguments
+///
+/// * `source` - Where the logfile is downloaded from
+/// * `path` - Path of the finished logfile
+/// * `target` - Whether downloads are resumed and verified and how their progress is reported
+pub async fn save_log_file<S: LogFileSource + Send>(
+    source: &mut S,
+    path: &Path,
+    target: &LogTarget,
+) -> Result<LogDownload, Error> {
+    let mut download = fetch_log_file(source, path, target).await?;
+    for _ in 0..VERIFY_RETRIES {
+        if !matches!(download, LogDownload::ChecksumMismatch(..)) {
+            break;
+        }
+        if target.progress != ProgressMode::Off {
+            eprintln!(
+                "{:>4} {} doesn't match its checksum, downloading it again",
+                "⟳",
+                path.display()
+            );
+        }
+        download = fetch_log_file(source, path, target).await?;
+    }
+    Ok(download)
+}
+
+/// How often a logfile that doesn't match its checksum is downloaded again
+const VERIFY_RETRIES: u32 = 1;
+
+/// Downloads a logfile once, see [`save_log_file`]. A logfile that doesn't match its checksum is
+/// removed, so that the next attempt starts over.
+async fn fetch_log_file<S: LogFileSource + Send>(
+    source: &mut S,
+    path: &Path,
+    target: &LogTarget,
+) -> Result<LogDownload, Error> {
+    let partial = partial_path(path);
+    let validator_file = validator_path(path);
+    let resume = if target.resume {
+        let offset = std::fs::metadata(&partial).map_or(0, |metadata| metadata.len());
+        std::fs::read_to_string(&validator_file)
+            .ok()
+            .filter(|_| offset > 0)
+            .map(|validator| (offset, validator))
+    } else {
+        None
+    };
+    let offset = resume.as_ref().map_or(0, |(offset, _)| *offset);
+    let mut response = source
+        .fetch(resume.as_ref().map(|(offset, v)| (*offset, v.as_str())))
+        .await?;
+    if offset > 0 && response.status() == StatusCode::RANGE_NOT_SATISFIABLE {
+        debug!(
+            "Unable to resume {} at {}, downloading it again",
+            path.display(),
+            offset
+        );
+        response = source.fetch(None).await?;
+    }
+
+    let to_size = |length: Option<i64>| length.and_then(|l| u64::try_from(l).ok());
+    let (resumed, expected) = match response.status() {
+        StatusCode::NOT_FOUND => return Ok(LogDownload::NotFound),
+        StatusCode::PARTIAL_CONTENT if offset > 0 => {
+            (true, to_size(content_range_total(response.headers())))
+        }
+        StatusCode::OK => (false, to_size(header_length(response.headers()))),
+        status => {
+            return Ok(LogDownload::Failed(
+                status,
+                read_error_body(response).await.unwrap_or_default(),
+            ))
+        }
+    };
+    let checksum = if target.verify {
+        expected_md5(response.headers(), resumed)
+    } else {
+        None
+    };
+
+    // Save archive to the partial file, creating missing directories
+    if let Some(parent) = path.parent().filter(|p| !p.as_os_str().is_empty()) {
+        create_dir(parent);
+    }
+    if !resumed {
+        // Remember what the partial file is a part of, so a later run only resumes the same logfile
+        let _ = match range_validator(response.headers()) {
+            Some(validator) => std::fs::write(&validator_file, validator),
+            None => std::fs::remove_file(&validator_file),
+        };
+    }
+    let file = if resumed {
+        OpenOptions::new().append(true).open(&partial)
+    } else {
+        File::create(&partial)
+    }
+    .unwrap_or_else(|err| {
+        eprintln!(
+            "{} {}: {}",
+            "❌ Unable to create".red(),
+            partial.display(),
+            err
+        );
+        audit::exit(1);
+    });
+    let name = path.file_name().map_or_else(
+        || path.display().to_string(),
+        |n| n.to_string_lossy().into(),
+    );
+    if resumed && target.progress != ProgressMode::Off {
+        eprintln!("{:>4} Resuming {} at {}", "⟳", name, format_bytes(offset));
+    }
+    let mut writer = ProgressWriter::new(
+        file,
+        io::stderr(),
+        SystemClock::new(),
+        target.progress,
+        &name,
+        to_size(header_length(response.headers())),
+    );
+    // Stream the download, big CDN logs don't have to fit into memory
+    while let Some(chunk) = response.chunk().await? {
+        writer.write_all(&chunk).unwrap_or_else(|err| {
+            eprintln!(
+                "{} {}: {}",
+                "❌ Unable to write".red(),
+                partial.display(),
+                err
+            );
+            audit::exit(1);
+        });
+    }
+    writer.finish().unwrap_or_else(|err| {
+        eprintln!(
+            "{} {}: {}",
+            "❌ Unable to write".red(),
+            partial.display(),
+            err
+        );
+        audit::exit(1);
+    });
+    drop(writer);
+
+    let size = std::fs::metadata(&partial).map_or(0, |metadata| metadata.len());
+    if let Some(expected) = expected.filter(|expected| *expected != size) {
+        return Ok(LogDownload::Incomplete(
+            partial.display().to_string(),
+            size,
+            expected,
+        ));
+    }
+    if let Some(checksum) = checksum {
+        let actual = file_md5(&partial).unwrap_or_else(|err| {
+            eprintln!(
+                "{} {}: {}",
+                "❌ Unable to read".red(),
+                partial.display(),
+                err
+            );
+            audit::exit(1);
+        });
+        if actual != checksum {
+            let _ = std::fs::remove_file(&partial);
+            let _ = std::fs::remove_file(&validator_file);
+            return Ok(LogDownload::ChecksumMismatch(
+                path.display().to_string(),
+                checksum,
+                actual,
+            ));
+        }
+        debug!("{} matches its checksum {}", path.display(), checksum);
+    }
+    std::fs::rename(&partial, path).unwrap_or_else(|err| {
+        eprintln!(
+            "{} {}: {}",
+            "❌ Unable to rename".red(),
+            partial.display(),
+            err
+        );
+        audit::exit(1);
+    });
+    let _ = std::fs::remove_file(&validator_file);
+    Ok(LogDownload::Saved(path.display().to_string()))
+}
+
+/// Returns the MD5 that the server announces for a logfile as lowercase hex, if it sends one.
+///
+/// `Content-MD5` holds the base64 encoded hash of the body, so it is only used for complete
+/// downloads. A strong `ETag` that consists of 32 hex digits, as sent by S3-like storage for
+/// files that weren't uploaded in parts, is used otherwise. Other headers can't be verified.
+///
+/// # Arguments
+///
+/// * `headers` - Headers of the download response
+/// * `resumed` - Whether the response only contains the rest of a partial download
+pub fn expected_md5(headers: &HeaderMap, resumed: bool) -> Option<String> {
+    let content_md5 = headers
+        .get("content-md5")
+        .filter(|_| !resumed)
+        .and_then(|value| value.to_str().ok())
+        .and_then(|value| BASE64_STANDARD.decode(value.trim()).ok())
+        .filter(|hash| hash.len() == 16)
+        .map(|hash| hex(&hash));
+    content_md5.or_else(|| {
+        headers
+            .get(ETAG)
+            .and_then(|value| value.to_str().ok())
+            .map(|etag| etag.trim().trim_matches('"'))
+            .filter(|etag| etag.len() == 32 && etag.chars().all(|c| c.is_ascii_hexdigit()))
+            .map(|etag| etag.to_ascii_lowercase())
+    })
+}
+
+/// Computes the MD5 of a file as lowercase hex, reading it in chunks.
+///
+/// # Arguments
+///
+/// * `path` - Path of the file
+pub fn file_md5(path: &Path) -> io::Result<String> {
+    let mut file = File::open(path)?;
+    let mut hasher = Md5::new();
+    let mut buffer = [0u8; 64 * 1024];
+    loop {
+        let read = file.read(&mut buffer)?;
+        if read == 0 {
+            break;
+        }
+        hasher.update(&buffer[..read]);
+    }
+    Ok(hex(&hasher.finalize()))
+}
+
+fn hex(bytes: &[u8]) -> String {
+    bytes.iter().map(|byte| format!("{:02x}", byte)).collect()
+}
+
+/// Downloads the specified log and writes it to `out` instead of a file, e.g. to pipe it into
+/// other tools.
+///
+/// Takes the same arguments as [`download_log`], except for the target. Returns the number of
+/// bytes that were received.
+///
+/// # Arguments
+///
+/// * `out` - Where the logfile is written to
+/// * `decompress` - Whether the gzipped logfile is decompressed while it is written
+#[allow(clippy::too_many_arguments)]
+pub async fn stream_log<W: Write>(
+    client: &mut CloudManagerClient,
+    program_id: u32,
+    env_id: EnvironmentId,
+    service: ServiceType,
+    logname: LogName,
+    date: NaiveDate,
+    out: W,
+    decompress: bool,
+) -> Result<u64, LogError> {
+    let mut source = CloudLogFileSource {
+        client,
+        request_path: format!(
+            "{}/api/program/{}/environment/{}/logs/download",
+            client.host_name, program_id, env_id
+        ),
+        service,
+        logname,
+        date: date.to_string(),
+    };
+    stream_log_file(&mut source, out, decompress).await
+}
+
+/// Writes a logfile to `out` as it is received.
+///
+/// Fails if the download breaks off or ends before the size announced by the server. A reader
+/// that stops early (e.g. `head`) simply ends the download.
+///
+/// # Arguments
+///
+/// * `source` - Where the logfile is downloaded from
+/// * `out` - Where the logfile is written to
+/// * `decompress` - Whether the gzipped logfile is decompressed while it is written
+pub async fn stream_log_file<S: LogFileSource + Send, W: Write>(
+    source: &mut S,
+    out: W,
+    decompress: bool,
+) -> Result<u64, LogError> {
+    let mut response = source.fetch(None).await?;
+    match response.status() {
+        StatusCode::OK => {}
+        StatusCode::NOT_FOUND => return Err(LogError::NotFound),
+        status => {
+            let body = read_error_body(response).await.unwrap_or_default();
+            return Err(LogError::from_status(status, body));
+        }
+    }
+    let expected = header_length(response.headers()).and_then(|l| u64::try_from(l).ok());
+    let (received, reader_gone) = if decompress {
+        let mut decoder = GzDecoder::new(out);
+        let written = write_body(&mut response, &mut decoder).await?;
+        finish_body(written, decoder.finish().and_then(|mut out| out.flush()))?
+    } else {
+        let mut out = out;
+        let written = write_body(&mut response, &mut out).await?;
+        finish_body(written, out.flush())?
+    };
+    match expected {
+        Some(expected) if !reader_gone && expected != received => Err(LogError::Request(format!(
+            "download ended after {} of {} bytes",
+            received, expected
+        ))),
+        _ => Ok(received),
+    }
+}
+
+/// Combines the outcome of [`write_body`] with the final flush of the output.
+fn finish_body(written: (u64, bool), flushed: io::Result<()>) -> Result<(u64, bool), LogError> {
+    match flushed {
+        Ok(()) => Ok(written),
+        Err(err) if err.kind() == io::ErrorKind::BrokenPipe => Ok((written.0, true)),
+        Err(err) => Err(LogError::Request(format!(
+            "unable to write the log: {}",
+            err
+        ))),
+    }
+}
+
+/// Writes the body of a response to `out` as it is received.
+///
+/// Returns the number of received bytes and whether the reader went away before the end.
+async fn write_body<W: Write>(
+    response: &mut Response,
+    out: &mut W,
+) -> Result<(u64, bool), LogError> {
+    let mut received: u64 = 0;
+    while let Some(chunk) = response.chunk().await? {
+        received += chunk.len() as u64;
+        match out.write_all(&chunk) {
+            Ok(()) => {}
+            Err(err) if err.kind() == io::ErrorKind::BrokenPipe => return Ok((received, true)),
+            Err(err) => {
+                return Err(LogError::Request(format!(
+                    "unable to write the log: {}",
+                    err
+                )))
+            }
+        }
+    }
+    Ok((received, false))
+}
+
+/// Describes a download that ended early and how to resume it.
+fn incomplete_message(partial: &str, size: u64, expected: u64) -> String {
+    format!(
+        "download ended after {} of {} bytes, run again to resume {}",
+        size, expected, partial
+    )
+}
+
+fn checksum_message(path: &str, expected: &str, actual: &str) -> String {
+    format!(
+        "{} doesn't match its checksum, expected MD5 {} but got {}",
+        path, expected, actual
+    )
+}
+
+/// Creates a directory and its parents, exiting if that fails.
+fn create_dir(dir: &Path) {
+    std::fs::create_dir_all(dir).unwrap_or_else(|err| {
+        eprintln!("{} {}: {}", "❌ Unable to create".red(), dir.display(), err);
+        audit::exit(1);
+    });
+}
+
+/// Downloads every logfile that an environment offers for the given date.
+///
+/// Logfiles that don't exist for the date are skipped. Prints a summary of what was and wasn't
+/// available and returns `false` if any download failed for another reason.
+///
+/// # Arguments
+///
+/// * `client` - A mutable reference to a CloudManagerClient instance
+/// * `program_id` - A u32 that holds the program ID
+/// * `env_id` - An EnvironmentId that holds the environment ID
+/// * `date` - Date you want to retrieve the logs from
+/// * `target` - Where the logfiles are written to
+/// * `tag` - Prefix of every printed line, see [`environment_tag`]
+pub async fn download_all_logs(
+    client: &mut CloudManagerClient,
+    program_id: u32,
+    env_id: EnvironmentId,
+    date: NaiveDate,
+    target: &LogTarget,
+    tag: &str,
+) -> Result<bool, Error> {
+    let env = get_environment(client, program_id, env_id).await?;
+    let combinations = log_combinations(&env.available_log_options);
+
+    let mut saved = vec![];
+    let mut not_found = vec![];
+    let mut failed = vec![];
+    for (service, logname) in combinations {
+        let label = format!("{}/{}", Into::<&str>::into(&service), logname.as_str());
+        match try_download_log(client, program_id, env_id, service, logname, date, target).await? {
+            LogDownload::Saved(path) => {
+                println!("{}{:>4} {} → {}", tag, "✔".green(), label, path);
+                saved.push(label);
+            }
+            LogDownload::Exists(path) => {
+                eprintln!(
+                    "{}{:>4} {} {} already exists, use --overwrite to replace it",
+                    tag,
+                    "❌".red(),
+                    label,
+                    path
+                );
+                failed.push(label);
+            }
+            LogDownload::NotFound => {
+                println!("{}{:>4} {} not available", tag, "✖", label);
+                not_found.push(label);
+            }
+            LogDownload::Failed(status, _) => {
+                eprintln!("{}{:>4} {} failed: {}", tag, "❌".red(), label, status);
+                failed.push(label);
+            }
+            LogDownload::Incomplete(partial, size, expected) => {
+                eprintln!(
+                    "{}{:>4} {} failed: {}",
+                    tag,
+                    "❌".red(),
+                    label,
+                    incomplete_message(&partial, size, expected)
+                );
+                failed.push(label);
+            }
+            LogDownload::ChecksumMismatch(path, expected, actual) => {
+                eprintln!(
+                    "{}{:>4} {} failed: {}",
+                    tag,
+                    "❌".red(),
+                    label,
+                    checksum_message(&path, &expected, &actual)
+                );
+                failed.push(label);
+            }
+        }
+    }
+
+    println!(
+        "\n{}{} saved, {} not available ({}), {} failed",
+        tag,
+        saved.len(),
+        not_found.len(),
+        not_found.join(", "),
+        failed.len()
+    );
+    Ok(failed.is_empty())
+}
+
+/// Prefix for the output of one environment when several are worked on at once
+///
+/// # Arguments
+///
+/// * `env_id` - An EnvironmentId that holds the environment ID
+pub fn environment_tag(env_id: EnvironmentId) -> String {
+    format!("[{}] ", env_id)
+}
+
+/// Maximum number of logs that `log tail` follows at the same time
+pub const MAX_TAILS: usize = 8;
+
+/// Colors that the prefixes of merged tails cycle through
+const TAIL_COLORS: [Color; 6] = [
+    Color::Cyan,
+    Color::Magenta,
+    Color::Yellow,
+    Color::Blue,
+    Color::Green,
+    Color::BrightCyan,
+];
+
+/// Parses a log to tail, given as `service:log`, e.g. `dispatcher:httpderror`.
+///
+/// # Arguments
+///
+/// * `input` - String slice that holds the spec
+pub fn parse_tail_spec(input: &str) -> Result<(ServiceType, LogName), String> {
+    let (service, logname) = input
+        .split_once(':')
+        .ok_or_else(|| format!("'{}' must have the form service:log", input))?;
+    let service =
+        ServiceType::from_str(service).map_err(|_| format!("unknown service '{}'", service))?;
+    let logname = LogName::from_str(logname)?;
+    Ok((service, logname))
+}
+
+/// Prefix for the lines of one tail when the output of several tails is merged.
+///
+/// # Arguments
+///
+/// * `env_id` - The tailed environment, if several environments are tailed
+/// * `source` - The tailed service and log, if several logs are tailed
+/// * `index` - Position of the log among the tailed logs, picks the color of its prefix
+pub fn tail_tag(
+    env_id: Option<EnvironmentId>,
+    source: Option<(&ServiceType, &LogName)>,
+    index: usize,
+) -> String {
+    let mut tag = env_id.map(environment_tag).unwrap_or_default();
+    if let Some((service, logname)) = source {
+        let service: &str = service.as_str();
+        let logname = logname.as_str();
+        let prefix = format!("[{}/{}]", service, logname);
+        tag.push_str(&format!(
+            "{} ",
+            prefix.color(TAIL_COLORS[index % TAIL_COLORS.len()])
+        ));
+    }
+    tag
+}
+
+/// Downloads logs of several environments, at most `concurrency` environments at the same time.
+///
+/// Every printed line is tagged with its environment. Returns `false` if any download failed for
+/// another reason than a missing logfile.
+///
+/// # Arguments
+///
+/// * `client` - A reference to a CloudManagerClient instance, clones of it are used per environment
+/// * `program_id` - A u32 that holds the program ID
+/// * `env_ids` - The environments to download the logs of
+/// * `selection` - Services and logs to download, or `None` for all logs the environments offer
+/// * `dates` - The days to download
+/// * `target` - Where the logfiles are written to, its template must contain `{env}`
+/// * `concurrency` - Maximum number of environments that are downloaded in parallel
+pub async fn download_logs_of_environments(
+    client: &CloudManagerClient,
+    program_id: u32,
+    env_ids: Vec<EnvironmentId>,
+    selection: Option<Vec<(ServiceType, LogName)>>,
+    dates: Vec<NaiveDate>,
+    target: LogTarget,
+    concurrency: usize,
+) -> bool {
+    let semaphore = Arc::new(Semaphore::new(concurrency.max(1)));
+    let dates = Arc::new(dates);
+    let target = Arc::new(target);
+    let selection = Arc::new(selection);
+    let mut tasks = JoinSet::new();
+    for env_id in env_ids {
+        let mut client = client.clone();
+        let semaphore = semaphore.clone();
+        let dates = dates.clone();
+        let target = target.clone();
+        let selection = selection.clone();
+        tasks.spawn(async move {
+            let _permit = semaphore.acquire_owned().await.unwrap();
+            let tag = environment_tag(env_id);
+            let result = match selection.as_ref() {
+                // The environments already run in parallel, so their files are fetched one by one
+                Some(selection) => Ok(download_log_selection(
+                    &client, program_id, env_id, selection, &dates, &target, &tag, 1,
+                )
+                .await),
+                None => {
+                    let mut complete = Ok(true);
+                    for date in dates.iter() {
+                        match download_all_logs(
+                            &mut client,
+                            program_id,
+                            env_id,
+                            *date,
+                            &target,
+                            &tag,
+                        )
+                        .await
+                        {
+                            Ok(day_complete) => {
+                                complete = complete.map(|c| c && day_complete);
+                            }
+                            Err(err) => {
+                                complete = Err(err);
+                                break;
+                            }
+                        }
+                    }
+                    complete
+                }
+            };
+            result.unwrap_or_else(|err| {
+                eprintln!("{}{} {}", tag, "❌ API error:".red(), err);
+                false
+            })
+        });
+    }
+    let mut complete = true;
+    while let Some(joined) = tasks.join_next().await {
+        complete &= joined.expect("Downloading logs panicked");
+    }
+    complete
+}
+
+/// Expands a range of dates, including both ends.
+///
+/// # Arguments
+///
+/// * `from` - First day of the range
+/// * `to` - Last day of the range
+pub fn expand_date_range(from: NaiveDate, to: NaiveDate) -> Result<Vec<NaiveDate>, String> {
+    if from > to {
+        return Err(format!("--from {} is after --to {}", from, to));
+    }
+    Ok(from.iter_days().take_while(|d| *d <= to).collect())
+}
+
+/// Parses the date of a logfile, either as `YYYY-MM-DD`, `today`, `yesterday` or `-N` for N
+/// days ago.
+///
+/// # Arguments
+///
+/// * `input` - String slice that holds the date from the command line
+/// * `today` - The day that relative dates are counted from
+pub fn parse_log_date(input: &str, today: NaiveDate) -> Result<NaiveDate, String> {
+    let days_ago = match input.trim() {
+        "today" => Some(0),
+        "yesterday" => Some(1),
+        relative if relative.starts_with('-') => relative[1..].parse::<u32>().ok(),
+        absolute => {
+            return NaiveDate::from_str(absolute).map_err(|err| {
+                format!(
+                    "{} ('{}' is neither YYYY-MM-DD, today, yesterday nor -N for N days ago)",
+                    err, input
+                )
+            })
+        }
+    };
+    days_ago
+        .and_then(|days| today.checked_sub_signed(ChronoDuration::days(i64::from(days))))
+        .ok_or_else(|| {
+            format!(
+                "'{}' is neither YYYY-MM-DD, today, yesterday nor -N for N days ago",
+                input
+            )
+        })
+}
+
+/// Returns the last `days` days up to and including `today`, oldest first.
+///
+/// # Arguments
+///
+/// * `today` - Last day of the range
+/// * `days` - Number of days, at least 1
+pub fn last_days(today: NaiveDate, days: u32) -> Vec<NaiveDate> {
+    let from = today - ChronoDuration::days(i64::from(days.max(1)) - 1);
+    from.iter_days().take_while(|d| *d <= today).collect()
+}
+
+/// Outcome of downloading a logfile over a range of days
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct LogRangeSummary {
+    pub saved: Vec<String>,
+    pub skipped: Vec<NaiveDate>,
+    pub failed: Vec<NaiveDate>,
+}
+
+impl LogRangeSummary {
+    /// Records the download of one day and returns the progress line for it. Days without a
+    /// logfile are skipped instead of failing the whole range.
+    ///
+    /// # Arguments
+    ///
+    /// * `date` - The downloaded day
+    /// * `download` - Result of the download
+    pub fn record(&mut self, date: NaiveDate, download: LogDownload) -> String {
+        match download {
+            LogDownload::Saved(path) => {
+                let line = format!("{:>4} {} → {}", "✔", date, path);
+                self.saved.push(path);
+                line
+            }
+            LogDownload::Exists(path) => {
+                self.failed.push(date);
+                format!(
+                    "{:>4} {} {} already exists, use --overwrite to replace it",
+                    "❌", date, path
+                )
+            }
+            LogDownload::NotFound => {
+                self.skipped.push(date);
+                format!("{:>4} {} not available, skipping", "⚠", date)
+            }
+            LogDownload::Failed(status, _) => {
+                self.failed.push(date);
+                format!("{:>4} {} failed: {}", "❌", date, status)
+            }
+            LogDownload::Incomplete(partial, size, expected) => {
+                self.failed.push(date);
+                format!(
+                    "{:>4} {} failed: {}",
+                    "❌",
+                    date,
+                    incomplete_message(&partial, size, expected)
+                )
+            }
+            LogDownload::ChecksumMismatch(path, expected, actual) => {
+                self.failed.push(date);
+                format!(
+                    "{:>4} {} failed: {}",
+                    "❌",
+                    date,
+                    checksum_message(&path, &expected, &actual)
+                )
+            }
+        }
+    }
+
+    /// Records a download that didn't get an answer, e.g. because the connection broke, and
+    /// returns the progress line for it.
+    ///
+    /// # Arguments
+    ///
+    /// * `date` - The downloaded day
+    /// * `error` - Why the download failed
+    pub fn record_error(&mut self, date: NaiveDate, error: &str) -> String {
+        self.failed.push(date);
+        format!("{:>4} {} failed: {}", "❌", date, error)
+    }
+}
+
+/// Value of `--service` and `--log` that stands for every service or log
+pub const ALL_LOGS: &str = "all";
+
+/// Checks a value of `log save --log`, which is either a log name or `all`.
+///
+/// # Arguments
+///
+/// * `input` - String slice that holds the value
+pub fn parse_log_arg(input: &str) -> Result<String, String> {
+    if input != ALL_LOGS {
+        LogName::from_str(input)?;
+    }
+    Ok(input.to_string())
+}
+
+/// Expands the services and logs given to `log save` into every combination of them, in the
+/// given order and without duplicates.
+///
+/// # Arguments
+///
+/// * `services` - Names of services or `all`
+/// * `lognames` - Names of logs or `all`
+pub fn expand_log_selection(
+    services: &[String],
+    lognames: &[String],
+) -> Result<Vec<(ServiceType, LogName)>, String> {
+    fn expand<T: PartialEq>(
+        names: &[String],
+        all: impl Fn() -> Vec<T>,
+        parse: impl Fn(&str) -> Result<T, String>,
+    ) -> Result<Vec<T>, String> {
+        let mut values: Vec<T> = vec![];
+        for name in names {
+            let expanded = if name == ALL_LOGS {
+                all()
+            } else {
+                vec![parse(name)?]
+            };
+            for value in expanded {
+                if !values.contains(&value) {
+                    values.push(value);
+                }
+            }
+        }
+        Ok(values)
+    }
+
+    let services = expand(
+        services,
+        || ServiceType::iter().collect(),
+        |name| ServiceType::from_str(name).map_err(|_| format!("unknown service '{}'", name)),
+    )?;
+    let lognames = expand(
+        lognames,
+        || LogType::iter().map(LogName::from).collect(),
+        LogName::from_str,
+    )?;
+    Ok(services
+        .iter()
+        .flat_map(|service| {
+            lognames
+                .iter()
+                .map(move |logname| (service.clone(), logname.clone()))
+        })
+        .collect())
+}
+
+/// Sums up the downloads of several logs over a range of days.
+///
+/// # Arguments
+///
+/// * `results` - The outcome per service and log
+pub fn summarize_log_selection(results: &[(ServiceType, LogName, LogRangeSummary)]) -> String {
+    let count =
+        |f: fn(&LogRangeSummary) -> usize| results.iter().map(|(_, _, s)| f(s)).sum::<usize>();
+    let empty: Vec<String> = results
+        .iter()
+        .filter(|(_, _, summary)| summary.saved.is_empty() && summary.failed.is_empty())
+        .map(|(service, logname, _)| {
+            let service: &str = service.as_str();
+            let logname = logname.as_str();
+            format!("{}/{}", service, logname)
+        })
+        .collect();
+    let mut line = format!(
+        "{} logs: {} files written, {} not available, {} failed",
+        results.len(),
+        count(|s| s.saved.len()),
+        count(|s| s.skipped.len()),
+        count(|s| s.failed.len())
+    );
+    if !empty.is_empty() {
+        line.push_str(&format!(" (nothing found for {})", empty.join(", ")));
+    }
+    line
+}
+
+/// Runs a job for every input, at most `concurrency` at the same time, and hands each result to
+/// `done` as soon as it is there. A failing job doesn't cancel the others.
+///
+/// # Arguments
+///
+/// * `jobs` - The inputs of the jobs
+/// * `concurrency` - Maximum number of jobs that run at the same time
+/// * `start` - Creates the job for an input
+/// * `done` - Is called with the input and the result of every job, in the order they finish
+pub async fn run_bounded<J, T, F, Fut, D>(jobs: Vec<J>, concurrency: usize, start: F, mut done: D)
+where
+    J: Send + 'static,
+    T: Send + 'static,
+    F: Fn(&J) -> Fut,
+    Fut: std::future::Future<Output = T> + Send + 'static,
+    D: FnMut(J, T),
+{
+    let semaphore = Arc::new(Semaphore::new(concurrency.max(1)));
+    let mut tasks = JoinSet::new();
+    for job in jobs {
+        let semaphore = semaphore.clone();
+        let future = start(&job);
+        tasks.spawn(async move {
+            let _permit = semaphore.acquire_owned().await.unwrap();
+            (job, future.await)
+        });
+    }
+    while let Some(joined) = tasks.join_next().await {
+        let (job, result) = joined.expect("Downloading logs panicked");
+        done(job, result);
+    }
+}
+
+/// Downloads several logs once per day, at most `concurrency` files at the same time.
+///
+/// Prints a line per downloaded file and a summary at the end. Days that have no logfile are
+/// skipped with a warning. Returns `false` if any download failed for another reason than a
+/// missing logfile.
+///
+/// # Arguments
+///
+/// * `client` - A reference to a CloudManagerClient instance, cloned for every download
+/// * `program_id` - A u32 that holds the program ID
+/// * `env_id` - An EnvironmentId that holds the environment ID
+/// * `selection` - Services and logs to download, see [`expand_log_selection`]
+/// * `dates` - The days to download
+/// * `target` - Where the logfiles are written to
+/// * `tag` - Prefix of every printed line, see [`environment_tag`]
+/// * `concurrency` - Maximum number of files that are downloaded at the same time
+#[allow(clippy::too_many_arguments)]
+pub async fn download_log_selection(
+    client: &CloudManagerClient,
+    program_id: u32,
+    env_id: EnvironmentId,
+    selection: &[(ServiceType, LogName)],
+    dates: &[NaiveDate],
+    target: &LogTarget,
+    tag: &str,
+    concurrency: usize,
+) -> bool {
+    let mut jobs = vec![];
+    for (index, (service, logname)) in selection.iter().enumerate() {
+        for date in dates {
+            jobs.push((index, service.clone(), logname.clone(), *date));
+        }
+    }
+    let total = jobs.len();
+    let mut target = target.clone();
+    // Live progress lines of concurrent downloads would overwrite each other
+    if total > 1 && concurrency > 1 && target.progress == ProgressMode::Live {
+        target.progress = ProgressMode::Milestones;
+    }
+    let target = Arc::new(target);
+    let prefix = |index: usize| {
+        if selection.len() > 1 {
+            let service: &str = selection[index].0.as_str();
+            let logname = selection[index].1.as_str();
+            format!("{}[{}/{}] ", tag, service, logname)
+        } else {
+            tag.to_string()
+        }
+    };
+
+    let mut summaries: Vec<LogRangeSummary> = selection
+        .iter()
+        .map(|_| LogRangeSummary::default())
+        .collect();
+    let mut finished = 0;
+    run_bounded(
+        jobs,
+        concurrency,
+        |(index, service, logname, date)| {
+            let mut client = client.clone();
+            let target = target.clone();
+            let (service, logname, date) = (service.clone(), logname.clone(), *date);
+            let prefix = prefix(*index);
+            async move {
+                println!("{}Downloading {}", prefix, date);
+                try_download_log(
+                    &mut client,
+                    program_id,
+                    env_id,
+                    service,
+                    logname,
+                    date,
+                    &target,
+                )
+                .await
+            }
+        },
+        |(index, _, _, date), download| {
+            finished += 1;
+            let summary = &mut summaries[index];
+            let line = match download {
+                Ok(download) => summary.record(date, download),
+                Err(err) => summary.record_error(date, &err.to_string()),
+            };
+            let line = format!("{}[{}/{}] {}", prefix(index), finished, total, line);
+            match summary.failed.last() {
+                Some(failed) if *failed == date => eprintln!("{}", line.red()),
+                _ => println!("{}", line),
+            }
+        },
+    )
+    .await;
+
+    let results: Vec<(ServiceType, LogName, LogRangeSummary)> = selection
+        .iter()
+        .cloned()
+        .zip(summaries)
+        .map(|((service, logname), summary)| (service, logname, summary))
+        .collect();
+    if results.len() > 1 {
+        println!("\n{}{}", tag, summarize_log_selection(&results));
+    } else if let Some((_, _, summary)) = results.first() {
+        println!(
+            "\n{}{} of {} files written, {} days not available, {} failed",
+            tag,
+            summary.saved.len(),
+            dates.len(),
+            summary.skipped.len(),
+            summary.failed.len()
+        );
+    }
+    results
+        .iter()
+        .all(|(_, _, summary)| summary.failed.is_empty())
+}
+
+/// Logfiles of one service that can be downloaded
+#[derive(Debug, PartialEq, Serialize)]
+pub struct LogGroup {
+    pub service: ServiceType,
+    pub logs: Vec<AvailableLog>,
+}
+
+/// A logfile of a service that can be downloaded
+#[derive(Debug, PartialEq, Serialize)]
+pub struct AvailableLog {
+    pub name: LogName,
+    pub date: NaiveDate,
+}
+
+/// Lists the logfiles that an environment has for the last days.
+///
+/// Asks for every service and log that the environment offers.
+///
+/// # Arguments
+///
+/// * `client` - A mutable reference to a CloudManagerClient instance
+/// * `program_id` - A u32 that holds the program ID
+/// * `env_id` - An EnvironmentId that holds the environment ID
+/// * `days` - Number of days, counting back from today
+///
+/// # Performed API Requests
+///
+/// ```
+/// GET https://cloudmanager.adobe.io/api/program/{program_id}/environment/{env_id}
+/// GET https://cloudmanager.adobe.io/api/program/{program_id}/environment/{env_id}/logs
+/// ```
+pub async fn get_available_logs(
+    client: &mut CloudManagerClient,
+    program_id: u32,
+    env_id: EnvironmentId,
+    days: u32,
+) -> Result<Vec<Log>, Error> {
+    let env = get_environment(client, program_id, env_id).await?;
+    let combinations = log_combinations(&env.available_log_options);
+    if combinations.is_empty() {
+        return Ok(vec![]);
+    }
+
+    let mut services: Vec<&str> = vec![];
+    let mut names: Vec<&str> = vec![];
+    for (service, logname) in &combinations {
+        let service: &str = service.into();
+        let logname = logname.as_str();
+        if !services.contains(&service) {
+            services.push(service);
+        }
+        if !names.contains(&logname) {
+            names.push(logname);
+        }
+    }
+    let days = days.to_string();
+    let mut query_parameters: Vec<(&str, &str)> = vec![("days", days.as_str())];
+    query_parameters.extend(services.iter().map(|s| ("service", *s)));
+    query_parameters.extend(names.iter().map(|n| ("name", *n)));
+
+    let request_path = format!(
+        "{}/api/program/{}/environment/{}/logs",
+        client.host_name, program_id, env_id
+    );
+    let response = client
+        .perform_request(
+            Method::GET,
+            request_path,
+            None::<()>,
+            Some(query_parameters),
+        )
+        .await?
+        .text()
+        .await?;
+    client.print_raw_response(&response);
+    let logs: LogsResponse = serde_json::from_str(response.as_str()).unwrap_or_else(|_| {
+        throw_adobe_api_error(response);
+        audit::exit(1);
+    });
+    Ok(logs.embedded.downloads)
+}
+
+/// Groups available logfiles by service, keeping the order in which services appear. Within a
+/// service, logs are sorted by name and newest date first.
+///
+/// # Arguments
+///
+/// * `logs` - The available logfiles
+pub fn group_logs_by_service(logs: Vec<Log>) -> Vec<LogGroup> {
+    let mut groups: Vec<LogGroup> = vec![];
+    for Log {
+        name,
+        service,
+        date,
+    } in logs
+    {
+        let available = AvailableLog { name, date };
+        match groups.iter_mut().find(|g| g.service == service) {
+            Some(group) => group.logs.push(available),
+            None => groups.push(LogGroup {
+                service,
+                logs: vec![available],
+            }),
+        }
+    }
+    for group in &mut groups {
+        group.logs.sort_by(|a, b| {
+            a.name
+                .as_str()
+                .cmp(b.name.as_str())
+                .then(b.date.cmp(&a.date))
+        });
+    }
+    groups
+}
+
+/// Renders grouped logfiles as one header per service and one line per log and date.
+///
+/// # Arguments
+///
+/// * `groups` - The logfiles grouped by service
+pub fn render_log_groups(groups: &[LogGroup]) -> Vec<String> {
+    let mut lines = vec![];
+    for group in groups {
+        lines.push(format!("☁ {}", Into::<&str>::into(&group.service)));
+        for log in &group.logs {
+            lines.push(format!("{:>4} {:<16} {}", "", log.name.as_str(), log.date));
+        }
+    }
+    lines
+}
+
+/// Returns the combinations of service and log that an environment offers.
+///
+/// Services and logs that pippo doesn't know yet are kept as they are, so they can be downloaded
+/// like every other.
+///
+/// # Arguments
+///
+/// * `options` - The log options that an environment offers
+pub fn log_combinations(options: &[LogOption]) -> Vec<(ServiceType, LogName)> {
+    options
+        .iter()
+        .map(|option| {
+            (
+                ServiceType::from_api(&option.service),
+                LogName::from_api(&option.name),
+            )
+        })
+        .collect()
+}
+
+/// Tails the specified log.
+///
+/// # Arguments
+///
+/// * `client` - A mutable reference to a CloudManagerClient instance
+/// * `program_id` - A u32 that holds the program ID
+/// * `env_id` - An EnvironmentId that holds the environment ID
+/// * `service` - Name of the service type - can be either author, publish, dispatcher, preview_dispatcher, or preview_publish
+/// * `logname` - Name of the logfile - can be either aemaccess, aemdispatcher, aemerror, aemrequest, cdn, httpdaccess, or httpderror
+/// * `options` - How the log is printed and recorded
+///
+/// # Performed API Request
+///
+/// ```
+/// GET https://cloudmanager.adobe.io/api/program/{program_id}/environment/{env_id}/logs/download
+/// ```
+pub async fn tail_log(
+    client: &mut CloudManagerClient,
+    program_id: u32,
+    env_id: EnvironmentId,
+    service: ServiceType,
+    logname: LogName,
+    options: &mut TailOptions,
+) -> Result<(), LogError> {
+    let tag = options.tag.clone();
+    // Coloring matches only helps when the output is colored at all
+    let highlight = control::SHOULD_COLORIZE.should_colorize();
+    // With JSON lines on stdout, everything meant for humans goes to stderr
+    let format = options.format;
+    let banner = move |message: String| match format {
+        TailFormat::Text => println!("{}", message),
+        TailFormat::Ndjson => eprintln!("{}", message),
+    };
+    banner(format!(
+        "{}{}",
+        tag,
+        "Tailing requested log (exit with Ctrl-C)".yellow()
+    ));
+    banner(format!(
+        "{}{}", tag, "⚠ Be aware that Adobe doesn't provide logs in realtime, so it might take a couple of seconds before logs start showing up.".yellow()
+    ));
+
+    // -> get log path from API
+
+    let mut tail_url =
+        get_tail_log_url(client, program_id, env_id, service.clone(), logname.clone()).await?;
+
+    // Reuse the pooled HTTP client of the run instead of opening new connections
+    let reqwest_client = client.client.clone();
+    let init_response = reqwest_client.head(&tail_url).send().await?;
+
+    let last_content_length = match init_response.status() {
+        StatusCode::OK => {
+            debug!("Init response: {:?}", init_response);
+            let length = match header_length(init_response.headers()) {
+                Some(length) => length,
+                // Some CDNs leave out the length of HEAD responses, so ask for it with a range
+                None => match probe_content_length(&reqwest_client, &tail_url).await {
+                    Ok(length) => length,
+                    Err(err) => {
+                        eprintln!(
+                            "{}{:>4} Unable to determine the size of the logfile, starting at its beginning: {:?}",
+                            tag, "⚠", err
+                        );
+                        0
+                    }
+                },
+            };
+            debug!("initial Content Length: {:?}", length);
+            length
+        }
+        status => {
+            let body = read_error_body(init_response).await.unwrap_or_default();
+            return Err(LogError::from_status(status, body));
+        }
+    };
+
+    let origin = TailOrigin {
+        program_id,
+        env_id,
+        service: service.clone(),
+        log: logname.clone(),
+    };
+    let layout = LineTimestamp::of(&logname);
+    let mut source = CloudTailSource {
+        client,
+        program_id,
+        env_id,
+        service,
+        logname,
+    };
+
+    let since_start = match (options.since, layout) {
+        (Some(since), Some(layout)) => match scan_since(
+            &mut source,
+            &tail_url,
+            last_content_length,
+            Utc::now() - since,
+            layout,
+            SINCE_CHUNK_BYTES,
+            SINCE_MAX_BYTES,
+        )
+        .await
+        {
+            Ok(Some(offset)) => Some(offset),
+            Ok(None) => {
+                eprintln!(
+                    "{}{:>4} No timestamps found in the logfile, falling back to --backfill-bytes",
+                    tag, "⚠"
+                );
+                None
+            }
+            Err(err) => {
+                eprintln!(
+                    "{}{:>4} Unable to look back in the logfile, falling back to --backfill-bytes: {:?}",
+                    tag, "⚠", err
+                );
+                None
+            }
+        },
+        (Some(_), None) => {
+            eprintln!(
+                "{}{:>4} pippo doesn't know the timestamps of {}, falling back to --backfill-bytes",
+                tag, "⚠", origin.log
+            );
+            None
+        }
+        (None, _) => None,
+    };
+    let mut offset = TailOffset::new(
+        since_start
+            .unwrap_or_else(|| initial_tail_offset(last_content_length, options.backfill_bytes)),
+    );
+
+    // Now we can start printing what's being added to the logfile.
+    loop {
+        let chunk = poll_with_retries(
+            &mut source,
+            &mut tail_url,
+            offset.get(),
+            &options.retry,
+            &tag,
+        )
+        .await;
+        match chunk {
+            Ok(TailChunk::Content(buffer, current_content_length)) => {
+                let current_log_lines = buffer.split('\n').collect::<Vec<_>>();
+
+                let printed = filter_log_lines(&current_log_lines, &options.filter);
+                let received = Utc::now();
+                for line in &printed {
+                    println!(
+                        "{}",
+                        format_tail_line(line, options, highlight, &origin, received)
+                    );
+                }
+                if let Some(recorder) = options.recorder.as_mut() {
+                    let received = filter_log_lines(&current_log_lines, &LineFilter::default());
+                    if let Err(err) = recorder.record(&received, &printed) {
+                        eprintln!("{}{} {}", tag, "❌ Unable to write tailed log:".red(), err);
+                        audit::exit(1);
+                    }
+                }
+                // sum with current content length because we need a new range start value
+                // for our next request
+                offset.advance(current_content_length);
+                sleep(options.interval).await;
+            }
+            Ok(TailChunk::NoContent) => {
+                // No new content, unless the logfile was rotated and is now shorter than our offset
+                match source.content_length(&tail_url).await {
+                    Ok(length) => {
+                        if offset.check_length(length) == OffsetUpdate::Rotated {
+                            banner(format!(
+                                "{}{:>4} Log rotated, continuing with the new file",
+                                tag, "ℹ"
+                            ));
+                            continue;
+                        }
+                    }
+                    Err(err) => debug!("Unable to check for log rotation: {:?}", err),
+                }
+                sleep(options.interval).await;
+            }
+            Err(err) => {
+                eprintln!("{}{} {}", tag, "❌ Giving up tailing:".red(), err);
+                audit::exit(1);
+            }
+        }
+    }
+}
+
+/// What a poll of a tailed logfile returned
+#[derive(Debug, PartialEq)]
+pub enum TailChunk {
+    /// New content and its length in bytes
+    Content(String, i64),
+    /// Nothing was added since the last poll
+    NoContent,
+}
+
+/// Reasons why a poll of a tailed logfile failed
+#[derive(Debug, PartialEq)]
+pub enum TailPollError {
+    /// The signed tail URL expired and has to be resolved again
+    Expired,
+    /// A network error or unexpected status that may go away on its own
+    Transient(String),
+}
+
+/// Where a tailed logfile is polled from
+#[async_trait]
+pub trait TailSource {
+    /// Requests everything after `offset` from the tail URL
+    async fn poll(&mut self, url: &str, offset: i64) -> Result<TailChunk, TailPollError>;
+
+    /// Resolves a new tail URL once the current one expired
+    async fn renew_url(&mut self) -> Result<String, String>;
+
+    /// Returns the current size of the logfile in bytes
+    async fn content_length(&mut self, url: &str) -> Result<i64, TailPollError>;
+
+    /// Reads the bytes from `start` up to and including `end` of the logfile
+    async fn read_range(
+        &mut self,
+        url: &str,
+        start: i64,
+        end: i64,
+    ) -> Result<Vec<u8>, TailPollError>;
+}
+
+/// Polls the tail URL of a Cloud Manager logfile
+struct CloudTailSource<'a> {
+    client: &'a mut CloudManagerClient,
+    program_id: u32,
+    env_id: EnvironmentId,
+    service: ServiceType,
+    logname: LogName,
+}
+
+#[async_trait]
+impl TailSource for CloudTailSource<'_> {
+    async fn poll(&mut self, url: &str, offset: i64) -> Result<TailChunk, TailPollError> {
+        let range_header_value = format!("bytes={}-", offset);
+
+        debug!("range_header_value: {:?}", range_header_value);
+        let response = self
+            .client
+            .client
+            .get(url)
+            .header("Range", range_header_value)
+            .send()
+            .await
+            .map_err(|err| TailPollError::Transient(err.to_string()))?;
+
+        debug!("Content Length: {:?}", response.content_length());
+        debug!("response.status(): {:?}", response.status());
+
+        tail_chunk_of(response).await
+    }
+
+    async fn renew_url(&mut self) -> Result<String, String> {
+        get_tail_log_url(
+            self.client,
+            self.program_id,
+            self.env_id,
+            self.service.clone(),
+            self.logname.clone(),
+        )
+        .await
+        .map_err(|err| err.to_string())
+    }
+
+    async fn content_length(&mut self, url: &str) -> Result<i64, TailPollError> {
+        let response = self
+            .client
+            .client
+            .head(url)
+            .send()
+            .await
+            .map_err(|err| TailPollError::Transient(err.to_string()))?;
+        match response.status() {
+            StatusCode::OK => match header_length(response.headers()) {
+                Some(length) => Ok(length),
+                None => probe_content_length(&self.client.client, url).await,
+            },
+            StatusCode::FORBIDDEN => Err(TailPollError::Expired),
+            status => Err(TailPollError::Transient(format!("API Error: {}", status))),
+        }
+    }
+
+    async fn read_range(
+        &mut self,
+        url: &str,
+        start: i64,
+        end: i64,
+    ) -> Result<Vec<u8>, TailPollError> {
+        let response = self
+            .client
+            .client
+            .get(url)
+            .header(RANGE, format!("bytes={}-{}", start, end))
+            .send()
+            .await
+            .map_err(|err| TailPollError::Transient(err.to_string()))?;
+        match response.status() {
+            StatusCode::PARTIAL_CONTENT => response
+                .bytes()
+                .await
+                .map(|body| body.to_vec())
+                .map_err(|err| TailPollError::Transient(err.to_string())),
+            StatusCode::FORBIDDEN => Err(TailPollError::Expired),
+            status => Err(TailPollError::Transient(format!("API Error: {}", status))),
+        }
+    }
+}
+
+/// Returns the `Content-Length` of a response, if it has a valid one.
+///
+/// # Arguments
+///
+/// * `headers` - The headers of the response
+fn header_length(headers: &HeaderMap) -> Option<i64> {
+    headers
+        .get(CONTENT_LENGTH)
+        .and_then(|value| value.to_str().ok())
+        .and_then(|value| value.trim().parse::<i64>().ok())
+}
+
+/// Returns the total size from a `Content-Range` header like `bytes 0-0/12345`.
+///
+/// # Arguments
+///
+/// * `headers` - The headers of the response
+fn content_range_total(headers: &HeaderMap) -> Option<i64> {
+    headers
+        .get(CONTENT_RANGE)
+        .and_then(|value| value.to_str().ok())
+        .and_then(|value| value.rsplit_once('/'))
+        .and_then(|(_, total)| total.trim().parse::<i64>().ok())
+}
+
+/// Asks for the first byte of a logfile to learn its size from the `Content-Range` of the answer.
+///
+/// # Arguments
+///
+/// * `http` - The HTTP client of the run
+/// * `url` - The tail URL of the logfile
+async fn probe_content_length(http: &reqwest::Client, url: &str) -> Result<i64, TailPollError> {
+    let response = http
+        .get(url)
+        .header(RANGE, "bytes=0-0")
+        .send()
+        .await
+        .map_err(|err| TailPollError::Transient(err.to_string()))?;
+    match response.status() {
+        StatusCode::PARTIAL_CONTENT | StatusCode::RANGE_NOT_SATISFIABLE => {
+            content_range_total(response.headers())
+                .ok_or_else(|| TailPollError::Transient(String::from("no content range")))
+        }
+        StatusCode::FORBIDDEN => Err(TailPollError::Expired),
+        status => Err(TailPollError::Transient(format!("API Error: {}", status))),
+    }
+}
+
+/// Turns the answer to a Range request of a tailed logfile into a chunk.
+///
+/// The offset advances by the `Content-Length` of the answer, or by the number of received bytes
+/// if the header is missing.
+///
+/// # Arguments
+///
+/// * `response` - The answer to the Range request
+async fn tail_chunk_of(response: Response) -> Result<TailChunk, TailPollError> {
+    match response.status() {
+        StatusCode::PARTIAL_CONTENT => {
+            let length = header_length(response.headers());
+            let body = response
+                .bytes()
+                .await
+                .map_err(|err| TailPollError::Transient(err.to_string()))?;
+            let length = length.unwrap_or_else(|| {
+                debug!("Partial content without Content-Length, using the body length");
+                body.len() as i64
+            });
+            Ok(TailChunk::Content(
+                String::from_utf8_lossy(&body).into_owned(),
+                length,
+            ))
+        }
+        StatusCode::RANGE_NOT_SATISFIABLE => Ok(TailChunk::NoContent),
+        StatusCode::FORBIDDEN => Err(TailPollError::Expired),
+        status => Err(TailPollError::Transient(format!("API Error: {}", status))),
+    }
+}
+
+/// How the offset of a tailed logfile changed after comparing it with the size of the file
+#[derive(Debug, PartialEq)]
+pub enum OffsetUpdate {
+    /// The file has grown, the new content is read with the next poll
+    Grew,
+    /// Nothing was added
+    Unchanged,
+    /// The file is shorter than what was already read, so it was replaced by a new one
+    Rotated,
+}
+
+/// Keeps track of how many bytes of a tailed logfile were read
+#[derive(Debug, PartialEq)]
+pub struct TailOffset {
+    offset: i64,
+}
+
+impl TailOffset {
+    /// Starts at the given offset, usually the size of the file when tailing started
+    pub fn new(offset: i64) -> Self {
+        TailOffset { offset }
+    }
+
+    /// Returns where the next poll starts reading
+    pub fn get(&self) -> i64 {
+        self.offset
+    }
+
+    /// Moves the offset past content that was read
+    ///
+    /// # Arguments
+    ///
+    /// * `length` - Number of bytes that were read
+    pub fn advance(&mut self, length: i64) {
+        self.offset += length;
+    }
+
+    /// Compares the offset with the current size of the file. After a rotation, the offset is reset
+    /// to the start of the new file, so none of its lines are missed.
+    ///
+    /// # Arguments
+    ///
+    /// * `length` - Current size of the file in bytes
+    pub fn check_length(&mut self, length: i64) -> OffsetUpdate {
+        match length {
+            length if length < self.offset => {
+                self.offset = 0;
+                OffsetUpdate::Rotated
+            }
+            length if length == self.offset => OffsetUpdate::Unchanged,
+            _ => OffsetUpdate::Grew,
+        }
+    }
+}
+
+/// How failing polls of a tailed logfile are retried
+#[derive(Clone, Debug)]
+pub struct TailRetryPolicy {
+    /// Number of failed polls in a row after which tailing is given up
+    pub max_retries: u32,
+    /// Delay before the first retry, doubled for every further one
+    pub base_delay: Duration,
+}
+
+impl Default for TailRetryPolicy {
+    fn default() -> Self {
+        TailRetryPolicy {
+            max_retries: 5,
+            base_delay: Duration::from_secs(2),
+        }
+    }
+}
+
+impl TailRetryPolicy {
+    /// Delay before the given retry, at most a minute
+    ///
+    /// # Arguments
+    ///
+    /// * `attempt` - Number of the retry, starting with 1
+    pub fn delay(&self, attempt: u32) -> Duration {
+        let factor = 2u32.saturating_pow(attempt.saturating_sub(1));
+        self.base_delay
+            .saturating_mul(factor)
+            .min(Duration::from_secs(60))
+    }
+}
+
+/// Polls a tailed logfile, retrying transient failures with backoff and renewing an expired URL.
+///
+/// Only fails once `policy.max_retries` polls in a row failed; the error describes the last failure.
+///
+/// # Arguments
+///
+/// * `source` - Where the logfile is polled from
+/// * `url` - The current tail URL, replaced when it is renewed
+/// * `offset` - Number of bytes that were already read
+/// * `policy` - How often and how fast failed polls are retried
+/// * `tag` - Prefix of every printed line, see [`environment_tag`]
+pub async fn poll_with_retries<S: TailSource + Send>(
+    source: &mut S,
+    url: &mut String,
+    offset: i64,
+    policy: &TailRetryPolicy,
+    tag: &str,
+) -> Result<TailChunk, String> {
+    let mut attempt = 0;
+    loop {
+        let failure = match source.poll(url, offset).await {
+            Ok(chunk) => return Ok(chunk),
+            Err(TailPollError::Expired) => match source.renew_url().await {
+                Ok(renewed) => {
+                    *url = renewed;
+                    attempt += 1;
+                    if attempt > policy.max_retries {
+                        return Err(format!(
+                            "tail URL keeps expiring ({} retries failed)",
+                            policy.max_retries
+                        ));
+                    }
+                    // A fresh URL can be used right away
+                    eprintln!("{}{:>4} Tail URL expired, renewed it", tag, "⟳".yellow());
+                    continue;
+                }
+                Err(err) => format!("tail URL expired and can't be renewed: {}", err),
+            },
+            Err(TailPollError::Transient(reason)) => reason,
+        };
+        attempt += 1;
+        if attempt > policy.max_retries {
+            return Err(format!(
+                "{} ({} retries failed)",
+                failure, policy.max_retries
+            ));
+        }
+        let delay = policy.delay(attempt);
+        eprintln!(
+            "{}{:>4} {}, retrying in {}s ({}/{})",
+            tag,
+            "⚠".yellow(),
+            failure,
+            delay.as_secs(),
+            attempt,
+            policy.max_retries
+        );
+        sleep(delay).await;
+    }
+}
+
+///  Gets the Url of the log we want to tail
+///
+/// # Arguments
+///
+/// * `client` - A mutable reference to a CloudManagerClient instance
+/// * `program_id` - A u32 that holds the program ID
+/// * `env_id` - An EnvironmentId that holds the environment ID
+/// * `service` - Name of the service type - can be either author, publish, dispatcher, preview_dispatcher, or preview_publish
+/// * `logname` - Name of the logfile - can be either aemaccess, aemdispatcher, aemerror, aemrequest, cdn, httpdaccess, or httpderror
+///
+/// # Performed API Request
+///
+/// ```
+/// GET https://cloudmanager.adobe.io/api/program/{program_id}/environment/{env_id}/logs/
+/// ```
+pub async fn get_tail_log_url(
+    client: &mut CloudManagerClient,
+    program_id: u32,
+    env_id: EnvironmentId,
+    service: ServiceType,
+    logname: LogName,
+) -> Result<String, LogError> {
+    let query_parameters = vec![
+        ("service", service.as_str()),
+        ("name", logname.as_str()),
+        ("days", "2"),
+    ];
+
+    let request_path = format!(
+        "{}/api/program/{}/environment/{}/logs",
+        client.host_name, program_id, env_id
+    );
+
+    let response = client
+        .perform_request(
+            Method::GET,
+            request_path,
+            None::<()>,
+            Some(query_parameters),
+        )
+        .await?;
+    let status = response.status();
+    let body = response.text().await?;
+    tail_url_from_response(status, body)
+}
+
+/// Takes the tail URL from the answer of Cloud Manager to a request of the available logs.
+///
+/// # Arguments
+///
+/// * `status` - Status of the answer
+/// * `body` - Body of the answer
+pub fn tail_url_from_response(status: StatusCode, body: String) -> Result<String, LogError> {
+    if !status.is_success() {
+        return Err(LogError::from_status(status, body));
+    }
+    let response: LogTailResponse = serde_json::from_str(&body)
+        .map_err(|_| LogError::UnexpectedStatus { code: status, body })?;
+    let download = response
+        .embedded
+        .downloads
+        .into_iter()
+        .next()
+        .ok_or(LogError::NotFound)?;
+    download
+        .links
+        .http_ns_adobe_com_adobecloud_rel_logs_tail
+        .map(|link| link.href)
+        .ok_or(LogError::NoTailLink)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::client::mock::{MockResponse, MockServer};
+    use crate::models::environment::EnvironmentsResponse;
+    use crate::models::tests::read_json_from_file;
+    use chrono::TimeZone;
+
+    fn date(input: &str) -> NaiveDate {
+        NaiveDate::from_str(input).unwrap()
+    }
+
+    fn names(values: &[&str]) -> Vec<String> {
+        values.iter().map(|value| value.to_string()).collect()
+    }
+
+    #[test]
+    fn expand_several_services_and_logs() {
+        let selection = expand_log_selection(
+            &names(&["author", "publish", "author"]),
+            &names(&["aemerror", "aemaccess"]),
+        )
+        .unwrap();
+        assert_eq!(
+            selection,
+            vec![
+                (ServiceType::Author, LogName::Known(LogType::AemError)),
+                (ServiceType::Author, LogName::Known(LogType::AemAccess)),
+                (ServiceType::Publish, LogName::Known(LogType::AemError)),
+                (ServiceType::Publish, LogName::Known(LogType::AemAccess)),
+            ]
+        );
+    }
+
+    #[test]
+    fn expand_custom_logs() {
+        let selection = expand_log_selection(
+            &names(&["author"]),
+            &names(&["custom:aemsidecar", "aemerror"]),
+        )
+        .unwrap();
+        assert_eq!(
+            selection,
+            vec![
+                (
+                    ServiceType::Author,
+                    LogName::Custom(String::from("aemsidecar"))
+                ),
+                (ServiceType::Author, LogName::Known(LogType::AemError)),
+            ]
+        );
+        assert!(expand_log_selection(&names(&["author"]), &names(&["aemsidecar"])).is_err());
+        assert_eq!(parse_log_arg("all"), Ok(String::from("all")));
+        assert!(parse_log_arg("aemsidecar").is_err());
+    }
+
+    #[test]
+    fn expand_all_services() {
+        let selection = expand_log_selection(&names(&["all"]), &names(&["aemerror"])).unwrap();
+        assert_eq!(selection.len(), 5);
+        assert_eq!(
+            selection[0],
+            (ServiceType::Author, LogName::Known(LogType::AemError))
+        );
+        assert_eq!(
+            selection[4],
+            (
+                ServiceType::PreviewPublish,
+                LogName::Known(LogType::AemError)
+            )
+        );
+        assert_eq!(
+            expand_log_selection(&names(&["dispatcher", "all"]), &names(&["all"]))
+                .unwrap()
+                .len(),
+            35
+        );
+    }
+
+    #[test]
+    fn expand_unknown_service_fails() {
+        assert_eq!(
+            expand_log_selection(&names(&["publisher"]), &names(&["aemerror"])),
+            Err(String::from("unknown service 'publisher'"))
+        );
+    }
+
+    #[test]
+    fn summarize_selection_counts_all_logs() {
+        let mut author = LogRangeSummary::default();
+        author.record(
+            date("2025-03-01"),
+            LogDownload::Saved(String::from("a1.log")),
+        );
+        author.record(
+            date("2025-03-02"),
+            LogDownload::Saved(String::from("a2.log")),
+        );
+        let mut publish = LogRangeSummary::default();
+        publish.record(date("2025-03-01"), LogDownload::NotFound);
+        publish.record(date("2025-03-02"), LogDownload::NotFound);
+        let mut dispatcher = LogRangeSummary::default();
+        dispatcher.record(
+            date("2025-03-01"),
+            LogDownload::Saved(String::from("d1.log")),
+        );
+        dispatcher.record(
+            date("2025-03-02"),
+            LogDownload::Exists(String::from("d2.log")),
+        );
+        let results = vec![
+            (
+                ServiceType::Author,
+                LogName::Known(LogType::AemError),
+                author,
+            ),
+            (
+                ServiceType::Publish,
+                LogName::Known(LogType::AemError),
+                publish,
+            ),
+            (
+                ServiceType::Dispatcher,
+                LogName::Known(LogType::HttpdAccess),
+                dispatcher,
+            ),
+        ];
+
+        assert_eq!(
+            summarize_log_selection(&results),
+            "3 logs: 3 files written, 2 not available, 1 failed (nothing found for publish/aemerror)"
+        );
+    }
+
+    #[test]
+    fn tail_starts_before_the_end_with_backfill() {
+        assert_eq!(initial_tail_offset(10_000, 0), 10_000);
+        assert_eq!(initial_tail_offset(10_000, 4096), 5904);
+        assert_eq!(initial_tail_offset(10_000, 10_000), 0);
+        assert_eq!(initial_tail_offset(1000, 4096), 0);
+        assert_eq!(initial_tail_offset(1000, u64::MAX), 0);
+        assert_eq!(initial_tail_offset(0, 4096), 0);
+    }
+
+    fn utc(input: &str) -> DateTime<Utc> {
+        DateTime::parse_from_rfc3339(input)
+            .unwrap()
+            .with_timezone(&Utc)
+    }
+
+    #[test]
+    fn parse_since_periods() {
+        assert_eq!(parse_since("30s"), Ok(ChronoDuration::seconds(30)));
+        assert_eq!(parse_since("5m"), Ok(ChronoDuration::minutes(5)));
+        assert_eq!(parse_since("1h"), Ok(ChronoDuration::hours(1)));
+        assert_eq!(parse_since("2d"), Ok(ChronoDuration::days(2)));
+        assert_eq!(
+            parse_since("5"),
+            Err(String::from("'5' is no period like 30s, 5m, 1h or 2d"))
+        );
+        assert!(parse_since("m").is_err());
+        assert!(parse_since("0m").is_err());
+        assert!(parse_since("5w").is_err());
+        assert!(parse_since("-5m").is_err());
+    }
+
+    #[test]
+    fn timestamp_layouts_per_log() {
+        let cases = [
+            (
+                LogType::AemError,
+                "01.03.2025 10:00:01.002 [cm-p1-e2-aem-author-1] *ERROR* [qtp-42] Rendering failed",
+            ),
+            (
+                LogType::AemRequest,
+                "01/Mar/2025:10:00:01 +0000 [137] -> POST /libs/granite/core/content/login.html HTTP/1.1",
+            ),
+            (
+                LogType::AemAccess,
+                "cm-p1-e2-aem-author-1 - jdoe@example.com 01/Mar/2025:10:00:01 +0000 \"GET /libs/granite/ui HTTP/1.1\" 200 1141",
+            ),
+            (
+                LogType::HttpdAccess,
+                "cm-p1-e2-aem-publish-1 - - 01/Mar/2025:11:00:01 +0100  \"GET /content/site.html HTTP/1.1\" 200 310",
+            ),
+            (
+                LogType::AemDispatcher,
+                "[01/Mar/2025:10:00:01 +0000] [I] [cm-p1-e2-aem-author-1] \"GET /content/site.html\" 3ms [publishfarm/0] [action none] \"publish\"",
+            ),
+            (
+                LogType::HttpdError,
+                "Sat Mar 01 10:00:01.093820 2025 [mpm_worker:notice] [pid 1:tid 140272153361288] AH00292: Apache resuming normal operations",
+            ),
+            (
+                LogType::Cdn,
+                r#"{"timestamp": "2025-03-01T10:00:01+0000", "ttfb": 89, "cli_ip": "203.0.113.1", "host": "www.example.com"}"#,
+            ),
+        ];
+        for (logname, line) in cases {
+            let layout = LineTimestamp::of(&logname.clone().into()).unwrap();
+            let expected = if logname == LogType::AemError {
+                utc("2025-03-01T10:00:01.002Z")
+            } else if logname == LogType::HttpdError {
+                utc("2025-03-01T10:00:01.093820Z")
+            } else {
+                utc("2025-03-01T10:00:01Z")
+            };
+            assert_eq!(layout.parse(line), Some(expected), "{:?}", logname);
+        }
+        assert_eq!(
+            LineTimestamp::Cdn.parse(r#"{"timestamp": "2025-03-01T10:00:01Z"}"#),
+            Some(utc("2025-03-01T10:00:01Z"))
+        );
+        assert_eq!(
+            LineTimestamp::of(&LogName::Custom(String::from("aemsidecar"))),
+            None
+        );
+    }
+
+    #[test]
+    fn lines_without_timestamp() {
+        assert_eq!(
+            LineTimestamp::AemError.parse("\tat org.apache.sling.Foo.bar(Foo.java:42)"),
+            None
+        );
+        assert_eq!(LineTimestamp::AemError.parse(""), None);
+        assert_eq!(LineTimestamp::Dispatcher.parse("no bracket"), None);
+        assert_eq!(LineTimestamp::Cdn.parse("{\"ttfb\": 89}"), None);
+        assert_eq!(LineTimestamp::HttpdError.parse("Sat Mar 01"), None);
+        assert_eq!(
+            LineTimestamp::AccessLog.parse("cm-p1-e2-aem-publish-1 - - \"GET / HTTP/1.1\" 200"),
+            None
+        );
+    }
+
+    /// An AEM error log with one entry per minute from 10:00 to 10:04 and a stack trace at 10:02
+    fn timestamped_log() -> String {
+        [
+            "01.03.2025 10:00:00.000 *INFO* [main] started\n",
+            "01.03.2025 10:01:00.000 *INFO* [main] running\n",
+            "01.03.2025 10:02:00.000 *ERROR* [qtp-1] failed\n",
+            "\tat org.apache.sling.Foo.bar(Foo.java:42)\n",
+            "01.03.2025 10:03:00.000 *WARN* [qtp-2] slow\n",
+            "01.03.2025 10:04:00.000 *INFO* [qtp-3] done\n",
+        ]
+        .concat()
+    }
+
+    fn offset_of(log: &str, line: &str) -> i64 {
+        log.find(line).unwrap() as i64
+    }
+
+    #[test]
+    fn since_starts_after_the_last_older_line() {
+        let log = timestamped_log();
+        let scan = |cutoff: &str| {
+            since_offset(
+                log.as_bytes(),
+                0,
+                true,
+                utc(cutoff),
+                LineTimestamp::AemError,
+            )
+        };
+        // The stack trace belongs to the line before it, so it is not part of the period
+        assert_eq!(
+            scan("2025-03-01T10:02:30Z"),
+            SinceScan::Found(offset_of(&log, "01.03.2025 10:03"))
+        );
+        assert_eq!(
+            scan("2025-03-01T10:02:00Z"),
+            SinceScan::Found(offset_of(&log, "01.03.2025 10:02"))
+        );
+        assert_eq!(scan("2025-03-01T09:00:00Z"), SinceScan::Found(0));
+        assert_eq!(
+            scan("2025-03-01T11:00:00Z"),
+            SinceScan::Found(log.len() as i64)
+        );
+    }
+
+    #[test]
+    fn since_needs_more_until_an_older_line_is_read() {
+        let log = timestamped_log();
+        let start = offset_of(&log, "01.03.2025 10:03") - 5;
+        let tail = &log.as_bytes()[start as usize..];
+        let cutoff = utc("2025-03-01T10:00:30Z");
+        assert_eq!(
+            since_offset(tail, start, false, cutoff, LineTimestamp::AemError),
+            SinceScan::NeedMore
+        );
+        // When nothing more can be read, the period starts at the first complete line
+        assert_eq!(
+            since_offset(tail, start, true, cutoff, LineTimestamp::AemError),
+            SinceScan::Found(offset_of(&log, "01.03.2025 10:03"))
+        );
+        assert_eq!(
+            since_offset(
+                b"no timestamps\nat all\n",
+                0,
+                true,
+                cutoff,
+                LineTimestamp::AemError
+            ),
+            SinceScan::NoTimestamps
+        );
+    }
+
+    /// Serves byte ranges of a logfile and remembers which ranges were read
+    struct FakeLogTail {
+        content: Vec<u8>,
+        reads: Vec<(i64, i64)>,
+    }
+
+    #[async_trait]
+    impl TailSource for FakeLogTail {
+        async fn poll(&mut self, _url: &str, _offset: i64) -> Result<TailChunk, TailPollError> {
+            unimplemented!("not used by scan_since")
+        }
+
+        async fn renew_url(&mut self) -> Result<String, String> {
+            unimplemented!("not used by scan_since")
+        }
+
+        async fn content_length(&mut self, _url: &str) -> Result<i64, TailPollError> {
+            unimplemented!("not used by scan_since")
+        }
+
+        async fn read_range(
+            &mut self,
+            _url: &str,
+            start: i64,
+            end: i64,
+        ) -> Result<Vec<u8>, TailPollError> {
+            self.reads.push((start, end));
+            Ok(self.content[start as usize..=end as usize].to_vec())
+        }
+    }
+
+    #[tokio::test]
+    async fn scan_since_reads_backwards_in_chunks() {
+        let log = timestamped_log();
+        let length = log.len() as i64;
+        let mut source = FakeLogTail {
+            content: log.clone().into_bytes(),
+            reads: vec![],
+        };
+        let offset = scan_since(
+            &mut source,
+            "https://tail/1",
+            length,
+            utc("2025-03-01T10:01:30Z"),
+            LineTimestamp::AemError,
+            64,
+            1024,
+        )
+        .await;
+        assert_eq!(offset, Ok(Some(offset_of(&log, "01.03.2025 10:02"))));
+        // Every chunk ends where the one read before it starts
+        assert_eq!(source.reads[0], (length - 64, length - 1));
+        assert_eq!(source.reads[1], (length - 128, length - 65));
+        assert!(source.reads.len() < 5);
+    }
+
+    #[tokio::test]
+    async fn scan_since_stops_at_the_limit() {
+        let log = timestamped_log();
+        let length = log.len() as i64;
+        let mut source = FakeLogTail {
+            content: log.clone().into_bytes(),
+            reads: vec![],
+        };
+        let offset = scan_since(
+            &mut source,
+            "https://tail/1",
+            length,
+            utc("2025-03-01T09:00:00Z"),
+            LineTimestamp::AemError,
+            32,
+            100,
+        )
+        .await;
+        assert_eq!(offset, Ok(Some(offset_of(&log, "01.03.2025 10:03"))));
+        assert_eq!(source.reads.last(), Some(&(length - 100, length - 97)));
+    }
+
+    #[tokio::test]
+    async fn scan_since_without_timestamps_falls_back() {
+        let mut source = FakeLogTail {
+            content: b"plain\nlines\nonly\n".to_vec(),
+            reads: vec![],
+        };
+        let offset = scan_since(
+            &mut source,
+            "https://tail/1",
+            17,
+            utc("2025-03-01T10:00:00Z"),
+            LineTimestamp::AemError,
+            4,
+            1024,
+        )
+        .await;
+        assert_eq!(offset, Ok(None));
+        assert_eq!(source.reads.last(), Some(&(0, 0)));
+    }
+
+    #[test]
+    fn parse_tail_specs() {
+        assert_eq!(
+            parse_tail_spec("dispatcher:httpderror"),
+            Ok((ServiceType::Dispatcher, LogName::Known(LogType::HttpdError)))
+        );
+        assert_eq!(
+            parse_tail_spec("preview_publish:aemerror"),
+            Ok((
+                ServiceType::PreviewPublish,
+                LogName::Known(LogType::AemError)
+            ))
+        );
+        assert_eq!(
+            parse_tail_spec("dispatcher"),
+            Err(String::from("'dispatcher' must have the form service:log"))
+        );
+        assert_eq!(
+            parse_tail_spec("dispatcher:error"),
+            Err(String::from(
+                "unknown log 'error', use custom:error for logs that pippo doesn't know yet"
+            ))
+        );
+        assert_eq!(
+            parse_tail_spec("author:custom:aemsidecar"),
+            Ok((
+                ServiceType::Author,
+                LogName::Custom(String::from("aemsidecar"))
+            ))
+        );
+    }
+
+    #[test]
+    fn parse_absolute_and_relative_log_dates() {
+        let today = date("2025-03-01");
+        assert_eq!(parse_log_date("2025-02-14", today), Ok(date("2025-02-14")));
+        assert_eq!(parse_log_date("today", today), Ok(today));
+        assert_eq!(parse_log_date("yesterday", today), Ok(date("2025-02-28")));
+        assert_eq!(parse_log_date("-0", today), Ok(today));
+        assert_eq!(parse_log_date("-7", today), Ok(date("2025-02-22")));
+        assert_eq!(parse_log_date("-365", today), Ok(date("2024-03-01")));
+    }
+
+    #[test]
+    fn parse_invalid_log_dates() {
+        let today = date("2025-03-01");
+        for input in ["-", "-x", "+3", "tomorrow", "2025-02-30", "01.03.2025"] {
+            let err = parse_log_date(input, today).unwrap_err();
+            assert!(
+                err.contains("is neither YYYY-MM-DD, today, yesterday nor -N for N days ago"),
+                "{}: {}",
+                input,
+                err
+            );
+        }
+    }
+
+    #[test]
+    fn format_tail_line_as_text_or_ndjson() {
+        let origin = TailOrigin {
+            program_id: 11111,
+            env_id: EnvironmentId(22222),
+            service: ServiceType::Dispatcher,
+            log: LogName::Known(LogType::HttpdError),
+        };
+        let received = Utc.with_ymd_and_hms(2025, 3, 1, 10, 0, 0).unwrap();
+        let mut options = TailOptions {
+            tag: String::from("[22222] "),
+            ..TailOptions::default()
+        };
+        assert_eq!(
+            format_tail_line("[error] boom", &options, false, &origin, received),
+            "[22222] [error] boom"
+        );
+
+        options.format = TailFormat::from_str("ndjson").unwrap();
+        let line = format_tail_line("[error] boom", &options, true, &origin, received);
+        assert_eq!(
+            line,
+            r#"{"ts_received":"2025-03-01T10:00:00.000Z","program":11111,"env":22222,"service":"dispatcher","log":"httpderror","line":"[error] boom"}"#
+        );
+    }
+
+    #[test]
+    fn tail_tags_of_merged_tails() {
+        colored::control::set_override(false);
+        let source = (ServiceType::Dispatcher, LogName::Known(LogType::HttpdError));
+        assert_eq!(tail_tag(None, None, 0), "");
+        assert_eq!(
+            tail_tag(None, Some((&source.0, &source.1)), 3),
+            "[dispatcher/httpderror] "
+        );
+        assert_eq!(
+            tail_tag(Some(EnvironmentId(22222)), Some((&source.0, &source.1)), 0),
+            "[22222] [dispatcher/httpderror] "
+        );
+        assert_eq!(tail_tag(Some(EnvironmentId(22222)), None, 0), "[22222] ");
+    }
+
+    fn partial_response(status: u16, headers: &[(&str, &str)], body: &str) -> Response {
+        let mut builder = http::Response::builder().status(status);
+        for (name, value) in headers {
+            builder = builder.header(*name, *value);
+        }
+        Response::from(builder.body(body.to_string()).unwrap())
+    }
+
+    #[tokio::test]
+    async fn partial_content_without_content_length_uses_body_length() {
+        let response = partial_response(206, &[], "line 1\nline 2\n");
+        assert_eq!(
+            tail_chunk_of(response).await,
+            Ok(TailChunk::Content(String::from("line 1\nline 2\n"), 14))
+        );
+    }
+
+    #[tokio::test]
+    async fn partial_content_with_content_length() {
+        let response = partial_response(206, &[("content-length", "7")], "line 1\n");
+        assert_eq!(
+            tail_chunk_of(response).await,
+            Ok(TailChunk::Content(String::from("line 1\n"), 7))
+        );
+        let response = partial_response(416, &[], "");
+        assert_eq!(tail_chunk_of(response).await, Ok(TailChunk::NoContent));
+        let response = partial_response(403, &[], "");
+        assert_eq!(tail_chunk_of(response).await, Err(TailPollError::Expired));
+    }
+
+    #[test]
+    fn lengths_from_headers() {
+        let response = partial_response(
+            206,
+            &[
+                ("content-length", "abc"),
+                ("content-range", "bytes 0-0/12345"),
+            ],
+            "x",
+        );
+        assert_eq!(header_length(response.headers()), None);
+        assert_eq!(content_range_total(response.headers()), Some(12345));
+        let response = partial_response(206, &[("content-range", "bytes 0-0/*")], "x");
+        assert_eq!(content_range_total(response.headers()), None);
+    }
+
+    type FakeTime = std::rc::Rc<std::cell::Cell<Duration>>;
+
+    struct FakeClock(FakeTime);
+
+    impl Clock for FakeClock {
+        fn elapsed(&self) -> Duration {
+            self.0.get()
+        }
+    }
+
+    fn progress_writer(
+        mode: ProgressMode,
+        total: Option<u64>,
+    ) -> (ProgressWriter<Vec<u8>, Vec<u8>, FakeClock>, FakeTime) {
+        let time = std::rc::Rc::new(std::cell::Cell::new(Duration::ZERO));
+        let writer = ProgressWriter::new(
+            vec![],
+            vec![],
+            FakeClock(time.clone()),
+            mode,
+            "cdn.log",
+            total,
+        );
+        (writer, time)
+    }
+
+    #[test]
+    fn bytes_are_formatted_for_humans() {
+        assert_eq!(format_bytes(512), "512 B");
+        assert_eq!(format_bytes(1536), "1.5 KB");
+        assert_eq!(format_bytes(4 * 1024 * 1024 * 1024), "4.0 GB");
+    }
+
+    #[test]
+    fn live_progress_is_reported_at_most_once_per_second() {
+        let (mut writer, time) = progress_writer(ProgressMode::Live, Some(4096));
+        writer.write_all(&[0; 1024]).unwrap();
+        assert!(writer.report.is_empty());
+        time.set(Duration::from_secs(1));
+        writer.write_all(&[0; 1024]).unwrap();
+        time.set(Duration::from_millis(1500));
+        writer.write_all(&[0; 1024]).unwrap();
+        time.set(Duration::from_secs(2));
+        writer.write_all(&[0; 1024]).unwrap();
+        writer.finish().unwrap();
+
+        assert_eq!(writer.inner.len(), 4096);
+        let report = String::from_utf8(writer.report).unwrap();
+        assert_eq!(
+            report,
+            format!(
+                "\r{:>4} cdn.log: 2.0 KB / 4.0 KB (50%), 2.0 KB/s\r{:>4} cdn.log: 4.0 KB / 4.0 KB (100%), 2.0 KB/s\r{:>4} cdn.log: 4.0 KB / 4.0 KB (100%), 2.0 KB/s\n",
+                "⏬", "⏬", "⏬"
+            )
+        );
+    }
+
+    #[test]
+    fn live_progress_without_size_shows_downloaded_bytes() {
+        let (mut writer, time) = progress_writer(ProgressMode::Live, None);
+        time.set(Duration::from_secs(2));
+        writer.write_all(&[0; 2048]).unwrap();
+        let report = String::from_utf8(writer.report).unwrap();
+        assert!(report.ends_with("cdn.log: 2.0 KB downloaded, 1.0 KB/s"));
+    }
+
+    #[test]
+    fn milestones_are_reported_once() {
+        let (mut writer, _) = progress_writer(ProgressMode::Milestones, Some(1000));
+        writer.write_all(&[0; 100]).unwrap();
+        writer.write_all(&[0; 500]).unwrap();
+        writer.write_all(&[0; 400]).unwrap();
+        writer.finish().unwrap();
+        let report = String::from_utf8(writer.report).unwrap();
+        let lines: Vec<&str> = report.lines().collect();
+        assert_eq!(lines.len(), 3);
+        assert!(lines[0].ends_with("cdn.log: 25% of 1000 B"));
+        assert!(lines[1].ends_with("cdn.log: 50% of 1000 B"));
+        assert!(lines[2].ends_with("cdn.log: 75% of 1000 B"));
+    }
+
+    #[test]
+    fn no_progress_when_off() {
+        let (mut writer, time) = progress_writer(ProgressMode::Off, Some(1000));
+        time.set(Duration::from_secs(5));
+        writer.write_all(&[0; 1000]).unwrap();
+        writer.finish().unwrap();
+        assert!(writer.report.is_empty());
+        assert_eq!(writer.inner.len(), 1000);
+    }
+
+    #[tokio::test]
+    async fn downloads_run_bounded_and_failures_dont_cancel_others() {
+        use std::sync::atomic::{AtomicUsize, Ordering};
+
+        let running = Arc::new(AtomicUsize::new(0));
+        let max_running = Arc::new(AtomicUsize::new(0));
+        let jobs = vec![
+            (ServiceType::Author, date("2025-03-01")),
+            (ServiceType::Publish, date("2025-03-01")),
+            (ServiceType::Dispatcher, date("2025-03-01")),
+        ];
+        let mut summaries = vec![LogRangeSummary::default(); 3];
+        run_bounded(
+            jobs,
+            2,
+            |(service, _)| {
+                let (running, max_running) = (running.clone(), max_running.clone());
+                let service = service.clone();
+                async move {
+                    let now = running.fetch_add(1, Ordering::SeqCst) + 1;
+                    max_running.fetch_max(now, Ordering::SeqCst);
+                    sleep(Duration::from_millis(20)).await;
+                    running.fetch_sub(1, Ordering::SeqCst);
+                    match service {
+                        ServiceType::Author => Ok(LogDownload::Saved(String::from("author.log"))),
+                        ServiceType::Publish => Ok(LogDownload::NotFound),
+                        _ => Err(String::from("connection reset")),
+                    }
+                }
+            },
+            |(service, date), download| {
+                let index = match service {
+                    ServiceType::Author => 0,
+                    ServiceType::Publish => 1,
+                    _ => 2,
+                };
+                match download {
+                    Ok(download) => summaries[index].record(date, download),
+                    Err(err) => summaries[index].record_error(date, &err),
+                };
+            },
+        )
+        .await;
+
+        assert_eq!(max_running.load(Ordering::SeqCst), 2);
+        assert_eq!(summaries[0].saved, vec![String::from("author.log")]);
+        assert_eq!(summaries[1].skipped, vec![date("2025-03-01")]);
+        assert_eq!(summaries[2].failed, vec![date("2025-03-01")]);
+    }
+
+    #[test]
+    fn log_errors_by_status() {
+        assert_eq!(
+            LogError::from_status(StatusCode::NOT_FOUND, String::new()),
+            LogError::NotFound
+        );
+        assert_eq!(
+            LogError::from_status(StatusCode::UNAUTHORIZED, String::new()),
+            LogError::Unauthorized
+        );
+        assert_eq!(
+            LogError::from_status(StatusCode::FORBIDDEN, String::new()),
+            LogError::Unauthorized
+        );
+        let bad_gateway = LogError::from_status(StatusCode::BAD_GATEWAY, String::from("<html>"));
+        assert_eq!(
+            bad_gateway,
+            LogError::UnexpectedStatus {
+                code: StatusCode::BAD_GATEWAY,
+                body: String::from("<html>")
+            }
+        );
+        assert_eq!(
+            bad_gateway.to_string(),
+            "Unexpected answer from Cloud Manager: 502 Bad Gateway: <html>"
+        );
+        let codes: Vec<i32> = [
+            LogError::Request(String::from("reset")),
+            LogError::NotFound,
+            LogError::Unauthorized,
+            LogError::NoTailLink,
+            bad_gateway,
+        ]
+        .iter()
+        .map(|err| err.exit_code().code())
+        .collect();
+        assert_eq!(codes, vec![1, 7, 8, 9, 10]);
+    }
+
+    #[test]
+    fn tail_url_from_cloud_manager_response() {
+        let with_link = r#"{"_embedded":{"downloads":[{"_links":{"http://ns.adobe.com/adobecloud/rel/logs/tail":{"href":"https://logs.example.com/tail"}}}]}}"#;
+        assert_eq!(
+            tail_url_from_response(StatusCode::OK, with_link.to_string()),
+            Ok(String::from("https://logs.example.com/tail"))
+        );
+        let without_link = r#"{"_embedded":{"downloads":[{"_links":{}}]}}"#;
+        assert_eq!(
+            tail_url_from_response(StatusCode::OK, without_link.to_string()),
+            Err(LogError::NoTailLink)
+        );
+        let no_downloads = r#"{"_embedded":{"downloads":[]}}"#;
+        assert_eq!(
+            tail_url_from_response(StatusCode::OK, no_downloads.to_string()),
+            Err(LogError::NotFound)
+        );
+        assert_eq!(
+            tail_url_from_response(StatusCode::UNAUTHORIZED, String::new()),
+            Err(LogError::Unauthorized)
+        );
+        assert_eq!(
+            tail_url_from_response(StatusCode::OK, String::from("not json")),
+            Err(LogError::UnexpectedStatus {
+                code: StatusCode::OK,
+                body: String::from("not json")
+            })
+        );
+    }
+
+    #[test]
+    fn list_available_logs_of_fixture() {
+        let response: LogsResponse = read_json_from_file("test/test_log_response.json").unwrap();
+        let groups = group_logs_by_service(response.embedded.downloads);
+
+        assert_eq!(groups.len(), 1);
+        assert_eq!(
+            render_log_groups(&groups),
+            vec![
+                "☁ author",
+                "     aemaccess        2024-10-18",
+                "     aemaccess        2024-10-17",
+                "     aemaccess        2024-10-16",
+            ]
+        );
+        let json = serde_json::to_value(&groups).unwrap();
+        assert_eq!(json[0]["service"], "author");
+        assert_eq!(json[0]["logs"][2]["date"], "2024-10-16");
+    }
+
+    #[test]
+    fn group_logs_by_service_sorts_by_name_and_newest_date() {
+        let log = |service, name, day| Log {
+            service,
+            name,
+            date: date(day),
+        };
+        let groups = group_logs_by_service(vec![
+            log(
+                ServiceType::Publish,
+                LogName::Known(LogType::AemError),
+                "2024-10-17",
+            ),
+            log(
+                ServiceType::Author,
+                LogName::Known(LogType::AemError),
+                "2024-10-17",
+            ),
+            log(
+                ServiceType::Publish,
+                LogName::Known(LogType::AemError),
+                "2024-10-18",
+            ),
+            log(
+                ServiceType::Publish,
+                LogName::Known(LogType::AemAccess),
+                "2024-10-17",
+            ),
+        ]);
+
+        assert_eq!(groups[0].service, ServiceType::Publish);
+        assert_eq!(
+            groups[0].logs,
+            vec![
+                AvailableLog {
+                    name: LogName::Known(LogType::AemAccess),
+                    date: date("2024-10-17")
+                },
+                AvailableLog {
+                    name: LogName::Known(LogType::AemError),
+                    date: date("2024-10-18")
+                },
+                AvailableLog {
+                    name: LogName::Known(LogType::AemError),
+                    date: date("2024-10-17")
+                },
+            ]
+        );
+        assert_eq!(groups[1].service, ServiceType::Author);
+    }
+
+    #[test]
+    fn render_default_log_filename() {
+        assert_eq!(
+            render_log_filename(
+                DEFAULT_LOG_FILENAME_TEMPLATE,
+                date("2025-03-01"),
+                11111,
+                EnvironmentId(22222),
+                &ServiceType::Author,
+                &LogName::Known(LogType::AemError)
+            ),
+            Ok(String::from("2025-03-01_22222-author_aemerror.log.gz"))
+        );
+    }
+
+    #[test]
+    fn render_custom_log_filename() {
+        assert_eq!(
+            render_log_filename(
+                "{program}/{env}/{service}-{log}-{date}.gz",
+                date("2025-03-01"),
+                11111,
+                EnvironmentId(22222),
+                &ServiceType::PreviewDispatcher,
+                &LogName::Known(LogType::HttpdAccess)
+            ),
+            Ok(String::from(
+                "11111/22222/preview_dispatcher-httpdaccess-2025-03-01.gz"
+            ))
+        );
+    }
+
+    #[test]
+    fn render_invalid_log_filename_templates() {
+        let render = |template| {
+            render_log_filename(
+                template,
+                date("2025-03-01"),
+                11111,
+                EnvironmentId(22222),
+                &ServiceType::Author,
+                &LogName::Known(LogType::AemError),
+            )
+        };
+        assert!(render("{date}_{tier}.log")
+            .unwrap_err()
+            .contains("'{tier}'"));
+        assert!(render("{date").unwrap_err().contains("unclosed"));
+        assert!(render("").is_err());
+    }
+
+    #[test]
+    fn log_target_joins_output_dir() {
+        let target = LogTarget {
+            dir: PathBuf::from("logs/prod"),
+            ..LogTarget::default()
+        };
+        assert_eq!(
+            target.path_for(
+                date("2025-03-01"),
+                11111,
+                EnvironmentId(22222),
+                &ServiceType::Publish,
+                &LogName::Known(LogType::AemAccess)
+            ),
+            Ok(PathBuf::from(
+                "logs/prod/2025-03-01_22222-publish_aemaccess.log.gz"
+            ))
+        );
+    }
+
+    #[test]
+    fn expand_date_range_includes_both_ends() {
+        assert_eq!(
+            expand_date_range(date("2025-02-27"), date("2025-03-02")),
+            Ok(vec![
+                date("2025-02-27"),
+                date("2025-02-28"),
+                date("2025-03-01"),
+                date("2025-03-02"),
+            ])
+        );
+        assert_eq!(
+            expand_date_range(date("2025-03-02"), date("2025-03-02")),
+            Ok(vec![date("2025-03-02")])
+        );
+        assert!(expand_date_range(date("2025-03-02"), date("2025-03-01")).is_err());
+    }
+
+    #[test]
+    fn last_days_ends_today() {
+        assert_eq!(
+            last_days(date("2025-03-01"), 3),
+            vec![date("2025-02-27"), date("2025-02-28"), date("2025-03-01")]
+        );
+        assert_eq!(last_days(date("2025-03-01"), 1), vec![date("2025-03-01")]);
+    }
+
+    #[test]
+    fn log_range_summary_skips_missing_days() {
+        let mut summary = LogRangeSummary::default();
+        summary.record(
+            date("2025-03-01"),
+            LogDownload::Saved(String::from("2025-03-01_1-author_aemerror.log.gz")),
+        );
+        let line = summary.record(date("2025-03-02"), LogDownload::NotFound);
+        summary.record(
+            date("2025-03-03"),
+            LogDownload::Failed(StatusCode::INTERNAL_SERVER_ERROR, String::new()),
+        );
+
+        assert_eq!(line, "   ⚠ 2025-03-02 not available, skipping");
+        assert_eq!(
+            summary,
+            LogRangeSummary {
+                saved: vec![String::from("2025-03-01_1-author_aemerror.log.gz")],
+                skipped: vec![date("2025-03-02")],
+                failed: vec![date("2025-03-03")],
+            }
+        );
+    }
+
+    #[test]
+    fn log_combinations_of_environment() {
+        let response: EnvironmentsResponse =
+            read_json_from_file("test/test_environment_response.json").unwrap();
+        let rde = &response.environments_list.environments[2];
+
+        let combinations = log_combinations(&rde.available_log_options);
+        assert_eq!(combinations.len(), 11);
+        assert_eq!(
+            combinations[0],
+            (ServiceType::Author, LogName::Known(LogType::AemAccess))
+        );
+    }
+
+    #[test]
+    fn log_combinations_keep_unknown_services_and_logs() {
+        let options = vec![
+            LogOption {
+                service: String::from("preview_publish"),
+                name: String::from("aemerror"),
+            },
+            LogOption {
+                service: String::from("author"),
+                name: String::from("aemhtmllibrarymanager"),
+            },
+            LogOption {
+                service: String::from("sidecar"),
+                name: String::from("httpdaccess"),
+            },
+        ];
+        assert_eq!(
+            log_combinations(&options),
+            vec![
+                (
+                    ServiceType::PreviewPublish,
+                    LogName::Known(LogType::AemError)
+                ),
+                (
+                    ServiceType::Author,
+                    LogName::Custom(String::from("aemhtmllibrarymanager"))
+                ),
+                (
+                    ServiceType::Other(String::from("sidecar")),
+                    LogName::Known(LogType::HttpdAccess)
+                ),
+            ]
+        );
+    }
+
+    const CHUNK: &str = "01.03.2025 10:00:00.001 *INFO* [main] Startup finished\n\
+        01.03.2025 10:00:01.002 *ERROR* [qtp-42] Rendering /content/site failed\n\
+        01.03.2025 10:00:02.003 *WARN* [healthcheck] Slow query\n\
+        01.03.2025 10:00:03.004 *error* [qtp-43] Timeout in /content/site\n";
+
+    fn lines() -> Vec<&'static str> {
+        CHUNK.split('\n').collect()
+    }
+
+    #[test]
+    fn filter_without_expressions_drops_only_empty_lines() {
+        let filter = LineFilter::default();
+        let printed = filter_log_lines(&lines(), &filter);
+        assert_eq!(printed.len(), 4);
+        assert!(!printed.contains(&""));
+    }
+
+    #[test]
+    fn filter_with_grep() {
+        let filter = LineFilter::new(Some(r"\*ERROR\*"), None, false).unwrap();
+        let printed = filter_log_lines(&lines(), &filter);
+        assert_eq!(
+            printed,
+            vec!["01.03.2025 10:00:01.002 *ERROR* [qtp-42] Rendering /content/site failed"]
+        );
+    }
+
+    #[test]
+    fn filter_with_grep_ignoring_case() {
+        let filter = LineFilter::new(Some(r"\*error\*"), None, true).unwrap();
+        let printed = filter_log_lines(&lines(), &filter);
+        assert_eq!(printed.len(), 2);
+        assert!(printed[0].contains("[qtp-42]"));
+        assert!(printed[1].contains("[qtp-43]"));
+    }
+
+    #[test]
+    fn filter_with_exclude() {
+        let filter = LineFilter::new(None, Some("healthcheck|Startup"), false).unwrap();
+        let printed = filter_log_lines(&lines(), &filter);
+        assert_eq!(printed.len(), 2);
+        assert!(printed.iter().all(|line| line.contains("/content/site")));
+    }
+
+    #[test]
+    fn filter_with_grep_and_exclude() {
+        let filter = LineFilter::new(Some("/content/site"), Some("timeout"), true).unwrap();
+        let printed = filter_log_lines(&lines(), &filter);
+        assert_eq!(
+            printed,
+            vec!["01.03.2025 10:00:01.002 *ERROR* [qtp-42] Rendering /content/site failed"]
+        );
+
+        // Without ignoring the case, "timeout" doesn't match "Timeout"
+        let filter = LineFilter::new(Some("/content/site"), Some("timeout"), false).unwrap();
+        assert_eq!(filter_log_lines(&lines(), &filter).len(), 2);
+    }
+
+    #[test]
+    fn filter_rejects_invalid_expressions() {
+        assert!(LineFilter::new(Some("(unclosed"), None, false).is_err());
+        assert!(LineFilter::new(None, Some("[a-"), false).is_err());
+    }
+
+    #[test]
+    fn highlight_marks_every_match() {
+        let filter = LineFilter::new(Some("site"), None, true).unwrap();
+        assert_eq!(
+            filter.highlight("Site /content/site", |m| format!("<{}>", m)),
+            "<Site> /content/<site>"
+        );
+
+        // Only --grep matches are highlighted
+        let filter = LineFilter::new(None, Some("site"), false).unwrap();
+        assert_eq!(
+            filter.highlight("/content/site", |m| format!("<{}>", m)),
+            "/content/site"
+        );
+    }
+    #[test]
+    fn tail_header_names_the_tailed_log() {
+        let started = Local.with_ymd_and_hms(2025, 3, 1, 10, 0, 0).unwrap();
+        let header = tail_header(
+            &ServiceType::Author,
+            &LogName::Known(LogType::AemError),
+            EnvironmentId(22222),
+            started,
+        );
+        assert!(header.starts_with(
+            "# pippo log tail service=author log=aemerror env=22222 started=2025-03-01T10:00:00"
+        ));
+    }
+
+    #[test]
+    fn recorder_writes_header_and_every_received_line() {
+        let mut file = Vec::new();
+        let lines = lines();
+        let filter = LineFilter::new(Some("ERROR"), None, false).unwrap();
+        {
+            let mut recorder = TailRecorder::new(&mut file, "# header", false).unwrap();
+            let received = filter_log_lines(&lines, &LineFilter::default());
+            let printed = filter_log_lines(&lines, &filter);
+            recorder.record(&received, &printed).unwrap();
+            recorder.record(&["second cycle"], &[]).unwrap();
+        }
+        let content = String::from_utf8(file).unwrap();
+        let written: Vec<&str> = content.lines().collect();
+        assert_eq!(written.len(), 6);
+        assert_eq!(written[0], "# header");
+        assert_eq!(written[1], lines[0]);
+        assert_eq!(written[5], "second cycle");
+    }
+
+    #[test]
+    fn recorder_writes_only_filtered_lines_if_requested() {
+        let mut file = Vec::new();
+        let lines = lines();
+        let filter = LineFilter::new(Some("ERROR"), None, false).unwrap();
+        {
+            let mut recorder = TailRecorder::new(&mut file, "# header", true).unwrap();
+            let received = filter_log_lines(&lines, &LineFilter::default());
+            let printed = filter_log_lines(&lines, &filter);
+            recorder.record(&received, &printed).unwrap();
+        }
+        assert_eq!(
+            String::from_utf8(file).unwrap(),
+            format!("# header\n{}\n", lines[1])
+        );
+    }
+
+    #[test]
+    fn recorder_appends_to_existing_file() {
+        let path = std::env::temp_dir().join(format!("pippo-tail-{}.log", uuid::Uuid::new_v4()));
+        TailRecorder::create(&path, "# first", false)
+            .unwrap()
+            .record(&["a"], &[])
+            .unwrap();
+        TailRecorder::create(&path, "# second", false)
+            .unwrap()
+            .record(&["b"], &[])
+            .unwrap();
+        let content = std::fs::read_to_string(&path).unwrap();
+        std::fs::remove_file(&path).unwrap();
+        assert_eq!(content, "# first\na\n# second\nb\n");
+    }
+
+    #[test]
+    fn recorder_fails_for_unwritable_path() {
+        let path = std::env::temp_dir()
+            .join(format!("pippo-missing-{}", uuid::Uuid::new_v4()))
+            .join("tail.log");
+        assert!(TailRecorder::create(&path, "# header", false).is_err());
+    }
+
+    /// Answers polls from a prepared sequence and remembers what was requested
+    struct FakeTailSource {
+        polls: std::collections::VecDeque<Result<TailChunk, TailPollError>>,
+        renewals: std::collections::VecDeque<Result<String, String>>,
+        requested: Vec<(String, i64)>,
+    }
+
+    impl FakeTailSource {
+        fn new(
+            polls: Vec<Result<TailChunk, TailPollError>>,
+            renewals: Vec<Result<String, String>>,
+        ) -> Self {
+            FakeTailSource {
+                polls: polls.into(),
+                renewals: renewals.into(),
+                requested: vec![],
+            }
+        }
+    }
+
+    #[async_trait]
+    impl TailSource for FakeTailSource {
+        async fn poll(&mut self, url: &str, offset: i64) -> Result<TailChunk, TailPollError> {
+            self.requested.push((url.to_string(), offset));
+            self.polls.pop_front().expect("unexpected poll")
+        }
+
+        async fn renew_url(&mut self) -> Result<String, String> {
+            self.renewals.pop_front().expect("unexpected renewal")
+        }
+
+        async fn content_length(&mut self, _url: &str) -> Result<i64, TailPollError> {
+            unimplemented!("not used by poll_with_retries")
+        }
+
+        async fn read_range(
+            &mut self,
+            _url: &str,
+            _start: i64,
+            _end: i64,
+        ) -> Result<Vec<u8>, TailPollError> {
+            unimplemented!("not used by poll_with_retries")
+        }
+    }
+
+    fn no_delay(max_retries: u32) -> TailRetryPolicy {
+        TailRetryPolicy {
+            max_retries,
+            base_delay: Duration::ZERO,
+        }
+    }
+
+    #[tokio::test]
+    async fn poll_retries_after_transient_error() {
+        let mut source = FakeTailSource::new(
+            vec![
+                Err(TailPollError::Transient(String::from("connection reset"))),
+                Ok(TailChunk::Content(String::from("line\n"), 5)),
+            ],
+            vec![],
+        );
+        let mut url = String::from("https://tail/1");
+        let chunk = poll_with_retries(&mut source, &mut url, 42, &no_delay(3), "").await;
+        assert_eq!(chunk, Ok(TailChunk::Content(String::from("line\n"), 5)));
+        assert_eq!(
+            source.requested,
+            vec![
+                (String::from("https://tail/1"), 42),
+                (String::from("https://tail/1"), 42)
+            ]
+        );
+    }
+
+    #[tokio::test]
+    async fn poll_renews_expired_url_and_keeps_offset() {
+        let mut source = FakeTailSource::new(
+            vec![Err(TailPollError::Expired), Ok(TailChunk::NoContent)],
+            vec![Ok(String::from("https://tail/2"))],
+        );
+        let mut url = String::from("https://tail/1");
+        let chunk = poll_with_retries(&mut source, &mut url, 42, &no_delay(3), "").await;
+        assert_eq!(chunk, Ok(TailChunk::NoContent));
+        assert_eq!(url, "https://tail/2");
+        assert_eq!(
+            source.requested,
+            vec![
+                (String::from("https://tail/1"), 42),
+                (String::from("https://tail/2"), 42)
+            ]
+        );
+    }
+
+    #[tokio::test]
+    async fn poll_gives_up_after_persistent_failure() {
+        let mut source = FakeTailSource::new(
+            vec![
+                Err(TailPollError::Transient(String::from("timeout"))),
+                Err(TailPollError::Transient(String::from("timeout"))),
+                Err(TailPollError::Transient(String::from("timeout"))),
+            ],
+            vec![],
+        );
+        let mut url = String::from("https://tail/1");
+        let chunk = poll_with_retries(&mut source, &mut url, 0, &no_delay(2), "").await;
+        assert_eq!(chunk, Err(String::from("timeout (2 retries failed)")));
+        assert_eq!(source.requested.len(), 3);
+
+        let mut source = FakeTailSource::new(
+            vec![Err(TailPollError::Expired)],
+            vec![Err(String::from("403 Forbidden"))],
+        );
+        let chunk = poll_with_retries(&mut source, &mut url, 0, &no_delay(0), "").await;
+        assert_eq!(
+            chunk,
+            Err(String::from(
+                "tail URL expired and can't be renewed: 403 Forbidden (0 retries failed)"
+            ))
+        );
+    }
+
+    #[test]
+    fn retry_delay_doubles_up_to_a_minute() {
+        let policy = TailRetryPolicy::default();
+        assert_eq!(policy.delay(1), Duration::from_secs(2));
+        assert_eq!(policy.delay(2), Duration::from_secs(4));
+        assert_eq!(policy.delay(3), Duration::from_secs(8));
+        assert_eq!(policy.delay(10), Duration::from_secs(60));
+        assert_eq!(policy.delay(100), Duration::from_secs(60));
+    }
+
+    #[test]
+    fn offset_grows_with_read_content() {
+        let mut offset = TailOffset::new(100);
+        offset.advance(20);
+        assert_eq!(offset.get(), 120);
+        // Cloud Manager already knows about more content than we read
+        assert_eq!(offset.check_length(150), OffsetUpdate::Grew);
+        assert_eq!(offset.get(), 120);
+    }
+
+    #[test]
+    fn offset_without_new_content_is_kept() {
+        let mut offset = TailOffset::new(100);
+        assert_eq!(offset.check_length(100), OffsetUpdate::Unchanged);
+        assert_eq!(offset.get(), 100);
+    }
+
+    #[test]
+    fn offset_is_reset_after_rotation() {
+        let mut offset = TailOffset::new(5_000_000);
+        assert_eq!(offset.check_length(1_024), OffsetUpdate::Rotated);
+        assert_eq!(offset.get(), 0);
+        offset.advance(1_024);
+        assert_eq!(offset.check_length(1_024), OffsetUpdate::Unchanged);
+    }
+
+    /// ETag of the logfile that FakeLogFileSource serves
+    const FAKE_ETAG: &str = "\"v1\"";
+
+    /// Serves a logfile like the download URL of Cloud Manager, with or without support for ranges
+    struct FakeLogFileSource {
+        content: &'static str,
+        honors_range: bool,
+        requested: Vec<Option<u64>>,
+    }
+
+    impl FakeLogFileSource {
+        fn new(content: &'static str, honors_range: bool) -> Self {
+            FakeLogFileSource {
+                content,
+                honors_range,
+                requested: vec![],
+            }
+        }
+    }
+
+    #[async_trait]
+    impl LogFileSource for FakeLogFileSource {
+        async fn fetch(&mut self, resume: Option<(u64, &str)>) -> Result<Response, Error> {
+            self.requested.push(resume.map(|(offset, _)| offset));
+            let total = self.content.len();
+            let length = total.to_string();
+            // Like If-Range, a range of another version of the logfile gets the whole logfile
+            let offset = resume
+                .filter(|(_, validator)| *validator == FAKE_ETAG)
+                .map(|(offset, _)| offset);
+            Ok(match offset.map(|o| o as usize) {
+                Some(start) if self.honors_range && start >= total => {
+                    partial_response(416, &[("content-range", &format!("bytes */{}", total))], "")
+                }
+                Some(start) if self.honors_range => partial_response(
+                    206,
+                    &[
+                        ("content-length", &(total - start).to_string()),
+                        (
+                            "content-range",
+                            &format!("bytes {}-{}/{}", start, total - 1, total),
+                        ),
+                    ],
+                    &self.content[start..],
+                ),
+                _ => partial_response(
+                    200,
+                    &[("content-length", &length), ("etag", FAKE_ETAG)],
+                    self.content,
+                ),
+            })
+        }
+    }
+
+    fn download_path() -> PathBuf {
+        std::env::temp_dir().join(format!("pippo-download-{}.log", uuid::Uuid::new_v4()))
+    }
+
+    /// Leaves a partial download of the logfile at `path` behind, like a run that broke off
+    fn write_partial(path: &Path, content: &str, validator: &str) {
+        std::fs::write(partial_path(path), content).unwrap();
+        std::fs::write(validator_path(path), validator).unwrap();
+    }
+
+    fn read_and_remove(path: &Path) -> String {
+        let content = std::fs::read_to_string(path).unwrap();
+        std::fs::remove_file(path).unwrap();
+        content
+    }
+
+    #[tokio::test]
+    async fn download_resumes_partial_file_with_range() {
+        let path = download_path();
+        write_partial(&path, "line 1\n", FAKE_ETAG);
+        let mut source = FakeLogFileSource::new("line 1\nline 2\n", true);
+
+        let download = save_log_file(&mut source, &path, &LogTarget::default())
+            .await
+            .unwrap();
+
+        assert_eq!(download, LogDownload::Saved(path.display().to_string()));
+        assert_eq!(source.requested, vec![Some(7)]);
+        assert!(!partial_path(&path).exists());
+        assert!(!validator_path(&path).exists());
+        assert_eq!(read_and_remove(&path), "line 1\nline 2\n");
+    }
+
+    #[tokio::test]
+    async fn download_starts_over_when_logfile_changed() {
+        let path = download_path();
+        write_partial(&path, "old 1\n", "\"v0\"");
+        let mut source = FakeLogFileSource::new("line 1\nline 2\n", true);
+
+        let download = save_log_file(&mut source, &path, &LogTarget::default())
+            .await
+            .unwrap();
+
+        assert_eq!(download, LogDownload::Saved(path.display().to_string()));
+        assert_eq!(source.requested, vec![Some(6)]);
+        assert_eq!(read_and_remove(&path), "line 1\nline 2\n");
+    }
+
+    #[tokio::test]
+    async fn cloud_manager_download_is_resumed_with_if_range() {
+        let server = MockServer::start();
+        server.route(
+            "GET",
+            "/api/program/1/environment/2/logs/download",
+            vec![MockResponse::new(206, "line 2\n").with_header("content-range", "bytes 7-13/14")],
+        );
+        let client = server.client();
+        let mut source = CloudLogFileSource {
+            client: &client,
+            request_path: format!("{}/api/program/1/environment/2/logs/download", server.url),
+            service: ServiceType::Author,
+            logname: LogName::Custom(String::from("aemerror")),
+            date: String::from("2025-03-01"),
+        };
+        let path = download_path();
+        write_partial(&path, "line 1\n", FAKE_ETAG);
+
+        save_log_file(&mut source, &path, &LogTarget::default())
+            .await
+            .unwrap();
+
+        let request = &server.requests()[0];
+        assert_eq!(request.header("range"), Some("bytes=7-"));
+        assert_eq!(request.header("if-range"), Some(FAKE_ETAG));
+        assert_eq!(read_and_remove(&path), "line 1\nline 2\n");
+    }
+
+    #[tokio::test]
+    async fn partial_file_without_validator_is_not_resumed() {
+        let path = download_path();
+        std::fs::write(partial_path(&path), "line 1\n").unwrap();
+        let mut source = FakeLogFileSource::new("line 1\nline 2\n", true);
+
+        save_log_file(&mut source, &path, &LogTarget::default())
+            .await
+            .unwrap();
+
+        assert_eq!(source.requested, vec![None]);
+        assert_eq!(read_and_remove(&path), "line 1\nline 2\n");
+    }
+
+    #[test]
+    fn range_validator_prefers_a_strong_etag() {
+        let headers = |pairs: &[(&'static str, &'static str)]| {
+            let mut headers = HeaderMap::new();
+            for (name, value) in pairs {
+                headers.insert(*name, HeaderValue::from_static(value));
+            }
+            headers
+        };
+        let last_modified = ("last-modified", "Sat, 01 Mar 2025 10:00:00 GMT");
+        assert_eq!(
+            range_validator(&headers(&[("etag", "\"abc\""), last_modified])),
+            Some(String::from("\"abc\""))
+        );
+        assert_eq!(
+            range_validator(&headers(&[("etag", "W/\"abc\""), last_modified])),
+            Some(String::from("Sat, 01 Mar 2025 10:00:00 GMT"))
+        );
+        assert_eq!(range_validator(&headers(&[("etag", "W/\"abc\"")])), None);
+    }
+
+    #[tokio::test]
+    async fn download_starts_over_when_range_is_ignored() {
+        let path = download_path();
+        write_partial(&path, "stale\n", FAKE_ETAG);
+        let mut source = FakeLogFileSource::new("line 1\nline 2\n", false);
+
+        let download = save_log_file(&mut source, &path, &LogTarget::default())
+            .await
+            .unwrap();
+
+        assert_eq!(download, LogDownload::Saved(path.display().to_string()));
+        assert_eq!(source.requested, vec![Some(6)]);
+        assert_eq!(read_and_remove(&path), "line 1\nline 2\n");
+    }
+
+    #[tokio::test]
+    async fn download_starts_over_when_range_is_not_satisfiable() {
+        let path = download_path();
+        write_partial(&path, "a much longer stale file\n", FAKE_ETAG);
+        let mut source = FakeLogFileSource::new("line 1\n", true);
+
+        let download = save_log_file(&mut source, &path, &LogTarget::default())
+            .await
+            .unwrap();
+
+        assert_eq!(download, LogDownload::Saved(path.display().to_string()));
+        assert_eq!(source.requested, vec![Some(25), None]);
+        assert_eq!(read_and_remove(&path), "line 1\n");
+    }
+
+    #[tokio::test]
+    async fn download_without_resume_ignores_partial_file() {
+        let path = download_path();
+        write_partial(&path, "line 1\n", FAKE_ETAG);
+        let mut source = FakeLogFileSource::new("line 1\nline 2\n", true);
+        let target = LogTarget {
+            resume: false,
+            ..LogTarget::default()
+        };
+
+        save_log_file(&mut source, &path, &target).await.unwrap();
+
+        assert_eq!(source.requested, vec![None]);
+        assert_eq!(read_and_remove(&path), "line 1\nline 2\n");
+    }
+
+    #[tokio::test]
+    async fn short_download_keeps_partial_file() {
+        struct ShortSource;
+
+        #[async_trait]
+        impl LogFileSource for ShortSource {
+            async fn fetch(&mut self, _resume: Option<(u64, &str)>) -> Result<Response, Error> {
+                Ok(partial_response(
+                    200,
+                    &[
+                        ("content-length", "100"),
+                        ("last-modified", "Sat, 01 Mar 2025 10:00:00 GMT"),
+                    ],
+                    "line 1\n",
+                ))
+            }
+        }
+
+        let path = download_path();
+        let download = save_log_file(&mut ShortSource, &path, &LogTarget::default())
+            .await
+            .unwrap();
+
+        let partial = partial_path(&path);
+        assert_eq!(
+            download,
+            LogDownload::Incomplete(partial.display().to_string(), 7, 100)
+        );
+        assert!(!path.exists());
+        assert_eq!(read_and_remove(&partial), "line 1\n");
+        assert_eq!(
+            read_and_remove(&validator_path(&path)),
+            "Sat, 01 Mar 2025 10:00:00 GMT"
+        );
+    }
+
+    /// Serves one body per request along with a hash header
+    struct HashedSource {
+        header: (&'static str, &'static str),
+        bodies: Vec<&'static str>,
+        requests: usize,
+    }
+
+    #[async_trait]
+    impl LogFileSource for HashedSource {
+        async fn fetch(&mut self, _resume: Option<(u64, &str)>) -> Result<Response, Error> {
+            let body = self.bodies[self.requests.min(self.bodies.len() - 1)];
+            self.requests += 1;
+            Ok(partial_response(200, &[self.header], body))
+        }
+    }
+
+    fn verified() -> LogTarget {
+        LogTarget {
+            verify: true,
+            ..LogTarget::default()
+        }
+    }
+
+    #[test]
+    fn expected_md5_from_headers() {
+        let mut headers = HeaderMap::new();
+        assert_eq!(expected_md5(&headers, false), None);
+        headers.insert(ETAG, HeaderValue::from_static("W/\"abc\""));
+        assert_eq!(expected_md5(&headers, false), None);
+        headers.insert(
+            ETAG,
+            HeaderValue::from_static("\"D41D8CD98F00B204E9800998ECF8427E\""),
+        );
+        assert_eq!(
+            expected_md5(&headers, true).as_deref(),
+            Some("d41d8cd98f00b204e9800998ecf8427e")
+        );
+        // MD5 of "line 1\n"
+        headers.insert(
+            "content-md5",
+            HeaderValue::from_static("XCzlYeHiY2ldvSZycbhvuA=="),
+        );
+        assert_eq!(
+            expected_md5(&headers, false).as_deref(),
+            Some("5c2ce561e1e263695dbd267271b86fb8")
+        );
+        // Content-MD5 only covers the rest of a resumed download
+        assert_eq!(
+            expected_md5(&headers, true).as_deref(),
+            Some("d41d8cd98f00b204e9800998ecf8427e")
+        );
+    }
+
+    #[tokio::test]
+    async fn verified_download_matches_checksum() {
+        let path = download_path();
+        let mut source = HashedSource {
+            header: ("content-md5", "XCzlYeHiY2ldvSZycbhvuA=="),
+            bodies: vec!["line 1\n"],
+            requests: 0,
+        };
+
+        let download = save_log_file(&mut source, &path, &verified())
+            .await
+            .unwrap();
+
+        assert_eq!(download, LogDownload::Saved(path.display().to_string()));
+        assert_eq!(read_and_remove(&path), "line 1\n");
+    }
+
+    #[tokio::test]
+    async fn corrupted_download_is_retried_once() {
+        let path = download_path();
+        let mut source = HashedSource {
+            header: ("etag", "\"5c2ce561e1e263695dbd267271b86fb8\""),
+            bodies: vec!["line X\n", "line 1\n"],
+            requests: 0,
+        };
+
+        let download = save_log_file(&mut source, &path, &verified())
+            .await
+            .unwrap();
+
+        assert_eq!(download, LogDownload::Saved(path.display().to_string()));
+        assert_eq!(source.requests, 2);
+        assert_eq!(read_and_remove(&path), "line 1\n");
+    }
+
+    #[tokio::test]
+    async fn corrupted_download_fails_after_retry() {
+        let path = download_path();
+        let mut source = HashedSource {
+            header: ("etag", "\"5c2ce561e1e263695dbd267271b86fb8\""),
+            bodies: vec!["line X\n"],
+            requests: 0,
+        };
+
+        let download = save_log_file(&mut source, &path, &verified())
+            .await
+            .unwrap();
+
+        assert_eq!(
+            download,
+            LogDownload::ChecksumMismatch(
+                path.display().to_string(),
+                String::from("5c2ce561e1e263695dbd267271b86fb8"),
+                String::from("508880027d18acae4e4e546bcd0b310c")
+            )
+        );
+        assert_eq!(source.requests, 2);
+        assert!(!path.exists());
+        assert!(!partial_path(&path).exists());
+    }
+
+    #[tokio::test]
+    async fn unverified_download_ignores_checksum() {
+        let path = download_path();
+        let mut source = HashedSource {
+            header: ("etag", "\"5c2ce561e1e263695dbd267271b86fb8\""),
+            bodies: vec!["line X\n"],
+            requests: 0,
+        };
+
+        save_log_file(&mut source, &path, &LogTarget::default())
+            .await
+            .unwrap();
+
+        assert_eq!(source.requests, 1);
+        assert_eq!(read_and_remove(&path), "line X\n");
+    }
+
+    /// Serves a single response, e.g. a gzipped logfile from the `test` directory
+    struct ResponseSource {
+        status: u16,
+        content_length: Option<usize>,
+        body: Vec<u8>,
+    }
+
+    impl ResponseSource {
+        fn gzipped_log() -> Self {
+            let body = std::fs::read("test/test_log.log.gz").unwrap();
+            ResponseSource {
+                status: 200,
+                content_length: Some(body.len()),
+                body,
+            }
+        }
+    }
+
+    #[async_trait]
+    impl LogFileSource for ResponseSource {
+        async fn fetch(&mut self, resume: Option<(u64, &str)>) -> Result<Response, Error> {
+            assert_eq!(resume, None);
+            let mut builder = http::Response::builder().status(self.status);
+            if let Some(length) = self.content_length {
+                builder = builder.header("content-length", length);
+            }
+            Ok(Response::from(builder.body(self.body.clone()).unwrap()))
+        }
+    }
+
+    const TEST_LOG: &str = "01.03.2025 10:00:00.000 *INFO* [main] started\n01.03.2025 10:00:01.000 *ERROR* [main] boom\n01.03.2025 10:00:02.000 *INFO* [main] done\n";
+
+    #[tokio::test]
+    async fn stream_gzipped_log_as_it_is() {
+        let mut source = ResponseSource::gzipped_log();
+        let mut out = vec![];
+        let received = stream_log_file(&mut source, &mut out, false).await.unwrap();
+        assert_eq!(received, 91);
+        assert_eq!(out, source.body);
+    }
+
+    #[tokio::test]
+    async fn stream_gzipped_log_decompressed() {
+        let mut source = ResponseSource::gzipped_log();
+        let mut out = vec![];
+        stream_log_file(&mut source, &mut out, true).await.unwrap();
+        assert_eq!(String::from_utf8(out).unwrap(), TEST_LOG);
+    }
+
+    #[tokio::test]
+    async fn stream_fails_when_download_ends_early() {
+        let mut source = ResponseSource::gzipped_log();
+        source.content_length = Some(200);
+        let result = stream_log_file(&mut source, &mut vec![], true).await;
+        assert_eq!(
+            result,
+            Err(LogError::Request(String::from(
+                "download ended after 91 of 200 bytes"
+            )))
+        );
+    }
+
+    #[tokio::test]
+    async fn stream_reports_missing_logfile() {
+        let mut source = ResponseSource {
+            status: 404,
+            content_length: None,
+            body: vec![],
+        };
+        let result = stream_log_file(&mut source, &mut vec![], false).await;
+        assert_eq!(result, Err(LogError::NotFound));
+    }
+
+    #[tokio::test]
+    async fn stream_ends_quietly_when_reader_goes_away() {
+        struct ClosedPipe;
+
+        impl Write for ClosedPipe {
+            fn write(&mut self, _buf: &[u8]) -> io::Result<usize> {
+                Err(io::Error::from(io::ErrorKind::BrokenPipe))
+            }
+
+            fn flush(&mut self) -> io::Result<()> {
+                Err(io::Error::from(io::ErrorKind::BrokenPipe))
+            }
+        }
+
+        let mut source = ResponseSource::gzipped_log();
+        source.content_length = Some(200);
+        assert!(stream_log_file(&mut source, ClosedPipe, true).await.is_ok());
+        assert!(stream_log_file(&mut source, ClosedPipe, false)
+            .await
+            .is_ok());
     }
 }
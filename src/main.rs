@@ -9,6 +9,8 @@
 
 extern crate core;
 
+mod apply;
+mod audit;
 mod auth;
 mod clap_app;
 mod clap_models;
@@ -19,10 +21,17 @@ mod encryption;
 mod environments;
 mod errors;
 mod execution;
+mod exit_code;
+mod ip_allowlists;
+mod keyring;
 mod logs;
 mod models;
+mod output;
 mod pipelines;
 mod programs;
+mod report;
+mod resolve;
+mod schema;
 mod variables;
 
 use crate::clap_app::init_cli;
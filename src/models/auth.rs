@@ -16,6 +16,9 @@ pub struct JwtClaims {
 #[derive(Debug, Deserialize)]
 pub struct BearerResponse {
     pub access_token: String,
+    /// Lifetime of the token, in seconds for OAuth and in milliseconds for the JWT exchange
+    #[serde(default)]
+    pub expires_in: Option<i64>,
 }
 
 #[cfg(test)]
@@ -30,6 +33,7 @@ mod tests {
         let vobj: BearerResponse =
             read_json_from_file("test/test_auth_bearer_response.json").unwrap();
         assert_eq!(vobj.access_token, "ths.is.a.token");
+        assert_eq!(vobj.expires_in, Some(14));
     }
     #[test]
     fn deserialize_jwt_claims() {
@@ -39,7 +43,7 @@ mod tests {
         assert_eq!(vobj.exp, 1550001438);
         assert_eq!(vobj.iss, "C74F69D7594880280.....@AdobeOrg");
         assert_eq!(vobj.sub, "6657031C5C095BB40A4.....@techacct.adobe.com");
-        assert_eq!(vobj.scope_ent_aem_cloud_api, false);
-        assert_eq!(vobj.scope_ent_cloudmgr_sdk, true);
+        assert!(!vobj.scope_ent_aem_cloud_api);
+        assert!(vobj.scope_ent_cloudmgr_sdk);
     }
 }
@@ -1,21 +1,204 @@
+use colored::Colorize;
 use serde::{Deserialize, Serialize};
+use std::io::{self, Read};
+use std::sync::OnceLock;
 
+use super::environment::EnvironmentId;
+use super::ip_allowlist::BindingTier;
 use super::variables::{EnvironmentVariable, PipelineVariable};
+use crate::audit;
+use crate::schema::{array, object, value, JsonSchema};
+use serde_json::{json, Value};
+
+/// Input path that stands for stdin, e.g. `pippo env vars set -`
+pub const STDIN_PATH: &str = "-";
+
+/// Content of stdin, read once so that commands reading their input several times get it each time
+static STDIN_INPUT: OnceLock<String> = OnceLock::new();
+
+/// Reads an input file, or stdin if the path is [`STDIN_PATH`].
+///
+/// # Arguments
+///
+/// * `path` - String slice that holds the path to the input file
+pub fn read_input(path: &str) -> io::Result<String> {
+    if path != STDIN_PATH {
+        return std::fs::read_to_string(path);
+    }
+    if let Some(input) = STDIN_INPUT.get() {
+        return Ok(input.clone());
+    }
+    let mut input = String::new();
+    io::stdin().read_to_string(&mut input)?;
+    Ok(STDIN_INPUT.get_or_init(|| input).clone())
+}
+
+/// Versions of the configuration YAML format that this pippo release understands
+pub const SUPPORTED_YAML_CONFIG_VERSIONS: [u64; 1] = [1];
 
 /// Model for all programs that will be read from the configuration YAML
 #[derive(Debug, Deserialize, Serialize)]
 pub struct YamlConfig {
+    #[serde(default = "default_yaml_config_version")]
+    pub version: u64,
     pub programs: Vec<ProgramsConfig>,
 }
 
+/// Files without a `version` key use the first version of the format
+fn default_yaml_config_version() -> u64 {
+    1
+}
+
+impl YamlConfig {
+    /// Reads the configuration YAML from a file, or from stdin if the path is `-`.
+    ///
+    /// The version is checked before the rest of the file is parsed, so files written for a newer
+    /// format fail with an upgrade hint instead of a confusing parse error.
+    ///
+    /// # Arguments
+    ///
+    /// * `path` - String slice that holds the path to the YAML config file
+    pub fn from_file(path: &str) -> Self {
+        let input = read_input(path).expect("Unable to read file");
+        YamlConfig::parse(&input).unwrap_or_else(|err| {
+            eprintln!("{}", format!("❌ {}", err).red());
+            audit::exit(1);
+        })
+    }
+
+    /// Returns the ID of the only program in the configuration, for commands that work on one
+    /// program.
+    pub fn single_program_id(&self) -> Result<u32, String> {
+        match self.programs.as_slice() {
+            [program] => Ok(program.program_id()),
+            [] => Err(String::from("the YAML config doesn't declare any program")),
+            programs => Err(format!(
+                "the YAML config declares {} programs ({}), use --program to pick one",
+                programs.len(),
+                programs
+                    .iter()
+                    .map(|program| program.label())
+                    .collect::<Vec<_>>()
+                    .join(", ")
+            )),
+        }
+    }
+
+    /// Parses the configuration YAML, checking its version first.
+    ///
+    /// # Arguments
+    ///
+    /// * `input` - String slice that holds the YAML
+    pub fn parse(input: &str) -> Result<Self, String> {
+        let value: serde_yaml::Value =
+            serde_yaml::from_str(input).map_err(|err| format!("Malformed YAML: {}", err))?;
+        check_yaml_config_version(value.get("version"))
+            .map_err(|err| format!("Unsupported YAML config: {}", err))?;
+        let config: YamlConfig =
+            serde_yaml::from_value(value).map_err(|err| format!("Malformed YAML: {}", err))?;
+        config
+            .check_program_keys()
+            .map_err(|err| format!("Malformed YAML: {}", err))?;
+        Ok(config)
+    }
+
+    /// Checks that every program is given by exactly one of `id` and `name`.
+    pub fn check_program_keys(&self) -> Result<(), String> {
+        for (index, program) in self.programs.iter().enumerate() {
+            match (&program.id, &program.name) {
+                (Some(_), None) | (None, Some(_)) => {}
+                (Some(id), Some(name)) => {
+                    return Err(format!(
+                        "program {} has both id {} and name '{}', use only one of them",
+                        index + 1,
+                        id,
+                        name
+                    ))
+                }
+                (None, None) => {
+                    return Err(format!(
+                        "program {} needs either an id or a name",
+                        index + 1
+                    ))
+                }
+            }
+        }
+        Ok(())
+    }
+}
+
+impl ProgramsConfig {
+    /// Returns the program ID.
+    ///
+    /// Panics if the program is given by a name that wasn't resolved yet.
+    pub fn program_id(&self) -> u32 {
+        self.id
+            .expect("program names in the YAML config are resolved before use")
+    }
+
+    /// Returns how the program is referred to in the configuration, for messages about it.
+    pub fn label(&self) -> String {
+        match (&self.id, &self.name) {
+            (Some(id), _) => id.to_string(),
+            (None, Some(name)) => format!("'{}'", name),
+            (None, None) => String::from("?"),
+        }
+    }
+}
+
+/// Checks the `version` key of a configuration YAML against the supported versions.
+///
+/// # Arguments
+///
+/// * `version` - The value of the `version` key, if present
+pub fn check_yaml_config_version(version: Option<&serde_yaml::Value>) -> Result<u64, String> {
+    let version = match version {
+        None => return Ok(default_yaml_config_version()),
+        Some(value) => value
+            .as_u64()
+            .ok_or_else(|| format!("version must be a number, found '{:?}'", value))?,
+    };
+    if SUPPORTED_YAML_CONFIG_VERSIONS.contains(&version) {
+        Ok(version)
+    } else {
+        Err(format!(
+            "version {} is not supported by pippo {} (supported: {}). Please upgrade pippo to use this file.",
+            version,
+            env!("CARGO_PKG_VERSION"),
+            SUPPORTED_YAML_CONFIG_VERSIONS
+                .iter()
+                .map(|v| v.to_string())
+                .collect::<Vec<_>>()
+                .join(", ")
+        ))
+    }
+}
+
 /// Model for a program's ID and all its environments that will be read from the configuration YAML
+///
+/// The program is given either by `id` or by `name`; names are resolved to IDs before the
+/// configuration is used, see [`crate::programs::read_config`].
 #[derive(Debug, Deserialize, Serialize)]
 pub struct ProgramsConfig {
-    pub id: u32,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub id: Option<u32>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub name: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub defaults: Option<DefaultsConfig>,
+    #[serde(skip_serializing_if = "Option::is_none")]
     pub environments: Option<Vec<EnvironmentsConfig>>,
+    #[serde(skip_serializing_if = "Option::is_none")]
     pub pipelines: Option<Vec<PipelinesConfig>>,
 }
 
+/// Model for the variables that apply to all environments of a program, read from the configuration YAML
+#[derive(Debug, Deserialize, Serialize)]
+pub struct DefaultsConfig {
+    #[serde(default)]
+    pub variables: Vec<EnvironmentVariable>,
+}
+
 /// Model for an environment's ID and all its variables that will be read from the configuration YAML
 #[derive(Debug, Deserialize, Serialize)]
 pub struct DomainConfig {
@@ -26,9 +209,20 @@ pub struct DomainConfig {
 /// Model for an environment's ID and all its variables that will be read from the configuration YAML
 #[derive(Debug, Deserialize, Serialize)]
 pub struct EnvironmentsConfig {
-    pub id: u32,
+    pub id: EnvironmentId,
+    #[serde(default)]
     pub variables: Vec<EnvironmentVariable>,
+    #[serde(skip_serializing_if = "Option::is_none")]
     pub domains: Option<Vec<DomainConfig>>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub bindings: Option<Vec<BindingConfig>>,
+}
+
+/// Model for an IP allowlist binding of an environment that will be read from the configuration YAML
+#[derive(Debug, Deserialize, Serialize)]
+pub struct BindingConfig {
+    pub allowlist: String,
+    pub service: BindingTier,
 }
 
 /// Model for a pipeline's ID and all its variables that will be read from the configuration YAML
@@ -38,17 +232,325 @@ pub struct PipelinesConfig {
     pub variables: Vec<PipelineVariable>,
 }
 
+impl JsonSchema for YamlConfig {
+    fn json_schema() -> Value {
+        let mut version = value("integer", "Version of the configuration format");
+        version["enum"] = json!(SUPPORTED_YAML_CONFIG_VERSIONS);
+        object(
+            "pippo configuration",
+            vec![
+                ("version", version),
+                ("programs", array(ProgramsConfig::json_schema())),
+            ],
+            &["programs"],
+        )
+    }
+}
+
+impl JsonSchema for ProgramsConfig {
+    fn json_schema() -> Value {
+        let mut schema = object(
+            "A Cloud Manager program",
+            vec![
+                ("id", value("integer", "Program ID")),
+                (
+                    "name",
+                    value(
+                        "string",
+                        "Program name, resolved to the ID when the file is used",
+                    ),
+                ),
+                ("defaults", DefaultsConfig::json_schema()),
+                ("environments", array(EnvironmentsConfig::json_schema())),
+                ("pipelines", array(PipelinesConfig::json_schema())),
+            ],
+            &[],
+        );
+        schema["oneOf"] = json!([{ "required": ["id"] }, { "required": ["name"] }]);
+        schema
+    }
+}
+
+impl JsonSchema for DefaultsConfig {
+    fn json_schema() -> Value {
+        object(
+            "Variables that apply to all environments of the program",
+            vec![("variables", array(EnvironmentVariable::json_schema()))],
+            &[],
+        )
+    }
+}
+
+impl JsonSchema for DomainConfig {
+    fn json_schema() -> Value {
+        object(
+            "A domain of the environment",
+            vec![
+                ("domainname", value("string", "Domain name")),
+                (
+                    "certificate_id",
+                    value("integer", "ID of the certificate for the domain"),
+                ),
+            ],
+            &["domainname", "certificate_id"],
+        )
+    }
+}
+
+impl JsonSchema for EnvironmentsConfig {
+    fn json_schema() -> Value {
+        object(
+            "A Cloud Manager environment",
+            vec![
+                ("id", EnvironmentId::json_schema()),
+                ("variables", array(EnvironmentVariable::json_schema())),
+                ("domains", array(DomainConfig::json_schema())),
+                ("bindings", array(BindingConfig::json_schema())),
+            ],
+            &["id"],
+        )
+    }
+}
+
+impl JsonSchema for BindingConfig {
+    fn json_schema() -> Value {
+        object(
+            "An IP allowlist binding of the environment",
+            vec![
+                ("allowlist", value("string", "Name of the IP allowlist")),
+                ("service", BindingTier::json_schema()),
+            ],
+            &["allowlist", "service"],
+        )
+    }
+}
+
+impl JsonSchema for PipelinesConfig {
+    fn json_schema() -> Value {
+        object(
+            "A Cloud Manager pipeline",
+            vec![
+                ("id", value("integer", "Pipeline ID")),
+                ("variables", array(PipelineVariable::json_schema())),
+            ],
+            &["id", "variables"],
+        )
+    }
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
     use crate::models::tests::read_yaml_from_file;
+    use crate::models::variables::{
+        EnvironmentVariableServiceType, PipelineVariableServiceType, VariableType,
+    };
+    use std::collections::HashMap;
+
+    /// Collects the paths of all keys in `yaml` that the schema doesn't describe
+    fn undocumented_keys(yaml: &serde_yaml::Value, schema: &Value, path: &str) -> Vec<String> {
+        match yaml {
+            serde_yaml::Value::Mapping(mapping) => mapping
+                .iter()
+                .flat_map(|(key, value)| {
+                    let key = key.as_str().unwrap();
+                    let path = format!("{}.{}", path, key);
+                    match schema["properties"].get(key) {
+                        Some(property) => undocumented_keys(value, property, &path),
+                        None => vec![path],
+                    }
+                })
+                .collect(),
+            serde_yaml::Value::Sequence(items) => items
+                .iter()
+                .flat_map(|item| undocumented_keys(item, &schema["items"], path))
+                .collect(),
+            _ => vec![],
+        }
+    }
+
+    #[test]
+    fn read_input_from_file() {
+        let path = std::env::temp_dir().join(format!("pippo-input-{}.yml", uuid::Uuid::new_v4()));
+        std::fs::write(&path, "programs: []\n").unwrap();
+        let input = read_input(path.to_str().unwrap());
+        std::fs::remove_file(&path).unwrap();
+        assert_eq!(input.unwrap(), "programs: []\n");
+        assert!(read_input("does/not/exist.yml").is_err());
+    }
+
+    #[test]
+    fn programs_need_either_id_or_name() {
+        let config = YamlConfig::parse("programs:\n  - name: Intranet\n").unwrap();
+        assert_eq!(config.programs[0].id, None);
+        assert_eq!(config.programs[0].label(), "'Intranet'");
+
+        assert_eq!(
+            YamlConfig::parse("programs:\n  - id: 1\n  - id: 2\n    name: Intranet\n").unwrap_err(),
+            "Malformed YAML: program 2 has both id 2 and name 'Intranet', use only one of them"
+        );
+        assert_eq!(
+            YamlConfig::parse("programs:\n  - environments: []\n").unwrap_err(),
+            "Malformed YAML: program 1 needs either an id or a name"
+        );
+    }
+
+    #[test]
+    fn single_program_id_of_config() {
+        let config: YamlConfig = read_yaml_from_file("test/test_yaml_config.yml").unwrap();
+        assert_eq!(
+            config.single_program_id(),
+            Ok(config.programs[0].program_id())
+        );
+
+        let config: YamlConfig = read_yaml_from_file("test/test_yaml_config_apply.yml").unwrap();
+        let err = config.single_program_id().unwrap_err();
+        assert!(err.contains("declares 2 programs"), "{}", err);
+
+        let config = YamlConfig::parse("programs: []\n").unwrap();
+        assert_eq!(
+            config.single_program_id(),
+            Err(String::from("the YAML config doesn't declare any program"))
+        );
+    }
+
+    #[test]
+    fn schema_describes_all_keys_of_the_examples() {
+        let schema = crate::schema::document::<YamlConfig>("pippo");
+        assert_eq!(schema["$schema"], "http://json-schema.org/draft-07/schema#");
+        assert_eq!(schema["required"], json!(["programs"]));
+
+        for file in [
+            "test/test_yaml_config.yml",
+            "test/test_yaml_config_defaults.yml",
+            "test/test_yaml_config_domains.yml",
+        ]
+        .iter()
+        {
+            let yaml: serde_yaml::Value = read_yaml_from_file(file).unwrap();
+            assert_eq!(
+                undocumented_keys(&yaml, &schema, ""),
+                Vec::<String>::new(),
+                "{}",
+                file
+            );
+        }
+    }
+
+    /// A config that sets every field, so each one shows up when it is serialized. Adding a field
+    /// to a config struct fails to compile until it is set here as well.
+    fn config_with_every_field() -> YamlConfig {
+        let environment_variable = EnvironmentVariable {
+            name: String::from("A"),
+            value: Some(String::from("a")),
+            variable_type: VariableType::String,
+            service: EnvironmentVariableServiceType::Author,
+            extra: HashMap::new(),
+        };
+        YamlConfig {
+            version: 1,
+            programs: vec![ProgramsConfig {
+                id: Some(1),
+                name: Some(String::from("Intranet")),
+                defaults: Some(DefaultsConfig {
+                    variables: vec![environment_variable.clone()],
+                }),
+                environments: Some(vec![EnvironmentsConfig {
+                    id: EnvironmentId(2),
+                    variables: vec![environment_variable],
+                    domains: Some(vec![DomainConfig {
+                        domainname: String::from("example.com"),
+                        certificate_id: 3,
+                    }]),
+                    bindings: Some(vec![BindingConfig {
+                        allowlist: String::from("office"),
+                        service: BindingTier::Author,
+                    }]),
+                }]),
+                pipelines: Some(vec![PipelinesConfig {
+                    id: 4,
+                    variables: vec![PipelineVariable {
+                        name: String::from("B"),
+                        value: Some(String::from("b")),
+                        variable_type: VariableType::SecretString,
+                        service: PipelineVariableServiceType::UiTest,
+                    }],
+                }]),
+            }],
+        }
+    }
+
+    #[test]
+    fn schema_describes_every_config_field() {
+        let schema = crate::schema::document::<YamlConfig>("pippo");
+        let yaml = serde_yaml::to_value(config_with_every_field()).unwrap();
+        assert_eq!(undocumented_keys(&yaml, &schema, ""), Vec::<String>::new());
+        // The check itself notices a missing entry
+        let mut incomplete = schema.clone();
+        incomplete["properties"]["programs"]["items"]["properties"]
+            .as_object_mut()
+            .unwrap()
+            .remove("pipelines");
+        assert_eq!(
+            undocumented_keys(&yaml, &incomplete, ""),
+            vec![String::from(".programs.pipelines")]
+        );
+    }
+
+    #[test]
+    fn schema_lists_enum_values() {
+        let schema = YamlConfig::json_schema();
+        let environment =
+            &schema["properties"]["programs"]["items"]["properties"]["environments"]["items"];
+        let variable = &environment["properties"]["variables"]["items"];
+        assert_eq!(
+            variable["properties"]["type"]["enum"],
+            json!(["string", "secretString"])
+        );
+        assert_eq!(variable["required"], json!(["name", "type"]));
+        assert_eq!(
+            environment["properties"]["bindings"]["items"]["properties"]["service"]["enum"],
+            json!(["author", "publish", "preview"])
+        );
+    }
 
     #[test]
     fn deserialize_yaml_config() {
         let vobj: YamlConfig = read_yaml_from_file("test/test_yaml_config.yml").unwrap();
 
         assert_eq!(vobj.programs.len(), 1);
-        assert_eq!(vobj.programs.first().unwrap().id, 222222);
-        assert_eq!(vobj.programs.first().unwrap().pipelines.is_some(), true);
+        assert_eq!(vobj.programs.first().unwrap().id, Some(222222));
+        assert!(vobj.programs.first().unwrap().pipelines.is_some());
+    }
+
+    #[test]
+    fn yaml_config_version_defaults_to_1() {
+        let vobj: YamlConfig = read_yaml_from_file("test/test_yaml_config.yml").unwrap();
+        assert_eq!(vobj.version, 1);
+        assert_eq!(check_yaml_config_version(None), Ok(1));
+    }
+
+    #[test]
+    fn check_supported_and_unknown_yaml_config_versions() {
+        let supported = serde_yaml::Value::from(1);
+        let unknown = serde_yaml::Value::from(2);
+        let invalid = serde_yaml::Value::from("one");
+
+        assert_eq!(check_yaml_config_version(Some(&supported)), Ok(1));
+        assert!(check_yaml_config_version(Some(&unknown))
+            .unwrap_err()
+            .contains("Please upgrade pippo"));
+        assert!(check_yaml_config_version(Some(&invalid)).is_err());
+    }
+
+    #[test]
+    fn deserialize_yaml_config_with_defaults() {
+        let vobj: YamlConfig = read_yaml_from_file("test/test_yaml_config_defaults.yml").unwrap();
+
+        let program = vobj.programs.first().unwrap();
+        assert_eq!(program.defaults.as_ref().unwrap().variables.len(), 2);
+        let environments = program.environments.as_ref().unwrap();
+        assert_eq!(environments[0].variables.len(), 1);
+        assert!(environments[1].variables.is_empty());
     }
 }
@@ -1,4 +1,7 @@
+use super::environment::EnvironmentId;
 use serde::{Deserialize, Serialize};
+use serde_json::Value;
+use std::collections::HashMap;
 
 /// Model for a list of programs
 #[derive(Debug, Deserialize, Serialize)]
@@ -23,7 +26,7 @@ pub struct Domain {
     pub name: String,
     pub status: Option<String>,
     pub dns_txt_record: String,
-    pub environment_id: i64,
+    pub environment_id: EnvironmentId,
     pub environment_name: Option<String>,
     pub tier: Option<String>,
     pub certificate_id: i64,
@@ -31,6 +34,9 @@ pub struct Domain {
     pub certificate_expire_at: Option<String>,
     pub created_at: Option<String>,
     pub updated_at: Option<String>,
+    /// Fields that pippo doesn't know, kept so they survive a round trip
+    #[serde(flatten)]
+    pub extra: HashMap<String, Value>,
 }
 
 #[derive(Debug, Deserialize, Serialize)]
@@ -38,7 +44,7 @@ pub struct Domain {
 pub struct MinimumDomain {
     pub name: String,
     pub dns_txt_record: String,
-    pub environment_id: i64,
+    pub environment_id: EnvironmentId,
     pub certificate_id: i64,
     pub dns_zone: String,
 }
@@ -86,4 +92,16 @@ mod tests {
         let vobj: DomainResponse = read_user_from_file("test/test_domain_response.json").unwrap();
         assert_eq!(vobj.domain_list.list.len(), 20);
     }
+
+    #[test]
+    fn unknown_fields_of_domain_survive_a_round_trip() {
+        let vobj: DomainResponse = read_user_from_file("test/test_domain_response.json").unwrap();
+        let domain = &vobj.domain_list.list[0];
+        assert!(domain.extra.contains_key("_links"));
+        assert!(!domain.extra.contains_key("name"));
+
+        let output = serde_json::to_value(domain).unwrap();
+        assert_eq!(output["name"], domain.name.as_str());
+        assert_eq!(output["_links"], domain.extra["_links"]);
+    }
 }
@@ -1,8 +1,76 @@
-use serde::{Deserialize, Serialize};
+use super::log::LogOption;
+use crate::schema::JsonSchema;
+use serde::de::{self, Visitor};
+use serde::{Deserialize, Deserializer, Serialize, Serializer};
+use std::convert::TryFrom;
+use std::fmt;
+use std::str::FromStr;
+
+/// ID of a Cloud Manager environment.
+///
+/// The API returns environment IDs as strings while the CLI and the YAML config use numbers, so
+/// both forms are accepted when deserializing. It is always serialized as a number.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash, PartialOrd, Ord)]
+pub struct EnvironmentId(pub u64);
+
+impl fmt::Display for EnvironmentId {
+    fn fmt(&self, formatter: &mut fmt::Formatter) -> fmt::Result {
+        write!(formatter, "{}", self.0)
+    }
+}
+
+impl FromStr for EnvironmentId {
+    type Err = String;
+
+    fn from_str(input: &str) -> Result<Self, Self::Err> {
+        input
+            .trim()
+            .parse()
+            .map(EnvironmentId)
+            .map_err(|_| format!("invalid environment ID '{}'", input))
+    }
+}
+
+impl Serialize for EnvironmentId {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        serializer.serialize_u64(self.0)
+    }
+}
+
+impl<'de> Deserialize<'de> for EnvironmentId {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        struct EnvironmentIdVisitor;
+
+        impl<'de> Visitor<'de> for EnvironmentIdVisitor {
+            type Value = EnvironmentId;
+
+            fn expecting(&self, formatter: &mut fmt::Formatter) -> fmt::Result {
+                formatter.write_str("an environment ID as number or numeric string")
+            }
+
+            fn visit_u64<E: de::Error>(self, value: u64) -> Result<Self::Value, E> {
+                Ok(EnvironmentId(value))
+            }
+
+            fn visit_i64<E: de::Error>(self, value: i64) -> Result<Self::Value, E> {
+                u64::try_from(value)
+                    .map(EnvironmentId)
+                    .map_err(|_| E::custom(format!("invalid environment ID {}", value)))
+            }
+
+            fn visit_str<E: de::Error>(self, value: &str) -> Result<Self::Value, E> {
+                EnvironmentId::from_str(value).map_err(E::custom)
+            }
+        }
+
+        deserializer.deserialize_any(EnvironmentIdVisitor)
+    }
+}
+
 /// Model for a list of environments
 #[derive(Debug, Deserialize, Serialize)]
 pub struct EnvironmentsList {
-    environments: Vec<Environment>,
+    pub environments: Vec<Environment>,
 }
 /// Struct that holds the response when requesting /api/program/{id}/environments
 #[derive(Deserialize, Serialize)]
@@ -15,12 +83,67 @@ pub struct EnvironmentsResponse {
 #[derive(Debug, Deserialize, Serialize)]
 pub struct Environment {
     pub name: String,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub description: Option<String>,
     #[serde(rename(deserialize = "type", serialize = "type"))]
-    env_type: String,
+    pub env_type: String,
     pub status: String,
-    id: String,
+    pub id: EnvironmentId,
     #[serde(rename(deserialize = "programId", serialize = "programId"))]
     program_id: String,
+    #[serde(
+        default,
+        rename(deserialize = "availableLogOptions", serialize = "availableLogOptions")
+    )]
+    pub available_log_options: Vec<LogOption>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub region: Option<String>,
+    #[serde(
+        default,
+        rename(deserialize = "_links", serialize = "_links"),
+        skip_serializing_if = "Option::is_none"
+    )]
+    pub links: Option<EnvironmentLinks>,
+}
+
+/// Links of an environment to the URLs of its tiers
+#[derive(Debug, Deserialize, Serialize)]
+pub struct EnvironmentLinks {
+    #[serde(rename = "http://ns.adobe.com/adobecloud/rel/author")]
+    pub author: Option<EnvironmentLink>,
+    #[serde(rename = "http://ns.adobe.com/adobecloud/rel/publish")]
+    pub publish: Option<EnvironmentLink>,
+    #[serde(rename = "http://ns.adobe.com/adobecloud/rel/preview")]
+    pub preview: Option<EnvironmentLink>,
+}
+
+/// Model for a single link of an environment
+#[derive(Debug, Deserialize, Serialize)]
+pub struct EnvironmentLink {
+    pub href: String,
+}
+
+/// Body that is sent to update an environment
+#[derive(Debug, Deserialize, Serialize)]
+pub struct EnvironmentUpdate {
+    pub description: String,
+}
+
+/// Model for the readiness of an environment as reported by `env ready`
+#[derive(Debug, Deserialize, Serialize)]
+pub struct EnvironmentReadiness {
+    pub status: String,
+    pub ready: bool,
+}
+
+impl JsonSchema for EnvironmentId {
+    fn json_schema() -> serde_json::Value {
+        serde_json::json!({
+            "description": "Environment ID",
+            "type": ["integer", "string"],
+            "pattern": "^[0-9]+$",
+        })
+    }
 }
 
 #[cfg(test)]
@@ -36,7 +159,49 @@ mod tests {
 
         assert_eq!(
             vobj.environments_list.environments.first().unwrap().id,
-            "222222"
+            EnvironmentId(222222)
+        );
+    }
+
+    #[test]
+    fn deserialize_environment_description() {
+        let vobj: EnvironmentsResponse =
+            read_json_from_file("test/test_environment_response.json").unwrap();
+
+        let environments = vobj.environments_list.environments;
+        assert_eq!(
+            environments[0].description.as_deref(),
+            Some("production environment for demo ")
+        );
+        assert_eq!(environments[1].description.as_deref(), Some(""));
+    }
+
+    #[test]
+    fn serialize_environment_update() {
+        let update = EnvironmentUpdate {
+            description: String::from("owned by team-a"),
+        };
+        assert_eq!(
+            serde_json::to_string(&update).unwrap(),
+            "{\"description\":\"owned by team-a\"}"
+        );
+    }
+
+    #[test]
+    fn deserialize_environment_id_from_string_and_number() {
+        let from_string: EnvironmentId = serde_json::from_str("\"33333\"").unwrap();
+        let from_number: EnvironmentId = serde_json::from_str("33333").unwrap();
+        assert_eq!(from_string, EnvironmentId(33333));
+        assert_eq!(from_number, EnvironmentId(33333));
+        assert!(serde_json::from_str::<EnvironmentId>("\"demo\"").is_err());
+        assert!(serde_json::from_str::<EnvironmentId>("-1").is_err());
+    }
+
+    #[test]
+    fn serialize_environment_id_as_number() {
+        assert_eq!(
+            serde_json::to_string(&EnvironmentId(33333)).unwrap(),
+            "33333"
         );
     }
 }
@@ -4,7 +4,7 @@ use serde::{Deserialize, Serialize};
 #[derive(Debug, Deserialize, Serialize)]
 pub struct ExecutionList {
     #[serde(rename = "executions")]
-    list: Vec<Execution>,
+    pub list: Vec<Execution>,
 }
 
 #[derive(Debug, Deserialize, Serialize)]
@@ -30,6 +30,46 @@ pub struct Execution {
     pipeline_type: String,
     pipeline_execution_mode: String,
     finished_at: Option<String>,
+    #[serde(rename = "_embedded", default, skip_serializing_if = "Option::is_none")]
+    pub embedded: Option<ExecutionEmbedment>,
+}
+
+impl Execution {
+    /// Returns the states of the steps of this execution
+    pub fn step_states(&self) -> &[StepState] {
+        self.embedded
+            .as_ref()
+            .map_or(&[], |embedded| &embedded.step_states[..])
+    }
+}
+
+/// Helper struct that is used because of the JSON structure that Execution has
+#[derive(Debug, Deserialize, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct ExecutionEmbedment {
+    #[serde(default)]
+    pub step_states: Vec<StepState>,
+}
+
+/// Model for the state of a single step of an execution
+#[derive(Debug, Deserialize, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct StepState {
+    pub id: String,
+    pub step_id: String,
+    pub phase_id: String,
+    pub action: String,
+    pub status: String,
+    pub environment: Option<String>,
+    pub started_at: Option<String>,
+    pub finished_at: Option<String>,
+}
+
+/// Answer of Cloud Manager when the log of a step is requested
+#[derive(Debug, Deserialize)]
+pub struct StepLogRedirect {
+    /// Short-lived URL that the log can be downloaded from without credentials
+    pub redirect: String,
 }
 
 #[cfg(test)]
@@ -44,5 +84,28 @@ mod tests {
             read_json_from_file("test/test_execution_response.json").unwrap();
 
         assert_eq!(vobj.execution_list.list.first().unwrap().id, "66666");
+        assert_eq!(
+            vobj.execution_list
+                .list
+                .first()
+                .unwrap()
+                .step_states()
+                .len(),
+            8
+        );
+    }
+
+    #[test]
+    fn deserialize_execution_detail() {
+        let vobj: Execution =
+            read_json_from_file("test/test_execution_detail_response.json").unwrap();
+
+        assert_eq!(vobj.status, "RUNNING");
+        let steps = vobj.step_states();
+        assert_eq!(steps.len(), 3);
+        assert_eq!(steps[1].action, "build");
+        assert_eq!(steps[1].status, "RUNNING");
+        assert_eq!(steps[2].environment.as_deref(), Some("development"));
+        assert!(steps[2].started_at.is_none());
     }
 }
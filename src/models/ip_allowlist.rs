@@ -0,0 +1,99 @@
+use crate::schema::{string_enum, JsonSchema};
+use serde::{Deserialize, Serialize};
+use strum_macros::{EnumString, IntoStaticStr};
+
+// Models for representing Cloud Manager IP allowlists and their bindings
+// -------------------------------------------------------------------------------------------------
+
+/// Struct that holds the response when requesting /api/program/{id}/ipAllowlists
+#[derive(Debug, Deserialize, Serialize)]
+pub struct IpAllowlistsResponse {
+    #[serde(rename(deserialize = "_embedded", serialize = "_embedded"))]
+    pub ip_allowlists_list: IpAllowlistsList,
+}
+
+/// Model for a list of IP allowlists
+#[derive(Debug, Deserialize, Serialize)]
+pub struct IpAllowlistsList {
+    #[serde(rename(deserialize = "ipAllowlists", serialize = "ipAllowlists"))]
+    pub ip_allowlists: Vec<IpAllowlist>,
+}
+
+/// Model for an IP allowlist and the environments it is bound to
+#[derive(Debug, Deserialize, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct IpAllowlist {
+    pub id: String,
+    pub name: String,
+    pub ip_cidr_set: Vec<String>,
+    pub program_id: String,
+    #[serde(default)]
+    pub bindings: Vec<IpAllowlistBinding>,
+}
+
+/// Model for the binding of an IP allowlist to a tier of an environment
+#[derive(Debug, Deserialize, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct IpAllowlistBinding {
+    pub id: String,
+    pub tier: BindingTier,
+    pub status: Option<String>,
+    pub environment_id: String,
+}
+
+/// Body that is sent to bind an IP allowlist
+#[derive(Debug, Deserialize, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct MinimumIpAllowlistBinding {
+    pub environment_id: String,
+    pub tier: BindingTier,
+}
+
+/// Possible tiers that an IP allowlist can be bound to
+#[derive(Clone, Debug, Deserialize, Serialize, IntoStaticStr, EnumString, PartialEq, Eq)]
+#[strum(serialize_all = "lowercase")]
+#[serde(rename_all = "lowercase")]
+pub enum BindingTier {
+    Author,
+    Publish,
+    Preview,
+}
+
+impl JsonSchema for BindingTier {
+    fn json_schema() -> serde_json::Value {
+        string_enum("Tier of the environment", &["author", "publish", "preview"])
+    }
+}
+
+// -------------------------------------------------------------------------------------------------
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::models::tests::read_json_from_file;
+
+    #[test]
+    fn deserialize_ip_allowlists_response() {
+        let vobj: IpAllowlistsResponse =
+            read_json_from_file("test/test_ip_allowlists_response.json").unwrap();
+
+        let allowlists = vobj.ip_allowlists_list.ip_allowlists;
+        assert_eq!(allowlists.len(), 2);
+        assert_eq!(allowlists[0].name, "office");
+        assert_eq!(allowlists[0].bindings.len(), 1);
+        assert_eq!(allowlists[0].bindings[0].tier, BindingTier::Author);
+        assert!(allowlists[1].bindings.is_empty());
+    }
+
+    #[test]
+    fn serialize_ip_allowlist_binding() {
+        let binding = MinimumIpAllowlistBinding {
+            environment_id: String::from("33333"),
+            tier: BindingTier::Publish,
+        };
+        assert_eq!(
+            serde_json::to_string(&binding).unwrap(),
+            "{\"environmentId\":\"33333\",\"tier\":\"publish\"}"
+        );
+    }
+}
@@ -1,10 +1,17 @@
 use chrono::NaiveDate;
-use serde::{Deserialize, Serialize};
+use serde::{Deserialize, Deserializer, Serialize, Serializer};
+use std::fmt;
+use std::str::FromStr;
 
-use strum_macros::{EnumString, IntoStaticStr};
+use crate::models::environment::EnvironmentId;
+
+use strum_macros::{EnumIter, EnumString, IntoStaticStr};
 
 /// Possible types that a service can have
-#[derive(Clone, Deserialize, Serialize, IntoStaticStr, EnumString)]
+///
+/// Services that pippo doesn't know yet only occur in API responses and are kept as `Other`, the
+/// command line only accepts the known ones.
+#[derive(Clone, Debug, PartialEq, Deserialize, Serialize, EnumString, EnumIter)]
 #[strum(serialize_all = "lowercase")]
 #[serde(rename_all = "lowercase")]
 pub enum ServiceType {
@@ -14,11 +21,52 @@ pub enum ServiceType {
     #[strum(serialize = "preview_dispatcher")]
     #[serde(rename(deserialize = "preview_dispatcher", serialize = "preview_dispatcher"))]
     PreviewDispatcher,
+    #[strum(serialize = "preview_publish")]
+    #[serde(rename(deserialize = "preview_publish", serialize = "preview_publish"))]
+    PreviewPublish,
+    #[strum(disabled)]
+    #[serde(untagged)]
+    Other(String),
+}
+
+impl ServiceType {
+    /// The name as Cloud Manager uses it
+    pub fn as_str(&self) -> &str {
+        match self {
+            ServiceType::Author => "author",
+            ServiceType::Publish => "publish",
+            ServiceType::Dispatcher => "dispatcher",
+            ServiceType::PreviewDispatcher => "preview_dispatcher",
+            ServiceType::PreviewPublish => "preview_publish",
+            ServiceType::Other(name) => name,
+        }
+    }
+
+    /// Returns the service that Cloud Manager uses, known or not.
+    ///
+    /// # Arguments
+    ///
+    /// * `name` - String slice that holds the name from an API response
+    pub fn from_api(name: &str) -> ServiceType {
+        ServiceType::from_str(name).unwrap_or_else(|_| ServiceType::Other(name.to_string()))
+    }
+}
+
+impl<'a> From<&'a ServiceType> for &'a str {
+    fn from(service: &'a ServiceType) -> Self {
+        service.as_str()
+    }
+}
+
+impl fmt::Display for ServiceType {
+    fn fmt(&self, formatter: &mut fmt::Formatter) -> fmt::Result {
+        formatter.write_str(self.as_str())
+    }
 }
 
 // Models for representing Cloud Manager logs
 /// Possible types that a log can have
-#[derive(Clone, Deserialize, Serialize, IntoStaticStr, EnumString)]
+#[derive(Clone, Debug, PartialEq, Deserialize, Serialize, IntoStaticStr, EnumString, EnumIter)]
 #[strum(serialize_all = "lowercase")]
 #[serde(rename_all = "lowercase")]
 pub enum LogType {
@@ -31,12 +79,97 @@ pub enum LogType {
     HttpdError,
 }
 
+/// Prefix of log names that pippo doesn't know yet, e.g. `custom:aemhtmllibrarymanager`
+pub const CUSTOM_LOG_PREFIX: &str = "custom:";
+
+/// Name of a logfile, either one that pippo knows or any other that Cloud Manager offers
+///
+/// Adobe adds new logfiles from time to time. They are parsed from `custom:<name>` on the command
+/// line and sent to Cloud Manager as `<name>`; in API responses, every unknown name is custom.
+#[derive(Clone, Debug, PartialEq)]
+pub enum LogName {
+    Known(LogType),
+    Custom(String),
+}
+
+impl LogName {
+    /// The name as Cloud Manager uses it
+    pub fn as_str(&self) -> &str {
+        match self {
+            LogName::Known(logname) => logname.into(),
+            LogName::Custom(name) => name,
+        }
+    }
+
+    /// Returns the name that Cloud Manager uses for a logfile, known or not.
+    ///
+    /// # Arguments
+    ///
+    /// * `name` - String slice that holds the name from an API response
+    pub fn from_api(name: &str) -> LogName {
+        LogType::from_str(name).map_or_else(|_| LogName::Custom(name.to_string()), LogName::Known)
+    }
+}
+
+impl From<LogType> for LogName {
+    fn from(logname: LogType) -> Self {
+        LogName::Known(logname)
+    }
+}
+
+impl fmt::Display for LogName {
+    fn fmt(&self, formatter: &mut fmt::Formatter) -> fmt::Result {
+        formatter.write_str(self.as_str())
+    }
+}
+
+impl FromStr for LogName {
+    type Err = String;
+
+    /// Parses a known log name or `custom:<name>` for any other.
+    fn from_str(input: &str) -> Result<Self, Self::Err> {
+        match input.strip_prefix(CUSTOM_LOG_PREFIX) {
+            Some(name)
+                if !name.is_empty()
+                    && name
+                        .chars()
+                        .all(|c| c.is_ascii_alphanumeric() || c == '_' || c == '-') =>
+            {
+                Ok(LogName::from_api(name))
+            }
+            Some(_) => Err(format!(
+                "invalid log '{}', custom names may only contain letters, digits, '_' and '-'",
+                input
+            )),
+            None => LogType::from_str(input).map(LogName::Known).map_err(|_| {
+                format!(
+                    "unknown log '{}', use {}{} for logs that pippo doesn't know yet",
+                    input, CUSTOM_LOG_PREFIX, input
+                )
+            }),
+        }
+    }
+}
+
+impl Serialize for LogName {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        serializer.serialize_str(self.as_str())
+    }
+}
+
+impl<'de> Deserialize<'de> for LogName {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        let name = String::deserialize(deserializer)?;
+        Ok(LogName::from_api(&name))
+    }
+}
+
 /// Struct that holds the response when requesting /api/program/{id}/environment/{id}/logs
 #[derive(Deserialize, Serialize)]
 pub struct LogsResponse {
-    days: u32,
-    name: Vec<String>,
-    service: Vec<String>,
+    pub days: u32,
+    pub name: Vec<String>,
+    pub service: Vec<String>,
     #[serde(rename(deserialize = "_embedded", serialize = "_embedded"))]
     pub embedded: LogsEmbedment,
 }
@@ -48,11 +181,18 @@ pub struct LogsEmbedment {
 }
 
 /// Struct that represents an available logfile
-#[derive(Deserialize, Serialize)]
+#[derive(Debug, Deserialize, Serialize)]
 pub struct Log {
-    name: LogType,
-    service: ServiceType,
-    date: NaiveDate,
+    pub name: LogName,
+    pub service: ServiceType,
+    pub date: NaiveDate,
+}
+
+/// Model for a combination of service and log that an environment offers
+#[derive(Clone, Debug, Deserialize, Serialize)]
+pub struct LogOption {
+    pub service: String,
+    pub name: String,
 }
 
 // Tail Log
@@ -90,15 +230,146 @@ pub struct HttpNsAdobeComAdobecloudRelLogsTail {
     pub href: String,
 }
 
+/// A tailed log line with its origin, as printed by `log tail --output-format ndjson`
+#[derive(Debug, PartialEq, Deserialize, Serialize)]
+pub struct TailEvent {
+    /// When pippo received the line, as RFC 3339 timestamp in UTC
+    pub ts_received: String,
+    pub program: u32,
+    pub env: EnvironmentId,
+    pub service: ServiceType,
+    pub log: LogName,
+    pub line: String,
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
     use crate::models::tests::read_json_from_file;
+    use strum::IntoEnumIterator;
 
     #[test]
     fn deserialize_logs_response() {
         let vobj: LogsResponse = read_json_from_file("test/test_log_response.json").unwrap();
 
         assert_eq!(vobj.embedded.downloads.len(), 3);
+        assert_eq!(vobj.days, 2);
+        assert_eq!(vobj.embedded.downloads[0].service, ServiceType::Author);
+        assert_eq!(
+            vobj.embedded.downloads[0].name,
+            LogName::Known(LogType::AemAccess)
+        );
+    }
+
+    #[test]
+    fn serialize_tail_event() {
+        let event = TailEvent {
+            ts_received: String::from("2025-03-01T10:00:00.123Z"),
+            program: 11111,
+            env: EnvironmentId(22222),
+            service: ServiceType::PreviewDispatcher,
+            log: LogType::HttpdError.into(),
+            line: String::from("[error] \"GET /\" failed"),
+        };
+        assert_eq!(
+            serde_json::to_string(&event).unwrap(),
+            r#"{"ts_received":"2025-03-01T10:00:00.123Z","program":11111,"env":22222,"service":"preview_dispatcher","log":"httpderror","line":"[error] \"GET /\" failed"}"#
+        );
+    }
+
+    #[test]
+    fn deserialize_tail_event() {
+        let event: TailEvent = serde_json::from_str(
+            r#"{"ts_received":"2025-03-01T10:00:00.123Z","program":11111,"env":22222,"service":"author","log":"aemerror","line":"*ERROR* boom"}"#,
+        )
+        .unwrap();
+        assert_eq!(event.env, EnvironmentId(22222));
+        assert_eq!(event.service, ServiceType::Author);
+        assert_eq!(event.log, LogName::Known(LogType::AemError));
+        assert_eq!(event.line, "*ERROR* boom");
+    }
+
+    #[test]
+    fn parse_known_and_custom_log_names() {
+        assert_eq!(
+            LogName::from_str("aemerror"),
+            Ok(LogName::Known(LogType::AemError))
+        );
+        assert_eq!(
+            LogName::from_str("custom:aemhtmllibrarymanager"),
+            Ok(LogName::Custom(String::from("aemhtmllibrarymanager")))
+        );
+        // Known logs stay known, even when they are given as custom
+        assert_eq!(
+            LogName::from_str("custom:cdn"),
+            Ok(LogName::Known(LogType::Cdn))
+        );
+        assert_eq!(
+            LogName::from_str("aemsidecar"),
+            Err(String::from(
+                "unknown log 'aemsidecar', use custom:aemsidecar for logs that pippo doesn't know yet"
+            ))
+        );
+        assert!(LogName::from_str("custom:").is_err());
+        assert!(LogName::from_str("custom:a&b=c").is_err());
+    }
+
+    #[test]
+    fn log_names_are_sent_without_prefix() {
+        let custom = LogName::from_str("custom:aemsidecar").unwrap();
+        assert_eq!(custom.as_str(), "aemsidecar");
+        assert_eq!(custom.to_string(), "aemsidecar");
+        assert_eq!(LogName::from(LogType::HttpdAccess).as_str(), "httpdaccess");
+    }
+
+    #[test]
+    fn serde_of_log_names() {
+        let names: Vec<LogName> = serde_json::from_str(r#"["aemerror", "aemsidecar"]"#).unwrap();
+        assert_eq!(
+            names,
+            vec![
+                LogName::Known(LogType::AemError),
+                LogName::Custom(String::from("aemsidecar"))
+            ]
+        );
+        assert_eq!(
+            serde_json::to_string(&names).unwrap(),
+            r#"["aemerror","aemsidecar"]"#
+        );
+    }
+
+    #[test]
+    fn serde_of_unknown_services() {
+        let services: Vec<ServiceType> =
+            serde_json::from_str(r#"["preview_publish", "sidecar"]"#).unwrap();
+        assert_eq!(
+            services,
+            vec![
+                ServiceType::PreviewPublish,
+                ServiceType::Other(String::from("sidecar"))
+            ]
+        );
+        assert_eq!(
+            serde_json::to_string(&services).unwrap(),
+            r#"["preview_publish","sidecar"]"#
+        );
+    }
+
+    #[test]
+    fn only_known_services_are_parsed() {
+        assert_eq!(
+            ServiceType::from_str("preview_dispatcher"),
+            Ok(ServiceType::PreviewDispatcher)
+        );
+        assert!(ServiceType::from_str("sidecar").is_err());
+        assert_eq!(
+            ServiceType::from_api("sidecar"),
+            ServiceType::Other(String::from("sidecar"))
+        );
+        assert_eq!(ServiceType::iter().count(), 5);
+        assert_eq!(
+            ServiceType::Other(String::from("sidecar")).as_str(),
+            "sidecar"
+        );
     }
 }
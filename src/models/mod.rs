@@ -3,13 +3,15 @@ pub mod config;
 pub mod domain;
 pub mod environment;
 pub mod execution;
+pub mod ip_allowlist;
 pub mod log;
 pub mod pipeline;
 pub mod program;
+pub mod region;
 pub mod variables;
 
 #[cfg(test)]
-mod tests {
+pub mod tests {
     use serde::de::DeserializeOwned;
     use std::fs::File;
     use std::io::BufReader;
@@ -1,6 +1,7 @@
 // Models for representing Cloud Manager pipelines and descendant objects
 // -------------------------------------------------------------------------------------------------
 
+use super::environment::EnvironmentId;
 use serde::{Deserialize, Serialize};
 
 /// Struct that holds the response when requesting /api/programs
@@ -13,7 +14,7 @@ pub struct PipelinesResponse {
 /// Model for a list of pipelines
 #[derive(Debug, Deserialize, Serialize)]
 pub struct PipelinesList {
-    pipelines: Vec<Pipeline>,
+    pub pipelines: Vec<Pipeline>,
 }
 
 /// Model for a pipeline and its relevant metadata
@@ -21,9 +22,48 @@ pub struct PipelinesList {
 pub struct Pipeline {
     pub name: String,
     pub status: String,
-    id: String,
+    pub id: String,
     #[serde(rename(deserialize = "programId", serialize = "programId"))]
-    program_id: String,
+    pub program_id: String,
+    #[serde(rename(deserialize = "type", serialize = "type"))]
+    pub pipeline_type: Option<String>,
+    /// How executions are started, e.g. `ON_COMMIT`, `MANUAL` or `SCHEDULE`
+    pub trigger: Option<String>,
+    /// Only reported by Cloud Manager for some pipelines, pipelines without it are enabled
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub enabled: Option<bool>,
+    #[serde(default)]
+    pub phases: Vec<PipelinePhase>,
+}
+
+impl Pipeline {
+    /// Whether executions of the pipeline can be started
+    pub fn is_enabled(&self) -> bool {
+        self.enabled.unwrap_or(true)
+    }
+}
+
+/// Model for a phase of a pipeline, deploy phases reference their target environment
+#[derive(Debug, Deserialize, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct PipelinePhase {
+    pub name: String,
+    #[serde(rename(deserialize = "type", serialize = "type"))]
+    pub phase_type: String,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub environment_id: Option<EnvironmentId>,
+}
+
+/// Model for a pipeline that deploys to an environment, as printed by `env pipelines`
+#[derive(Debug, Deserialize, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct EnvironmentPipeline {
+    pub id: String,
+    pub name: String,
+    #[serde(rename(deserialize = "type", serialize = "type"))]
+    pub pipeline_type: Option<String>,
+    pub status: String,
+    pub last_execution_status: Option<String>,
 }
 
 // -------------------------------------------------------------------------------------------------
@@ -40,4 +80,36 @@ mod tests {
 
         assert_eq!(vobj.pipelines_list.pipelines.len(), 5);
     }
+
+    #[test]
+    fn deserialize_pipeline_phases() {
+        let vobj: PipelinesResponse =
+            read_json_from_file("test/test_pipeline_response.json").unwrap();
+
+        let pipeline = vobj.pipelines_list.pipelines.first().unwrap();
+        assert_eq!(pipeline.pipeline_type, Some(String::from("CI_CD")));
+        let deploy_phase = pipeline
+            .phases
+            .iter()
+            .find(|phase| phase.phase_type == "DEPLOY")
+            .unwrap();
+        assert_eq!(deploy_phase.environment_id, Some(EnvironmentId(22222)));
+        assert!(pipeline.phases[0].environment_id.is_none());
+    }
+
+    #[test]
+    fn deserialize_pipeline_trigger_and_enabled() {
+        let vobj: PipelinesResponse =
+            read_json_from_file("test/test_pipeline_disabled_response.json").unwrap();
+
+        let pipelines = vobj.pipelines_list.pipelines;
+        assert_eq!(pipelines[0].trigger, Some(String::from("ON_COMMIT")));
+        assert!(pipelines[0].is_enabled());
+        assert_eq!(pipelines[1].trigger, Some(String::from("MANUAL")));
+        assert_eq!(pipelines[1].enabled, Some(false));
+        assert!(!pipelines[1].is_enabled());
+        assert_eq!(pipelines[2].enabled, None);
+        assert!(pipelines[2].is_enabled());
+        assert_eq!(pipelines[1].program_id, "22222");
+    }
 }
@@ -1,4 +1,6 @@
 use serde::{Deserialize, Serialize};
+use serde_json::Value;
+use std::collections::HashMap;
 
 // Models for representing Cloud Manager programs
 // -------------------------------------------------------------------------------------------------
@@ -8,23 +10,79 @@ use serde::{Deserialize, Serialize};
 pub struct ProgramsResponse {
     #[serde(rename(deserialize = "_embedded", serialize = "_embedded"))]
     pub programs_list: ProgramsList,
+    #[serde(
+        rename(deserialize = "_links", serialize = "_links"),
+        default,
+        skip_serializing_if = "Option::is_none"
+    )]
+    pub links: Option<ProgramsListLinks>,
+}
+
+/// Model for the links of a page of programs
+#[derive(Debug, Deserialize, Serialize)]
+pub struct ProgramsListLinks {
+    /// Link to the next page, missing on the last one
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub next: Option<Link>,
 }
 
 /// Model for a list of programs
 #[derive(Debug, Deserialize, Serialize)]
 pub struct ProgramsList {
-    programs: Vec<Program>,
+    pub programs: Vec<Program>,
 }
 
 /// Model for a program and its relevant metadata
 #[derive(Debug, Deserialize, Serialize)]
 pub struct Program {
-    id: String,
-    name: String,
+    pub id: String,
+    pub name: String,
     #[serde(rename(deserialize = "tenantId", serialize = "tenantId"))]
-    tenant_id: String,
-    enabled: bool,
-    status: String,
+    pub tenant_id: String,
+    pub enabled: bool,
+    pub status: String,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub description: Option<String>,
+    /// Solutions the program is licensed for, e.g. `aem` and `commerce`
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub capabilities: Option<Vec<String>>,
+    /// Region that the environments of the program are created in by default
+    #[serde(
+        rename(deserialize = "primaryRegion", serialize = "primaryRegion"),
+        default,
+        skip_serializing_if = "Option::is_none"
+    )]
+    pub primary_region: Option<String>,
+    #[serde(
+        rename(deserialize = "_links", serialize = "_links"),
+        default,
+        skip_serializing_if = "Option::is_none"
+    )]
+    pub links: Option<ProgramLinks>,
+    /// Fields that pippo doesn't model, e.g. `createdAt`, kept so they are printed as well
+    #[serde(flatten)]
+    pub extra: HashMap<String, Value>,
+}
+
+/// Model for the links of a program that can be used to deep-link into Cloud Manager
+#[derive(Debug, Deserialize, Serialize)]
+pub struct ProgramLinks {
+    #[serde(rename(deserialize = "self", serialize = "self"), default)]
+    pub self_link: Option<Link>,
+    #[serde(
+        rename(
+            deserialize = "http://ns.adobe.com/adobecloud/rel/tenant",
+            serialize = "http://ns.adobe.com/adobecloud/rel/tenant"
+        ),
+        default
+    )]
+    pub tenant: Option<Link>,
+}
+
+/// Model for a single HAL link
+#[derive(Debug, Deserialize, Serialize)]
+pub struct Link {
+    pub href: String,
 }
 
 #[cfg(test)]
@@ -40,4 +98,64 @@ mod tests {
 
         assert_eq!(vobj.programs_list.programs.first().unwrap().id, "22222");
     }
+
+    #[test]
+    fn deserialize_program_description_and_links() {
+        let vobj: ProgramsResponse =
+            read_json_from_file("test/test_programs_full_response.json").unwrap();
+
+        let with_description = vobj.programs_list.programs.first().unwrap();
+        assert_eq!(
+            with_description.description.as_deref(),
+            Some("Program for the demo sites")
+        );
+        let links = with_description.links.as_ref().unwrap();
+        assert_eq!(links.self_link.as_ref().unwrap().href, "/api/program/22222");
+        assert_eq!(links.tenant.as_ref().unwrap().href, "/api/tenant/22222");
+
+        let without_description = vobj.programs_list.programs.get(1).unwrap();
+        assert!(without_description.description.is_none());
+        assert!(without_description.links.is_none());
+    }
+
+    #[test]
+    fn deserialize_program_capabilities_and_region() {
+        let vobj: ProgramsResponse =
+            read_json_from_file("test/test_programs_full_response.json").unwrap();
+
+        let with_metadata = vobj.programs_list.programs.first().unwrap();
+        assert_eq!(with_metadata.capabilities, Some(vec![String::from("aem")]));
+        assert_eq!(with_metadata.primary_region.as_deref(), Some("nld2"));
+        assert!(!with_metadata.extra.contains_key("primaryRegion"));
+
+        let without_metadata = vobj.programs_list.programs.get(1).unwrap();
+        assert!(without_metadata.capabilities.is_none());
+        assert!(without_metadata.primary_region.is_none());
+        let json = serde_json::to_value(without_metadata).unwrap();
+        assert!(json.get("capabilities").is_none());
+        assert!(json.get("primaryRegion").is_none());
+    }
+
+    #[test]
+    fn deserialize_single_program() {
+        let program: Program = read_json_from_file("test/test_program_response.json").unwrap();
+
+        assert_eq!(program.id, "22222");
+        assert_eq!(program.name, "Demo");
+        assert_eq!(program.tenant_id, "Demo");
+        assert!(program.enabled);
+        assert_eq!(program.status, "ready");
+        assert_eq!(
+            program.capabilities,
+            Some(vec![String::from("aem"), String::from("commerce")])
+        );
+        assert_eq!(program.primary_region.as_deref(), Some("va7"));
+        assert_eq!(program.extra["type"], "aem_cloud_service");
+        assert_eq!(program.extra["createdAt"], "2021-12-01T12:26:30.036+0000");
+        assert!(!program.extra.contains_key("tenantId"));
+        assert_eq!(
+            program.links.unwrap().self_link.unwrap().href,
+            "/api/program/22222"
+        );
+    }
 }
@@ -0,0 +1,42 @@
+use serde::{Deserialize, Serialize};
+
+// Models for representing the regions that environments of a program can be created in
+// -------------------------------------------------------------------------------------------------
+
+/// Struct that holds the response when requesting /api/program/{id}/regions
+#[derive(Debug, Deserialize, Serialize)]
+pub struct RegionsResponse {
+    #[serde(rename(deserialize = "_embedded", serialize = "_embedded"))]
+    pub regions_list: RegionsList,
+}
+
+/// Model for a list of regions
+#[derive(Debug, Deserialize, Serialize)]
+pub struct RegionsList {
+    pub regions: Vec<Region>,
+}
+
+/// Model for a region
+#[derive(Debug, Deserialize, Serialize)]
+pub struct Region {
+    pub id: String,
+    pub name: String,
+}
+
+// -------------------------------------------------------------------------------------------------
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::models::tests::read_json_from_file;
+
+    #[test]
+    fn deserialize_regions_response() {
+        let vobj: RegionsResponse = read_json_from_file("test/test_regions_response.json").unwrap();
+
+        let regions = vobj.regions_list.regions;
+        assert_eq!(regions.len(), 3);
+        assert_eq!(regions[0].id, "va7");
+        assert_eq!(regions[0].name, "East US");
+    }
+}
@@ -1,9 +1,12 @@
+use crate::schema::{object, string_enum, value, JsonSchema};
 use serde::de::Visitor;
 use serde::{de, Deserialize, Deserializer, Serialize};
+use serde_json::Value;
+use std::collections::HashMap;
 use std::fmt;
 use strum_macros::{EnumString, IntoStaticStr};
 
-/// Model for common cloud manager variables
+// Model for common cloud manager variables
 
 /// Possible types that a variable can have
 #[derive(Clone, Debug, PartialEq, Deserialize, Serialize)]
@@ -26,6 +29,9 @@ pub struct EnvironmentVariable {
         skip_serializing_if = "environment_variable_skip_serializing"
     )]
     pub service: EnvironmentVariableServiceType,
+    /// Fields that pippo doesn't know, kept so they survive a round trip
+    #[serde(flatten)]
+    pub extra: HashMap<String, Value>,
 }
 
 /// Possible service types that an environment variable can have
@@ -43,17 +49,39 @@ pub enum EnvironmentVariableServiceType {
 
 impl fmt::Display for EnvironmentVariableServiceType {
     fn fmt(&self, formatter: &mut fmt::Formatter) -> fmt::Result {
-        write!(
-            formatter,
-            "{}",
-            format!("{}", serde_json::to_string(self).unwrap().to_string())
-        )
+        write!(formatter, "{}", serde_json::to_string(self).unwrap())
     }
 }
 fn environment_variable_skip_serializing(t: &EnvironmentVariableServiceType) -> bool {
     *t == EnvironmentVariableServiceType::All
 }
 
+/// Serializes environment variables for a PATCH. Fields that pippo doesn't know are only kept for
+/// the output, they are not sent back to Cloud Manager.
+///
+/// # Arguments
+///
+/// * `variables` - The variables to serialize
+/// * `explicit_service` - Send the `service` even if it is `all`, for API versions that don't
+///   fall back to it when the field is missing
+pub fn patch_body(variables: &[EnvironmentVariable], explicit_service: bool) -> Vec<Value> {
+    variables
+        .iter()
+        .map(|variable| {
+            let mut value = serde_json::to_value(variable).unwrap();
+            if let Value::Object(fields) = &mut value {
+                for name in variable.extra.keys() {
+                    fields.remove(name);
+                }
+            }
+            if explicit_service {
+                value["service"] = Value::from(<&str>::from(&variable.service));
+            }
+            value
+        })
+        .collect()
+}
+
 impl<'de> serde::Deserialize<'de> for EnvironmentVariableServiceType {
     fn deserialize<D>(deserializer: D) -> Result<Self, D::Error>
     where
@@ -73,7 +101,7 @@ impl<'de> serde::Deserialize<'de> for EnvironmentVariableServiceType {
                 E: de::Error,
             {
                 match value {
-                    "" => Ok(EnvironmentVariableServiceType::All), // Handle empty string as `All`
+                    "" | "all" => Ok(EnvironmentVariableServiceType::All), // Handle empty string as `All`
                     "author" => Ok(EnvironmentVariableServiceType::Author),
                     "publish" => Ok(EnvironmentVariableServiceType::Publish),
                     "preview" => Ok(EnvironmentVariableServiceType::Preview),
@@ -119,11 +147,7 @@ pub enum PipelineVariableServiceType {
 
 impl fmt::Display for PipelineVariableServiceType {
     fn fmt(&self, formatter: &mut fmt::Formatter) -> fmt::Result {
-        write!(
-            formatter,
-            "{}",
-            format!("{}", serde_json::to_string(self).unwrap().to_string())
-        )
+        write!(formatter, "{}", serde_json::to_string(self).unwrap())
     }
 }
 
@@ -159,6 +183,66 @@ pub struct PipelineVariablesList {
     pub variables: Vec<PipelineVariable>,
 }
 
+impl JsonSchema for EnvironmentVariable {
+    fn json_schema() -> Value {
+        object(
+            "An environment variable",
+            vec![
+                ("name", value("string", "Name of the variable")),
+                (
+                    "value",
+                    value(
+                        "string",
+                        "Value of the variable, secrets may be encrypted as '$enc <value>'",
+                    ),
+                ),
+                ("type", VariableType::json_schema()),
+                (
+                    "service",
+                    string_enum(
+                        "Service the variable applies to, all services if omitted",
+                        &["author", "publish", "preview"],
+                    ),
+                ),
+            ],
+            &["name", "type"],
+        )
+    }
+}
+
+impl JsonSchema for PipelineVariable {
+    fn json_schema() -> Value {
+        object(
+            "A pipeline variable",
+            vec![
+                ("name", value("string", "Name of the variable")),
+                (
+                    "value",
+                    value(
+                        "string",
+                        "Value of the variable, secrets may be encrypted as '$enc <value>'",
+                    ),
+                ),
+                ("type", VariableType::json_schema()),
+                (
+                    "service",
+                    string_enum(
+                        "Pipeline step the variable applies to, build if omitted",
+                        &["build", "uiTest", "functionalTest"],
+                    ),
+                ),
+            ],
+            &["name", "type"],
+        )
+    }
+}
+
+impl JsonSchema for VariableType {
+    fn json_schema() -> Value {
+        string_enum("Type of the variable", &["string", "secretString"])
+    }
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
@@ -169,7 +253,7 @@ mod tests {
         let vobj: EnvironmentVariablesResponse =
             read_json_from_file("test/variables/environment_variables_response.json").unwrap();
 
-        let under_test: &EnvironmentVariable = vobj.variables_list.variables.get(0).unwrap();
+        let under_test: &EnvironmentVariable = vobj.variables_list.variables.first().unwrap();
         assert_eq!(under_test.service, EnvironmentVariableServiceType::All,);
         assert_eq!(under_test.name, "VARIABLE",);
         assert_eq!(
@@ -263,6 +347,7 @@ mod tests {
             variable_type: VariableType::String,
             service: EnvironmentVariableServiceType::All,
             value: Some(String::from("authorVarValue")),
+            extra: HashMap::new(),
         };
         let under_test: String = serde_json::to_string(&variable).unwrap();
         assert_eq!(
@@ -278,6 +363,7 @@ mod tests {
             variable_type: VariableType::SecretString,
             service: EnvironmentVariableServiceType::Publish,
             value: Some(String::from("publishValue")),
+            extra: HashMap::new(),
         };
         let under_test: String = serde_json::to_string(&variable).unwrap();
         assert_eq!(
@@ -293,6 +379,7 @@ mod tests {
             variable_type: VariableType::String,
             service: EnvironmentVariableServiceType::Preview,
             value: Some(String::from("previewValue")),
+            extra: HashMap::new(),
         };
         let under_test: String = serde_json::to_string(&variable).unwrap();
         assert_eq!(
@@ -300,4 +387,125 @@ mod tests {
             "{\"name\":\"previewVarName\",\"value\":\"previewValue\",\"type\":\"string\",\"service\":\"preview\"}",
         );
     }
+
+    #[test]
+    fn serialize_all_service_environment_variable_explicitly() {
+        let variables = vec![
+            EnvironmentVariable {
+                name: String::from("allVarName"),
+                variable_type: VariableType::String,
+                service: EnvironmentVariableServiceType::All,
+                value: Some(String::from("allVarValue")),
+                extra: HashMap::new(),
+            },
+            EnvironmentVariable {
+                name: String::from("authorVarName"),
+                variable_type: VariableType::String,
+                service: EnvironmentVariableServiceType::Author,
+                value: None,
+                extra: HashMap::new(),
+            },
+        ];
+        assert_eq!(
+            serde_json::to_string(&variables).unwrap(),
+            "[{\"name\":\"allVarName\",\"value\":\"allVarValue\",\"type\":\"string\"},{\"name\":\"authorVarName\",\"type\":\"string\",\"service\":\"author\"}]",
+        );
+        assert_eq!(
+            serde_json::to_string(&patch_body(&variables, true)).unwrap(),
+            "[{\"name\":\"allVarName\",\"service\":\"all\",\"type\":\"string\",\"value\":\"allVarValue\"},{\"name\":\"authorVarName\",\"service\":\"author\",\"type\":\"string\"}]",
+        );
+
+        // The explicit service reads back as the default
+        let explicit: Vec<EnvironmentVariable> =
+            serde_json::from_value(Value::from(patch_body(&variables, true))).unwrap();
+        assert_eq!(explicit[0].service, EnvironmentVariableServiceType::All);
+    }
+
+    #[test]
+    fn unknown_fields_of_environment_variable_survive_a_round_trip() {
+        let input = r#"{"name":"VARIABLE","type":"string","status":"ready","tags":["a","b"]}"#;
+        let variable: EnvironmentVariable = serde_json::from_str(input).unwrap();
+        assert_eq!(variable.service, EnvironmentVariableServiceType::All);
+        assert_eq!(variable.extra.len(), 2);
+        assert_eq!(variable.extra["status"], "ready");
+
+        // Service "all" and a missing value are still left out
+        let output: Value = serde_json::to_value(&variable).unwrap();
+        assert_eq!(output, serde_json::from_str::<Value>(input).unwrap());
+    }
+
+    #[test]
+    fn unknown_fields_of_environment_variable_are_not_patched() {
+        let input = r#"{"name":"VARIABLE","type":"string","service":"author","status":"ready"}"#;
+        let variable: EnvironmentVariable = serde_json::from_str(input).unwrap();
+        assert_eq!(
+            Value::from(patch_body(&[variable], false)),
+            serde_json::json!([{"name": "VARIABLE", "type": "string", "service": "author"}])
+        );
+    }
+
+    #[test]
+    fn known_fields_are_not_captured_as_unknown() {
+        let vobj: EnvironmentVariablesResponse =
+            read_json_from_file("test/variables/environment_variables_response.json").unwrap();
+        for variable in &vobj.variables_list.variables {
+            for key in ["name", "value", "type", "service"] {
+                assert!(!variable.extra.contains_key(key));
+            }
+        }
+    }
+
+    #[test]
+    fn deserialize_service_of_pipeline_variables() {
+        let vobj: PipelineVariablesResponse =
+            read_json_from_file("test/variables/pipeline_variables_response.json").unwrap();
+        let services: Vec<(&str, &PipelineVariableServiceType)> = vobj
+            .variables_list
+            .variables
+            .iter()
+            .map(|v| (v.name.as_str(), &v.service))
+            .collect();
+        assert_eq!(
+            services,
+            vec![
+                ("DEFAULT_VARIABLE", &PipelineVariableServiceType::Build),
+                ("VARIABLE", &PipelineVariableServiceType::Build),
+                ("VARIABLE", &PipelineVariableServiceType::UiTest),
+                (
+                    "SECRET_VARIABLE",
+                    &PipelineVariableServiceType::FunctionalTest
+                ),
+                (
+                    "INVALID_SERVICE_VARIABLE",
+                    &PipelineVariableServiceType::Invalid
+                ),
+            ]
+        );
+        assert_eq!(
+            vobj.variables_list.variables[2].value.as_deref(),
+            Some("ui test variable")
+        );
+    }
+
+    #[test]
+    fn serialize_service_of_pipeline_variables() {
+        let variable = |service: PipelineVariableServiceType| PipelineVariable {
+            name: String::from("VARIABLE"),
+            value: Some(String::from("value")),
+            variable_type: VariableType::String,
+            service,
+        };
+        assert_eq!(
+            serde_json::to_string(&variable(PipelineVariableServiceType::Build)).unwrap(),
+            "{\"name\":\"VARIABLE\",\"value\":\"value\",\"type\":\"string\",\"service\":\"build\"}"
+        );
+        assert_eq!(
+            serde_json::to_string(&variable(PipelineVariableServiceType::UiTest)).unwrap(),
+            "{\"name\":\"VARIABLE\",\"value\":\"value\",\"type\":\"string\",\"service\":\"uiTest\"}"
+        );
+        assert_eq!(
+            serde_json::to_string(&variable(PipelineVariableServiceType::FunctionalTest)).unwrap(),
+            "{\"name\":\"VARIABLE\",\"value\":\"value\",\"type\":\"string\",\"service\":\"functionalTest\"}"
+        );
+    }
 }
@@ -0,0 +1,483 @@
+use chrono::{DateTime, FixedOffset};
+use serde::Serialize;
+use serde_json::Value;
+use std::cmp::Ordering;
+use std::collections::BTreeSet;
+use std::str::FromStr;
+
+/// Prefix of the output format that prints one line per item, e.g. `template:{id}\t{name}`
+const TEMPLATE_PREFIX: &str = "template:";
+
+/// Possible formats for the output of pippo commands
+#[derive(Clone, Debug, PartialEq)]
+pub enum OutputFormat {
+    /// Machine-readable JSON
+    Json,
+    /// Human-readable table or text
+    Table,
+    /// One line per item, with `{field}` placeholders replaced by the fields of the item
+    Template(String),
+}
+
+impl FromStr for OutputFormat {
+    type Err = String;
+
+    fn from_str(input: &str) -> Result<Self, Self::Err> {
+        match input {
+            "json" => Ok(OutputFormat::Json),
+            "table" | "text" => Ok(OutputFormat::Table),
+            _ if input.starts_with(TEMPLATE_PREFIX) => {
+                let template = &input[TEMPLATE_PREFIX.len()..];
+                parse_template(template)?;
+                Ok(OutputFormat::Template(template.to_string()))
+            }
+            _ => Err(format!(
+                "unknown output format '{}', possible values: json, table, template:<template>",
+                input
+            )),
+        }
+    }
+}
+
+/// A part of an output template
+#[derive(Debug, PartialEq)]
+enum TemplatePart {
+    Text(String),
+    /// Path to a field, e.g. `["links", "self"]` for `{links.self}`
+    Field(Vec<String>),
+}
+
+/// Splits an output template into text and `{field}` placeholders.
+///
+/// `{{` and `}}` stand for literal braces, `\t` and `\n` for a tab and a line break, so templates
+/// don't need shell quoting tricks.
+///
+/// # Arguments
+///
+/// * `template` - String slice that holds the template, e.g. `{id}\t{name}`
+fn parse_template(template: &str) -> Result<Vec<TemplatePart>, String> {
+    let mut parts = vec![];
+    let mut text = String::new();
+    let mut chars = template.chars().peekable();
+    while let Some(c) = chars.next() {
+        match (c, chars.peek()) {
+            ('{', Some('{')) | ('}', Some('}')) => {
+                chars.next();
+                text.push(c);
+            }
+            ('\\', Some('t')) => {
+                chars.next();
+                text.push('\t');
+            }
+            ('\\', Some('n')) => {
+                chars.next();
+                text.push('\n');
+            }
+            ('{', _) => {
+                let mut field = String::new();
+                loop {
+                    match chars.next() {
+                        Some('}') => break,
+                        Some(c) => field.push(c),
+                        None => return Err(format!("unclosed '{{{}' in output template", field)),
+                    }
+                }
+                if field.trim().is_empty() || field.contains('{') {
+                    return Err(format!(
+                        "invalid placeholder '{{{}}}' in output template",
+                        field
+                    ));
+                }
+                if !text.is_empty() {
+                    parts.push(TemplatePart::Text(std::mem::take(&mut text)));
+                }
+                parts.push(TemplatePart::Field(
+                    field.trim().split('.').map(String::from).collect(),
+                ));
+            }
+            ('}', _) => return Err(String::from("unmatched '}' in output template, use '}}'")),
+            _ => text.push(c),
+        }
+    }
+    if !text.is_empty() {
+        parts.push(TemplatePart::Text(text));
+    }
+    Ok(parts)
+}
+
+/// Renders one line per item, replacing the `{field}` placeholders of the template with the
+/// serialized fields of the item.
+///
+/// Fails if a placeholder names a field that none of the items has. A field that only some items
+/// have, e.g. an optional one, renders as an empty string for the others, and so does `null`.
+///
+/// # Arguments
+///
+/// * `template` - String slice that holds the template, e.g. `{id}\t{name}\t{status}`
+/// * `items` - The items to render
+pub fn render_template<T: Serialize>(template: &str, items: &[T]) -> Result<Vec<String>, String> {
+    let parts = parse_template(template)?;
+    let items: Vec<Value> = items
+        .iter()
+        .map(|item| serde_json::to_value(item).map_err(|err| err.to_string()))
+        .collect::<Result<_, _>>()?;
+    if !items.is_empty() {
+        for part in &parts {
+            if let TemplatePart::Field(path) = part {
+                if items.iter().all(|item| lookup(item, path).is_none()) {
+                    let fields: BTreeSet<&String> = items
+                        .iter()
+                        .filter_map(Value::as_object)
+                        .flat_map(|object| object.keys())
+                        .collect();
+                    return Err(format!(
+                        "unknown field '{}' in output template, available fields: {}",
+                        path.join("."),
+                        fields
+                            .into_iter()
+                            .map(String::as_str)
+                            .collect::<Vec<_>>()
+                            .join(", ")
+                    ));
+                }
+            }
+        }
+    }
+    Ok(items
+        .iter()
+        .map(|item| {
+            parts
+                .iter()
+                .map(|part| match part {
+                    TemplatePart::Text(text) => text.clone(),
+                    TemplatePart::Field(path) => match lookup(item, path) {
+                        None | Some(Value::Null) => String::new(),
+                        Some(Value::String(value)) => value.clone(),
+                        Some(value) => value.to_string(),
+                    },
+                })
+                .collect()
+        })
+        .collect())
+}
+
+/// Follows a path of field names into a JSON value.
+fn lookup<'a>(value: &'a Value, path: &[String]) -> Option<&'a Value> {
+    path.iter().try_fold(value, |value, field| value.get(field))
+}
+
+/// Field that the items of list commands are sorted by, e.g. `name` or `-createdAt` for
+/// descending order
+#[derive(Clone, Debug, PartialEq)]
+pub struct SortKey {
+    /// Path to the field, e.g. `["links", "self"]` for `links.self`
+    path: Vec<String>,
+    descending: bool,
+}
+
+impl FromStr for SortKey {
+    type Err = String;
+
+    fn from_str(input: &str) -> Result<Self, Self::Err> {
+        let (descending, field) = match input.strip_prefix('-') {
+            Some(field) => (true, field),
+            None => (false, input),
+        };
+        let path: Vec<String> = field.split('.').map(String::from).collect();
+        if path.iter().any(|part| part.trim().is_empty()) {
+            return Err(format!("invalid sort field '{}'", input));
+        }
+        Ok(SortKey { path, descending })
+    }
+}
+
+/// Sorts items by a field of their serialized form, keeping the order of items with equal values.
+///
+/// If all values are numbers, also numeric strings like IDs, they are compared by value, if all are
+/// timestamps by time, otherwise case-insensitively as text. Items without the field or with `null` come last in both
+/// directions. Field names are the ones of the JSON output, `created_at` also finds `createdAt`.
+/// Fails if none of the items has the field.
+///
+/// # Arguments
+///
+/// * `items` - The items to sort
+/// * `key` - The field to sort by
+pub fn sort_items<T: Serialize>(items: &mut Vec<T>, key: &SortKey) -> Result<(), String> {
+    let values: Vec<Value> = items
+        .iter()
+        .map(|item| serde_json::to_value(item).map_err(|err| err.to_string()))
+        .collect::<Result<_, _>>()?;
+    let keys: Vec<Option<Value>> = values
+        .iter()
+        .map(|value| {
+            sort_lookup(value, &key.path)
+                .filter(|v| !v.is_null())
+                .cloned()
+        })
+        .collect();
+    if !items.is_empty() && values.iter().all(|v| sort_lookup(v, &key.path).is_none()) {
+        let fields: BTreeSet<&String> = values
+            .iter()
+            .filter_map(Value::as_object)
+            .flat_map(|object| object.keys())
+            .collect();
+        return Err(format!(
+            "unknown sort field '{}', available fields: {}",
+            key.path.join("."),
+            fields
+                .into_iter()
+                .map(String::as_str)
+                .collect::<Vec<_>>()
+                .join(", ")
+        ));
+    }
+    let mut keyed: Vec<(Option<SortValue>, T)> =
+        sort_values(keys).into_iter().zip(items.drain(..)).collect();
+    keyed.sort_by(|(a, _), (b, _)| match (a, b) {
+        (Some(a), Some(b)) if key.descending => b.compare(a),
+        (Some(a), Some(b)) => a.compare(b),
+        (Some(_), None) => Ordering::Less,
+        (None, Some(_)) => Ordering::Greater,
+        (None, None) => Ordering::Equal,
+    });
+    items.extend(keyed.into_iter().map(|(_, item)| item));
+    Ok(())
+}
+
+/// Like [`lookup`], but also finds camelCase fields by their snake_case name.
+fn sort_lookup<'a>(value: &'a Value, path: &[String]) -> Option<&'a Value> {
+    path.iter().try_fold(value, |value, field| {
+        value.get(field).or_else(|| value.get(camel_case(field)))
+    })
+}
+
+fn camel_case(field: &str) -> String {
+    let mut parts = field.split('_');
+    let mut camel = parts.next().unwrap_or_default().to_string();
+    for part in parts {
+        let mut chars = part.chars();
+        if let Some(first) = chars.next() {
+            camel.extend(first.to_uppercase());
+            camel.push_str(chars.as_str());
+        }
+    }
+    camel
+}
+
+/// A value of the sort field, converted the same way for all items
+enum SortValue {
+    Number(f64),
+    Time(DateTime<FixedOffset>),
+    Bool(bool),
+    /// Strings as they are, other values as JSON
+    Text(String),
+}
+
+impl SortValue {
+    /// Compares two values of the same column. All values of a column have the same variant, see
+    /// [`sort_values`], so this is a total order.
+    fn compare(&self, other: &SortValue) -> Ordering {
+        match (self, other) {
+            (SortValue::Number(a), SortValue::Number(b)) => a.total_cmp(b),
+            (SortValue::Time(a), SortValue::Time(b)) => a.cmp(b),
+            (SortValue::Bool(a), SortValue::Bool(b)) => a.cmp(b),
+            (SortValue::Text(a), SortValue::Text(b)) => a
+                .to_lowercase()
+                .cmp(&b.to_lowercase())
+                .then_with(|| a.cmp(b)),
+            (a, b) => a.rank().cmp(&b.rank()),
+        }
+    }
+
+    fn rank(&self) -> u8 {
+        match self {
+            SortValue::Number(_) => 0,
+            SortValue::Time(_) => 1,
+            SortValue::Bool(_) => 2,
+            SortValue::Text(_) => 3,
+        }
+    }
+}
+
+/// Decides once for the whole column how its values are compared: as numbers if all of them are
+/// numbers or numeric strings, as times if all are timestamps, as booleans if all are booleans and
+/// as text otherwise. Comparing each pair on its own would not be a total order, e.g. with "2",
+/// "10" and "1a".
+///
+/// # Arguments
+///
+/// * `values` - The values of the sort field, `None` for items without it
+fn sort_values(values: Vec<Option<Value>>) -> Vec<Option<SortValue>> {
+    let present = || values.iter().flatten();
+    let number = |value: &Value| match value {
+        Value::Number(number) => number.as_f64(),
+        Value::String(text) => text.parse::<f64>().ok().filter(|number| !number.is_nan()),
+        _ => None,
+    };
+    let time = |value: &Value| value.as_str().and_then(parse_timestamp);
+    let convert: Box<dyn Fn(Value) -> SortValue> = if present().all(|v| number(v).is_some()) {
+        Box::new(|value| SortValue::Number(number(&value).unwrap_or_default()))
+    } else if present().all(|v| time(v).is_some()) {
+        Box::new(|value| SortValue::Time(time(&value).unwrap()))
+    } else if present().all(Value::is_boolean) {
+        Box::new(|value| SortValue::Bool(value.as_bool().unwrap_or_default()))
+    } else {
+        Box::new(|value| match value {
+            Value::String(text) => SortValue::Text(text),
+            value => SortValue::Text(value.to_string()),
+        })
+    };
+    values
+        .into_iter()
+        .map(|value| value.map(&convert))
+        .collect()
+}
+
+/// Parses the timestamps of Cloud Manager, e.g. `2025-07-01T23:59:59.000+0000`, and RFC 3339.
+fn parse_timestamp(value: &str) -> Option<DateTime<FixedOffset>> {
+    DateTime::parse_from_rfc3339(value)
+        .or_else(|_| DateTime::parse_from_str(value, "%Y-%m-%dT%H:%M:%S%.f%z"))
+        .ok()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parse_output_format() {
+        assert_eq!(OutputFormat::from_str("json"), Ok(OutputFormat::Json));
+        assert_eq!(OutputFormat::from_str("table"), Ok(OutputFormat::Table));
+        assert_eq!(OutputFormat::from_str("text"), Ok(OutputFormat::Table));
+        assert!(OutputFormat::from_str("xml").is_err());
+        assert_eq!(
+            OutputFormat::from_str("template:{id}\\t{name}"),
+            Ok(OutputFormat::Template(String::from("{id}\\t{name}")))
+        );
+        assert!(OutputFormat::from_str("template:{id").is_err());
+    }
+
+    #[derive(Serialize)]
+    struct Item {
+        id: String,
+        name: String,
+        #[serde(skip_serializing_if = "Option::is_none")]
+        region: Option<String>,
+        enabled: bool,
+        links: Value,
+    }
+
+    fn items() -> Vec<Item> {
+        vec![
+            Item {
+                id: String::from("22222"),
+                name: String::from("Demo"),
+                region: Some(String::from("va7")),
+                enabled: true,
+                links: serde_json::json!({"self": {"href": "/api/program/22222"}}),
+            },
+            Item {
+                id: String::from("33333"),
+                name: String::from("Intranet"),
+                region: None,
+                enabled: false,
+                links: Value::Null,
+            },
+        ]
+    }
+
+    #[test]
+    fn render_items_with_template() {
+        assert_eq!(
+            render_template("{id}\\t{name}\\t{enabled}", &items()),
+            Ok(vec![
+                String::from("22222\tDemo\ttrue"),
+                String::from("33333\tIntranet\tfalse")
+            ])
+        );
+        assert_eq!(
+            render_template("{{{ name }}}: {region} {links.self.href}", &items()),
+            Ok(vec![
+                String::from("{Demo}: va7 /api/program/22222"),
+                String::from("{Intranet}:  ")
+            ])
+        );
+    }
+
+    #[test]
+    fn unknown_template_fields_are_rejected() {
+        assert_eq!(
+            render_template("{id} {status}", &items()),
+            Err(String::from(
+                "unknown field 'status' in output template, available fields: enabled, id, links, name, region"
+            ))
+        );
+        assert_eq!(
+            render_template("{id}}", &items()),
+            Err(String::from("unmatched '}' in output template, use '}}'"))
+        );
+        assert_eq!(render_template("{status}", &Vec::<Item>::new()), Ok(vec![]));
+    }
+
+    fn sorted(items: &[Value], key: &str) -> Vec<String> {
+        let mut items = items.to_vec();
+        sort_items(&mut items, &SortKey::from_str(key).unwrap()).unwrap();
+        items.iter().map(|item| item["id"].to_string()).collect()
+    }
+
+    #[test]
+    fn sort_items_by_type() {
+        let items = vec![
+            serde_json::json!({"id": 1, "name": "beta", "programId": "100", "createdAt": "2025-07-02T01:00:00.000+0200"}),
+            serde_json::json!({"id": 2, "name": "Alpha", "programId": "20", "createdAt": "2025-07-01T23:30:00.000+0000"}),
+            serde_json::json!({"id": 3, "name": "gamma", "programId": "3"}),
+        ];
+        assert_eq!(sorted(&items, "name"), vec!["2", "1", "3"]);
+        assert_eq!(sorted(&items, "-name"), vec!["3", "1", "2"]);
+        // Numeric strings are compared by value
+        assert_eq!(sorted(&items, "programId"), vec!["3", "2", "1"]);
+        // Timestamps by time, items without one come last in both directions
+        assert_eq!(sorted(&items, "created_at"), vec!["1", "2", "3"]);
+        assert_eq!(sorted(&items, "-createdAt"), vec!["2", "1", "3"]);
+        assert_eq!(sorted(&items, "-id"), vec!["3", "2", "1"]);
+    }
+
+    #[test]
+    fn sort_mixed_values_as_text() {
+        // Pairwise, "2" < "10" as numbers, "10" < "1a" and "1a" < "2" as text
+        let items: Vec<Value> = ["2", "10", "1a", "b", "10", "2"]
+            .iter()
+            .enumerate()
+            .map(|(id, name)| serde_json::json!({"id": id, "name": name}))
+            .collect();
+        assert_eq!(sorted(&items, "name"), vec!["1", "4", "2", "0", "5", "3"]);
+
+        let many: Vec<Value> = (0..100)
+            .map(|id| {
+                let name = match id % 4 {
+                    0 => serde_json::json!(id),
+                    1 => serde_json::json!(format!("{}a", id)),
+                    2 => serde_json::json!(null),
+                    _ => serde_json::json!("2025-07-01T23:30:00.000+0000"),
+                };
+                serde_json::json!({"id": id, "name": name})
+            })
+            .collect();
+        assert_eq!(sorted(&many, "-name").len(), 100);
+    }
+
+    #[test]
+    fn sort_items_keeps_order_of_equal_values() {
+        let mut list = items();
+        sort_items(&mut list, &SortKey::from_str("-links.self.href").unwrap()).unwrap();
+        assert_eq!(list[0].id, "22222");
+        assert_eq!(
+            sort_items(&mut list, &SortKey::from_str("status").unwrap()),
+            Err(String::from(
+                "unknown sort field 'status', available fields: enabled, id, links, name, region"
+            ))
+        );
+        assert!(SortKey::from_str("-").is_err());
+        assert!(SortKey::from_str("links.").is_err());
+    }
+}
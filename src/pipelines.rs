@@ -1,10 +1,11 @@
+use crate::audit;
 use crate::client::{AdobeConnector, CloudManagerClient};
-use crate::errors::throw_adobe_api_error;
+use crate::errors::{read_error_body, throw_adobe_api_error};
+use crate::execution::get_executions;
+use crate::models::environment::EnvironmentId;
 use crate::models::execution::Execution;
-use crate::models::pipeline::{Pipeline, PipelinesList, PipelinesResponse};
-use crate::HOST_NAME;
+use crate::models::pipeline::{EnvironmentPipeline, Pipeline, PipelinesList, PipelinesResponse};
 use reqwest::{Error, Method, StatusCode};
-use std::process;
 use std::thread::sleep;
 use std::time::Duration;
 
@@ -28,16 +29,17 @@ pub async fn get_pipeline(
 ) -> Result<Pipeline, Error> {
     let request_path = format!(
         "{}/api/program/{}/pipeline/{}",
-        HOST_NAME, program_id, pipeline_id
+        client.host_name, program_id, pipeline_id
     );
     let response = client
         .perform_request(Method::GET, request_path, None::<()>, None)
         .await?
         .text()
         .await?;
+    client.print_raw_response(&response);
     let pipeline: Pipeline = serde_json::from_str(response.as_str()).unwrap_or_else(|_| {
         throw_adobe_api_error(response);
-        process::exit(1);
+        audit::exit(1);
     });
     Ok(pipeline)
 }
@@ -71,7 +73,7 @@ pub async fn run_pipeline(
                 "{:>8} Skipped! This pipeline is currently busy and and ci mode (--ci) is active.",
                 "⚠️",
             );
-            process::exit(1);
+            audit::exit(1);
         } else if pipeline.status == "BUSY" {
             eprintln!(
                 "{:>8} This pipeline is currently busy. Retrying in 1 minute...",
@@ -81,7 +83,7 @@ pub async fn run_pipeline(
         } else {
             let request_path = format!(
                 "{}/api/program/{}/pipeline/{}/execution",
-                HOST_NAME, program_id, pipeline_id
+                client.host_name, program_id, pipeline_id
             );
             let response = client
                 .perform_request(Method::PUT, request_path, None::<()>, None)
@@ -91,7 +93,7 @@ pub async fn run_pipeline(
 
             execution = serde_json::from_str(response.as_str()).unwrap_or_else(|_| {
                 throw_adobe_api_error(response);
-                process::exit(1);
+                audit::exit(1);
             });
             break '_retry;
         }
@@ -127,7 +129,7 @@ pub async fn invalidate_pipeline_cache(
                 "{:>8} Skipped! This pipeline is currently busy and and ci mode (--ci) is active.",
                 "⚠️",
             );
-            process::exit(1);
+            audit::exit(1);
         } else if pipeline.status == "BUSY" {
             eprintln!(
                 "{:>8} This pipeline is currently busy. Retrying in 1 minute...",
@@ -137,20 +139,18 @@ pub async fn invalidate_pipeline_cache(
         } else {
             let request_path = format!(
                 "{}/api/program/{}/pipeline/{}/cache",
-                HOST_NAME, program_id, pipeline_id
+                client.host_name, program_id, pipeline_id
             );
             let response = client
                 .perform_request(Method::DELETE, request_path, None::<()>, None)
                 .await
                 .unwrap();
 
-            let status_code = response.status();
-            let response_text = response.text().await;
-            if status_code == StatusCode::NO_CONTENT {
+            if response.status() == StatusCode::NO_CONTENT {
                 println!("{:>8} Cache of {:?} invalidated", "✍", pipeline_id);
             } else {
-                throw_adobe_api_error(response_text.unwrap().clone());
-                process::exit(1);
+                throw_adobe_api_error(read_error_body(response).await.unwrap());
+                audit::exit(1);
             }
             break '_retry;
         }
@@ -172,17 +172,168 @@ pub async fn get_pipelines(
     client: &mut CloudManagerClient,
     program_id: u32,
 ) -> Result<PipelinesList, Error> {
-    let request_path = format!("{}/api/program/{}/pipelines", HOST_NAME, program_id);
+    let request_path = format!("{}/api/program/{}/pipelines", client.host_name, program_id);
     let response = client
         .perform_request(Method::GET, request_path, None::<()>, None)
-        .await?
-        .text()
         .await?;
+    let response = match client.read_list_response(response).await? {
+        Some(response) => response,
+        None => return Ok(PipelinesList { pipelines: vec![] }),
+    };
+    client.print_raw_response(&response);
     let pipelines: PipelinesResponse =
         serde_json::from_str(response.as_str()).unwrap_or_else(|_| {
             throw_adobe_api_error(response);
-            process::exit(1);
+            audit::exit(1);
         });
 
     Ok(pipelines.pipelines_list)
 }
+
+/// Retrieves all pipelines that deploy to the given environment together with the status of
+/// their last execution.
+///
+/// # Arguments
+///
+/// * `client` - A mutable reference to a CloudManagerClient instance
+/// * `program_id` - A u32 that holds the program ID
+/// * `env_id` - An EnvironmentId that holds the environment ID
+pub async fn get_environment_pipelines(
+    client: &mut CloudManagerClient,
+    program_id: u32,
+    env_id: EnvironmentId,
+) -> Result<Vec<EnvironmentPipeline>, Error> {
+    let pipelines = get_pipelines(client, program_id).await?;
+    let mut environment_pipelines = vec![];
+    for pipeline in pipelines_deploying_to(&pipelines.pipelines, env_id) {
+        let pipeline_id = pipeline.id.parse().expect("Pipeline IDs are numeric");
+        let executions = get_executions(client, program_id, pipeline_id).await?;
+        environment_pipelines.push(EnvironmentPipeline {
+            id: pipeline.id.clone(),
+            name: pipeline.name.clone(),
+            pipeline_type: pipeline.pipeline_type.clone(),
+            status: pipeline.status.clone(),
+            last_execution_status: executions.list.first().map(|e| e.status.clone()),
+        });
+    }
+    Ok(environment_pipelines)
+}
+
+/// Filters pipelines as requested by `pipeline list`.
+///
+/// # Arguments
+///
+/// * `pipelines` - The pipelines of a program
+/// * `status` - Only keep pipelines with this status, e.g. `BUSY` (case-insensitive)
+/// * `pipeline_type` - Only keep pipelines of this type, e.g. `CI_CD` (case-insensitive)
+/// * `include_disabled` - Whether disabled pipelines are kept
+pub fn filter_pipelines(
+    pipelines: Vec<Pipeline>,
+    status: Option<&str>,
+    pipeline_type: Option<&str>,
+    include_disabled: bool,
+) -> Vec<Pipeline> {
+    pipelines
+        .into_iter()
+        .filter(|pipeline| include_disabled || pipeline.is_enabled())
+        .filter(|pipeline| status.is_none_or(|status| pipeline.status.eq_ignore_ascii_case(status)))
+        .filter(|pipeline| {
+            pipeline_type.is_none_or(|wanted| {
+                pipeline
+                    .pipeline_type
+                    .as_deref()
+                    .is_some_and(|t| t.eq_ignore_ascii_case(wanted))
+            })
+        })
+        .collect()
+}
+
+/// Returns the pipelines that have a deploy phase targeting the given environment.
+///
+/// # Arguments
+///
+/// * `pipelines` - The pipelines of a program
+/// * `env_id` - An EnvironmentId that holds the environment ID
+pub fn pipelines_deploying_to(pipelines: &[Pipeline], env_id: EnvironmentId) -> Vec<&Pipeline> {
+    pipelines
+        .iter()
+        .filter(|pipeline| {
+            pipeline
+                .phases
+                .iter()
+                .any(|phase| phase.phase_type == "DEPLOY" && phase.environment_id == Some(env_id))
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::models::tests::read_json_from_file;
+
+    fn disabled_fixture_pipelines() -> Vec<Pipeline> {
+        let response: PipelinesResponse =
+            read_json_from_file("test/test_pipeline_disabled_response.json").unwrap();
+        response.pipelines_list.pipelines
+    }
+
+    fn ids(pipelines: &[Pipeline]) -> Vec<&str> {
+        pipelines.iter().map(|p| p.id.as_str()).collect()
+    }
+
+    #[test]
+    fn filter_pipelines_hides_disabled_ones() {
+        let pipelines = filter_pipelines(disabled_fixture_pipelines(), None, None, false);
+        assert_eq!(ids(&pipelines), vec!["66666666", "88888888"]);
+
+        let pipelines = filter_pipelines(disabled_fixture_pipelines(), None, None, true);
+        assert_eq!(ids(&pipelines), vec!["66666666", "77777777", "88888888"]);
+    }
+
+    #[test]
+    fn filter_pipelines_by_status_and_type() {
+        let pipelines = filter_pipelines(disabled_fixture_pipelines(), Some("idle"), None, true);
+        assert_eq!(ids(&pipelines), vec!["77777777", "88888888"]);
+
+        let pipelines = filter_pipelines(
+            disabled_fixture_pipelines(),
+            Some("IDLE"),
+            Some("ci_cd"),
+            true,
+        );
+        assert_eq!(ids(&pipelines), vec!["77777777"]);
+
+        let pipelines =
+            filter_pipelines(disabled_fixture_pipelines(), None, Some("WEB_TIER"), true);
+        assert!(pipelines.is_empty());
+    }
+
+    fn fixture_pipelines() -> Vec<Pipeline> {
+        let response: PipelinesResponse =
+            read_json_from_file("test/test_pipeline_response.json").unwrap();
+        response.pipelines_list.pipelines
+    }
+
+    #[test]
+    fn pipelines_deploying_to_environment() {
+        let pipelines = fixture_pipelines();
+
+        let dev: Vec<&str> = pipelines_deploying_to(&pipelines, EnvironmentId(22222))
+            .iter()
+            .map(|p| p.pipeline_type.as_deref().unwrap())
+            .collect();
+        assert_eq!(dev, vec!["CI_CD", "WEB_TIER"]);
+
+        // Pipelines with several deploy phases to the same environment are listed once
+        assert_eq!(
+            pipelines_deploying_to(&pipelines, EnvironmentId(333333)).len(),
+            2
+        );
+    }
+
+    #[test]
+    fn pipelines_deploying_to_unknown_environment() {
+        let pipelines = fixture_pipelines();
+        assert!(pipelines_deploying_to(&pipelines, EnvironmentId(1)).is_empty());
+    }
+}
@@ -1,32 +1,508 @@
+use crate::audit;
 use crate::client::{AdobeConnector, CloudManagerClient};
-use crate::errors::throw_adobe_api_error;
-use crate::models::program::{ProgramsList, ProgramsResponse};
-use crate::HOST_NAME;
-use reqwest::{Error, Method};
-use std::process;
+use crate::errors::{report_api_error, throw_adobe_api_error};
+use crate::models::config::YamlConfig;
+use crate::models::program::{Program, ProgramsList, ProgramsResponse};
+use crate::resolve::{resolve_by_name, ResolveError};
+use async_trait::async_trait;
+use colored::Colorize;
+use reqwest::{Error, Method, StatusCode};
+use std::collections::{BTreeMap, HashSet};
+use std::sync::Mutex;
 
-/// Retrieves all programs.
+/// Retrieves all programs, following the `next` links of the paginated list.
 ///
 /// # Arguments
 ///
 /// * `client` - A mutable reference to a CloudManagerClient instance
+/// * `limit` - Stop after this many programs instead of fetching all pages
 ///
 /// # Performed API Request
 ///
 /// ```
 /// GET https://cloudmanager.adobe.io/api/programs
 /// ```
-pub async fn get_programs(client: &mut CloudManagerClient) -> Result<ProgramsList, Error> {
-    let request_path = format!("{}/api/programs", HOST_NAME);
+pub async fn get_programs(
+    client: &mut CloudManagerClient,
+    limit: Option<usize>,
+) -> Result<ProgramsList, Error> {
+    let host_name = client.host_name.clone();
+    collect_programs(client, &host_name, limit).await
+}
+
+/// Fetches single pages of the program list
+#[async_trait]
+pub trait ProgramPages {
+    /// Fetches the page of programs behind the given URL
+    async fn fetch_page(&mut self, url: String) -> Result<ProgramsResponse, Error>;
+}
+
+#[async_trait]
+impl ProgramPages for CloudManagerClient {
+    async fn fetch_page(&mut self, url: String) -> Result<ProgramsResponse, Error> {
+        let response = self
+            .perform_request(Method::GET, url, None::<()>, None)
+            .await?
+            .text()
+            .await?;
+        self.print_raw_response(&response);
+        Ok(serde_json::from_str(response.as_str()).unwrap_or_else(|_| {
+            throw_adobe_api_error(response);
+            audit::exit(1);
+        }))
+    }
+}
+
+/// Collects the programs of all pages, starting at the first page of /api/programs.
+///
+/// A page that links back to an already fetched page ends the list, so a broken `next` link
+/// can't loop forever.
+///
+/// # Arguments
+///
+/// * `pages` - Source of the pages
+/// * `host_name` - Scheme and host of the API, which links that are only a path are relative to
+/// * `limit` - Stop after this many programs instead of fetching all pages
+pub async fn collect_programs<P>(
+    pages: &mut P,
+    host_name: &str,
+    limit: Option<usize>,
+) -> Result<ProgramsList, Error>
+where
+    P: ProgramPages + Send,
+{
+    let mut programs = vec![];
+    let mut fetched = HashSet::new();
+    let mut next = Some(format!("{}/api/programs", host_name));
+    while let Some(url) = next.take() {
+        if limit.is_some_and(|limit| programs.len() >= limit) || !fetched.insert(url.clone()) {
+            break;
+        }
+        let page = pages.fetch_page(url).await?;
+        programs.extend(page.programs_list.programs);
+        next = page
+            .links
+            .and_then(|links| links.next)
+            .map(|link| absolute_url(host_name, &link.href));
+    }
+    if let Some(limit) = limit {
+        programs.truncate(limit);
+    }
+    Ok(ProgramsList { programs })
+}
+
+/// Turns the link to another page, which Cloud Manager usually sends as a path, into a URL.
+fn absolute_url(host_name: &str, href: &str) -> String {
+    if href.starts_with("http://") || href.starts_with("https://") {
+        href.to_string()
+    } else {
+        format!("{}{}", host_name, href)
+    }
+}
+
+/// Resolves the ID of a program by its name.
+///
+/// # Arguments
+///
+/// * `client` - A mutable reference to a CloudManagerClient instance
+/// * `program_name` - String slice that holds the name of the program (case-insensitive)
+pub async fn resolve_program_id(
+    client: &mut CloudManagerClient,
+    program_name: &str,
+) -> Result<u32, ResolveError> {
+    let programs = get_programs(client, None).await?;
+    find_program_id(&programs, program_name)
+}
+
+/// Finds the ID of the program with the given name in a list of programs.
+fn find_program_id(programs: &ProgramsList, program_name: &str) -> Result<u32, ResolveError> {
+    let program = resolve_by_name(&programs.programs, program_name, |p| p.name.as_str())?;
+    Ok(program
+        .id
+        .parse()
+        .expect("Cloud Manager program IDs are numeric"))
+}
+
+/// Program IDs resolved from names in YAML configs during this run, so that a config that is read
+/// several times (e.g. by `apply`) is resolved only once
+static RESOLVED_PROGRAM_IDS: Mutex<BTreeMap<String, u32>> = Mutex::new(BTreeMap::new());
+
+/// Reads a YAML config and replaces the program names in it with their IDs.
+///
+/// Exits if a name doesn't match exactly one program.
+///
+/// # Arguments
+///
+/// * `client` - A mutable reference to a CloudManagerClient instance
+/// * `path` - String slice that holds the path to the YAML config file, or `-` for stdin
+pub async fn read_config(client: &mut CloudManagerClient, path: &str) -> YamlConfig {
+    let mut config = YamlConfig::from_file(path);
+    resolve_config_programs(client, &mut config)
+        .await
+        .unwrap_or_else(|err| {
+            eprintln!("{} {}", "❌ Unable to resolve program:".red(), err);
+            audit::exit(1);
+        });
+    config
+}
+
+/// Resolves the programs of a YAML config that are given by name, fetching the program list only
+/// for names that weren't resolved before in this run. Exits if the program list can't be fetched.
+///
+/// # Arguments
+///
+/// * `client` - A mutable reference to a CloudManagerClient instance
+/// * `config` - The YAML config whose program names are replaced by IDs
+pub async fn resolve_config_programs(
+    client: &mut CloudManagerClient,
+    config: &mut YamlConfig,
+) -> Result<(), ResolveError> {
+    let unresolved: Vec<String> = {
+        let resolved = RESOLVED_PROGRAM_IDS.lock().unwrap();
+        config
+            .programs
+            .iter()
+            .filter(|p| p.id.is_none())
+            .filter_map(|p| p.name.clone())
+            .filter(|name| !resolved.contains_key(name))
+            .collect()
+    };
+    if !unresolved.is_empty() {
+        // Error answers of Cloud Manager are already printed by throw_adobe_api_error
+        let programs = get_programs(client, None)
+            .await
+            .unwrap_or_else(|err| report_api_error(err).exit());
+        let mut resolved = RESOLVED_PROGRAM_IDS.lock().unwrap();
+        for name in unresolved {
+            let program_id = find_program_id(&programs, &name)?;
+            println!("🔎 Program '{}' is {}", name, program_id);
+            resolved.insert(name, program_id);
+        }
+    }
+    let resolved = RESOLVED_PROGRAM_IDS.lock().unwrap();
+    fill_program_ids(config, |name| resolved.get(name).copied());
+    Ok(())
+}
+
+/// Sets the ID of every program in a YAML config that is given by name.
+///
+/// # Arguments
+///
+/// * `config` - The YAML config whose program names are replaced by IDs
+/// * `program_id` - Returns the ID of the program with the given name
+fn fill_program_ids<F>(config: &mut YamlConfig, program_id: F)
+where
+    F: Fn(&str) -> Option<u32>,
+{
+    for program in config.programs.iter_mut().filter(|p| p.id.is_none()) {
+        program.id = program.name.as_deref().and_then(&program_id);
+    }
+}
+
+/// Retrieves a single program.
+///
+/// Returns `None` if Cloud Manager doesn't know the program.
+///
+/// # Arguments
+///
+/// * `client` - A mutable reference to a CloudManagerClient instance
+/// * `program_id` - A u32 that holds the program ID
+///
+/// # Performed API Request
+///
+/// ```
+/// GET https://cloudmanager.adobe.io/api/program/{program_id}
+/// ```
+pub async fn get_program(
+    client: &mut CloudManagerClient,
+    program_id: u32,
+) -> Result<Option<Program>, Error> {
+    let request_path = format!("{}/api/program/{}", client.host_name, program_id);
     let response = client
         .perform_request(Method::GET, request_path, None::<()>, None)
-        .await?
-        .text()
         .await?;
-    let programs: ProgramsResponse = serde_json::from_str(response.as_str()).unwrap_or_else(|_| {
+    if response.status() == StatusCode::NOT_FOUND {
+        return Ok(None);
+    }
+    let response = response.text().await?;
+    client.print_raw_response(&response);
+    let program: Program = serde_json::from_str(response.as_str()).unwrap_or_else(|_| {
         throw_adobe_api_error(response);
-        process::exit(1);
+        audit::exit(1);
     });
 
-    Ok(programs.programs_list)
+    Ok(Some(program))
+}
+
+/// Filters programs as requested by `program list`.
+///
+/// # Arguments
+///
+/// * `programs` - The programs of the organization
+/// * `enabled_only` - Whether disabled programs are dropped
+/// * `status` - Only keep programs with this status, e.g. `ready` (case-insensitive)
+/// * `name_contains` - Only keep programs whose name contains this text (case-insensitive)
+pub fn filter_programs(
+    programs: Vec<Program>,
+    enabled_only: bool,
+    status: Option<&str>,
+    name_contains: Option<&str>,
+) -> Vec<Program> {
+    let name_contains = name_contains.map(str::to_lowercase);
+    programs
+        .into_iter()
+        .filter(|program| !enabled_only || program.enabled)
+        .filter(|program| status.is_none_or(|status| program.status.eq_ignore_ascii_case(status)))
+        .filter(|program| {
+            name_contains
+                .as_deref()
+                .is_none_or(|part| program.name.to_lowercase().contains(part))
+        })
+        .collect()
+}
+
+/// Renders programs as a table with a header and one row per program.
+///
+/// # Arguments
+///
+/// * `programs` - The programs
+pub fn render_programs(programs: &[Program]) -> Vec<String> {
+    let mut lines = vec![format!(
+        "{:<10} {:<30} {:<20} {:<10} {:<8} {:<10} CAPABILITIES",
+        "ID", "NAME", "TENANT", "STATUS", "ENABLED", "REGION"
+    )];
+    for program in programs {
+        let capabilities = match &program.capabilities {
+            Some(capabilities) if !capabilities.is_empty() => capabilities.join(","),
+            _ => String::from("-"),
+        };
+        lines.push(format!(
+            "{:<10} {:<30} {:<20} {:<10} {:<8} {:<10} {}",
+            program.id,
+            program.name,
+            program.tenant_id,
+            program.status,
+            program.enabled,
+            program.primary_region.as_deref().unwrap_or("-"),
+            capabilities
+        ));
+    }
+    lines
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::client::mock::{MockResponse, MockServer};
+    use crate::models::tests::read_json_from_file;
+    use crate::HOST_NAME;
+
+    fn fixture_programs(path: &str) -> Vec<Program> {
+        let response: ProgramsResponse = read_json_from_file(path).unwrap();
+        response.programs_list.programs
+    }
+
+    fn ids(programs: &[Program]) -> Vec<&str> {
+        programs.iter().map(|p| p.id.as_str()).collect()
+    }
+
+    /// Serves fixture pages by their URL and records which were fetched
+    struct FixturePages {
+        pages: Vec<(String, &'static str)>,
+        fetched: Vec<String>,
+    }
+
+    impl FixturePages {
+        fn two_pages() -> Self {
+            FixturePages {
+                pages: vec![
+                    (
+                        format!("{}/api/programs", HOST_NAME),
+                        "test/test_programs_page_1_response.json",
+                    ),
+                    (
+                        format!("{}/api/programs?start=1&limit=1", HOST_NAME),
+                        "test/test_programs_page_2_response.json",
+                    ),
+                ],
+                fetched: vec![],
+            }
+        }
+    }
+
+    #[async_trait]
+    impl ProgramPages for FixturePages {
+        async fn fetch_page(&mut self, url: String) -> Result<ProgramsResponse, Error> {
+            let (_, path) = self.pages.iter().find(|(page, _)| *page == url).unwrap();
+            self.fetched.push(url);
+            Ok(read_json_from_file(path).unwrap())
+        }
+    }
+
+    #[tokio::test]
+    async fn collect_programs_of_all_pages() {
+        let mut pages = FixturePages::two_pages();
+        let programs = collect_programs(&mut pages, HOST_NAME, None).await.unwrap();
+        assert_eq!(ids(&programs.programs), vec!["22222", "33333"]);
+        assert_eq!(pages.fetched.len(), 2);
+    }
+
+    #[tokio::test]
+    async fn collect_programs_up_to_the_limit() {
+        let mut pages = FixturePages::two_pages();
+        let programs = collect_programs(&mut pages, HOST_NAME, Some(1))
+            .await
+            .unwrap();
+        assert_eq!(ids(&programs.programs), vec!["22222"]);
+        assert_eq!(pages.fetched.len(), 1);
+    }
+
+    #[tokio::test]
+    async fn collect_programs_stops_at_a_page_linking_back() {
+        let mut pages = FixturePages::two_pages();
+        pages.pages[1].1 = "test/test_programs_page_1_response.json";
+        let programs = collect_programs(&mut pages, HOST_NAME, None).await.unwrap();
+        assert_eq!(ids(&programs.programs), vec!["22222", "22222"]);
+        assert_eq!(pages.fetched.len(), 2);
+    }
+
+    #[tokio::test]
+    async fn raw_mode_reads_all_pages() {
+        let server = MockServer::start();
+        let page = |path: &str| MockResponse::new(200, &std::fs::read_to_string(path).unwrap());
+        server.route(
+            "GET",
+            "/api/programs",
+            vec![
+                page("test/test_programs_page_1_response.json"),
+                page("test/test_programs_page_2_response.json"),
+            ],
+        );
+        let mut client = server.client();
+        client.raw = true;
+        let programs = get_programs(&mut client, None).await.unwrap();
+        assert_eq!(ids(&programs.programs), vec!["22222", "33333"]);
+        let targets: Vec<String> = server.requests().into_iter().map(|r| r.target).collect();
+        assert_eq!(targets.len(), 2);
+        assert!(targets[1].starts_with("/api/programs?start=1&limit=1"));
+    }
+
+    #[test]
+    fn next_links_become_urls() {
+        assert_eq!(
+            absolute_url(HOST_NAME, "/api/programs?start=20"),
+            "https://cloudmanager.adobe.io/api/programs?start=20"
+        );
+        assert_eq!(
+            absolute_url(HOST_NAME, "https://example.com/api/programs"),
+            "https://example.com/api/programs"
+        );
+    }
+
+    #[test]
+    fn find_program_id_by_name() {
+        let programs = ProgramsList {
+            programs: fixture_programs("test/test_programs_full_response.json"),
+        };
+        assert_eq!(find_program_id(&programs, "Intranet"), Ok(33333));
+        assert_eq!(find_program_id(&programs, "demo"), Ok(22222));
+    }
+
+    #[test]
+    fn find_program_id_of_missing_or_ambiguous_name() {
+        let mut programs = ProgramsList {
+            programs: fixture_programs("test/test_programs_full_response.json"),
+        };
+        assert_eq!(
+            find_program_id(&programs, "Extranet"),
+            Err(ResolveError::NotFound {
+                name: String::from("Extranet"),
+                candidates: vec![String::from("Demo"), String::from("Intranet")],
+            })
+        );
+
+        programs.programs[1].name = String::from("DEMO");
+        assert_eq!(
+            find_program_id(&programs, "demo"),
+            Err(ResolveError::Ambiguous {
+                name: String::from("demo"),
+                matches: vec![String::from("Demo"), String::from("DEMO")],
+            })
+        );
+    }
+
+    #[test]
+    fn program_names_in_config_are_resolved() {
+        let programs = ProgramsList {
+            programs: fixture_programs("test/test_programs_full_response.json"),
+        };
+        let mut config =
+            YamlConfig::parse("programs:\n  - name: intranet\n  - id: 44444\n  - name: Demo\n")
+                .unwrap();
+        fill_program_ids(&mut config, |name| find_program_id(&programs, name).ok());
+        let ids: Vec<u32> = config.programs.iter().map(|p| p.program_id()).collect();
+        assert_eq!(ids, vec![33333, 44444, 22222]);
+        assert_eq!(config.programs[0].name.as_deref(), Some("intranet"));
+    }
+
+    #[test]
+    fn program_as_table_row() {
+        let program: Program = read_json_from_file("test/test_program_response.json").unwrap();
+        assert_eq!(
+            render_programs(&[program]),
+            vec![
+                "ID         NAME                           TENANT               STATUS     ENABLED  REGION     CAPABILITIES",
+                "22222      Demo                           Demo                 ready      true     va7        aem,commerce",
+            ]
+        );
+    }
+
+    #[test]
+    fn program_without_metadata_as_table_row() {
+        let programs = fixture_programs("test/test_programs_full_response.json");
+        assert_eq!(
+            render_programs(&programs[1..])[1],
+            "33333      Intranet                       Demo                 ready      false    -          -"
+        );
+    }
+
+    #[test]
+    fn filter_programs_by_enabled_and_status() {
+        let programs = filter_programs(
+            fixture_programs("test/test_programs_response.json"),
+            true,
+            Some("READY"),
+            None,
+        );
+        assert_eq!(ids(&programs), vec!["22222"]);
+
+        let all = fixture_programs("test/test_programs_full_response.json");
+        assert_eq!(
+            ids(&filter_programs(all, false, None, None)),
+            vec!["22222", "33333"]
+        );
+        let all = fixture_programs("test/test_programs_full_response.json");
+        assert_eq!(ids(&filter_programs(all, true, None, None)), vec!["22222"]);
+        let all = fixture_programs("test/test_programs_full_response.json");
+        assert!(filter_programs(all, false, Some("deleting"), None).is_empty());
+    }
+
+    #[test]
+    fn filter_programs_by_name() {
+        let programs = filter_programs(
+            fixture_programs("test/test_programs_response.json"),
+            false,
+            None,
+            Some("em"),
+        );
+        assert_eq!(ids(&programs), vec!["22222"]);
+
+        let all = fixture_programs("test/test_programs_full_response.json");
+        assert_eq!(
+            ids(&filter_programs(all, false, None, Some("INTRA"))),
+            vec!["33333"]
+        );
+        let all = fixture_programs("test/test_programs_response.json");
+        assert!(filter_programs(all, false, None, Some("intranet")).is_empty());
+    }
 }
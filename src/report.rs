@@ -0,0 +1,367 @@
+use crate::client::CloudManagerClient;
+use crate::environments::try_get_environments;
+use crate::models::domain::DomainResponse;
+use crate::models::environment::{EnvironmentId, EnvironmentsList};
+use crate::models::pipeline::{PipelinesList, PipelinesResponse};
+use chrono::DateTime;
+use serde::Serialize;
+use std::sync::Arc;
+use strum_macros::EnumString;
+use tokio::sync::Semaphore;
+use tokio::task::JoinSet;
+
+/// Number of domains that are fetched per program to find the earliest certificate expiry
+const DOMAIN_LIMIT: u32 = 1000;
+
+/// Formats that `report inventory` can print
+#[derive(Clone, Copy, Debug, PartialEq, EnumString)]
+#[strum(serialize_all = "lowercase")]
+pub enum InventoryFormat {
+    /// One JSON document with all programs
+    Json,
+    /// One line per program with counts instead of lists
+    Csv,
+}
+
+/// Inventory of a single program as reported by `report inventory`
+#[derive(Debug, Serialize)]
+pub struct ProgramInventory {
+    pub program_id: String,
+    pub program_name: String,
+    pub environments: Vec<InventoryEnvironment>,
+    pub pipelines: Vec<InventoryPipeline>,
+    /// Missing if the domains couldn't be fetched
+    pub domain_count: Option<i64>,
+    /// Expiry of the certificate that expires first, as sent by Cloud Manager
+    pub earliest_certificate_expiry: Option<String>,
+    /// What couldn't be fetched for this program
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    pub errors: Vec<String>,
+}
+
+/// An environment as reported by `report inventory`
+#[derive(Debug, Serialize)]
+pub struct InventoryEnvironment {
+    pub id: EnvironmentId,
+    pub name: String,
+    #[serde(rename = "type")]
+    pub env_type: String,
+    pub status: String,
+    pub region: Option<String>,
+}
+
+/// A pipeline as reported by `report inventory`
+#[derive(Debug, Serialize)]
+pub struct InventoryPipeline {
+    pub id: String,
+    pub name: String,
+    #[serde(rename = "type")]
+    pub pipeline_type: Option<String>,
+    pub status: String,
+}
+
+/// Collects environments, pipelines and domains of a single program.
+///
+/// # Arguments
+///
+/// * `client` - A mutable reference to a CloudManagerClient instance
+/// * `program_id` - A u32 that holds the program ID
+/// * `program_name` - Name of the program
+pub async fn get_program_inventory(
+    client: &mut CloudManagerClient,
+    program_id: u32,
+    program_name: String,
+) -> ProgramInventory {
+    let environments = try_get_environments(client, program_id).await;
+    let pipelines = client
+        .try_get::<PipelinesResponse>(
+            format!("{}/api/program/{}/pipelines", client.host_name, program_id),
+            None,
+        )
+        .await
+        .map(|response| response.pipelines_list);
+    let limit = DOMAIN_LIMIT.to_string();
+    let domains = client
+        .try_get::<DomainResponse>(
+            format!(
+                "{}/api/program/{}/domainNames",
+                client.host_name, program_id
+            ),
+            Some(vec![("start", "0"), ("limit", &limit)]),
+        )
+        .await;
+    summarize_inventory(program_id, program_name, environments, pipelines, domains)
+}
+
+/// Collects the inventory of several programs, fetching at most `concurrency` programs at the
+/// same time. The result keeps the order of `programs`.
+///
+/// # Arguments
+///
+/// * `client` - A reference to a CloudManagerClient instance, clones of it are used per program
+/// * `programs` - IDs and names of the programs
+/// * `concurrency` - Maximum number of programs that are fetched in parallel
+pub async fn get_inventory(
+    client: &CloudManagerClient,
+    programs: Vec<(u32, String)>,
+    concurrency: usize,
+) -> Vec<ProgramInventory> {
+    let semaphore = Arc::new(Semaphore::new(concurrency.max(1)));
+    let mut tasks = JoinSet::new();
+    for (index, (program_id, program_name)) in programs.into_iter().enumerate() {
+        let mut client = client.clone();
+        let semaphore = semaphore.clone();
+        tasks.spawn(async move {
+            let _permit = semaphore.acquire_owned().await.unwrap();
+            let inventory = get_program_inventory(&mut client, program_id, program_name).await;
+            (index, inventory)
+        });
+    }
+    let mut inventories = vec![];
+    while let Some(joined) = tasks.join_next().await {
+        inventories.push(joined.expect("Fetching the inventory panicked"));
+    }
+    inventories.sort_by_key(|(index, _)| *index);
+    inventories
+        .into_iter()
+        .map(|(_, inventory)| inventory)
+        .collect()
+}
+
+/// Builds the inventory of a program from what was fetched for it.
+///
+/// Everything that couldn't be fetched is recorded in `errors`, the rest is reported anyway.
+///
+/// # Arguments
+///
+/// * `program_id` - A u32 that holds the program ID
+/// * `program_name` - Name of the program
+/// * `environments` - The environments of the program or the reason why they couldn't be fetched
+/// * `pipelines` - The pipelines of the program or the reason why they couldn't be fetched
+/// * `domains` - The domains of the program or the reason why they couldn't be fetched
+pub fn summarize_inventory(
+    program_id: u32,
+    program_name: String,
+    environments: Result<EnvironmentsList, String>,
+    pipelines: Result<PipelinesList, String>,
+    domains: Result<DomainResponse, String>,
+) -> ProgramInventory {
+    let mut errors = vec![];
+    let environments = match environments {
+        Ok(list) => list
+            .environments
+            .into_iter()
+            .map(|env| InventoryEnvironment {
+                id: env.id,
+                name: env.name,
+                env_type: env.env_type,
+                status: env.status,
+                region: env.region,
+            })
+            .collect(),
+        Err(err) => {
+            errors.push(format!("environments: {}", err));
+            vec![]
+        }
+    };
+    let pipelines = match pipelines {
+        Ok(list) => list
+            .pipelines
+            .into_iter()
+            .map(|pipeline| InventoryPipeline {
+                id: pipeline.id,
+                name: pipeline.name,
+                pipeline_type: pipeline.pipeline_type,
+                status: pipeline.status,
+            })
+            .collect(),
+        Err(err) => {
+            errors.push(format!("pipelines: {}", err));
+            vec![]
+        }
+    };
+    let (domain_count, earliest_certificate_expiry) = match domains {
+        Ok(response) => (
+            Some(response.total_number_of_items),
+            earliest_expiry(
+                response
+                    .domain_list
+                    .list
+                    .iter()
+                    .filter_map(|domain| domain.certificate_expire_at.as_deref()),
+            ),
+        ),
+        Err(err) => {
+            errors.push(format!("domains: {}", err));
+            (None, None)
+        }
+    };
+    ProgramInventory {
+        program_id: program_id.to_string(),
+        program_name,
+        environments,
+        pipelines,
+        domain_count,
+        earliest_certificate_expiry,
+        errors,
+    }
+}
+
+/// Returns the earliest of the given certificate expiry dates, skipping dates that can't be parsed.
+///
+/// # Arguments
+///
+/// * `dates` - Expiry dates as sent by Cloud Manager, e.g. `2025-07-01T23:59:59.000+0000`
+fn earliest_expiry<'a, I>(dates: I) -> Option<String>
+where
+    I: Iterator<Item = &'a str>,
+{
+    dates
+        .filter_map(|date| {
+            DateTime::parse_from_str(date, "%Y-%m-%dT%H:%M:%S%.f%z")
+                .ok()
+                .map(|parsed| (parsed, date))
+        })
+        .min_by_key(|(parsed, _)| *parsed)
+        .map(|(_, date)| date.to_string())
+}
+
+/// Renders the inventory as CSV with a header and one row per program.
+///
+/// # Arguments
+///
+/// * `inventories` - The inventories of all programs
+pub fn render_inventory_csv(inventories: &[ProgramInventory]) -> Vec<String> {
+    let mut lines = vec![String::from(
+        "program_id,program_name,environments,pipelines,domains,earliest_certificate_expiry,errors",
+    )];
+    for inventory in inventories {
+        let fields = [
+            inventory.program_id.clone(),
+            inventory.program_name.clone(),
+            inventory.environments.len().to_string(),
+            inventory.pipelines.len().to_string(),
+            inventory
+                .domain_count
+                .map(|count| count.to_string())
+                .unwrap_or_default(),
+            inventory
+                .earliest_certificate_expiry
+                .clone()
+                .unwrap_or_default(),
+            inventory.errors.join("; "),
+        ];
+        lines.push(
+            fields
+                .iter()
+                .map(|field| csv_field(field))
+                .collect::<Vec<_>>()
+                .join(","),
+        );
+    }
+    lines
+}
+
+/// Quotes a CSV field if it contains a separator, a quote or a line break.
+fn csv_field(field: &str) -> String {
+    if field.contains([',', '"', '\n', '\r']) {
+        format!("\"{}\"", field.replace('"', "\"\""))
+    } else {
+        field.to_string()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::models::environment::EnvironmentsResponse;
+    use crate::models::tests::read_json_from_file;
+
+    fn fixture_inventory() -> ProgramInventory {
+        let environments: EnvironmentsResponse =
+            read_json_from_file("test/test_environment_response.json").unwrap();
+        let pipelines: PipelinesResponse =
+            read_json_from_file("test/test_pipeline_response.json").unwrap();
+        let domains: DomainResponse =
+            read_json_from_file("test/test_domain_response.json").unwrap();
+        summarize_inventory(
+            22222,
+            String::from("Demo"),
+            Ok(environments.environments_list),
+            Ok(pipelines.pipelines_list),
+            Ok(domains),
+        )
+    }
+
+    #[test]
+    fn inventory_of_a_program() {
+        let inventory = fixture_inventory();
+        assert_eq!(inventory.environments.len(), 4);
+        assert_eq!(inventory.pipelines.len(), 5);
+        assert_eq!(inventory.domain_count, Some(161));
+        assert_eq!(
+            inventory.earliest_certificate_expiry.as_deref(),
+            Some("2025-06-05T23:59:59.000+0000")
+        );
+        assert!(inventory.errors.is_empty());
+        let json = serde_json::to_value(&inventory).unwrap();
+        assert!(json.get("errors").is_none());
+    }
+
+    #[test]
+    fn failures_are_recorded_in_the_inventory() {
+        let pipelines: PipelinesResponse =
+            read_json_from_file("test/test_pipeline_response.json").unwrap();
+        let inventory = summarize_inventory(
+            33333,
+            String::from("Intranet"),
+            Err(String::from("403 Forbidden: no access")),
+            Ok(pipelines.pipelines_list),
+            Err(String::from("request timed out")),
+        );
+        assert!(inventory.environments.is_empty());
+        assert_eq!(inventory.pipelines.len(), 5);
+        assert_eq!(inventory.domain_count, None);
+        assert_eq!(
+            inventory.errors,
+            vec![
+                "environments: 403 Forbidden: no access",
+                "domains: request timed out"
+            ]
+        );
+    }
+
+    #[test]
+    fn earliest_expiry_skips_invalid_dates() {
+        let dates = [
+            "2025-07-01T23:59:59.000+0000",
+            "not a date",
+            "2025-07-02T03:00:00.000+0200",
+        ];
+        assert_eq!(
+            earliest_expiry(dates.iter().copied()).as_deref(),
+            Some("2025-07-01T23:59:59.000+0000")
+        );
+        assert_eq!(earliest_expiry(std::iter::empty()), None);
+    }
+
+    #[test]
+    fn inventory_as_csv() {
+        let failed = summarize_inventory(
+            33333,
+            String::from("Intranet, legacy"),
+            Err(String::from("500")),
+            Err(String::from("500")),
+            Err(String::from("500")),
+        );
+        let lines = render_inventory_csv(&[fixture_inventory(), failed]);
+        assert_eq!(
+            lines,
+            vec![
+                "program_id,program_name,environments,pipelines,domains,earliest_certificate_expiry,errors",
+                "22222,Demo,4,5,161,2025-06-05T23:59:59.000+0000,",
+                "33333,\"Intranet, legacy\",0,0,,,environments: 500; pipelines: 500; domains: 500",
+            ]
+        );
+    }
+}
@@ -0,0 +1,125 @@
+use std::fmt;
+
+/// Error that occurs when a resource can't be resolved by its name
+#[derive(Debug, PartialEq)]
+pub enum ResolveError {
+    /// No resource has the given name; holds the names of all candidates
+    NotFound {
+        name: String,
+        candidates: Vec<String>,
+    },
+    /// More than one resource has the given name; holds the names of all matches
+    Ambiguous { name: String, matches: Vec<String> },
+    /// The resources to search in couldn't be fetched
+    Request(String),
+}
+
+impl From<reqwest::Error> for ResolveError {
+    fn from(error: reqwest::Error) -> Self {
+        ResolveError::Request(error.to_string())
+    }
+}
+
+impl fmt::Display for ResolveError {
+    fn fmt(&self, formatter: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            ResolveError::NotFound { name, candidates } => write!(
+                formatter,
+                "no match for '{}', candidates are: {}",
+                name,
+                candidates.join(", ")
+            ),
+            ResolveError::Ambiguous { name, matches } => write!(
+                formatter,
+                "'{}' is ambiguous, it matches: {}",
+                name,
+                matches.join(", ")
+            ),
+            ResolveError::Request(reason) => write!(formatter, "request failed: {}", reason),
+        }
+    }
+}
+
+/// Finds the single item with the given name.
+///
+/// An exact match wins; otherwise names are compared case-insensitively and the match must be
+/// unique.
+///
+/// # Arguments
+///
+/// * `items` - The items to search in
+/// * `name` - String slice that holds the name to look for
+/// * `item_name` - Returns the name of an item
+pub fn resolve_by_name<'a, T, F>(
+    items: &'a [T],
+    name: &str,
+    item_name: F,
+) -> Result<&'a T, ResolveError>
+where
+    F: Fn(&T) -> &str,
+{
+    let exact_matches: Vec<&T> = items.iter().filter(|i| item_name(i) == name).collect();
+    if exact_matches.len() == 1 {
+        return Ok(exact_matches[0]);
+    }
+
+    let matches: Vec<&T> = items
+        .iter()
+        .filter(|i| item_name(i).eq_ignore_ascii_case(name))
+        .collect();
+    match matches.len() {
+        0 => Err(ResolveError::NotFound {
+            name: name.to_string(),
+            candidates: items.iter().map(|i| item_name(i).to_string()).collect(),
+        }),
+        1 => Ok(matches[0]),
+        _ => Err(ResolveError::Ambiguous {
+            name: name.to_string(),
+            matches: matches.iter().map(|i| item_name(i).to_string()).collect(),
+        }),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const NAMES: [&str; 4] = ["demo-prod", "development", "Demo-Stage", "demo-stage"];
+
+    fn resolve(name: &str) -> Result<&'static str, ResolveError> {
+        resolve_by_name(&NAMES, name, |n| n).copied()
+    }
+
+    #[test]
+    fn resolve_exact_match() {
+        assert_eq!(resolve("development"), Ok("development"));
+        assert_eq!(resolve("demo-stage"), Ok("demo-stage"));
+    }
+
+    #[test]
+    fn resolve_case_insensitive_match() {
+        assert_eq!(resolve("DEMO-PROD"), Ok("demo-prod"));
+    }
+
+    #[test]
+    fn resolve_missing_name() {
+        assert_eq!(
+            resolve("demo-rde"),
+            Err(ResolveError::NotFound {
+                name: String::from("demo-rde"),
+                candidates: NAMES.iter().map(|n| n.to_string()).collect(),
+            })
+        );
+    }
+
+    #[test]
+    fn resolve_ambiguous_name() {
+        assert_eq!(
+            resolve("DEMO-STAGE"),
+            Err(ResolveError::Ambiguous {
+                name: String::from("DEMO-STAGE"),
+                matches: vec![String::from("Demo-Stage"), String::from("demo-stage")],
+            })
+        );
+    }
+}
@@ -0,0 +1,72 @@
+use serde_json::{json, Map, Value};
+
+/// JSON Schema draft that the generated schema conforms to
+const SCHEMA_DRAFT: &str = "http://json-schema.org/draft-07/schema#";
+
+/// Types that can describe their YAML / JSON representation as a JSON Schema
+pub trait JsonSchema {
+    /// Returns the JSON Schema of the type
+    fn json_schema() -> Value;
+}
+
+/// Builds the schema of an object with the given properties.
+///
+/// # Arguments
+///
+/// * `description` - String slice that describes the object
+/// * `properties` - Names and schemas of the properties
+/// * `required` - Names of the properties that must be present
+pub fn object(description: &str, properties: Vec<(&str, Value)>, required: &[&str]) -> Value {
+    let properties: Map<String, Value> = properties
+        .into_iter()
+        .map(|(name, schema)| (name.to_string(), schema))
+        .collect();
+    json!({
+        "type": "object",
+        "description": description,
+        "properties": properties,
+        "required": required,
+    })
+}
+
+/// Builds the schema of a list of items.
+///
+/// # Arguments
+///
+/// * `items` - Schema of a single item
+pub fn array(items: Value) -> Value {
+    json!({ "type": "array", "items": items })
+}
+
+/// Builds the schema of a string with a fixed set of values.
+///
+/// # Arguments
+///
+/// * `description` - String slice that describes the value
+/// * `values` - The allowed values
+pub fn string_enum(description: &str, values: &[&str]) -> Value {
+    json!({ "type": "string", "description": description, "enum": values })
+}
+
+/// Builds the schema of a plain value.
+///
+/// # Arguments
+///
+/// * `schema_type` - JSON Schema type, e.g. `string` or `integer`
+/// * `description` - String slice that describes the value
+pub fn value(schema_type: &str, description: &str) -> Value {
+    json!({ "type": schema_type, "description": description })
+}
+
+/// Wraps the schema of the root type into a standalone document.
+///
+/// # Arguments
+///
+/// * `title` - String slice that holds the title of the schema
+pub fn document<T: JsonSchema>(title: &str) -> Value {
+    let mut schema = T::json_schema();
+    let root = schema.as_object_mut().unwrap();
+    root.insert(String::from("$schema"), json!(SCHEMA_DRAFT));
+    root.insert(String::from("title"), json!(title));
+    schema
+}
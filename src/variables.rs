@@ -1,20 +1,26 @@
+use crate::audit;
 use crate::client::{AdobeConnector, CloudManagerClient};
-use crate::encryption::decrypt;
+use crate::encryption::{decrypt, EncryptedValue};
 use crate::environments::get_environment;
-use crate::errors::throw_adobe_api_error;
-use crate::models::config::YamlConfig;
+use crate::errors::{report_api_error, throw_adobe_api_error};
+use crate::exit_code::ExitCode;
+use crate::models::config::{read_input, EnvironmentsConfig, ProgramsConfig, YamlConfig};
+use crate::models::environment::EnvironmentId;
+use crate::models::variables::patch_body;
 use crate::models::variables::{
     EnvironmentVariable, EnvironmentVariableServiceType, EnvironmentVariablesList,
     EnvironmentVariablesResponse, PipelineVariable, PipelineVariableServiceType,
     PipelineVariablesList, PipelineVariablesResponse, VariableType,
 };
 use crate::pipelines::get_pipeline;
-use crate::HOST_NAME;
+use crate::programs::read_config;
+use chrono::{DateTime, Utc};
 use colored::*;
 use reqwest::{Method, StatusCode};
-use std::collections::HashSet;
+use std::collections::{HashMap, HashSet};
+use std::fmt;
 use std::hash::{Hash, Hasher};
-use std::process;
+use std::path::{Path, PathBuf};
 use std::thread::sleep;
 use std::time::Duration;
 
@@ -97,7 +103,7 @@ impl Hash for PipelineVariableServiceType {
 ///
 /// * `client` - A mutable reference to a CloudManagerClient instance
 /// * `program_id` - A u32 that holds the program ID
-/// * `env_id` - A u32 that holds the environment ID
+/// * `env_id` - An EnvironmentId that holds the environment ID
 ///
 /// # Performed API Request
 ///
@@ -107,21 +113,22 @@ impl Hash for PipelineVariableServiceType {
 pub async fn get_env_vars(
     client: &mut CloudManagerClient,
     program_id: u32,
-    env_id: u32,
+    env_id: EnvironmentId,
 ) -> Result<EnvironmentVariablesList, reqwest::Error> {
     let request_path = format!(
         "{}/api/program/{}/environment/{}/variables",
-        HOST_NAME, program_id, env_id
+        client.host_name, program_id, env_id
     );
     let response = client
         .perform_request(Method::GET, request_path, None::<()>, None)
         .await?
         .text()
         .await?;
+    client.print_raw_response(&response);
     let variables: EnvironmentVariablesResponse = serde_json::from_str(response.as_str())
         .unwrap_or_else(|_| {
             throw_adobe_api_error(response);
-            process::exit(1);
+            audit::exit(1);
         });
     Ok(variables.variables_list)
 }
@@ -132,7 +139,7 @@ pub async fn get_env_vars(
 ///
 /// * `client` - A mutable reference to a CloudManagerClient instance
 /// * `program_id` - A u32 that holds the program ID
-/// * `env_id` - A u32 that holds the environment ID
+/// * `env_id` - An EnvironmentId that holds the environment ID
 /// * `variables` - Reference to an array that holds elements of type VariablesConfig
 ///
 /// # Performed API Request
@@ -143,15 +150,16 @@ pub async fn get_env_vars(
 pub async fn set_env_vars(
     client: &mut CloudManagerClient,
     program_id: u32,
-    env_id: u32,
+    env_id: EnvironmentId,
     variables: &[EnvironmentVariable],
 ) -> Result<StatusCode, reqwest::Error> {
     let request_path = format!(
         "{}/api/program/{}/environment/{}/variables",
-        HOST_NAME, program_id, env_id
+        client.host_name, program_id, env_id
     );
+    let body = patch_body(variables, client.config.explicit_service);
     let response = client
-        .perform_request(Method::PATCH, request_path, Some(variables), None)
+        .perform_request(Method::PATCH, request_path, Some(body), None)
         .await?;
     let response_code = response.status();
     // Print out additional info if request failed
@@ -166,38 +174,47 @@ pub async fn set_env_vars(
 /// Sets environment variables that are read from a given YAML file.
 ///
 /// When the target environment is currently updating, the function will retry until its state
-/// is back to ready.
+/// is back to ready. Errors are printed and returned as the exit code the run should end with.
 ///
 /// # Arguments
 ///
 /// * `file_path` - String slice that holds the path to the YAML variables config
 /// * `client` - A mutable reference to a CloudManagerClient instance
+/// * `only_changed` - Only send variables that differ from Cloud Manager and print whether
+///   anything changed (`changed=true|false`)
+/// * `snapshot_dir` - Directory that a snapshot of the current variables of each environment is
+///   written to before they are changed
+/// * `mode` - Which differences to Cloud Manager are applied, see [`SyncMode`]
 pub async fn set_env_vars_from_file(
     file_path: &str,
     client: &mut CloudManagerClient,
     ci_mode: bool,
     dry_run: bool,
-) {
-    let input = std::fs::read_to_string(file_path).expect("Unable to read file");
-    let input: YamlConfig = serde_yaml::from_str(input.as_str()).unwrap_or_else(|err| {
-        eprintln!("{} {}", "❌ Malformed YAML: ".red(), err);
-        process::exit(1);
-    });
+    only_changed: bool,
+    snapshot_dir: Option<&str>,
+    mode: SyncMode,
+) -> Result<(), ExitCode> {
+    let input = read_config(client, file_path).await;
 
     let mut skipped_environment: bool = false;
+    let mut changed_any: bool = false;
 
     let programs = input.programs;
 
     for p in &programs {
-        println!("☁ Program: {}", p.id,);
-        for e in p.environments.as_ref().unwrap() {
-            let env = get_environment(client, p.id, e.id).await.unwrap();
+        println!("☁ Program: {}", p.program_id(),);
+        for e in p.environments.iter().flatten() {
+            let env = get_environment(client, p.program_id(), e.id)
+                .await
+                .map_err(report_api_error)?;
 
             println!("{:>4} Environment: {} ({})", "⬛", e.id, env.name);
 
             // ensure there are no duplicate environment variables
-            let duplicates = find_duplicates(e.variables.clone());
-            if duplicates.len() > 0 {
+            let defaults = p.defaults.as_ref().map_or(&[][..], |d| &d.variables[..]);
+            let mut duplicates = find_duplicates(defaults.to_vec());
+            duplicates.extend(find_duplicates(e.variables.clone()));
+            if !duplicates.is_empty() {
                 for dv in &duplicates {
                     eprintln!(
                         "{:>8} {}  name: '{}' service: {}",
@@ -207,7 +224,7 @@ pub async fn set_env_vars_from_file(
                         dv.service
                     );
                 }
-                process::exit(4);
+                return Err(ExitCode::Duplicate);
             }
 
             // The vector that holds the final variables that will be set or deleted. Will be constructed
@@ -217,7 +234,9 @@ pub async fn set_env_vars_from_file(
 
             // Check if the targeted environment is ready
             '_retry: loop {
-                let env = get_environment(client, p.id, e.id).await.unwrap();
+                let env = get_environment(client, p.program_id(), e.id)
+                    .await
+                    .map_err(report_api_error)?;
 
                 if env.status == "updating" && ci_mode {
                     skipped_environment = true;
@@ -235,7 +254,16 @@ pub async fn set_env_vars_from_file(
                 } else {
                     // To simulate a stateful application of the variables (i.e. remove a variable that is defined
                     // in the cloud, but not in the YAML file), we have to compare them.
-                    let vars_yaml = e.variables.clone();
+                    let mut vars_yaml = merge_with_defaults(defaults, &e.variables);
+                    let vars_cloud = get_env_vars(client, p.program_id(), e.id)
+                        .await
+                        .map_err(report_api_error)?
+                        .variables;
+                    // Variables that Cloud Manager already has are never sent in add-only mode, so
+                    // their secrets don't have to be decrypted either
+                    if mode == SyncMode::AddOnly {
+                        vars_yaml.retain(|vy| !vars_cloud.contains(vy));
+                    }
 
                     // All variables in the YAML are definitely meant to be updated, so they will be
                     // pushed to vars_final.
@@ -248,32 +276,58 @@ pub async fn set_env_vars_from_file(
                             }
                             VariableType::SecretString => {
                                 // If the value is a secret, check if it's encrypted and decrypt it if that's the case
-                                let tmp_loop_var_value = tmp_loop_var.clone().value.unwrap();
-                                if tmp_loop_var_value.starts_with("$enc") {
-                                    let encrypted_value =
-                                        tmp_loop_var_value.split_whitespace().collect::<Vec<_>>();
-                                    let decrypted_value = decrypt(encrypted_value[1].to_string());
-                                    tmp_loop_var.value = Some(decrypted_value);
+                                let tmp_loop_var_value = match tmp_loop_var.value.clone() {
+                                    Some(value) => value,
+                                    None => {
+                                        eprintln!(
+                                            "{:>8} {}  '{}: {}'",
+                                            "❌".red(),
+                                            "ERROR, secret variable without value (restored from a snapshot?)".red(),
+                                            tmp_loop_var.name,
+                                            tmp_loop_var.service
+                                        );
+                                        return Err(ExitCode::Failure);
+                                    }
+                                };
+                                if EncryptedValue::is_encrypted(&tmp_loop_var_value) {
+                                    let decrypted_value = EncryptedValue::parse(
+                                        &tmp_loop_var_value,
+                                    )
+                                    .and_then(|value| decrypt(&value).map_err(|e| e.to_string()));
+                                    match decrypted_value {
+                                        Ok(value) => tmp_loop_var.value = Some(value),
+                                        Err(err) => {
+                                            eprintln!(
+                                            "{:>8} {}  '{}: {}' in program {} / environment {}: {}",
+                                            "❌".red(),
+                                            "ERROR, secret variable can't be decrypted".red(),
+                                            tmp_loop_var.name,
+                                            tmp_loop_var.service,
+                                            p.program_id(),
+                                            e.id,
+                                            err
+                                        );
+                                            return Err(ExitCode::Undecryptable);
+                                        }
+                                    }
                                 }
                                 vars_final.push(tmp_loop_var);
                             }
                         }
                     }
 
-                    // If a variable is only present on Cloud Manager and not in the YAML, then we
-                    // will set its value to None and push it to vars_final, so it will be deleted.
-                    let vars_cloud = get_env_vars(client, p.id, e.id).await.unwrap().variables;
-                    for vc in vars_cloud {
-                        if !vars_yaml.clone().contains(&vc) {
-                            let variable_to_be_deleted = EnvironmentVariable {
-                                name: vc.name,
-                                value: None,
-                                variable_type: vc.variable_type,
-                                service: vc.service,
-                            };
-                            vars_final.push(variable_to_be_deleted);
-                        }
+                    apply_sync_mode(&mut vars_final, &vars_cloud, mode);
+
+                    // Drop everything that is already set exactly like this in Cloud Manager
+                    if only_changed {
+                        vars_final.retain(|vf| !is_unchanged(vf, &vars_cloud));
                     }
+                    if vars_final.is_empty() && (only_changed || mode == SyncMode::AddOnly) {
+                        println!("{:>8} no changes", "✔");
+                        break '_retry;
+                    }
+                    changed_any = true;
+                    order_variables(&mut vars_final, |vf| (&vf.service).into());
 
                     if let Some(vf) = vars_final
                         .iter()
@@ -286,7 +340,21 @@ pub async fn set_env_vars_from_file(
                             vf.name,
                             vf.service
                         );
-                        process::exit(3);
+                        return Err(ExitCode::InvalidService);
+                    }
+
+                    let problems = preflight_env_vars(&vars_final, &vars_cloud);
+                    if !problems.is_empty() {
+                        for problem in &problems {
+                            eprintln!("{:>8} {}", "❌".red(), problem.red());
+                        }
+                        eprintln!(
+                            "{:>8} {}",
+                            "❌".red(),
+                            "ERROR, variables exceed the limits of Cloud Manager, not sending them"
+                                .red()
+                        );
+                        return Err(ExitCode::Failure);
                     }
 
                     for vf in &vars_final {
@@ -312,7 +380,30 @@ pub async fn set_env_vars_from_file(
                             "⚠️",
                         );
                     } else {
-                        match set_env_vars(client, p.id, e.id, &vars_final).await {
+                        if let Some(dir) = snapshot_dir {
+                            match write_env_vars_snapshot(
+                                dir,
+                                p.program_id(),
+                                e.id,
+                                &vars_cloud,
+                                Utc::now(),
+                            ) {
+                                Ok(path) => {
+                                    println!("{:>8} Snapshot saved to {}", "💾", path.display())
+                                }
+                                Err(error) => {
+                                    eprintln!(
+                                        "{:>8} {} {}",
+                                        "❌".red(),
+                                        "ERROR, unable to write snapshot, not changing anything:"
+                                            .red(),
+                                        error
+                                    );
+                                    return Err(ExitCode::Failure);
+                                }
+                            }
+                        }
+                        match set_env_vars(client, p.program_id(), e.id, &vars_final).await {
                             Ok(status) => match status {
                                 StatusCode::NO_CONTENT => {
                                     println!("{:>8} Success", "✔");
@@ -323,13 +414,10 @@ pub async fn set_env_vars_from_file(
                                         "Error, check output above".red(),
                                         "❌".red()
                                     );
-                                    process::exit(2);
+                                    return Err(ExitCode::Incomplete);
                                 }
                             },
-                            Err(error) => {
-                                eprintln!("{} {}", "❌ API error: ".red().bold(), error);
-                                process::exit(1);
-                            }
+                            Err(error) => return Err(report_api_error(error)),
                         }
                     }
                     break '_retry;
@@ -338,13 +426,322 @@ pub async fn set_env_vars_from_file(
         }
     }
 
-    if skipped_environment == true {
+    if only_changed {
+        println!("changed={}", changed_any);
+    }
+
+    if skipped_environment {
+        eprintln!(
+            "\n⚠️ Not all environments were changed because they were updating and --ci mode is active!"
+        );
+        return Err(ExitCode::Incomplete);
+    }
+    Ok(())
+}
+
+/// Which differences between the YAML and Cloud Manager `env vars set` applies
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum SyncMode {
+    /// Add, update and delete variables, so Cloud Manager matches the YAML
+    Full,
+    /// Add and update variables, but keep those that only Cloud Manager has (`--no-prune`)
+    Additive,
+    /// Only add variables that Cloud Manager doesn't have yet (`--add-only`)
+    AddOnly,
+}
+
+/// Turns the variables of the YAML into those that are sent to Cloud Manager in the given mode.
+///
+/// In full mode a variable that is only present in Cloud Manager is added without a value, so it
+/// is deleted. In add-only mode every variable that Cloud Manager already has is dropped, even if
+/// its value differs. Variables are matched by name and service.
+///
+/// # Arguments
+///
+/// * `vars_final` - The variables of the YAML, with decrypted secrets
+/// * `vars_cloud` - The variables that are currently set in Cloud Manager
+/// * `mode` - Which differences are applied
+pub fn apply_sync_mode(
+    vars_final: &mut Vec<EnvironmentVariable>,
+    vars_cloud: &[EnvironmentVariable],
+    mode: SyncMode,
+) {
+    match mode {
+        SyncMode::Full => {
+            let deleted: Vec<EnvironmentVariable> = vars_cloud
+                .iter()
+                .filter(|vc| !vars_final.contains(vc))
+                .map(|vc| EnvironmentVariable {
+                    name: vc.name.clone(),
+                    value: None,
+                    variable_type: vc.variable_type.clone(),
+                    service: vc.service.clone(),
+                    extra: HashMap::new(),
+                })
+                .collect();
+            vars_final.extend(deleted);
+        }
+        SyncMode::Additive => {}
+        SyncMode::AddOnly => vars_final.retain(|vf| !vars_cloud.contains(vf)),
+    }
+}
+
+/// Marker that is appended to secret variables in snapshots, since their values can't be exported
+const SNAPSHOT_SECRET_MARKER: &str =
+    "# SECRET: value is not returned by Cloud Manager, set it before re-applying";
+
+/// Builds the name of a snapshot file, e.g. `snapshot-11111-22222-20251014T083000Z.yml`.
+///
+/// # Arguments
+///
+/// * `program_id` - A u32 that holds the program ID
+/// * `env_id` - An EnvironmentId that holds the environment ID
+/// * `timestamp` - Time at which the snapshot is taken
+pub fn snapshot_file_name(
+    program_id: u32,
+    env_id: EnvironmentId,
+    timestamp: DateTime<Utc>,
+) -> String {
+    format!(
+        "snapshot-{}-{}-{}.yml",
+        program_id,
+        env_id,
+        timestamp.format("%Y%m%dT%H%M%SZ")
+    )
+}
+
+/// Renders the variables of an environment in the YAML input format, so the snapshot can be
+/// re-applied with `env vars set`.
+///
+/// Secret variables are kept without a value and marked with a comment.
+///
+/// # Arguments
+///
+/// * `program_id` - A u32 that holds the program ID
+/// * `env_id` - An EnvironmentId that holds the environment ID
+/// * `variables` - The variables that are currently set in Cloud Manager
+pub fn env_vars_snapshot(
+    program_id: u32,
+    env_id: EnvironmentId,
+    variables: &[EnvironmentVariable],
+) -> String {
+    let config = YamlConfig {
+        version: 1,
+        programs: vec![ProgramsConfig {
+            id: Some(program_id),
+            name: None,
+            defaults: None,
+            environments: Some(vec![EnvironmentsConfig {
+                id: env_id,
+                variables: variables.to_vec(),
+                domains: None,
+                bindings: None,
+            }]),
+            pipelines: None,
+        }],
+    };
+    let yaml = serde_yaml::to_string(&config).unwrap();
+    let mut snapshot = String::from(
+        "# Snapshot of the environment variables taken by pippo before changing them.\n",
+    );
+    if variables
+        .iter()
+        .any(|v| v.variable_type == VariableType::SecretString)
+    {
+        snapshot
+            .push_str("# Secret variables have no value, add them before re-applying this file.\n");
+    }
+    for line in yaml.lines() {
+        snapshot.push_str(line);
+        if line.trim_start() == "type: secretString" {
+            snapshot.push(' ');
+            snapshot.push_str(SNAPSHOT_SECRET_MARKER);
+        }
+        snapshot.push('\n');
+    }
+    snapshot
+}
+
+/// Writes a snapshot of the variables of an environment to a timestamped file and returns its path.
+///
+/// # Arguments
+///
+/// * `dir` - String slice that holds the directory the snapshot is written to
+/// * `program_id` - A u32 that holds the program ID
+/// * `env_id` - An EnvironmentId that holds the environment ID
+/// * `variables` - The variables that are currently set in Cloud Manager
+/// * `timestamp` - Time at which the snapshot is taken
+fn write_env_vars_snapshot(
+    dir: &str,
+    program_id: u32,
+    env_id: EnvironmentId,
+    variables: &[EnvironmentVariable],
+    timestamp: DateTime<Utc>,
+) -> std::io::Result<PathBuf> {
+    std::fs::create_dir_all(dir)?;
+    let path = Path::new(dir).join(snapshot_file_name(program_id, env_id, timestamp));
+    std::fs::write(&path, env_vars_snapshot(program_id, env_id, variables))?;
+    Ok(path)
+}
+
+/// Checks if a variable that should be set is already present in Cloud Manager with the same value.
+///
+/// Deletions and secret values are never considered unchanged, since Cloud Manager does not
+/// return the values of secrets.
+///
+/// # Arguments
+///
+/// * `variable` - The variable that should be set (or deleted, if it has no value)
+/// * `vars_cloud` - The variables that are currently set in Cloud Manager
+fn is_unchanged(variable: &EnvironmentVariable, vars_cloud: &[EnvironmentVariable]) -> bool {
+    if variable.value.is_none() || variable.variable_type == VariableType::SecretString {
+        return false;
+    }
+    vars_cloud.iter().any(|vc| {
+        vc == variable && vc.variable_type == variable.variable_type && vc.value == variable.value
+    })
+}
+
+/// Copies the variables of one environment to another environment of the same program.
+///
+/// Variables of the target that the source doesn't have are kept, unless `prune` is set. Secret
+/// values can't be read from Cloud Manager, so secrets are skipped and have to be set manually.
+///
+/// # Arguments
+///
+/// * `client` - A mutable reference to a CloudManagerClient instance
+/// * `program_id` - A u32 that holds the program ID
+/// * `from_env` - An EnvironmentId that holds the ID of the environment to copy from
+/// * `to_env` - An EnvironmentId that holds the ID of the environment to copy to
+/// * `dry_run` - Only print what would be done
+/// * `prune` - Delete the variables of the target that the source doesn't have
+pub async fn copy_env_vars(
+    client: &mut CloudManagerClient,
+    program_id: u32,
+    from_env: EnvironmentId,
+    to_env: EnvironmentId,
+    dry_run: bool,
+    prune: bool,
+) -> Result<(), ExitCode> {
+    let source = get_environment(client, program_id, from_env)
+        .await
+        .map_err(report_api_error)?;
+    let target = get_environment(client, program_id, to_env)
+        .await
+        .map_err(report_api_error)?;
+    println!(
+        "{:>4} Environment: {} ({}) → {} ({})",
+        "⬛", from_env, source.name, to_env, target.name
+    );
+
+    let vars_source = get_env_vars(client, program_id, from_env)
+        .await
+        .map_err(report_api_error)?
+        .variables;
+    let vars_target = get_env_vars(client, program_id, to_env)
+        .await
+        .map_err(report_api_error)?
+        .variables;
+    let (vars_final, skipped_secrets) = env_vars_to_copy(&vars_source, &vars_target, prune);
+
+    for secret in &skipped_secrets {
+        eprintln!(
+            "{:>8} SKIPPED secret '{}', service: {}",
+            "⚠".yellow(),
+            secret.name,
+            secret.service
+        );
+    }
+
+    if vars_final.is_empty() {
+        println!("{:>8} no changes", "✔");
+    } else {
+        if let Some(vf) = vars_final
+            .iter()
+            .find(|vf| vf.service == EnvironmentVariableServiceType::Invalid)
+        {
+            eprintln!(
+                "{:>8} {}  '{}: {}'",
+                "❌".red(),
+                "ERROR, invalid service type detected for variable".red(),
+                vf.name,
+                vf.service
+            );
+            return Err(ExitCode::InvalidService);
+        }
+
+        for vf in &vars_final {
+            match vf.value {
+                None => println!("{:>8} DELETING '{}', service: {}", "✍", vf.name, vf.service),
+                Some(_) => println!("{:>8} UPDATING '{}', service: {}", "✍", vf.name, vf.service),
+            }
+        }
+
+        if dry_run {
+            println!(
+                "{:>8} --dry-run detected. Not performing any actions.",
+                "⚠️",
+            );
+        } else {
+            match set_env_vars(client, program_id, to_env, &vars_final).await {
+                Ok(StatusCode::NO_CONTENT) => println!("{:>8} Success", "✔"),
+                Ok(_) => {
+                    eprintln!("{:>8} {}", "Error, check output above".red(), "❌".red());
+                    return Err(ExitCode::Incomplete);
+                }
+                Err(error) => return Err(report_api_error(error)),
+            }
+        }
+    }
+
+    if !skipped_secrets.is_empty() {
         eprintln!(
-            "\n{} Not all environments were changed because they were updating and --ci mode is active!",
-            "⚠️"
+            "\n⚠️ {} secret variable(s) could not be copied, set them manually on environment {}!",
+            skipped_secrets.len(),
+            to_env
         );
-        process::exit(2);
     }
+    Ok(())
+}
+
+/// Returns the variables that copying the source variables to the target sends, and the secrets
+/// that can't be copied.
+///
+/// Variables that the target already has with the same value are left out.
+///
+/// # Arguments
+///
+/// * `vars_source` - The variables of the environment to copy from
+/// * `vars_target` - The variables of the environment to copy to
+/// * `prune` - Delete the variables of the target that the source doesn't have
+pub fn env_vars_to_copy(
+    vars_source: &[EnvironmentVariable],
+    vars_target: &[EnvironmentVariable],
+    prune: bool,
+) -> (Vec<EnvironmentVariable>, Vec<EnvironmentVariable>) {
+    let (secrets, mut vars_final): (Vec<EnvironmentVariable>, Vec<EnvironmentVariable>) =
+        vars_source
+            .iter()
+            .cloned()
+            .partition(|vs| vs.variable_type == VariableType::SecretString);
+    vars_final.retain(|vf| !is_unchanged(vf, vars_target));
+
+    // Secrets of the source keep the target's secret with the same name, so they are not pruned
+    if prune {
+        for vt in vars_target {
+            if !vars_source.contains(vt) {
+                vars_final.push(EnvironmentVariable {
+                    name: vt.name.clone(),
+                    value: None,
+                    variable_type: vt.variable_type.clone(),
+                    service: vt.service.clone(),
+                    extra: HashMap::new(),
+                });
+            }
+        }
+    }
+    order_variables(&mut vars_final, |vf| (&vf.service).into());
+    (vars_final, secrets)
 }
 
 /// List the user defined variables for an pipeline.
@@ -367,17 +764,18 @@ pub async fn get_pipeline_vars(
 ) -> Result<PipelineVariablesList, reqwest::Error> {
     let request_path = format!(
         "{}/api/program/{}/pipeline/{}/variables",
-        HOST_NAME, program_id, pipeline_id
+        client.host_name, program_id, pipeline_id
     );
     let response = client
         .perform_request(Method::GET, request_path, None::<()>, None)
         .await?
         .text()
         .await?;
+    client.print_raw_response(&response);
     let variables: PipelineVariablesResponse = serde_json::from_str(response.as_str())
         .unwrap_or_else(|_| {
             throw_adobe_api_error(response);
-            process::exit(1);
+            audit::exit(1);
         });
     Ok(variables.variables_list)
 }
@@ -404,7 +802,7 @@ pub async fn set_pipeline_vars(
 ) -> Result<StatusCode, reqwest::Error> {
     let request_path = format!(
         "{}/api/program/{}/pipeline/{}/variables",
-        HOST_NAME, program_id, pipeline_id
+        client.host_name, program_id, pipeline_id
     );
     let response = client
         .perform_request(Method::PATCH, request_path, Some(variables), None)
@@ -422,7 +820,7 @@ pub async fn set_pipeline_vars(
 /// Sets pipeline variables that are read from a given YAML file.
 ///
 /// When the target pipeline is currently updating, the function will retry until its state
-/// is back to ready.
+/// is back to ready. Errors are printed and returned as the exit code the run should end with.
 ///
 /// # Arguments
 ///
@@ -433,27 +831,52 @@ pub async fn set_pipeline_vars_from_file(
     client: &mut CloudManagerClient,
     ci_mode: bool,
     dry_run: bool,
-) {
-    let input = std::fs::read_to_string(file_path).expect("Unable to read file");
-    let input: YamlConfig = serde_yaml::from_str(input.as_str()).unwrap_or_else(|err| {
-        eprintln!("{} {}", "❌ Malformed YAML: ".red(), err);
-        process::exit(1);
-    });
+) -> Result<(), ExitCode> {
+    let input = read_config(client, file_path).await;
+
+    if is_env_vars_config(&input) {
+        eprintln!(
+            "{:>4} {}",
+            "⚠".yellow().bold(),
+            format!(
+                "WARNING, {} has environments but no pipelines, it looks like a config for `env vars set`. No pipeline variables will be set!",
+                file_path
+            )
+            .yellow()
+            .bold()
+        );
+    }
+
+    // Check all services before the first change, so a typo doesn't leave the pipelines half updated
+    let invalid_services = invalid_pipeline_services(&input);
+    if !invalid_services.is_empty() {
+        for location in &invalid_services {
+            eprintln!(
+                "{:>4} {}  {}",
+                "❌".red(),
+                "ERROR, invalid service type for a pipeline variable, use build, uiTest or functionalTest".red(),
+                location
+            );
+        }
+        return Err(ExitCode::InvalidService);
+    }
 
     let mut skipped_pipeline: bool = false;
 
     let programs = input.programs;
 
     for p in &programs {
-        println!("☁ Program: {}", p.id,);
-        for l in p.pipelines.as_ref().unwrap() {
-            let pipeline = get_pipeline(client, p.id, l.id).await.unwrap();
+        println!("☁ Program: {}", p.program_id(),);
+        for l in p.pipelines.iter().flatten() {
+            let pipeline = get_pipeline(client, p.program_id(), l.id)
+                .await
+                .map_err(report_api_error)?;
 
             println!("{:>4} Pipeline: {} ({})", "⬛", l.id, pipeline.name);
 
             // ensure there are no duplicate environment variables
             let duplicates = find_duplicates(l.variables.clone());
-            if duplicates.len() > 0 {
+            if !duplicates.is_empty() {
                 for dv in &duplicates {
                     eprintln!(
                         "{:>8} {}  name: '{}' service: {}",
@@ -463,7 +886,7 @@ pub async fn set_pipeline_vars_from_file(
                         dv.service
                     );
                 }
-                process::exit(4);
+                return Err(ExitCode::Duplicate);
             }
 
             // The vector that holds the final variables that will be set or deleted. Will be constructed
@@ -473,7 +896,9 @@ pub async fn set_pipeline_vars_from_file(
 
             // Check if the targeted environment is ready
             '_retry: loop {
-                let pipeline = get_pipeline(client, p.id, l.id).await.unwrap();
+                let pipeline = get_pipeline(client, p.program_id(), l.id)
+                    .await
+                    .map_err(report_api_error)?;
 
                 if pipeline.status == "BUSY" && ci_mode {
                     skipped_pipeline = true;
@@ -504,12 +929,40 @@ pub async fn set_pipeline_vars_from_file(
                             }
                             VariableType::SecretString => {
                                 // If the value is a secret, check if it's encrypted and decrypt it if that's the case
-                                let tmp_loop_var_value = tmp_loop_var.clone().value.unwrap();
-                                if tmp_loop_var_value.starts_with("$enc") {
-                                    let encrypted_value =
-                                        tmp_loop_var_value.split_whitespace().collect::<Vec<_>>();
-                                    let decrypted_value = decrypt(encrypted_value[1].to_string());
-                                    tmp_loop_var.value = Some(decrypted_value);
+                                let tmp_loop_var_value = match tmp_loop_var.value.clone() {
+                                    Some(value) => value,
+                                    None => {
+                                        eprintln!(
+                                            "{:>8} {}  '{}: {}'",
+                                            "❌".red(),
+                                            "ERROR, secret variable without value (restored from a snapshot?)".red(),
+                                            tmp_loop_var.name,
+                                            tmp_loop_var.service
+                                        );
+                                        return Err(ExitCode::Failure);
+                                    }
+                                };
+                                if EncryptedValue::is_encrypted(&tmp_loop_var_value) {
+                                    let decrypted_value = EncryptedValue::parse(
+                                        &tmp_loop_var_value,
+                                    )
+                                    .and_then(|value| decrypt(&value).map_err(|e| e.to_string()));
+                                    match decrypted_value {
+                                        Ok(value) => tmp_loop_var.value = Some(value),
+                                        Err(err) => {
+                                            eprintln!(
+                                            "{:>8} {}  '{}: {}' in program {} / pipeline {}: {}",
+                                            "❌".red(),
+                                            "ERROR, secret variable can't be decrypted".red(),
+                                            tmp_loop_var.name,
+                                            tmp_loop_var.service,
+                                            p.program_id(),
+                                            l.id,
+                                            err
+                                        );
+                                            return Err(ExitCode::Undecryptable);
+                                        }
+                                    }
                                 }
                                 vars_final.push(tmp_loop_var);
                             }
@@ -518,21 +971,12 @@ pub async fn set_pipeline_vars_from_file(
 
                     // If a variable is only present on Cloud Manager and not in the YAML, then we
                     // will set its value to None and push it to vars_final, so it will be deleted.
-                    let vars_cloud = get_pipeline_vars(client, p.id, &l.id)
+                    let vars_cloud = get_pipeline_vars(client, p.program_id(), &l.id)
                         .await
-                        .unwrap()
+                        .map_err(report_api_error)?
                         .variables;
-                    for vc in vars_cloud {
-                        if !vars_yaml.clone().contains(&vc) {
-                            let variable_to_be_deleted = PipelineVariable {
-                                name: vc.name,
-                                value: None,
-                                variable_type: vc.variable_type,
-                                service: vc.service,
-                            };
-                            vars_final.push(variable_to_be_deleted);
-                        }
-                    }
+                    vars_final.extend(pipeline_vars_to_delete(&vars_yaml, vars_cloud));
+                    order_variables(&mut vars_final, |vf| (&vf.service).into());
 
                     if let Some(vf) = vars_final
                         .iter()
@@ -545,7 +989,7 @@ pub async fn set_pipeline_vars_from_file(
                             vf.name,
                             vf.service
                         );
-                        process::exit(3);
+                        return Err(ExitCode::InvalidService);
                     }
 
                     for vf in &vars_final {
@@ -571,7 +1015,7 @@ pub async fn set_pipeline_vars_from_file(
                             "⚠️",
                         );
                     } else {
-                        match set_pipeline_vars(client, p.id, l.id, &vars_final).await {
+                        match set_pipeline_vars(client, p.program_id(), l.id, &vars_final).await {
                             Ok(status) => match status {
                                 StatusCode::NO_CONTENT => {
                                     println!("{:>8} Success", "✔");
@@ -582,13 +1026,10 @@ pub async fn set_pipeline_vars_from_file(
                                         "Error, check output above".red(),
                                         "❌".red()
                                     );
-                                    process::exit(2);
+                                    return Err(ExitCode::Incomplete);
                                 }
                             },
-                            Err(error) => {
-                                eprintln!("{} {}", "❌ API error: ".red().bold(), error);
-                                process::exit(1);
-                            }
+                            Err(error) => return Err(report_api_error(error)),
                         }
                     }
 
@@ -598,13 +1039,585 @@ pub async fn set_pipeline_vars_from_file(
         }
     }
 
-    if skipped_pipeline == true {
+    if skipped_pipeline {
+        eprintln!(
+            "\n⚠️ Not all pipelines were changed because they were busy and --ci mode is active!"
+        );
+        return Err(ExitCode::Incomplete);
+    }
+    Ok(())
+}
+
+/// Returns the pipeline variables that are set in Cloud Manager but not in the YAML, with their
+/// value removed so that sending them deletes them.
+///
+/// Variables are matched by name and service, so a variable of one step (e.g. `functionalTest`)
+/// never deletes or replaces the variable with the same name of another step (e.g. `build`).
+///
+/// # Arguments
+///
+/// * `vars_yaml` - The variables of the pipeline in the YAML
+/// * `vars_cloud` - The variables that are currently set in Cloud Manager
+pub fn pipeline_vars_to_delete(
+    vars_yaml: &[PipelineVariable],
+    vars_cloud: Vec<PipelineVariable>,
+) -> Vec<PipelineVariable> {
+    vars_cloud
+        .into_iter()
+        .filter(|vc| !vars_yaml.contains(vc))
+        .map(|vc| PipelineVariable {
+            name: vc.name,
+            value: None,
+            variable_type: vc.variable_type,
+            service: vc.service,
+        })
+        .collect()
+}
+
+/// Merges the program-wide default variables with those of an environment.
+///
+/// A variable of the environment overrides the default with the same name and service, all other
+/// defaults are kept and variables that only the environment defines are added.
+///
+/// # Arguments
+///
+/// * `defaults` - The variables from the `defaults` block of the program
+/// * `env_vars` - The variables of the environment
+pub fn merge_with_defaults(
+    defaults: &[EnvironmentVariable],
+    env_vars: &[EnvironmentVariable],
+) -> Vec<EnvironmentVariable> {
+    let mut merged: Vec<EnvironmentVariable> = defaults
+        .iter()
+        .filter(|d| !env_vars.contains(d))
+        .cloned()
+        .collect();
+    merged.extend(env_vars.iter().cloned());
+    merged
+}
+
+/// Sorts the variables of a PATCH so that output and request body are the same on every run.
+///
+/// Updates come before deletions, each sorted by name and then by service.
+///
+/// # Arguments
+///
+/// * `variables` - The variables that are about to be sent
+/// * `service` - Returns the name of the service of a variable
+pub fn order_variables<T, F>(variables: &mut [T], service: F)
+where
+    T: VariableName,
+    F: Fn(&T) -> &'static str,
+{
+    variables.sort_by(|a, b| {
+        (a.is_deletion(), a.name(), service(a)).cmp(&(b.is_deletion(), b.name(), service(b)))
+    });
+}
+
+/// Variables that can be ordered by [`order_variables`]
+pub trait VariableName {
+    /// Name of the variable
+    fn name(&self) -> &str;
+    /// Whether the variable is sent without a value, which deletes it
+    fn is_deletion(&self) -> bool;
+}
+
+impl VariableName for EnvironmentVariable {
+    fn name(&self) -> &str {
+        &self.name
+    }
+
+    fn is_deletion(&self) -> bool {
+        self.value.is_none()
+    }
+}
+
+impl VariableName for PipelineVariable {
+    fn name(&self) -> &str {
+        &self.name
+    }
+
+    fn is_deletion(&self) -> bool {
+        self.value.is_none()
+    }
+}
+
+/// Maximum length of a variable name that Cloud Manager accepts
+pub const MAX_VARIABLE_NAME_LENGTH: usize = 100;
+
+/// Maximum length of a variable value that Cloud Manager accepts
+pub const MAX_VARIABLE_VALUE_LENGTH: usize = 2048;
+
+/// Maximum number of variables that an environment can have
+pub const MAX_VARIABLES_PER_ENVIRONMENT: usize = 200;
+
+/// Maximum size in bytes of the JSON body of a variables PATCH.
+///
+/// Cloud Manager doesn't document a limit for the request body, so this is an assumed value: it
+/// leaves room for the largest body that the documented limits allow with ASCII values and
+/// rejects bodies that only multi-byte values make this big.
+pub const MAX_PAYLOAD_BYTES: usize = 512 * 1024;
+
+/// Name prefixes that Cloud Manager reserves for its own variables
+const RESERVED_VARIABLE_PREFIXES: [&str; 3] = ["INTERNAL_", "ADOBE_", "CONST_"];
+
+/// Checks the variables of a PATCH against the limits of Cloud Manager before sending it.
+///
+/// Returns a message for every value that is too long, one that names the largest variables if
+/// the serialized body is bigger than [`MAX_PAYLOAD_BYTES`] and one if the environment would end up
+/// with more variables than it can have.
+///
+/// # Arguments
+///
+/// * `variables` - The variables that are about to be sent, with decrypted secrets
+/// * `vars_cloud` - The variables that are currently set in Cloud Manager
+pub fn preflight_env_vars(
+    variables: &[EnvironmentVariable],
+    vars_cloud: &[EnvironmentVariable],
+) -> Vec<String> {
+    let mut problems: Vec<String> = variables
+        .iter()
+        .filter_map(|v| {
+            v.value
+                .as_ref()
+                .map(|value| value.chars().count())
+                .filter(|length| *length > MAX_VARIABLE_VALUE_LENGTH)
+                .map(|length| {
+                    format!(
+                        "value of '{}' (service: {}) has {} characters, the limit is {}",
+                        v.name, v.service, length, MAX_VARIABLE_VALUE_LENGTH
+                    )
+                })
+        })
+        .collect();
+
+    let total = serde_json::to_vec(variables).map_or(0, |body| body.len());
+    if total > MAX_PAYLOAD_BYTES {
+        let mut sizes: Vec<(&EnvironmentVariable, usize)> = variables
+            .iter()
+            .map(|v| (v, serde_json::to_vec(v).map_or(0, |body| body.len())))
+            .collect();
+        sizes.sort_by(|a, b| b.1.cmp(&a.1).then_with(|| a.0.name.cmp(&b.0.name)));
+        // The largest variables that have to go for the body to fit
+        let mut remaining = total;
+        let mut largest = vec![];
+        for (v, size) in sizes {
+            if remaining <= MAX_PAYLOAD_BYTES {
+                break;
+            }
+            remaining -= size;
+            largest.push(format!("'{}' ({} bytes)", v.name, size));
+        }
+        problems.push(format!(
+            "variables add up to {} bytes, the limit is {}; largest: {}",
+            total,
+            MAX_PAYLOAD_BYTES,
+            largest.join(", ")
+        ));
+    }
+
+    let count = count_after_patch(variables, vars_cloud);
+    if count > MAX_VARIABLES_PER_ENVIRONMENT {
+        problems.push(format!(
+            "the environment would have {} variables, the limit is {}",
+            count, MAX_VARIABLES_PER_ENVIRONMENT
+        ));
+    }
+    problems
+}
+
+/// Counts the variables that an environment has once a PATCH is applied. Variables are matched
+/// by name and service; a variable without a value deletes the one in Cloud Manager.
+///
+/// # Arguments
+///
+/// * `variables` - The variables that are about to be sent
+/// * `vars_cloud` - The variables that are currently set in Cloud Manager
+pub fn count_after_patch(
+    variables: &[EnvironmentVariable],
+    vars_cloud: &[EnvironmentVariable],
+) -> usize {
+    let untouched = vars_cloud
+        .iter()
+        .filter(|vc| !variables.contains(vc))
+        .count();
+    untouched + variables.iter().filter(|v| v.value.is_some()).count()
+}
+
+/// A problem that `vars validate` found in a variables YAML
+#[derive(Debug, PartialEq)]
+pub struct ValidationProblem {
+    /// Where the problem is, e.g. `program 1 / environment 2 / NAME (author)`
+    pub location: String,
+    pub message: String,
+}
+
+impl fmt::Display for ValidationProblem {
+    fn fmt(&self, formatter: &mut fmt::Formatter) -> fmt::Result {
+        write!(formatter, "{}: {}", self.location, self.message)
+    }
+}
+
+/// Checks a single variable against the constraints of Cloud Manager, without any API request.
+///
+/// # Arguments
+///
+/// * `location` - Where the variable is defined, used in the problems
+/// * `name` - Name of the variable
+/// * `value` - Value of the variable as written in the YAML
+/// * `variable_type` - Type of the variable
+/// * `decrypt` - Decrypts a `$enc` value
+fn check_variable<F, E>(
+    location: &str,
+    name: &str,
+    value: Option<&str>,
+    variable_type: &VariableType,
+    decrypt: &F,
+) -> Vec<ValidationProblem>
+where
+    F: Fn(&EncryptedValue) -> Result<String, E>,
+    E: fmt::Display,
+{
+    let mut problems = vec![];
+    let mut problem = |message: String| {
+        problems.push(ValidationProblem {
+            location: location.to_string(),
+            message,
+        })
+    };
+
+    let mut chars = name.chars();
+    let valid_start = chars
+        .next()
+        .is_some_and(|c| c.is_ascii_alphabetic() || c == '_');
+    if !valid_start || !chars.all(|c| c.is_ascii_alphanumeric() || c == '_') {
+        problem(String::from(
+            "name must start with a letter or underscore and only contain letters, digits and underscores",
+        ));
+    }
+    if name.chars().count() > MAX_VARIABLE_NAME_LENGTH {
+        problem(format!(
+            "name is longer than {} characters",
+            MAX_VARIABLE_NAME_LENGTH
+        ));
+    }
+    if let Some(prefix) = RESERVED_VARIABLE_PREFIXES
+        .iter()
+        .find(|prefix| name.starts_with(*prefix))
+    {
+        problem(format!("names starting with {} are reserved", prefix));
+    }
+
+    let value = match value {
+        Some(value) => value,
+        None => {
+            problem(String::from("variable has no value"));
+            return problems;
+        }
+    };
+    let plain_value = if EncryptedValue::is_encrypted(value) {
+        match (variable_type, EncryptedValue::parse(value)) {
+            (VariableType::String, _) => {
+                problem(String::from(
+                    "encrypted value in a plain variable, use type secretString",
+                ));
+                None
+            }
+            (VariableType::SecretString, Ok(encrypted)) => match decrypt(&encrypted) {
+                Ok(plain) => Some(plain),
+                Err(err) => {
+                    problem(format!("encrypted value can't be decrypted: {}", err));
+                    None
+                }
+            },
+            (VariableType::SecretString, Err(err)) => {
+                problem(err);
+                None
+            }
+        }
+    } else {
+        Some(value.to_string())
+    };
+    if plain_value.is_some_and(|plain| plain.chars().count() > MAX_VARIABLE_VALUE_LENGTH) {
+        problem(format!(
+            "value is longer than {} characters",
+            MAX_VARIABLE_VALUE_LENGTH
+        ));
+    }
+    problems
+}
+
+/// Checks the environment variables of a YAML config offline: names, values, service types,
+/// duplicates and that every `$enc` value can be decrypted.
+///
+/// # Arguments
+///
+/// * `config` - The parsed YAML config
+/// * `decrypt` - Decrypts a `$enc` value
+pub fn validate_env_vars<F, E>(config: &YamlConfig, decrypt: F) -> Vec<ValidationProblem>
+where
+    F: Fn(&EncryptedValue) -> Result<String, E>,
+    E: fmt::Display,
+{
+    let mut problems = vec![];
+    for p in &config.programs {
+        let defaults = p.defaults.as_ref().map_or(&[][..], |d| &d.variables[..]);
+        let mut scopes = vec![(format!("program {} / defaults", p.label()), defaults)];
+        for e in p.environments.iter().flatten() {
+            scopes.push((
+                format!("program {} / environment {}", p.label(), e.id),
+                &e.variables[..],
+            ));
+        }
+        for (scope, variables) in scopes {
+            for dv in find_duplicates(variables.to_vec()) {
+                problems.push(ValidationProblem {
+                    location: format!("{} / {} ({})", scope, dv.name, dv.service),
+                    message: String::from("duplicate variable definition"),
+                });
+            }
+            for v in variables {
+                let location = format!("{} / {} ({})", scope, v.name, v.service);
+                if v.service == EnvironmentVariableServiceType::Invalid {
+                    problems.push(ValidationProblem {
+                        location: location.clone(),
+                        message: String::from(
+                            "invalid service, use author, publish, preview or leave it out",
+                        ),
+                    });
+                }
+                problems.extend(check_variable(
+                    &location,
+                    &v.name,
+                    v.value.as_deref(),
+                    &v.variable_type,
+                    &decrypt,
+                ));
+            }
+        }
+    }
+    problems
+}
+
+/// Returns whether a YAML config only has environments, i.e. was probably written for
+/// `env vars set` and not for `pipeline vars set`.
+///
+/// # Arguments
+///
+/// * `config` - The parsed YAML config
+pub fn is_env_vars_config(config: &YamlConfig) -> bool {
+    config.programs.iter().any(|p| p.environments.is_some())
+        && config.programs.iter().all(|p| p.pipelines.is_none())
+}
+
+/// Returns the locations of all pipeline variables whose service isn't valid for pipelines.
+///
+/// # Arguments
+///
+/// * `config` - The parsed YAML config
+pub fn invalid_pipeline_services(config: &YamlConfig) -> Vec<String> {
+    let mut locations = vec![];
+    for p in &config.programs {
+        for l in p.pipelines.iter().flatten() {
+            for v in &l.variables {
+                if v.service == PipelineVariableServiceType::Invalid {
+                    locations.push(format!(
+                        "program {} / pipeline {} / {}",
+                        p.label(),
+                        l.id,
+                        v.name
+                    ));
+                }
+            }
+        }
+    }
+    locations
+}
+
+/// Checks the pipeline variables of a YAML config offline, like [`validate_env_vars`].
+///
+/// # Arguments
+///
+/// * `config` - The parsed YAML config
+/// * `decrypt` - Decrypts a `$enc` value
+pub fn validate_pipeline_vars<F, E>(config: &YamlConfig, decrypt: F) -> Vec<ValidationProblem>
+where
+    F: Fn(&EncryptedValue) -> Result<String, E>,
+    E: fmt::Display,
+{
+    let mut problems = vec![];
+    for p in &config.programs {
+        for l in p.pipelines.iter().flatten() {
+            let scope = format!("program {} / pipeline {}", p.label(), l.id);
+            for dv in find_duplicates(l.variables.clone()) {
+                problems.push(ValidationProblem {
+                    location: format!("{} / {} ({})", scope, dv.name, dv.service),
+                    message: String::from("duplicate variable definition"),
+                });
+            }
+            for v in &l.variables {
+                let location = format!("{} / {} ({})", scope, v.name, v.service);
+                if v.service == PipelineVariableServiceType::Invalid {
+                    problems.push(ValidationProblem {
+                        location: location.clone(),
+                        message: String::from(
+                            "invalid service, use build, uiTest or functionalTest",
+                        ),
+                    });
+                }
+                problems.extend(check_variable(
+                    &location,
+                    &v.name,
+                    v.value.as_deref(),
+                    &v.variable_type,
+                    &decrypt,
+                ));
+            }
+        }
+    }
+    problems
+}
+
+/// Outcome of decrypting a single `$enc` value for `verify-secrets`
+#[derive(Debug, PartialEq)]
+pub struct SecretCheck {
+    /// Where the value is, e.g. `program 1 / environment 2 / NAME`
+    pub location: String,
+    /// Why the value can't be decrypted, `None` if it can
+    pub error: Option<String>,
+}
+
+/// Tries to decrypt every `$enc` value of a YAML config, environment and pipeline variables alike.
+///
+/// The decrypted values are dropped right away, only whether decrypting worked is returned.
+///
+/// # Arguments
+///
+/// * `config` - The parsed YAML config
+/// * `decrypt` - Decrypts a `$enc` value
+pub fn verify_secrets<F, E>(config: &YamlConfig, decrypt: F) -> Vec<SecretCheck>
+where
+    F: Fn(&EncryptedValue) -> Result<String, E>,
+    E: fmt::Display,
+{
+    let mut values: Vec<(String, Option<&str>)> = vec![];
+    for p in &config.programs {
+        for v in p.defaults.iter().flat_map(|d| &d.variables) {
+            let location = format!("program {} / defaults / {}", p.label(), v.name);
+            values.push((location, v.value.as_deref()));
+        }
+        for e in p.environments.iter().flatten() {
+            for v in &e.variables {
+                let location = format!("program {} / environment {} / {}", p.label(), e.id, v.name);
+                values.push((location, v.value.as_deref()));
+            }
+        }
+        for l in p.pipelines.iter().flatten() {
+            for v in &l.variables {
+                let location = format!("program {} / pipeline {} / {}", p.label(), l.id, v.name);
+                values.push((location, v.value.as_deref()));
+            }
+        }
+    }
+    values
+        .into_iter()
+        .filter_map(|(location, value)| {
+            value
+                .filter(|v| EncryptedValue::is_encrypted(v))
+                .map(|v| (location, v))
+        })
+        .map(|(location, value)| {
+            let error = match EncryptedValue::parse(value) {
+                Ok(encrypted) => decrypt(&encrypted).err().map(|err| err.to_string()),
+                Err(err) => Some(err),
+            };
+            SecretCheck { location, error }
+        })
+        .collect()
+}
+
+/// Checks that every `$enc` value of a YAML file can be decrypted with the local key and prints
+/// the outcome per variable, never the values.
+///
+/// Returns whether all values can be decrypted.
+///
+/// # Arguments
+///
+/// * `file_path` - String slice that holds the path to the YAML file
+pub fn verify_secrets_file(file_path: &str) -> bool {
+    let config = read_input(file_path)
+        .map_err(|err| format!("Unable to read {}: {}", file_path, err))
+        .and_then(|input| YamlConfig::parse(&input));
+    let config = match config {
+        Ok(config) => config,
+        Err(err) => {
+            eprintln!("{:>4} {}", "❌".red(), err.red());
+            return false;
+        }
+    };
+
+    let checks = verify_secrets(&config, decrypt);
+    for check in &checks {
+        match &check.error {
+            None => println!("{:>4} {}", "✔".green(), check.location),
+            Some(err) => eprintln!("{:>4} {}: {}", "❌".red(), check.location, err),
+        }
+    }
+    let failed = checks.iter().filter(|check| check.error.is_some()).count();
+    if checks.is_empty() {
+        println!("{:>4} {} has no encrypted values", "ℹ", file_path);
+    } else if failed == 0 {
+        println!(
+            "\nAll {} encrypted values of {} can be decrypted",
+            checks.len(),
+            file_path
+        );
+    } else {
         eprintln!(
-            "\n{} Not all pipelines were changed because they were busy and --ci mode is active!",
-            "⚠️"
+            "\n{} of {} encrypted values in {} can't be decrypted",
+            failed,
+            checks.len(),
+            file_path
         );
-        process::exit(2);
     }
+    failed == 0
+}
+
+/// Validates the variables of a YAML file offline and prints a report of all problems.
+///
+/// Returns whether the file is valid. Values are decrypted with the local key, but never printed.
+///
+/// # Arguments
+///
+/// * `file_path` - String slice that holds the path to the YAML file
+/// * `pipeline` - Whether the pipeline variables are validated instead of the environment variables
+pub fn validate_vars_file(file_path: &str, pipeline: bool) -> bool {
+    let config = read_input(file_path)
+        .map_err(|err| format!("Unable to read {}: {}", file_path, err))
+        .and_then(|input| YamlConfig::parse(&input));
+    let config = match config {
+        Ok(config) => config,
+        Err(err) => {
+            eprintln!("{:>4} {}", "❌".red(), err.red());
+            return false;
+        }
+    };
+
+    let problems = if pipeline {
+        validate_pipeline_vars(&config, decrypt)
+    } else {
+        validate_env_vars(&config, decrypt)
+    };
+
+    for problem in &problems {
+        eprintln!("{:>4} {}", "❌".red(), problem);
+    }
+    if problems.is_empty() {
+        println!("{:>4} {} is valid", "✔".green(), file_path);
+    } else {
+        eprintln!("\n{} problems found in {}", problems.len(), file_path);
+    }
+    problems.is_empty()
 }
 
 fn find_duplicates<T: Eq + Hash + Clone>(vec: Vec<T>) -> Vec<T> {
@@ -619,3 +1632,663 @@ fn find_duplicates<T: Eq + Hash + Clone>(vec: Vec<T>) -> Vec<T> {
 
     duplicates
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::client::mock::{MockResponse, MockServer};
+    use crate::models::tests::{read_json_from_file, read_yaml_from_file};
+    use chrono::TimeZone;
+
+    fn env_var(
+        name: &str,
+        value: Option<&str>,
+        variable_type: VariableType,
+    ) -> EnvironmentVariable {
+        EnvironmentVariable {
+            name: name.to_string(),
+            value: value.map(String::from),
+            variable_type,
+            service: EnvironmentVariableServiceType::All,
+            extra: HashMap::new(),
+        }
+    }
+
+    #[tokio::test]
+    async fn patch_leaves_out_unknown_fields() {
+        let server = MockServer::start();
+        server.route(
+            "PATCH",
+            "/api/program/1/environment/2/variables",
+            vec![MockResponse::json(serde_json::json!([]))],
+        );
+        let mut variable = env_var("A", Some("a"), VariableType::String);
+        variable
+            .extra
+            .insert(String::from("status"), serde_json::json!("ready"));
+        let mut client = server.client();
+        let status = set_env_vars(&mut client, 1, EnvironmentId(2), &[variable])
+            .await
+            .unwrap();
+        assert_eq!(status, StatusCode::OK);
+        let body: serde_json::Value = serde_json::from_str(&server.requests()[0].body).unwrap();
+        assert_eq!(
+            body,
+            serde_json::json!([{"name": "A", "value": "a", "type": "string"}])
+        );
+    }
+
+    #[tokio::test]
+    async fn add_only_does_not_decrypt_existing_secrets() {
+        let environments: serde_json::Value =
+            read_json_from_file("test/test_environment_response.json").unwrap();
+        let server = MockServer::start();
+        server
+            .route(
+                "GET",
+                "/api/program/11111/environment/33333",
+                vec![MockResponse::json(
+                    environments["_embedded"]["environments"][1].clone(),
+                )],
+            )
+            .route(
+                "GET",
+                "/api/program/11111/environment/33333/variables",
+                vec![MockResponse::json(
+                    read_json_from_file("test/variables/environment_variables_response.json")
+                        .unwrap(),
+                )],
+            )
+            .route(
+                "PATCH",
+                "/api/program/11111/environment/33333/variables",
+                vec![MockResponse::new(204, "")],
+            );
+        let path = std::env::temp_dir().join(format!("pippo-vars-{}.yml", uuid::Uuid::new_v4()));
+        std::fs::write(
+            &path,
+            "programs:\n  - id: 11111\n    environments:\n      - id: 33333\n        variables:\n          - name: SECRET_VARIABLE\n            value: $enc:gcm1 not-decryptable\n            type: secretString\n          - name: NEW_VARIABLE\n            value: added\n            type: string\n",
+        )
+        .unwrap();
+
+        let result = set_env_vars_from_file(
+            path.to_str().unwrap(),
+            &mut server.client(),
+            false,
+            false,
+            false,
+            None,
+            SyncMode::AddOnly,
+        )
+        .await;
+        std::fs::remove_file(path).unwrap();
+        assert_eq!(result, Ok(()));
+        let patch = server
+            .requests()
+            .into_iter()
+            .find(|r| r.method == "PATCH")
+            .unwrap();
+        let body: serde_json::Value = serde_json::from_str(&patch.body).unwrap();
+        assert_eq!(
+            body,
+            serde_json::json!([{"name": "NEW_VARIABLE", "value": "added", "type": "string"}])
+        );
+    }
+
+    #[test]
+    fn copy_skips_secrets_and_unchanged_variables() {
+        let source = vec![
+            env_var("LOG_LEVEL", Some("debug"), VariableType::String),
+            env_var("HOST", Some("dev.example.com"), VariableType::String),
+            env_var("TOKEN", None, VariableType::SecretString),
+        ];
+        let target = vec![
+            env_var("LOG_LEVEL", Some("debug"), VariableType::String),
+            env_var("HOST", Some("stage.example.com"), VariableType::String),
+            env_var("OTHER", Some("kept"), VariableType::String),
+        ];
+
+        let (vars_final, secrets) = env_vars_to_copy(&source, &target, false);
+        let sent: Vec<(&str, Option<&str>)> = vars_final
+            .iter()
+            .map(|v| (v.name.as_str(), v.value.as_deref()))
+            .collect();
+        assert_eq!(sent, vec![("HOST", Some("dev.example.com"))]);
+        assert_eq!(secrets.len(), 1);
+        assert_eq!(secrets[0].name, "TOKEN");
+    }
+
+    #[test]
+    fn copy_with_prune_deletes_what_the_source_does_not_have() {
+        let source = vec![
+            env_var("LOG_LEVEL", Some("debug"), VariableType::String),
+            env_var("TOKEN", None, VariableType::SecretString),
+        ];
+        let target = vec![
+            env_var("OTHER", Some("dropped"), VariableType::String),
+            env_var("TOKEN", None, VariableType::SecretString),
+            env_var("OLD_TOKEN", None, VariableType::SecretString),
+        ];
+
+        let (vars_final, _) = env_vars_to_copy(&source, &target, true);
+        let sent: Vec<(&str, bool)> = vars_final
+            .iter()
+            .map(|v| (v.name.as_str(), v.value.is_none()))
+            .collect();
+        assert_eq!(
+            sent,
+            vec![("LOG_LEVEL", false), ("OLD_TOKEN", true), ("OTHER", true)]
+        );
+    }
+
+    #[test]
+    fn pipeline_variables_of_other_steps_are_not_clobbered() {
+        let vobj: PipelineVariablesResponse =
+            read_json_from_file("test/variables/pipeline_variables_response.json").unwrap();
+        let vars_cloud = vobj.variables_list.variables;
+        let mut functional_test = vars_cloud[1].clone();
+        functional_test.service = PipelineVariableServiceType::FunctionalTest;
+        functional_test.value = Some(String::from("functional test variable"));
+        let vars_yaml = vec![
+            vars_cloud[0].clone(),
+            vars_cloud[1].clone(),
+            vars_cloud[2].clone(),
+            functional_test,
+        ];
+
+        let deleted = pipeline_vars_to_delete(&vars_yaml, vars_cloud);
+        let deleted: Vec<(&str, &PipelineVariableServiceType, bool)> = deleted
+            .iter()
+            .map(|v| (v.name.as_str(), &v.service, v.value.is_none()))
+            .collect();
+        // VARIABLE of build and uiTest are kept next to the new one of functionalTest
+        assert_eq!(
+            deleted,
+            vec![
+                (
+                    "SECRET_VARIABLE",
+                    &PipelineVariableServiceType::FunctionalTest,
+                    true
+                ),
+                (
+                    "INVALID_SERVICE_VARIABLE",
+                    &PipelineVariableServiceType::Invalid,
+                    true
+                ),
+            ]
+        );
+    }
+
+    #[test]
+    fn updates_come_before_deletions_sorted_by_name_and_service() {
+        let mut author_token = env_var("TOKEN", Some("a"), VariableType::String);
+        author_token.service = EnvironmentVariableServiceType::Author;
+        let mut vars = vec![
+            env_var("OLD", None, VariableType::String),
+            author_token,
+            env_var("TOKEN", Some("b"), VariableType::String),
+            env_var("ABANDONED", None, VariableType::SecretString),
+            env_var("LOG_LEVEL", Some("debug"), VariableType::String),
+        ];
+        order_variables(&mut vars, |v| (&v.service).into());
+
+        let order: Vec<(&str, bool)> = vars
+            .iter()
+            .map(|v| (v.name.as_str(), v.value.is_none()))
+            .collect();
+        assert_eq!(
+            order,
+            vec![
+                ("LOG_LEVEL", false),
+                ("TOKEN", false),
+                ("TOKEN", false),
+                ("ABANDONED", true),
+                ("OLD", true)
+            ]
+        );
+        assert_eq!(vars[1].service, EnvironmentVariableServiceType::All);
+        assert_eq!(vars[2].service, EnvironmentVariableServiceType::Author);
+    }
+
+    #[test]
+    fn snapshot_file_name_contains_ids_and_timestamp() {
+        let timestamp = Utc.with_ymd_and_hms(2025, 10, 14, 8, 30, 5).unwrap();
+        assert_eq!(
+            snapshot_file_name(11111, EnvironmentId(22222), timestamp),
+            "snapshot-11111-22222-20251014T083005Z.yml"
+        );
+    }
+
+    #[test]
+    fn snapshot_can_be_read_as_yaml_config() {
+        let vars_cloud = vec![
+            env_var("LOG_LEVEL", Some("debug"), VariableType::String),
+            env_var("API_TOKEN", None, VariableType::SecretString),
+        ];
+        let snapshot = env_vars_snapshot(11111, EnvironmentId(22222), &vars_cloud);
+
+        assert!(snapshot.starts_with("# Snapshot of the environment variables"));
+        assert!(snapshot.contains("# Secret variables have no value"));
+        assert!(snapshot.contains(&format!("type: secretString {}", SNAPSHOT_SECRET_MARKER)));
+        assert!(snapshot.contains("type: string\n"));
+
+        let config: YamlConfig = serde_yaml::from_str(&snapshot).unwrap();
+        assert_eq!(config.version, 1);
+        assert_eq!(config.programs[0].id, Some(11111));
+        let env = &config.programs[0].environments.as_ref().unwrap()[0];
+        assert_eq!(env.id, EnvironmentId(22222));
+        assert_eq!(env.variables.len(), 2);
+        assert_eq!(env.variables[0].value.as_deref(), Some("debug"));
+        assert_eq!(env.variables[1].variable_type, VariableType::SecretString);
+        assert!(env.variables[1].value.is_none());
+    }
+
+    #[test]
+    fn snapshot_without_secrets_has_no_secret_hint() {
+        let vars_cloud = vec![env_var("LOG_LEVEL", Some("debug"), VariableType::String)];
+        let snapshot = env_vars_snapshot(11111, EnvironmentId(22222), &vars_cloud);
+        assert!(!snapshot.contains("SECRET"));
+        assert!(!snapshot.contains("Secret variables"));
+    }
+
+    #[test]
+    fn merge_with_defaults_overrides_by_name_and_service() {
+        let defaults = vec![
+            env_var("LOG_LEVEL", Some("info"), VariableType::String),
+            env_var(
+                "API_URL",
+                Some("https://api.example.com"),
+                VariableType::String,
+            ),
+        ];
+        let env_vars = vec![env_var("LOG_LEVEL", Some("debug"), VariableType::String)];
+
+        let merged = merge_with_defaults(&defaults, &env_vars);
+        assert_eq!(merged.len(), 2);
+        assert_eq!(merged[0].name, "API_URL");
+        assert_eq!(merged[1].name, "LOG_LEVEL");
+        assert_eq!(merged[1].value, Some(String::from("debug")));
+    }
+
+    #[test]
+    fn merge_with_defaults_adds_environment_variables() {
+        let defaults = vec![env_var("LOG_LEVEL", Some("info"), VariableType::String)];
+        let mut author_only = env_var("LOG_LEVEL", Some("debug"), VariableType::String);
+        author_only.service = EnvironmentVariableServiceType::Author;
+        let env_vars = vec![
+            author_only,
+            env_var("FEATURE", Some("on"), VariableType::String),
+        ];
+
+        let merged = merge_with_defaults(&defaults, &env_vars);
+        assert_eq!(merged.len(), 3);
+        assert_eq!(merged[0].value, Some(String::from("info")));
+        assert!(merge_with_defaults(&defaults, &[]).contains(&defaults[0]));
+    }
+
+    #[test]
+    fn is_unchanged_compares_values_of_plain_variables() {
+        let vars_cloud = vec![
+            env_var("FOO", Some("bar"), VariableType::String),
+            env_var("SECRET", None, VariableType::SecretString),
+        ];
+
+        assert!(is_unchanged(
+            &env_var("FOO", Some("bar"), VariableType::String),
+            &vars_cloud
+        ));
+        assert!(!is_unchanged(
+            &env_var("FOO", Some("baz"), VariableType::String),
+            &vars_cloud
+        ));
+        assert!(!is_unchanged(
+            &env_var("NEW", Some("bar"), VariableType::String),
+            &vars_cloud
+        ));
+    }
+
+    #[test]
+    fn is_unchanged_never_skips_secrets_and_deletions() {
+        let vars_cloud = vec![env_var("SECRET", None, VariableType::SecretString)];
+
+        assert!(!is_unchanged(
+            &env_var("SECRET", Some("value"), VariableType::SecretString),
+            &vars_cloud
+        ));
+        assert!(!is_unchanged(
+            &env_var("SECRET", None, VariableType::SecretString),
+            &vars_cloud
+        ));
+    }
+
+    /// Applies a sync mode to a YAML with `NEW` and `FOO=yaml` while Cloud Manager has `FOO` and
+    /// `OLD`, returns name and value of what is sent
+    fn sync(mode: SyncMode) -> Vec<(String, Option<String>)> {
+        let mut vars_final = vec![
+            env_var("NEW", Some("new"), VariableType::String),
+            env_var("FOO", Some("yaml"), VariableType::String),
+        ];
+        let vars_cloud = vec![
+            env_var("FOO", Some("hotfix"), VariableType::String),
+            env_var("OLD", Some("old"), VariableType::String),
+        ];
+        apply_sync_mode(&mut vars_final, &vars_cloud, mode);
+        vars_final
+            .into_iter()
+            .map(|vf| (vf.name, vf.value))
+            .collect()
+    }
+
+    #[test]
+    fn full_sync_updates_and_deletes() {
+        assert_eq!(
+            sync(SyncMode::Full),
+            vec![
+                (String::from("NEW"), Some(String::from("new"))),
+                (String::from("FOO"), Some(String::from("yaml"))),
+                (String::from("OLD"), None),
+            ]
+        );
+    }
+
+    #[test]
+    fn additive_sync_never_deletes() {
+        assert_eq!(
+            sync(SyncMode::Additive),
+            vec![
+                (String::from("NEW"), Some(String::from("new"))),
+                (String::from("FOO"), Some(String::from("yaml"))),
+            ]
+        );
+    }
+
+    #[test]
+    fn add_only_sync_keeps_existing_variables() {
+        assert_eq!(
+            sync(SyncMode::AddOnly),
+            vec![(String::from("NEW"), Some(String::from("new")))]
+        );
+
+        // Only name and service count, a variable of another service is still added
+        let mut vars_final = vec![EnvironmentVariable {
+            service: EnvironmentVariableServiceType::Publish,
+            ..env_var("FOO", Some("yaml"), VariableType::String)
+        }];
+        let vars_cloud = vec![env_var("FOO", None, VariableType::SecretString)];
+        apply_sync_mode(&mut vars_final, &vars_cloud, SyncMode::AddOnly);
+        assert_eq!(vars_final.len(), 1);
+    }
+
+    fn fake_decrypt(encrypted: &EncryptedValue) -> Result<String, String> {
+        match encrypted.ciphertext {
+            "Z29vZA==" => Ok(String::from("good")),
+            _ => Err(String::from("wrong key?")),
+        }
+    }
+
+    fn invalid_config() -> YamlConfig {
+        read_yaml_from_file("test/test_yaml_config_invalid_vars.yml").unwrap()
+    }
+
+    fn messages_of<'a>(problems: &'a [ValidationProblem], name: &str) -> Vec<&'a str> {
+        problems
+            .iter()
+            .filter(|p| p.location.contains(&format!("/ {} (", name)))
+            .map(|p| p.message.as_str())
+            .collect()
+    }
+
+    #[test]
+    fn valid_files_have_no_problems() {
+        for file in [
+            "test/test_yaml_config.yml",
+            "test/test_yaml_config_defaults.yml",
+        ] {
+            let config: YamlConfig = read_yaml_from_file(file).unwrap();
+            assert_eq!(validate_env_vars(&config, fake_decrypt), vec![], "{}", file);
+            assert_eq!(
+                validate_pipeline_vars(&config, fake_decrypt),
+                vec![],
+                "{}",
+                file
+            );
+        }
+    }
+
+    #[test]
+    fn validate_env_vars_reports_every_problem() {
+        let problems = validate_env_vars(&invalid_config(), fake_decrypt);
+        assert_eq!(problems.len(), 7, "{:#?}", problems);
+        assert_eq!(
+            problems[0].to_string(),
+            "program 222222 / defaults / LOG_LEVEL (\"all\"): duplicate variable definition"
+        );
+        assert!(messages_of(&problems, "API_KEY").is_empty());
+        assert_eq!(
+            messages_of(&problems, "OTHER_KEY"),
+            vec!["encrypted value can't be decrypted: wrong key?"]
+        );
+        assert_eq!(
+            messages_of(&problems, "1_INVALID-NAME"),
+            vec!["name must start with a letter or underscore and only contain letters, digits and underscores"]
+        );
+        assert_eq!(
+            messages_of(&problems, "ADOBE_INTERNAL"),
+            vec!["names starting with ADOBE_ are reserved"]
+        );
+        assert_eq!(
+            messages_of(&problems, "PLAIN_ENCRYPTED"),
+            vec!["encrypted value in a plain variable, use type secretString"]
+        );
+        assert_eq!(
+            messages_of(&problems, "RESTORED_SECRET"),
+            vec!["variable has no value"]
+        );
+        assert_eq!(
+            messages_of(&problems, "WRONG_SERVICE"),
+            vec!["invalid service, use author, publish, preview or leave it out"]
+        );
+    }
+
+    #[test]
+    fn validate_pipeline_vars_reports_duplicates_per_service() {
+        let problems = validate_pipeline_vars(&invalid_config(), fake_decrypt);
+        assert_eq!(
+            problems
+                .iter()
+                .map(|p| p.to_string())
+                .collect::<Vec<_>>(),
+            vec![
+                "program 222222 / pipeline 1010101010 / FOO (\"uiTest\"): duplicate variable definition",
+                "program 222222 / pipeline 1010101010 / UNKNOWN (\"invalid\"): invalid service, use build, uiTest or functionalTest",
+            ]
+        );
+    }
+
+    #[test]
+    fn verify_secrets_reports_every_encrypted_value() {
+        let checks = verify_secrets(&invalid_config(), fake_decrypt);
+        assert_eq!(
+            checks,
+            vec![
+                SecretCheck {
+                    location: String::from("program 222222 / environment 333333 / API_KEY"),
+                    error: None,
+                },
+                SecretCheck {
+                    location: String::from("program 222222 / environment 333333 / OTHER_KEY"),
+                    error: Some(String::from("wrong key?")),
+                },
+                SecretCheck {
+                    location: String::from("program 222222 / environment 333333 / PLAIN_ENCRYPTED"),
+                    error: None,
+                },
+            ]
+        );
+
+        let config = YamlConfig::parse(
+            "programs:\n  - id: 1\n    pipelines:\n      - id: 2\n        variables:\n          - name: TOKEN\n            value: $enc\n            type: secretString\n",
+        )
+        .unwrap();
+        assert_eq!(
+            verify_secrets(&config, fake_decrypt)[0].error.as_deref(),
+            Some("encrypted value must have the form '$enc[:gcm1][:<key id>] <ciphertext>' or '$enc:age <ciphertext>'")
+        );
+    }
+
+    #[test]
+    fn verify_secrets_with_real_ciphertexts() {
+        let config = YamlConfig::parse(&format!(
+            "programs:\n  - id: 1\n    defaults:\n      variables:\n        - name: GOOD\n          value: $enc {}\n          type: secretString\n        - name: BAD\n          value: $enc {}\n          type: secretString\n        - name: ROTATED\n          value: $enc:v2 {}\n          type: secretString\n",
+            crate::encryption::encrypt_with_key("team key", "s3cret"),
+            crate::encryption::encrypt_with_key("personal key", "s3cret"),
+            crate::encryption::encrypt_with_key("new team key", "s3cret")
+        ))
+        .unwrap();
+        let checks = verify_secrets(&config, |encrypted: &EncryptedValue| {
+            let key = match encrypted.key_id {
+                Some("v2") => "new team key",
+                _ => "team key",
+            };
+            crate::encryption::decrypt_with_key(key, encrypted.ciphertext)
+        });
+        assert_eq!(checks[0].error, None);
+        assert!(checks[1].error.is_some());
+        assert_eq!(checks[1].location, "program 1 / defaults / BAD");
+        assert_eq!(checks[2].error, None);
+    }
+
+    #[test]
+    fn invalid_pipeline_services_are_found_before_any_change() {
+        assert_eq!(
+            invalid_pipeline_services(&invalid_config()),
+            vec!["program 222222 / pipeline 1010101010 / UNKNOWN"]
+        );
+        let config: YamlConfig = read_yaml_from_file("test/test_yaml_config.yml").unwrap();
+        assert!(invalid_pipeline_services(&config).is_empty());
+    }
+
+    #[test]
+    fn env_vars_configs_are_recognized() {
+        for (file, env_vars_config) in [
+            ("test/test_yaml_config.yml", false),
+            ("test/test_yaml_config_apply.yml", false),
+            ("test/test_yaml_config_defaults.yml", true),
+            ("test/test_yaml_config_domains.yml", true),
+        ] {
+            let config: YamlConfig = read_yaml_from_file(file).unwrap();
+            assert_eq!(is_env_vars_config(&config), env_vars_config, "{}", file);
+        }
+    }
+
+    #[test]
+    fn preflight_accepts_variables_within_limits() {
+        let vars = vec![
+            env_var("A", Some("a"), VariableType::String),
+            env_var("B", None, VariableType::String),
+            env_var(
+                "C",
+                Some(&"ü".repeat(MAX_VARIABLE_VALUE_LENGTH)),
+                VariableType::SecretString,
+            ),
+        ];
+        assert!(preflight_env_vars(&vars, &[]).is_empty());
+    }
+
+    #[test]
+    fn preflight_reports_too_long_values() {
+        let vars = vec![
+            env_var("A", Some("a"), VariableType::String),
+            env_var(
+                "LONG",
+                Some(&"x".repeat(MAX_VARIABLE_VALUE_LENGTH + 1)),
+                VariableType::String,
+            ),
+        ];
+        assert_eq!(
+            preflight_env_vars(&vars, &[]),
+            vec![String::from(
+                "value of 'LONG' (service: \"all\") has 2049 characters, the limit is 2048"
+            )]
+        );
+    }
+
+    #[test]
+    fn preflight_names_largest_variables_of_too_big_payload() {
+        // Within the character limit, but every character takes two bytes
+        let value = "ü".repeat(MAX_VARIABLE_VALUE_LENGTH);
+        let mut vars: Vec<EnvironmentVariable> = (0..130)
+            .map(|i| env_var(&format!("VAR_{:03}", i), Some(&value), VariableType::String))
+            .collect();
+        vars.push(env_var("SMALL", Some("s"), VariableType::String));
+
+        let problems = preflight_env_vars(&vars, &[]);
+        assert_eq!(problems.len(), 1, "{:?}", problems);
+        assert_eq!(
+            problems[0],
+            "variables add up to 538506 bytes, the limit is 524288; largest: 'VAR_000' (4141 bytes), \
+             'VAR_001' (4141 bytes), 'VAR_002' (4141 bytes), 'VAR_003' (4141 bytes)"
+        );
+    }
+
+    #[test]
+    fn preflight_counts_the_variables_after_the_patch() {
+        let cloud: Vec<EnvironmentVariable> = (0..MAX_VARIABLES_PER_ENVIRONMENT)
+            .map(|i| env_var(&format!("VAR_{:03}", i), Some("v"), VariableType::String))
+            .collect();
+        let changed = vec![env_var("VAR_000", Some("new"), VariableType::String)];
+        assert_eq!(count_after_patch(&changed, &cloud), 200);
+        assert!(preflight_env_vars(&changed, &cloud).is_empty());
+
+        let added = vec![env_var("NEW", Some("v"), VariableType::String)];
+        assert_eq!(
+            preflight_env_vars(&added, &cloud),
+            vec![String::from(
+                "the environment would have 201 variables, the limit is 200"
+            )]
+        );
+
+        let replaced = vec![
+            env_var("NEW", Some("v"), VariableType::String),
+            env_var("VAR_000", None, VariableType::String),
+        ];
+        assert_eq!(count_after_patch(&replaced, &cloud), 200);
+    }
+
+    #[test]
+    fn check_variable_limits() {
+        let long_name = "A".repeat(MAX_VARIABLE_NAME_LENGTH + 1);
+        let problems = check_variable(
+            "x",
+            &long_name,
+            Some("v"),
+            &VariableType::String,
+            &fake_decrypt,
+        );
+        assert_eq!(problems.len(), 1);
+        assert_eq!(problems[0].message, "name is longer than 100 characters");
+
+        let long_value = "v".repeat(MAX_VARIABLE_VALUE_LENGTH + 1);
+        let problems = check_variable(
+            "x",
+            "NAME",
+            Some(&long_value),
+            &VariableType::String,
+            &fake_decrypt,
+        );
+        assert_eq!(problems.len(), 1);
+        assert_eq!(problems[0].message, "value is longer than 2048 characters");
+
+        let problems = check_variable(
+            "x",
+            "NAME",
+            Some("$enc"),
+            &VariableType::SecretString,
+            &fake_decrypt,
+        );
+        assert_eq!(
+            problems[0].message,
+            "encrypted value must have the form '$enc[:gcm1][:<key id>] <ciphertext>' or '$enc:age <ciphertext>'"
+        );
+    }
+}
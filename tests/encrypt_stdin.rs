@@ -0,0 +1,62 @@
+//! Pipes secrets through `pippo encrypt` and `pippo decrypt` via stdin, like a shell would.
+
+use std::io::Write;
+use std::process::{Command, Stdio};
+
+/// Runs pippo with the given arguments and stdin, returns its stdout
+fn pippo(args: &[&str], stdin: &str) -> String {
+    let mut child = Command::new(env!("CARGO_BIN_EXE_pippo"))
+        .args(args)
+        .env("PIPPO_CRYPTKEY", "Test 123@!")
+        .env_remove("PIPPO_CIPHER")
+        .env_remove("PIPPO_AGE_IDENTITY")
+        .env_remove("PIPPO_AGE_IDENTITY_FILE")
+        .env_remove("PIPPO_AGE_RECIPIENTS")
+        .stdin(Stdio::piped())
+        .stdout(Stdio::piped())
+        .stderr(Stdio::piped())
+        .spawn()
+        .unwrap();
+    child
+        .stdin
+        .take()
+        .unwrap()
+        .write_all(stdin.as_bytes())
+        .unwrap();
+    let output = child.wait_with_output().unwrap();
+    assert!(
+        output.status.success(),
+        "pippo {:?} failed: {}",
+        args,
+        String::from_utf8_lossy(&output.stderr)
+    );
+    String::from_utf8(output.stdout).unwrap()
+}
+
+#[test]
+fn encrypt_and_decrypt_through_a_pipe() {
+    let encrypted = pippo(&["encrypt"], "s3cret with spaces\n");
+    assert!(encrypted.starts_with("$enc:gcm1 "));
+    assert!(!encrypted.contains("s3cret"));
+    assert_eq!(pippo(&["decrypt"], &encrypted), "s3cret with spaces\n");
+}
+
+#[test]
+fn dash_reads_stdin_and_only_one_newline_is_trimmed() {
+    let encrypted = pippo(&["encrypt", "--cipher", "cbc", "-"], "two lines\n\n");
+    assert_eq!(pippo(&["decrypt", "-"], &encrypted), "two lines\n\n");
+}
+
+#[test]
+fn age_values_decrypt_with_the_identity_file() {
+    let encrypted = pippo(
+        &["encrypt", "--recipients", "test/test_age_recipients.txt"],
+        "s3cret\n",
+    );
+    assert!(encrypted.starts_with("$enc:age "));
+    let decrypted = pippo(
+        &["decrypt", "--identity-file", "test/test_age_identity.txt"],
+        &encrypted,
+    );
+    assert_eq!(decrypted, "s3cret\n");
+}